@@ -1,28 +1,72 @@
 use std::{
     ffi::{OsStr, OsString},
     fs,
-    path::Path,
+    path::{Path, PathBuf},
 };
 
-use squishy::{error::SquishyError, EntryKind, SquashFS, SquashFSEntry};
+use serde::Serialize;
+use squishy::{
+    appimage::{AppImage, FoundEntry},
+    error::SquishyError,
+    unpack::{UnpackAction, UnpackedEntry},
+    EntryKind, SquashFSEntry,
+};
+
+use crate::json::{FoundJson, WrittenJson};
 
 pub type Result<T> = std::result::Result<T, SquishyError>;
 
+/// Results of the `appimage` subcommand, printed with `--json`
+#[derive(Default, Serialize)]
+pub struct AppImageReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub architecture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime_dump: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integrated_desktop: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub integrated_icons: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desktop: Option<FoundJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<FoundJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub appstream: Option<FoundJson>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub licenses: Vec<FoundJson>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub matches: Vec<FoundJson>,
+    pub written: Vec<WrittenJson>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub planned: Vec<UnpackedEntry>,
+    pub errors: Vec<String>,
+}
+
 pub fn extract_file<P: AsRef<Path>>(
-    squashfs: &SquashFS,
+    appimage: &AppImage,
     entry: &SquashFSEntry,
     output_dir: P,
     output_name: Option<&OsStr>,
     copy_permissions: bool,
-) -> Result<()> {
-    if let EntryKind::File(basic_file) = entry.kind {
+    dry_run: bool,
+) -> Result<Option<(PathBuf, UnpackAction)>> {
+    if let EntryKind::File(_) = entry.kind {
         let file = &entry.path;
         let file_name = output_name
             .map(|output_name| {
                 let name_with_extension = file
                     .extension()
                     .map(|ext| {
-                        let file_str = file.file_name().unwrap().to_string_lossy();
+                        let file_str = file.file_name().unwrap_or_default().to_string_lossy();
                         if file_str.ends_with("appdata.xml") || file_str.ends_with("metainfo.xml") {
                             let base_name = if file_str.ends_with("appdata.xml") {
                                 "appdata"
@@ -43,20 +87,117 @@ pub fn extract_file<P: AsRef<Path>>(
                             )
                         }
                     })
-                    .unwrap_or_else(|| file.file_name().unwrap().to_string_lossy().to_string());
+                    .unwrap_or_else(|| {
+                        file.file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string()
+                    });
 
                 OsString::from(name_with_extension)
             })
-            .unwrap_or_else(|| file.file_name().unwrap().to_os_string());
+            .unwrap_or_else(|| file.file_name().unwrap_or_default().to_os_string());
 
-        fs::create_dir_all(&output_dir)?;
         let output_path = output_dir.as_ref().join(file_name);
-        if copy_permissions {
-            squashfs.write_file_with_permissions(basic_file, &output_path, entry.header)?;
-        } else {
-            squashfs.write_file(basic_file, &output_path)?;
+        return write_file_to(appimage, entry, output_path, copy_permissions, dry_run);
+    }
+    Ok(None)
+}
+
+/// Writes the file entry to the exact output path, creating its parent directories
+pub fn write_file_to(
+    appimage: &AppImage,
+    entry: &SquashFSEntry,
+    output_path: PathBuf,
+    copy_permissions: bool,
+    dry_run: bool,
+) -> Result<Option<(PathBuf, UnpackAction)>> {
+    if !matches!(entry.kind, EntryKind::File(_)) {
+        return Ok(None);
+    }
+    let action = if output_path.symlink_metadata().is_ok() {
+        UnpackAction::Overwrite
+    } else {
+        UnpackAction::Create
+    };
+    if dry_run {
+        return Ok(Some((output_path, action)));
+    }
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if copy_permissions {
+        appimage.write_entry_with_permissions(entry, &output_path)?;
+    } else {
+        appimage.write_entry(entry, &output_path)?;
+    }
+    Ok(Some((output_path, action)))
+}
+
+pub fn describe_found(found: &FoundEntry) -> String {
+    match &found.resolved_from {
+        Some(symlink) => format!(
+            "{} ({}, resolved from {})",
+            found.entry.path.display(),
+            found.source,
+            symlink.display()
+        ),
+        None => format!("{} ({})", found.entry.path.display(), found.source),
+    }
+}
+
+/// Renders the found entry with the template, replacing `{path}`, `{name}`,
+/// `{size}`, `{kind}`, `{source}` and `{resolved_from}`, and the `\t`, `\n`
+/// and `\\` escapes. Unknown placeholders are kept as they are.
+pub fn format_found(template: &str, kind: &str, found: &FoundEntry) -> String {
+    let path = &found.entry.path;
+    let mut output = String::new();
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '\\' => match rest.chars().next() {
+                Some(escaped @ ('t' | 'n' | '\\')) => {
+                    output.push(match escaped {
+                        't' => '\t',
+                        'n' => '\n',
+                        _ => '\\',
+                    });
+                    rest = &rest[1..];
+                }
+                _ => output.push(c),
+            },
+            '{' => {
+                let value = rest.split_once('}').and_then(|(name, _)| {
+                    Some(match name {
+                        "path" => path.to_string_lossy().to_string(),
+                        "name" => path
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string(),
+                        "size" => found.entry.size.to_string(),
+                        "kind" => kind.to_string(),
+                        "source" => found.source.to_string(),
+                        "resolved_from" => found
+                            .resolved_from
+                            .as_deref()
+                            .map(|symlink| symlink.to_string_lossy().to_string())
+                            .unwrap_or_default(),
+                        _ => return None,
+                    })
+                });
+                match value {
+                    Some(value) => {
+                        output.push_str(&value);
+                        rest = &rest[rest.find('}').unwrap_or_default() + 1..];
+                    }
+                    None => output.push(c),
+                }
+            }
+            _ => output.push(c),
         }
-        println!("Wrote {} to {}", file.display(), output_path.display());
     }
-    Ok(())
+    output
 }
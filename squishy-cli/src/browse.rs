@@ -0,0 +1,376 @@
+use std::{
+    collections::HashMap,
+    fs,
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+};
+
+use indicatif::HumanBytes;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+use squishy::{EntryKind, SquashFS};
+
+use crate::{
+    appimage::Result,
+    utils::{format_mode, open_squashfs},
+};
+
+/// Files larger than this are not loaded for the preview
+const PREVIEW_LIMIT: u32 = 1 << 20;
+
+/// An entry of the image, detached from the reader so it can be listed freely
+struct Item {
+    path: PathBuf,
+    mode: String,
+    size: u32,
+    is_dir: bool,
+    target: Option<PathBuf>,
+}
+
+impl Item {
+    fn name(&self) -> String {
+        self.path.file_name().map_or_else(
+            || "/".to_owned(),
+            |name| name.to_string_lossy().into_owned(),
+        )
+    }
+}
+
+enum Mode {
+    Browse,
+    /// Typing a search query
+    Search,
+}
+
+struct Browser<'a, 'f> {
+    squashfs: &'a SquashFS<'f>,
+    items: Vec<Item>,
+    /// Indices of the children of every directory, directories first
+    children: HashMap<PathBuf, Vec<usize>>,
+    dir: PathBuf,
+    /// Indices of the listed items, the children of `dir` or the search results
+    listed: Vec<usize>,
+    state: ListState,
+    mode: Mode,
+    query: String,
+    preview: Vec<String>,
+    status: String,
+    output: PathBuf,
+}
+
+impl<'a, 'f> Browser<'a, 'f> {
+    fn new(squashfs: &'a SquashFS<'f>, output: PathBuf) -> Self {
+        let items = squashfs
+            .entries()
+            .map(|entry| Item {
+                mode: format_mode(&entry),
+                size: entry.size,
+                is_dir: matches!(entry.kind, EntryKind::Directory),
+                target: match entry.kind {
+                    EntryKind::Symlink(target) => Some(target),
+                    _ => None,
+                },
+                path: entry.path,
+            })
+            .collect::<Vec<_>>();
+
+        let mut children: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+        for (index, item) in items.iter().enumerate() {
+            if let Some(parent) = item.path.parent() {
+                children
+                    .entry(parent.to_path_buf())
+                    .or_default()
+                    .push(index);
+            }
+        }
+        for indices in children.values_mut() {
+            indices.sort_by(|a, b| {
+                let (a, b) = (&items[*a], &items[*b]);
+                b.is_dir.cmp(&a.is_dir).then_with(|| a.path.cmp(&b.path))
+            });
+        }
+
+        let mut browser = Self {
+            squashfs,
+            items,
+            children,
+            dir: PathBuf::from("/"),
+            listed: Vec::new(),
+            state: ListState::default(),
+            mode: Mode::Browse,
+            query: String::new(),
+            preview: Vec::new(),
+            status: "Enter: open  Backspace: up  /: search  x: extract  q: quit".to_owned(),
+            output,
+        };
+        browser.open_dir(PathBuf::from("/"), None);
+        browser
+    }
+
+    fn selected(&self) -> Option<&Item> {
+        self.state
+            .selected()
+            .and_then(|index| self.listed.get(index))
+            .map(|index| &self.items[*index])
+    }
+
+    /// Lists the directory, selecting the child with the path if given
+    fn open_dir(&mut self, dir: PathBuf, select: Option<&Path>) {
+        self.listed = self.children.get(&dir).cloned().unwrap_or_default();
+        let position = select
+            .and_then(|path| {
+                self.listed
+                    .iter()
+                    .position(|index| self.items[*index].path == path)
+            })
+            .unwrap_or(0);
+        self.state
+            .select((!self.listed.is_empty()).then_some(position));
+        self.dir = dir;
+        self.update_preview();
+    }
+
+    fn search(&mut self) {
+        let query = self.query.to_lowercase();
+        self.listed = (0..self.items.len())
+            .filter(|index| {
+                self.items[*index]
+                    .path
+                    .to_string_lossy()
+                    .to_lowercase()
+                    .contains(&query)
+            })
+            .collect();
+        self.state.select((!self.listed.is_empty()).then_some(0));
+        self.update_preview();
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.listed.is_empty() {
+            return;
+        }
+        let current = self.state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, self.listed.len() as isize - 1);
+        self.state.select(Some(next as usize));
+        self.update_preview();
+    }
+
+    /// Enters the selected directory, or jumps to the selected search result
+    fn enter(&mut self) {
+        let Some(item) = self.selected() else {
+            return;
+        };
+        let path = item.path.clone();
+        if item.is_dir && matches!(self.mode, Mode::Browse) {
+            self.open_dir(path, None);
+        } else if let Some(parent) = path.parent() {
+            self.mode = Mode::Browse;
+            self.open_dir(parent.to_path_buf(), Some(&path));
+        }
+    }
+
+    fn leave(&mut self) {
+        if let Some(parent) = self.dir.parent() {
+            let dir = self.dir.clone();
+            self.open_dir(parent.to_path_buf(), Some(&dir));
+        }
+    }
+
+    fn update_preview(&mut self) {
+        let Some(item) = self.selected() else {
+            self.preview = vec!["(empty)".to_owned()];
+            return;
+        };
+        self.preview = if item.is_dir {
+            let count = self.children.get(&item.path).map_or(0, Vec::len);
+            vec![format!("Directory with {} entries", count)]
+        } else if let Some(target) = &item.target {
+            vec![format!("Symlink to {}", target.display())]
+        } else if item.size > PREVIEW_LIMIT {
+            vec![format!(
+                "{} file, too large to preview",
+                HumanBytes(item.size as u64)
+            )]
+        } else {
+            match self
+                .squashfs
+                .find(&item.path)
+                .map(|entry| self.squashfs.read_entry(&entry))
+            {
+                Some(Ok(data)) => match std::str::from_utf8(&data) {
+                    Ok(text) if !data.contains(&0) => text.lines().map(str::to_owned).collect(),
+                    _ => vec![format!("Binary file, {}", HumanBytes(data.len() as u64))],
+                },
+                Some(Err(e)) => vec![format!("Failed to read: {}", e)],
+                None => vec!["Not a regular file".to_owned()],
+            }
+        };
+    }
+
+    /// Extracts the selected entry, with everything below it, into the output directory
+    fn extract(&mut self) {
+        let Some(item) = self.selected() else {
+            return;
+        };
+        let root = item.path.clone();
+        let base = root.parent().unwrap_or(Path::new("/")).to_path_buf();
+        let mut written = 0;
+        let result = self
+            .squashfs
+            .entries()
+            .filter(|entry| entry.path.starts_with(&root))
+            .try_for_each(|entry| {
+                let dest = self
+                    .output
+                    .join(entry.path.strip_prefix(&base).unwrap_or(&entry.path));
+                if let Some(parent) = dest.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                match entry.kind {
+                    EntryKind::Directory => fs::create_dir_all(&dest)?,
+                    EntryKind::File(file) => {
+                        self.squashfs
+                            .write_file_with_permissions(file, &dest, entry.header)?
+                    }
+                    EntryKind::Symlink(target) => symlink(target, &dest)?,
+                    EntryKind::Unknown => return Ok(()),
+                }
+                written += 1;
+                Ok::<_, squishy::error::SquishyError>(())
+            });
+        self.status = match result {
+            Ok(()) => format!(
+                "Extracted {} entries of {} to {}",
+                written,
+                root.display(),
+                self.output.display()
+            ),
+            Err(e) => format!("Failed to extract {}: {}", root.display(), e),
+        };
+    }
+
+    /// Handles a key press, returning false to quit
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        match self.mode {
+            Mode::Search => match code {
+                KeyCode::Esc => {
+                    self.mode = Mode::Browse;
+                    self.query.clear();
+                    let dir = self.dir.clone();
+                    self.open_dir(dir, None);
+                }
+                KeyCode::Enter => self.enter(),
+                KeyCode::Up => self.move_selection(-1),
+                KeyCode::Down => self.move_selection(1),
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.search();
+                }
+                KeyCode::Char(c) => {
+                    self.query.push(c);
+                    self.search();
+                }
+                _ => {}
+            },
+            Mode::Browse => match code {
+                KeyCode::Char('q') | KeyCode::Esc => return false,
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::PageUp => self.move_selection(-20),
+                KeyCode::PageDown => self.move_selection(20),
+                KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => self.enter(),
+                KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => self.leave(),
+                KeyCode::Char('/') => {
+                    self.mode = Mode::Search;
+                    self.search();
+                }
+                KeyCode::Char('x') => self.extract(),
+                _ => {}
+            },
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [list_area, preview_area] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(body);
+
+        let title = match self.mode {
+            Mode::Search => format!("Search: {}", self.query),
+            Mode::Browse => self.dir.display().to_string(),
+        };
+        frame.render_widget(Line::from(title).style(Style::new().bold()), header);
+
+        let rows = self
+            .listed
+            .iter()
+            .map(|index| {
+                let item = &self.items[*index];
+                let name = match self.mode {
+                    Mode::Search => item.path.display().to_string(),
+                    Mode::Browse => item.name(),
+                };
+                let mut row = format!(
+                    "{} {:>10} {}",
+                    item.mode,
+                    HumanBytes(item.size as u64),
+                    name
+                );
+                if item.is_dir {
+                    row.push('/');
+                }
+                if let Some(target) = &item.target {
+                    row.push_str(&format!(" -> {}", target.display()));
+                }
+                ListItem::new(row)
+            })
+            .collect::<Vec<_>>();
+        let list = List::new(rows)
+            .block(Block::new().borders(Borders::ALL))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.state);
+
+        let preview = Paragraph::new(
+            self.preview
+                .iter()
+                .map(|line| Line::raw(line.as_str()))
+                .collect::<Vec<_>>(),
+        )
+        .block(Block::new().borders(Borders::ALL).title("Preview"));
+        frame.render_widget(preview, preview_area);
+
+        frame.render_widget(Line::raw(self.status.as_str()), footer);
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle_key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Opens an interactive file browser for the image, extracting selected
+/// entries into the output directory
+pub fn browse<P: AsRef<Path>>(file: &P, offset: Option<u64>, output: PathBuf) -> Result<()> {
+    let squashfs = open_squashfs(file, offset)?;
+    let mut browser = Browser::new(&squashfs, output);
+    ratatui::run(|terminal| browser.run(terminal))
+}
@@ -0,0 +1,35 @@
+use std::{
+    io::{self, BufWriter, ErrorKind, Write},
+    path::Path,
+};
+
+use squishy::{error::SquishyError, EntryKind};
+
+use crate::{appimage::Result, utils::open_squashfs};
+
+/// Streams a single file from the image to stdout, following symlinks
+pub fn cat<P: AsRef<Path>, Q: AsRef<Path>>(
+    file: &P,
+    offset: Option<u64>,
+    inner_path: Q,
+) -> Result<()> {
+    let inner_path = inner_path.as_ref();
+    let squashfs = open_squashfs(file, offset)?;
+    let not_found = || SquishyError::FileNotFound(inner_path.to_path_buf());
+
+    let entry = squashfs.find(inner_path).ok_or_else(not_found)?;
+    let entry = match entry.kind {
+        EntryKind::Symlink(_) => squashfs.resolve_symlink(&entry)?.ok_or_else(not_found)?,
+        _ => entry,
+    };
+
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    let result = squashfs
+        .copy_entry_to(&entry, &mut stdout)
+        .and_then(|_| Ok(stdout.flush()?));
+    match result {
+        // The reading end of the pipe went away, e.g. `squishy cat ... | head`
+        Err(SquishyError::Io(e)) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::{Path, PathBuf};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::Serialize;
+use squishy::{filter::PathFilter, EntryKind, SquashFSEntry};
+
+use crate::{
+    appimage::Result, cli::HashAlgorithm, hash::digest, json::print_json, utils::open_squashfs,
+};
+
+/// The digest of a file, as printed by `checksum`
+#[derive(Serialize)]
+struct ChecksumJson {
+    path: PathBuf,
+    digest: String,
+}
+
+/// Prints `<digest>  <path>` for the file entries matching the filter, with
+/// paths relative to the image root so the output can be checked with
+/// `sha256sum -c` or `b3sum -c` against an extracted tree
+pub fn checksum<P: AsRef<Path>>(
+    file: &P,
+    offset: Option<u64>,
+    filter: &PathFilter,
+    algorithm: HashAlgorithm,
+    json: bool,
+) -> Result<()> {
+    let squashfs = open_squashfs(file, offset)?;
+    let entries: Vec<SquashFSEntry> = squashfs
+        .entries()
+        .filter(|entry| matches!(entry.kind, EntryKind::File(_)) && filter.matches(&entry.path))
+        .collect();
+    let checksums: Vec<ChecksumJson> = entries
+        .into_par_iter()
+        .map(|entry| {
+            Ok(ChecksumJson {
+                digest: digest(&squashfs, &entry, algorithm)?,
+                path: entry.path,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    if json {
+        print_json(&checksums);
+        return Ok(());
+    }
+    for checksum in checksums {
+        let path = checksum.path.strip_prefix("/").unwrap_or(&checksum.path);
+        println!("{}  {}", checksum.digest, path.display());
+    }
+    Ok(())
+}
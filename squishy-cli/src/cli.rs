@@ -1,6 +1,16 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::{Parser, Subcommand};
+use clap::{
+    builder::PossibleValuesParser, ArgAction, Args as ClapArgs, Parser, Subcommand, ValueEnum,
+};
+use clap_complete::Shell;
+use squishy::{
+    lint::BUILTIN_RULES,
+    unpack::{OverwritePolicy, PermissionOptions, SymlinkPolicy, UnpackOptions},
+    Compressor,
+};
+
+use crate::pack::parse_block_size;
 
 #[derive(Parser)]
 #[command(
@@ -12,38 +22,102 @@ use clap::{Parser, Subcommand};
 {usage-heading} {usage}
 
 {all-args}{after-help}",
+    after_help = "Exit codes:
+  0  success
+  1  general failure
+  2  input file or entry not found
+  3  invalid image, pattern, embedded file or configuration
+  4  extraction finished with errors
+  5  image is damaged
+  6  lint found problems",
     arg_required_else_help = true
 )]
 pub struct Args {
     #[clap(subcommand)]
     pub command: Commands,
 
+    /// Suppress logs [env: SQUISHY_QUIET]
     #[clap(required = false, long, short)]
     pub quiet: bool,
+
+    /// Log more: -v prints a line for every extracted entry, -vv traces everything
+    #[clap(required = false, long, short, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Print log messages as JSON lines
+    #[clap(required = false, long, global = true)]
+    pub log_json: bool,
+
+    /// Also append log messages with timestamps to the file, at least at debug level
+    #[clap(required = false, long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Number of threads for parallel extraction (default: number of CPUs)
+    #[clap(
+        required = false,
+        long,
+        short = 'j',
+        global = true,
+        env = "SQUISHY_JOBS"
+    )]
+    pub jobs: Option<usize>,
+
+    /// Process entries one at a time, in image order (overrides --jobs)
+    #[clap(required = false, long, global = true)]
+    pub no_parallel: bool,
+
+    /// Process and print entries sorted by path, one at a time, for output that
+    /// is the same on every run (implies --no-parallel)
+    #[clap(required = false, long, global = true)]
+    pub sorted: bool,
+
+    /// Print machine-readable JSON instead of logs [env: SQUISHY_JSON]
+    #[clap(required = false, long, global = true)]
+    pub json: bool,
+
+    /// Don't read or remember image offsets in the cache directory
+    #[clap(required = false, long, global = true)]
+    pub no_cache: bool,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// AppImage specific tasks
-    #[command(arg_required_else_help = true)]
+    #[command(
+        arg_required_else_help = true,
+        args_conflicts_with_subcommands = true,
+        subcommand_negates_reqs = true
+    )]
     #[clap(name = "appimage", alias = "ai")]
     AppImage {
-        /// Path to appimage file
+        #[command(subcommand)]
+        action: Option<Box<AppImageAction>>,
+
+        /// Path to appimage file, or - to read from stdin
         #[arg(required = true)]
-        file: PathBuf,
+        file: Option<PathBuf>,
 
         /// Offset
         #[arg(required = false, long, short)]
         offset: Option<u64>,
 
-        /// Filter to apply
+        /// Filter to apply (substring or glob, can be repeated)
+        #[arg(required = false, long, short)]
+        filter: Vec<String>,
+
+        /// Exclude paths matching pattern (substring or glob, can be repeated)
         #[arg(required = false, long, short)]
-        filter: Option<String>,
+        exclude: Vec<String>,
 
         /// Whether to search for icon
         #[arg(required = false, long, short)]
         icon: bool,
 
+        /// Directories to search for icons, instead of /usr/share/icons and
+        /// /usr/share/pixmaps (comma separated or repeated)
+        #[arg(required = false, long, value_delimiter = ',')]
+        icon_paths: Vec<PathBuf>,
+
         /// Whether to search for desktop file
         #[arg(required = false, long, short)]
         desktop: bool,
@@ -52,6 +126,32 @@ pub enum Commands {
         #[arg(required = false, long, short)]
         appstream: bool,
 
+        /// Whether to search for icon, desktop and appstream file
+        #[arg(required = false, long)]
+        all: bool,
+
+        /// Whether to search for license files (LICENSE*, COPYING*, usr/share/licenses/**).
+        /// With --write, they keep their path.
+        #[arg(required = false, long, conflicts_with_all = ["output", "stdout"])]
+        license: bool,
+
+        /// Search for files matching pattern (substring or glob, can be repeated),
+        /// e.g. 'usr/share/licenses/**'. With --write, matches keep their path.
+        #[arg(required = false, long, conflicts_with_all = ["output", "stdout"])]
+        find: Vec<String>,
+
+        /// Whether to install the desktop file and icon into the XDG data directory
+        #[arg(required = false, long)]
+        integrate: bool,
+
+        /// Whether to print AppImage information (offset, architecture, version, runtime)
+        #[arg(required = false, long)]
+        info: bool,
+
+        /// Write the runtime ELF (bytes before the squashfs offset) to the path
+        #[arg(required = false, long)]
+        dump_runtime: Option<PathBuf>,
+
         /// Whether to write files to disk
         #[arg(required = false, long, short)]
         write: Option<Option<PathBuf>>,
@@ -63,10 +163,35 @@ pub enum Commands {
         /// Copy permissions from the squashfs entry
         #[arg(required = false, long)]
         copy_permissions: bool,
+
+        /// Print what would be written without touching the disk
+        #[arg(required = false, long)]
+        dry_run: bool,
+
+        /// Write the selected file to this exact path
+        #[arg(required = false, long, conflicts_with_all = ["write", "stdout"])]
+        output: Option<PathBuf>,
+
+        /// Write the contents of the selected file to stdout
+        #[arg(
+            required = false,
+            long,
+            conflicts_with_all = ["write", "dry_run", "info", "integrate", "dump_runtime", "format", "print0"]
+        )]
+        stdout: bool,
+
+        /// Print found entries with a template instead of a description, using
+        /// {path}, {name}, {size}, {kind}, {source} and {resolved_from}, e.g. '{path}\t{size}'
+        #[arg(required = false, long)]
+        format: Option<String>,
+
+        /// Terminate printed entries with NUL instead of newline, implies --format '{path}'
+        #[arg(required = false, long)]
+        print0: bool,
     },
 
     Unsquashfs {
-        /// Path to squashfs file
+        /// Path to squashfs file, or - to read from stdin
         #[arg(required = true)]
         file: PathBuf,
 
@@ -77,5 +202,805 @@ pub enum Commands {
         /// Whether to write files to disk
         #[arg(required = false, long, short)]
         write: Option<Option<PathBuf>>,
+
+        #[command(flatten)]
+        overwrite: OverwriteArgs,
+
+        #[command(flatten)]
+        permissions: PermissionArgs,
+
+        #[command(flatten)]
+        paths: PathMapArgs,
+
+        #[command(flatten)]
+        manifest: ManifestArgs,
+
+        /// Print what would be written without touching the disk
+        #[arg(required = false, long)]
+        dry_run: bool,
+
+        /// Write the image as a tar archive to stdout instead, e.g. to pipe into `tar -x`
+        #[arg(
+            required = false,
+            long,
+            conflicts_with_all = ["write", "dry_run", "manifest"]
+        )]
+        to_stdout_tar: bool,
+    },
+
+    /// Extract an AppImage or squashfs image, detecting its format
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "x")]
+    X {
+        /// Path to AppImage or squashfs file, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Directory to extract to (default: squashfs-root)
+        #[arg(required = false, long, short)]
+        output: Option<PathBuf>,
+
+        #[command(flatten)]
+        overwrite: OverwriteArgs,
+
+        #[command(flatten)]
+        permissions: PermissionArgs,
+
+        #[command(flatten)]
+        paths: PathMapArgs,
+
+        #[command(flatten)]
+        manifest: ManifestArgs,
+
+        /// Print what would be written without touching the disk
+        #[arg(required = false, long)]
+        dry_run: bool,
+
+        /// Write the image as a tar archive to stdout instead, e.g. to pipe into `tar -x`
+        #[arg(
+            required = false,
+            long,
+            conflicts_with_all = ["output", "dry_run", "manifest"]
+        )]
+        to_stdout_tar: bool,
+    },
+
+    /// List entries of a squashfs image or AppImage
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "list", alias = "ls")]
+    List {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Offset
+        #[arg(required = false, long, short)]
+        offset: Option<u64>,
+
+        /// Sort entries by key
+        #[arg(required = false, long, value_enum)]
+        sort: Option<SortKey>,
+
+        /// Only list entries of type (f: file, d: directory, l: symlink)
+        #[arg(required = false, long = "type", short = 't', value_enum)]
+        entry_type: Option<EntryType>,
+
+        /// Only print entry paths
+        #[arg(required = false, long)]
+        paths_only: bool,
+
+        /// Show the type of files sniffed from their contents, regardless of extension
+        #[arg(required = false, long)]
+        mime: bool,
+    },
+
+    /// Extract entries matching glob patterns from a squashfs image or AppImage
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "extract")]
+    Extract {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Patterns of the entries to extract (substring or glob), all entries if none
+        #[arg(required = false)]
+        patterns: Vec<String>,
+
+        /// Directory to extract into (default: current directory)
+        #[arg(required = false, long, short)]
+        output: Option<PathBuf>,
+
+        /// Offset
+        #[arg(required = false, long)]
+        offset: Option<u64>,
+
+        /// Remove the number of leading path components from extracted paths
+        #[arg(required = false, long, default_value_t = 0)]
+        strip_components: usize,
+
+        #[command(flatten)]
+        overwrite: OverwriteArgs,
+
+        #[command(flatten)]
+        permissions: PermissionArgs,
+
+        #[command(flatten)]
+        paths: PathMapArgs,
+
+        #[command(flatten)]
+        manifest: ManifestArgs,
+
+        /// Resume an earlier extraction from its manifest, keeping the files whose size and SHA-256 still match
+        #[arg(
+            required = false,
+            long,
+            value_name = "MANIFEST",
+            conflicts_with = "dry_run"
+        )]
+        resume: Option<PathBuf>,
+
+        /// Print what would be written without touching the disk
+        #[arg(required = false, long)]
+        dry_run: bool,
+    },
+
+    /// Print a file from a squashfs image or AppImage to stdout
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "cat")]
+    Cat {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Path of the file inside the image
+        #[arg(required = true)]
+        path: PathBuf,
+
+        /// Offset
+        #[arg(required = false, long, short)]
+        offset: Option<u64>,
+    },
+
+    /// Show information about a squashfs image or AppImage
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "info")]
+    Info {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Offset
+        #[arg(required = false, long, short)]
+        offset: Option<u64>,
+    },
+
+    /// Show the filesystem as a tree
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "tree")]
+    Tree {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Directory inside the image to start from
+        #[arg(required = false)]
+        path: Option<PathBuf>,
+
+        /// Offset
+        #[arg(required = false, long, short)]
+        offset: Option<u64>,
+
+        /// Only descend this many levels
+        #[arg(required = false, long, short)]
+        depth: Option<usize>,
+
+        /// Show file sizes
+        #[arg(required = false, long, short)]
+        size: bool,
+    },
+
+    /// Summarize the architecture and linking of ELF binaries inside a squashfs image or AppImage
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "elf-scan")]
+    ElfScan {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Patterns of the files to inspect (substring or glob), all files if none
+        #[arg(required = false)]
+        patterns: Vec<String>,
+
+        /// Offset
+        #[arg(required = false, long, short)]
+        offset: Option<u64>,
+    },
+
+    /// Print digests of files inside a squashfs image or AppImage
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "checksum")]
+    Checksum {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Patterns of the files to hash (substring or glob), all files if none
+        #[arg(required = false)]
+        patterns: Vec<String>,
+
+        /// Offset
+        #[arg(required = false, long, short)]
+        offset: Option<u64>,
+
+        /// Hash algorithm
+        #[arg(required = false, long, value_enum, default_value_t = HashAlgorithm::Sha256)]
+        algo: HashAlgorithm,
+    },
+
+    /// Mount a squashfs image or AppImage read-only with FUSE
+    #[cfg(feature = "fuse")]
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "mount")]
+    Mount {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Directory to mount the image at
+        #[arg(required = true)]
+        mountpoint: PathBuf,
+
+        /// Offset
+        #[arg(required = false, long, short)]
+        offset: Option<u64>,
+
+        /// Allow other users to access the mount, and unmount it when squishy exits
+        #[arg(required = false, long)]
+        allow_other: bool,
     },
+
+    /// Browse a squashfs image or AppImage interactively
+    #[cfg(feature = "browse")]
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "browse")]
+    Browse {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Offset
+        #[arg(required = false, long, short)]
+        offset: Option<u64>,
+
+        /// Directory to extract selected entries to (default: current directory)
+        #[arg(required = false, long, short = 'C')]
+        output: Option<PathBuf>,
+    },
+
+    /// Build a squashfs image, or an AppImage with a runtime, from a directory
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "pack")]
+    Pack {
+        /// Directory to pack
+        #[arg(required = true)]
+        dir: PathBuf,
+
+        /// Path of the image to write
+        #[arg(required = true, long, short)]
+        output: PathBuf,
+
+        /// Compression algorithm
+        #[arg(required = false, long, value_enum, default_value_t = Compression::Zstd)]
+        comp: Compression,
+
+        /// Data block size, a power of two from 4K to 1M
+        #[arg(required = false, long, default_value = "128K", value_parser = parse_block_size)]
+        block_size: u32,
+
+        /// Prepend this AppImage runtime to produce an executable AppImage
+        #[arg(required = false, long)]
+        appimage_runtime: Option<PathBuf>,
+    },
+
+    /// Recompress a squashfs image, or the payload of an AppImage, without extracting it
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "repack")]
+    Repack {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Path of the image to write, an AppImage again for an AppImage
+        #[arg(required = true, long, short)]
+        output: PathBuf,
+
+        /// Compression algorithm
+        #[arg(required = false, long, value_enum, default_value_t = Compression::Zstd)]
+        comp: Compression,
+
+        /// Compression level (gzip: 1-9, xz: 0-9, zstd: 1-22)
+        #[arg(required = false, long)]
+        level: Option<u32>,
+
+        /// Data block size, a power of two from 4K to 1M (default: that of the image)
+        #[arg(required = false, long, value_parser = parse_block_size)]
+        block_size: Option<u32>,
+
+        /// Offset
+        #[arg(required = false, long)]
+        offset: Option<u64>,
+    },
+
+    /// Convert an AppImage, squashfs or EROFS image to a tar or zip archive
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "convert")]
+    Convert {
+        /// Path to AppImage, squashfs or EROFS file, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Patterns of the entries to put in the zip archive (substring or glob),
+        /// all entries if none
+        #[arg(required = false, requires = "zip")]
+        patterns: Vec<String>,
+
+        /// Path of the archive to write, compressed by its extension (.tar,
+        /// .tar.gz, .tgz, .tar.zst, .tzst), or - to write a plain tar to stdout
+        #[arg(required_unless_present = "zip", long, short)]
+        output: Option<PathBuf>,
+
+        /// Write a zip archive of the entries matching the patterns instead
+        #[arg(required = false, long, conflicts_with_all = ["output", "oci_layer"])]
+        zip: Option<PathBuf>,
+
+        /// Write an OCI image layer, printing its media type, digest, size and DiffID
+        #[arg(required = false, long)]
+        oci_layer: bool,
+
+        /// Offset
+        #[arg(required = false, long)]
+        offset: Option<u64>,
+    },
+
+    /// Compare the entries of two images
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "diff")]
+    Diff {
+        /// Path to the old squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        old: PathBuf,
+
+        /// Path to the new squashfs file or AppImage
+        #[arg(required = true)]
+        new: PathBuf,
+
+        /// Also compare the SHA-256 of files with equal sizes
+        #[arg(required = false, long, short)]
+        content: bool,
+    },
+
+    /// Report file contents duplicated across images and the space storing them once would save
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "dedup-report")]
+    DedupReport {
+        /// Paths to the squashfs files or AppImages, the first one can be - to read from stdin
+        #[arg(required = true, num_args = 2..)]
+        images: Vec<PathBuf>,
+
+        /// Hash algorithm
+        #[arg(required = false, long, value_enum, default_value_t = HashAlgorithm::Sha256)]
+        algo: HashAlgorithm,
+    },
+
+    /// Summarize uncompressed and compressed sizes per directory
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "du")]
+    Du {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Offset
+        #[arg(required = false, long, short)]
+        offset: Option<u64>,
+
+        /// Only show directories up to this depth (0: root only)
+        #[arg(required = false, long, short)]
+        depth: Option<usize>,
+    },
+
+    /// Search the contents of files inside a squashfs image or AppImage
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "grep")]
+    Grep {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Regular expression to search for
+        #[arg(required = true)]
+        pattern: String,
+
+        /// Only search files matching pattern (substring or glob, can be repeated)
+        #[arg(required = false, long, short)]
+        path: Vec<String>,
+
+        /// Offset
+        #[arg(required = false, long, short)]
+        offset: Option<u64>,
+
+        /// Match case-insensitively
+        #[arg(required = false, long, short)]
+        ignore_case: bool,
+
+        /// Also search binary files, printing the byte offset of matches
+        #[arg(required = false, long)]
+        binary: bool,
+    },
+
+    /// Check the image for corruption by decompressing every file
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "verify")]
+    Verify {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Offset
+        #[arg(required = false, long, short)]
+        offset: Option<u64>,
+    },
+
+    /// Check the image for large files, git metadata, private keys, world-writable
+    /// and setuid files, and broken symlinks
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "lint")]
+    Lint {
+        /// Path to squashfs file or AppImage, or - to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Largest file size allowed by the large-file rule, in MiB
+        #[arg(required = false, long, default_value_t = 100)]
+        max_size: u64,
+
+        /// Rules to skip, comma separated
+        #[arg(
+            required = false,
+            long,
+            value_delimiter = ',',
+            value_parser = PossibleValuesParser::new(BUILTIN_RULES)
+        )]
+        disable: Vec<String>,
+
+        /// Offset
+        #[arg(required = false, long, short)]
+        offset: Option<u64>,
+    },
+
+    /// Print shell completions
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "completions")]
+    Completions {
+        /// Shell to generate completions for
+        #[arg(required = true, value_enum)]
+        shell: Shell,
+    },
+
+    /// Print the manpage in roff format
+    #[clap(name = "manpage")]
+    Manpage,
+}
+
+#[derive(Subcommand)]
+pub enum AppImageAction {
+    /// Build an AppImage from an AppDir
+    #[command(arg_required_else_help = true)]
+    #[clap(name = "create")]
+    Create {
+        /// AppDir with AppRun, a desktop entry and its icon at the root
+        #[arg(required = true)]
+        appdir: PathBuf,
+
+        /// Path of the AppImage to write
+        #[arg(required = true, long, short)]
+        output: PathBuf,
+
+        /// AppImage runtime to prepend
+        #[arg(required = true, long)]
+        runtime: PathBuf,
+
+        /// Compression algorithm
+        #[arg(required = false, long, value_enum, default_value_t = Compression::Zstd)]
+        comp: Compression,
+
+        /// Data block size, a power of two from 4K to 1M
+        #[arg(required = false, long, default_value = "128K", value_parser = parse_block_size)]
+        block_size: u32,
+
+        /// Update information for the .upd_info section, e.g. 'zsync|https://example.com/App.AppImage.zsync'
+        #[arg(required = false, long, short)]
+        update_info: Option<String>,
+
+        /// File with the signature for the .sha256_sig section
+        #[arg(required = false, long)]
+        signature: Option<PathBuf>,
+
+        /// File with the public key for the .sig_key section
+        #[arg(required = false, long)]
+        signing_key: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SortKey {
+    Name,
+    Size,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+impl From<Compression> for Compressor {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::Gzip => Compressor::Gzip,
+            Compression::Xz => Compressor::Xz,
+            Compression::Zstd => Compressor::Zstd,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SymlinkMode {
+    Keep,
+    Relative,
+    SkipExternal,
+}
+
+impl From<SymlinkMode> for SymlinkPolicy {
+    fn from(mode: SymlinkMode) -> Self {
+        match mode {
+            SymlinkMode::Keep => SymlinkPolicy::Keep,
+            SymlinkMode::Relative => SymlinkPolicy::Relative,
+            SymlinkMode::SkipExternal => SymlinkPolicy::SkipExternal,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+}
+
+impl From<HashAlgorithm> for squishy::digest::HashAlgorithm {
+    fn from(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => squishy::digest::HashAlgorithm::Sha256,
+            HashAlgorithm::Blake3 => squishy::digest::HashAlgorithm::Blake3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ManifestFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum EntryType {
+    F,
+    D,
+    L,
+}
+
+impl Commands {
+    /// Returns the input image of the subcommand, if it takes one
+    pub fn file(&self) -> Option<&Path> {
+        match self {
+            Commands::AppImage { file, .. } => file.as_deref(),
+            Commands::Unsquashfs { file, .. }
+            | Commands::X { file, .. }
+            | Commands::List { file, .. }
+            | Commands::Extract { file, .. }
+            | Commands::Cat { file, .. }
+            | Commands::Info { file, .. }
+            | Commands::Tree { file, .. }
+            | Commands::Du { file, .. }
+            | Commands::Grep { file, .. }
+            | Commands::Verify { file, .. }
+            | Commands::Lint { file, .. }
+            | Commands::Checksum { file, .. }
+            | Commands::ElfScan { file, .. }
+            | Commands::Convert { file, .. }
+            | Commands::Repack { file, .. }
+            | Commands::Diff { old: file, .. } => Some(file),
+            Commands::DedupReport { images, .. } => images.first().map(PathBuf::as_path),
+            #[cfg(feature = "fuse")]
+            Commands::Mount { file, .. } => Some(file),
+            #[cfg(feature = "browse")]
+            Commands::Browse { file, .. } => Some(file),
+            Commands::Completions { .. } | Commands::Manpage | Commands::Pack { .. } => None,
+        }
+    }
+
+    /// Returns the input image of the subcommand for replacing it, if it takes one
+    pub fn file_mut(&mut self) -> Option<&mut PathBuf> {
+        match self {
+            Commands::AppImage { file, .. } => file.as_mut(),
+            Commands::Unsquashfs { file, .. }
+            | Commands::X { file, .. }
+            | Commands::List { file, .. }
+            | Commands::Extract { file, .. }
+            | Commands::Cat { file, .. }
+            | Commands::Info { file, .. }
+            | Commands::Tree { file, .. }
+            | Commands::Du { file, .. }
+            | Commands::Grep { file, .. }
+            | Commands::Verify { file, .. }
+            | Commands::Lint { file, .. }
+            | Commands::Checksum { file, .. }
+            | Commands::ElfScan { file, .. }
+            | Commands::Convert { file, .. }
+            | Commands::Repack { file, .. }
+            | Commands::Diff { old: file, .. } => Some(file),
+            Commands::DedupReport { images, .. } => images.first_mut(),
+            #[cfg(feature = "fuse")]
+            Commands::Mount { file, .. } => Some(file),
+            #[cfg(feature = "browse")]
+            Commands::Browse { file, .. } => Some(file),
+            Commands::Completions { .. } | Commands::Manpage | Commands::Pack { .. } => None,
+        }
+    }
+}
+
+/// Flags selecting what happens when an extracted path already exists
+#[derive(ClapArgs)]
+#[group(multiple = false)]
+pub struct OverwriteArgs {
+    /// Overwrite existing files
+    #[arg(required = false, long)]
+    force: bool,
+
+    /// Keep existing files (default)
+    #[arg(required = false, long)]
+    skip_existing: bool,
+
+    /// Overwrite existing files only if they are older than the entry
+    #[arg(required = false, long)]
+    keep_newer: bool,
+
+    /// Policy used when no flag is given, from the configuration
+    #[arg(skip)]
+    pub default: OverwritePolicy,
+}
+
+impl OverwriteArgs {
+    pub fn policy(&self) -> OverwritePolicy {
+        if self.force {
+            OverwritePolicy::Overwrite
+        } else if self.keep_newer {
+            OverwritePolicy::KeepNewer
+        } else if self.skip_existing {
+            OverwritePolicy::Skip
+        } else {
+            self.default
+        }
+    }
+}
+
+/// Flags normalizing the modes and ownership of extracted paths
+#[derive(ClapArgs)]
+pub struct PermissionArgs {
+    /// Don't apply modes from the image, create files with the default umask
+    #[arg(required = false, long, conflicts_with = "perm_mask")]
+    no_perms: bool,
+
+    /// Clear these octal mode bits from modes in the image, like a umask (e.g. 022)
+    #[arg(required = false, long, value_name = "OCTAL", value_parser = parse_octal)]
+    perm_mask: Option<u32>,
+
+    /// Change the owner of extracted paths
+    #[arg(required = false, long, value_name = "UID:GID", value_parser = parse_owner)]
+    owner: Option<(u32, u32)>,
+}
+
+impl PermissionArgs {
+    pub fn options(&self) -> PermissionOptions {
+        PermissionOptions {
+            ignore_mode: self.no_perms,
+            mask: self.perm_mask.unwrap_or(0),
+            owner: self.owner,
+        }
+    }
+}
+
+/// Flags changing where and how extracted paths are written
+#[derive(ClapArgs)]
+pub struct PathMapArgs {
+    /// Remove the leading path from extracted paths (e.g. /usr), skipping entries outside of it
+    #[arg(required = false, long, value_name = "PATH")]
+    strip_prefix: Option<PathBuf>,
+
+    /// Extract the image directory SRC to DEST in the output directory, e.g. /usr/lib=lib (can be repeated)
+    #[arg(required = false, long, value_name = "SRC=DEST", value_parser = parse_mapping)]
+    map: Vec<(PathBuf, PathBuf)>,
+
+    /// Extract entries with `..` in their path and write through symlinks in the output directory
+    #[arg(required = false, long)]
+    insecure_paths: bool,
+
+    /// How to write symlink targets: as they are, with absolute targets made relative,
+    /// or skipping symlinks that point outside of the image
+    #[arg(
+        required = false,
+        long,
+        value_name = "MODE",
+        default_value = "relative"
+    )]
+    symlinks: SymlinkMode,
+
+    /// Write blocks of zeros in files as data instead of leaving holes
+    #[arg(required = false, long)]
+    no_sparse: bool,
+}
+
+impl PathMapArgs {
+    /// Returns the unpack options with the prefix, mappings and path handling set
+    pub fn apply(self, options: UnpackOptions) -> UnpackOptions {
+        UnpackOptions {
+            strip_prefix: self.strip_prefix,
+            map: self.map,
+            insecure_paths: self.insecure_paths,
+            symlinks: self.symlinks.into(),
+            dense: self.no_sparse,
+            ..options
+        }
+    }
+}
+
+/// Flags for recording what was extracted
+#[derive(ClapArgs)]
+pub struct ManifestArgs {
+    /// Write a manifest of the extracted entries with their size, mode and SHA-256 to the file
+    #[arg(
+        required = false,
+        long,
+        value_name = "PATH",
+        conflicts_with = "dry_run"
+    )]
+    pub manifest: Option<PathBuf>,
+
+    /// Format of the manifest (default: csv for a .csv file, json otherwise)
+    #[arg(required = false, long, value_enum, requires = "manifest")]
+    pub manifest_format: Option<ManifestFormat>,
+}
+
+fn parse_octal(value: &str) -> Result<u32, String> {
+    let digits = value.strip_prefix("0o").unwrap_or(value);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!("invalid octal mode: {}", value)),
+    }
+}
+
+fn parse_mapping(value: &str) -> Result<(PathBuf, PathBuf), String> {
+    value
+        .split_once('=')
+        .filter(|(source, _)| !source.is_empty())
+        .map(|(source, dest)| (PathBuf::from(source), PathBuf::from(dest)))
+        .ok_or_else(|| format!("expected SRC=DEST, got {}", value))
+}
+
+fn parse_owner(value: &str) -> Result<(u32, u32), String> {
+    value
+        .split_once(':')
+        .and_then(|(uid, gid)| Some((uid.parse().ok()?, gid.parse().ok()?)))
+        .ok_or_else(|| format!("expected numeric UID:GID, got {}", value))
 }
@@ -0,0 +1,21 @@
+use std::io::{self, Write};
+
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+use crate::cli::Args;
+
+/// Prints the completion script for the shell to stdout
+pub fn completions(shell: Shell) -> io::Result<()> {
+    let mut command = Args::command();
+    // generated into a buffer, clap_complete panics on write errors like a closed pipe
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut command, env!("CARGO_BIN_NAME"), &mut script);
+    io::stdout().write_all(&script)
+}
+
+/// Prints the manpage in roff format to stdout
+pub fn manpage() -> io::Result<()> {
+    let command = Args::command().name(env!("CARGO_BIN_NAME"));
+    clap_mangen::Man::new(command).render(&mut io::stdout())
+}
@@ -0,0 +1,140 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use squishy::unpack::OverwritePolicy;
+
+use crate::{
+    cli::{Args, Commands},
+    error::CliError,
+};
+
+/// Defaults read from the config file and `SQUISHY_*` environment variables.
+/// Environment variables take precedence over the file, explicit flags over both.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory to extract or write into
+    output: Option<PathBuf>,
+    /// Number of threads for parallel extraction
+    jobs: Option<usize>,
+    /// Suppress logs
+    quiet: Option<bool>,
+    /// Print JSON instead of logs
+    json: Option<bool>,
+    /// One of `skip`, `overwrite` or `keep-newer`
+    overwrite: Option<String>,
+}
+
+/// Returns the path of the config file, `$SQUISHY_CONFIG` or `squishy/config.toml`
+/// in the XDG config directory
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("SQUISHY_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("squishy").join("config.toml"))
+}
+
+/// Reads a boolean environment variable, accepting 1/0, true/false, yes/no and on/off
+fn env_bool(name: &str) -> Result<Option<bool>, CliError> {
+    let Ok(value) = env::var(name) else {
+        return Ok(None);
+    };
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(Some(true)),
+        "" | "0" | "false" | "no" | "off" => Ok(Some(false)),
+        _ => Err(CliError::Config(format!(
+            "{} must be a boolean, got {}",
+            name, value
+        ))),
+    }
+}
+
+fn parse_overwrite(value: &str) -> Result<OverwritePolicy, CliError> {
+    match value {
+        "skip" => Ok(OverwritePolicy::Skip),
+        "overwrite" => Ok(OverwritePolicy::Overwrite),
+        "keep-newer" => Ok(OverwritePolicy::KeepNewer),
+        _ => Err(CliError::Config(format!(
+            "overwrite must be skip, overwrite or keep-newer, got {}",
+            value
+        ))),
+    }
+}
+
+impl Config {
+    /// Loads the config file, if it exists, and applies the environment variables
+    pub fn load() -> Result<Self, CliError> {
+        let mut config = match config_path().map(fs::read_to_string) {
+            Some(Ok(contents)) => {
+                toml::from_str(&contents).map_err(|e| CliError::Config(e.to_string()))?
+            }
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => Config::default(),
+        };
+
+        if let Some(output) = env::var_os("SQUISHY_OUTPUT") {
+            config.output = Some(PathBuf::from(output));
+        }
+        if let Some(quiet) = env_bool("SQUISHY_QUIET")? {
+            config.quiet = Some(quiet);
+        }
+        if let Some(json) = env_bool("SQUISHY_JSON")? {
+            config.json = Some(json);
+        }
+        if let Ok(overwrite) = env::var("SQUISHY_OVERWRITE") {
+            config.overwrite = Some(overwrite);
+        }
+        Ok(config)
+    }
+
+    /// Fills in the options not given on the command line.
+    /// `SQUISHY_JOBS` is handled by clap and already wins over the file.
+    pub fn apply(self, args: &mut Args) -> Result<(), CliError> {
+        args.quiet |= self.quiet.unwrap_or_default();
+        args.json |= self.json.unwrap_or_default();
+        args.jobs = args.jobs.or(self.jobs);
+
+        let overwrite = self.overwrite.as_deref().map(parse_overwrite).transpose()?;
+        match &mut args.command {
+            Commands::Extract {
+                output,
+                overwrite: overwrite_args,
+                ..
+            }
+            | Commands::X {
+                output,
+                overwrite: overwrite_args,
+                ..
+            } => {
+                if output.is_none() {
+                    *output = self.output;
+                }
+                overwrite_args.default = overwrite.unwrap_or_default();
+            }
+            Commands::Unsquashfs {
+                write,
+                overwrite: overwrite_args,
+                ..
+            } => {
+                if let (Some(write @ None), Some(output)) = (write, self.output) {
+                    *write = Some(output);
+                }
+                overwrite_args.default = overwrite.unwrap_or_default();
+            }
+            Commands::AppImage { write, .. } => {
+                if let (Some(write @ None), Some(output)) = (write, self.output) {
+                    *write = Some(output);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    io::{self, BufWriter, ErrorKind, Write},
+    path::Path,
+};
+
+use flate2::{write::GzEncoder, Compression};
+use indicatif::HumanBytes;
+use serde::Serialize;
+use squishy::{erofs::Erofs, error::SquishyError, filter::PathFilter, SquashFS};
+use tracing::info;
+
+use crate::{
+    appimage::Result,
+    detect::{self, Container},
+    hash::Sha256Writer,
+    json::print_json,
+    pack::write_image,
+    utils::open_squashfs,
+};
+
+/// zstd level of compressed archives, the default of the zstd CLI
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compression of the archive, chosen by the extension of the output
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TarCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl TarCompression {
+    fn from_path(path: &Path) -> Self {
+        let name = path.to_string_lossy();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            TarCompression::Gzip
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            TarCompression::Zstd
+        } else {
+            TarCompression::None
+        }
+    }
+
+    /// Returns the media type of an OCI image layer with the compression
+    fn media_type(self) -> &'static str {
+        match self {
+            TarCompression::None => "application/vnd.oci.image.layer.v1.tar",
+            TarCompression::Gzip => "application/vnd.oci.image.layer.v1.tar+gzip",
+            TarCompression::Zstd => "application/vnd.oci.image.layer.v1.tar+zstd",
+        }
+    }
+}
+
+/// Descriptor of an archive written as an OCI image layer, printed with `--json`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OciLayer {
+    media_type: &'static str,
+    /// SHA-256 of the archive as written
+    digest: String,
+    size: u64,
+    /// SHA-256 of the uncompressed tar, for the `rootfs.diff_ids` of the image config
+    #[serde(rename = "diffID")]
+    diff_id: String,
+}
+
+/// An image of either filesystem
+enum Image<'a> {
+    SquashFS(SquashFS<'a>),
+    Erofs(Erofs<'a>),
+}
+
+/// Opens the image, failing for DwarFS images
+fn open_image<P: AsRef<Path>>(file: &P, offset: Option<u64>) -> Result<Image<'_>> {
+    let (container, detected) = detect::detect(file)?;
+    let offset = offset.unwrap_or(detected);
+    match container {
+        Container::DwarFS => Err(SquishyError::InvalidSquashFS(format!(
+            "{} is a DwarFS image, which is not supported",
+            file.as_ref().display()
+        ))),
+        Container::Erofs => Ok(Image::Erofs(Erofs::from_path_with_offset(file, offset)?)),
+        Container::AppImage | Container::SquashFS => {
+            Ok(Image::SquashFS(open_squashfs(file, Some(offset))?))
+        }
+    }
+}
+
+/// Writes the image as a tar archive to the writer
+fn write_tar<P: AsRef<Path>, W: Write>(
+    file: &P,
+    offset: Option<u64>,
+    writer: &mut W,
+) -> Result<()> {
+    match open_image(file, offset)? {
+        Image::SquashFS(squashfs) => squashfs.write_tar(writer),
+        Image::Erofs(erofs) => erofs.write_tar(writer),
+    }
+}
+
+/// Writes the image as a tar archive to the writer, compressed, returning the
+/// SHA-256 of the uncompressed archive
+fn write_compressed<P: AsRef<Path>, W: Write>(
+    file: &P,
+    offset: Option<u64>,
+    compression: TarCompression,
+    writer: W,
+) -> Result<String> {
+    let diff_id = match compression {
+        TarCompression::None => {
+            let mut tar = Sha256Writer::new(writer);
+            write_tar(file, offset, &mut tar)?;
+            tar.finish().1
+        }
+        TarCompression::Gzip => {
+            let mut tar = Sha256Writer::new(GzEncoder::new(writer, Compression::default()));
+            write_tar(file, offset, &mut tar)?;
+            let (encoder, diff_id, _) = tar.finish();
+            encoder.finish()?;
+            diff_id
+        }
+        TarCompression::Zstd => {
+            let mut tar = Sha256Writer::new(zstd::Encoder::new(writer, ZSTD_LEVEL)?);
+            write_tar(file, offset, &mut tar)?;
+            let (encoder, diff_id, _) = tar.finish();
+            encoder.finish()?;
+            diff_id
+        }
+    };
+    Ok(diff_id)
+}
+
+/// Writes the image as a plain tar archive to stdout, e.g. for
+/// `squishy unsquashfs --to-stdout-tar ... | tar -x`
+pub fn write_tar_stdout<P: AsRef<Path>>(file: &P, offset: Option<u64>) -> Result<()> {
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    let result = write_tar(file, offset, &mut stdout).and_then(|_| Ok(stdout.flush()?));
+    match result {
+        // The reading end of the pipe went away, e.g. `squishy convert ... | head`
+        Err(SquishyError::Io(e)) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// Writes the image as a tar archive to the output, compressed by its
+/// extension, or a plain tar archive to stdout for `-`. As an OCI layer, the
+/// digest of the archive and of its uncompressed tar (the DiffID) are printed.
+pub fn convert<P: AsRef<Path>>(
+    file: &P,
+    offset: Option<u64>,
+    output: &Path,
+    oci_layer: bool,
+    json: bool,
+) -> Result<()> {
+    if output == Path::new("-") {
+        if oci_layer {
+            return Err(SquishyError::Unsupported(
+                "writing an OCI layer to stdout, its digests would end up in the archive".into(),
+            ));
+        }
+        return write_tar_stdout(file, offset);
+    }
+
+    // rather than a tar archive with a misleading name
+    if output
+        .extension()
+        .is_some_and(|extension| extension == "dwarfs")
+    {
+        return Err(SquishyError::Unsupported(
+            "writing DwarFS images, squishy has no DwarFS backend".into(),
+        ));
+    }
+
+    let compression = TarCompression::from_path(output);
+    let layer = write_image(output, |image| {
+        let mut blob = Sha256Writer::new(BufWriter::new(image));
+        let diff_id = write_compressed(file, offset, compression, &mut blob)?;
+        let (mut writer, digest, size) = blob.finish();
+        writer.flush()?;
+        Ok(OciLayer {
+            media_type: compression.media_type(),
+            digest: format!("sha256:{}", digest),
+            size,
+            diff_id: format!("sha256:{}", diff_id),
+        })
+    })?;
+
+    if !oci_layer {
+        info!(
+            "Converted {} to {}",
+            file.as_ref().display(),
+            output.display()
+        );
+    } else if json {
+        print_json(&layer);
+    } else {
+        info!(
+            "Wrote OCI layer {} ({}, {})",
+            output.display(),
+            layer.media_type,
+            HumanBytes(layer.size)
+        );
+        info!("digest:  {}", layer.digest);
+        info!("diff ID: {}", layer.diff_id);
+    }
+    Ok(())
+}
+
+/// Writes the entries of the image matching the filter as a zip archive
+pub fn convert_zip<P: AsRef<Path>>(
+    file: &P,
+    offset: Option<u64>,
+    output: &Path,
+    filter: &PathFilter,
+) -> Result<()> {
+    let image = open_image(file, offset)?;
+    write_image(output, |zip| {
+        let writer = BufWriter::new(zip);
+        let mut writer = match &image {
+            Image::SquashFS(squashfs) => squashfs.write_zip(writer, filter)?,
+            Image::Erofs(erofs) => erofs.write_zip(writer, filter)?,
+        };
+        Ok(writer.flush()?)
+    })?;
+
+    info!(
+        "Converted {} to {}",
+        file.as_ref().display(),
+        output.display()
+    );
+    Ok(())
+}
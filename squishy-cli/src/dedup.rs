@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use indicatif::HumanBytes;
+use serde::Serialize;
+use squishy::{digest::DedupReport, SquashFS};
+use tracing::info;
+
+use crate::{appimage::Result, cli::HashAlgorithm, json::print_json, utils::open_squashfs};
+
+/// The report with the images it indexes into, as printed by `dedup-report`
+#[derive(Serialize)]
+struct DedupJson<'a> {
+    images: &'a [PathBuf],
+    #[serde(flatten)]
+    report: &'a DedupReport,
+    savings: u64,
+}
+
+/// Prints the file contents found in more than one of the images, largest
+/// savings first, with every copy and the space content-addressed storage
+/// of the images would save
+pub fn dedup_report(images: &[PathBuf], algorithm: HashAlgorithm, json: bool) -> Result<()> {
+    let squashfs = images
+        .iter()
+        .map(|image| open_squashfs(image, None))
+        .collect::<Result<Vec<SquashFS>>>()?;
+    let report = DedupReport::new(&squashfs, algorithm.into())?;
+
+    if json {
+        print_json(&DedupJson {
+            images,
+            report: &report,
+            savings: report.savings(),
+        });
+        return Ok(());
+    }
+
+    for group in &report.duplicates {
+        println!(
+            "{:>12} saved  {} ({} each)",
+            HumanBytes(group.savings).to_string(),
+            group.digest,
+            HumanBytes(group.size)
+        );
+        for file in &group.files {
+            println!(
+                "    {}:{}",
+                images[file.image].display(),
+                file.path.display()
+            );
+        }
+    }
+    let percent = match report.bytes {
+        0 => 0.0,
+        bytes => report.savings() as f64 * 100.0 / bytes as f64,
+    };
+    info!(
+        "{} files in {} images, {} shared contents, {} of {} could be saved ({:.1}%)",
+        report.files,
+        images.len(),
+        report.duplicates.len(),
+        HumanBytes(report.savings()),
+        HumanBytes(report.bytes),
+        percent
+    );
+    Ok(())
+}
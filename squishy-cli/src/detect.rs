@@ -0,0 +1,138 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use squishy::{
+    detect::{detect_filesystem_type, FilesystemType},
+    error::SquishyError,
+};
+
+use crate::appimage::Result;
+
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+
+/// Container types recognized by `x`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    AppImage,
+    SquashFS,
+    DwarFS,
+    /// An EROFS image, or an AppImage with an EROFS payload
+    Erofs,
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Container::AppImage => write!(f, "AppImage"),
+            Container::SquashFS => write!(f, "squashfs image"),
+            Container::DwarFS => write!(f, "DwarFS image"),
+            Container::Erofs => write!(f, "EROFS image"),
+        }
+    }
+}
+
+/// Detects the filesystem in the file and its offset. Filesystems in an ELF
+/// are reported as AppImages, unless they are EROFS or DwarFS.
+pub fn detect<P: AsRef<Path>>(file: &P) -> Result<(Container, u64)> {
+    let mut reader = BufReader::new(File::open(file)?);
+    let mut magic = [0; 4];
+    let elf = reader.read_exact(&mut magic).is_ok() && magic == *ELF_MAGIC;
+
+    let (kind, offset) = detect_filesystem_type(&mut reader).map_err(|e| match e {
+        SquishyError::NoFilesystemFound if elf => SquishyError::InvalidSquashFS(format!(
+            "{} is an ELF without an embedded filesystem",
+            file.as_ref().display()
+        )),
+        SquishyError::NoFilesystemFound => SquishyError::InvalidSquashFS(format!(
+            "{} is neither an AppImage nor a squashfs, EROFS or DwarFS image",
+            file.as_ref().display()
+        )),
+        e => e,
+    })?;
+    let container = match kind {
+        FilesystemType::SquashFS if elf => Container::AppImage,
+        FilesystemType::SquashFS => Container::SquashFS,
+        FilesystemType::Erofs => Container::Erofs,
+        FilesystemType::DwarFS => Container::DwarFS,
+    };
+    Ok((container, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// A little endian ELF64 header without program or section headers
+    fn elf() -> Vec<u8> {
+        let mut header = vec![0; 64];
+        header[..7].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+        header[0x34..0x36].copy_from_slice(&64u16.to_le_bytes());
+        header
+    }
+
+    /// The superblock of EROFS data with 4 KiB blocks, at 1024 from its start
+    fn erofs() -> Vec<u8> {
+        let mut data = vec![0; 4096];
+        data[1024..1028].copy_from_slice(&0xE0F5_E1E2_u32.to_le_bytes());
+        data[1024 + 12] = 12;
+        data
+    }
+
+    fn detect_bytes(contents: &[u8]) -> Result<(Container, u64)> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, contents).unwrap();
+        detect(&path)
+    }
+
+    #[test]
+    fn filesystems_in_an_elf_are_appimages_unless_erofs_or_dwarfs() {
+        let mut appimage = elf();
+        appimage.extend_from_slice(b"hsqs");
+        assert!(matches!(
+            detect_bytes(&appimage),
+            Ok((Container::AppImage, 64))
+        ));
+
+        let mut erofs_appimage = elf();
+        erofs_appimage.extend_from_slice(&erofs());
+        assert!(matches!(
+            detect_bytes(&erofs_appimage),
+            Ok((Container::Erofs, 64))
+        ));
+
+        let mut dwarfs_appimage = elf();
+        dwarfs_appimage.extend_from_slice(b"DWARFS");
+        assert!(matches!(
+            detect_bytes(&dwarfs_appimage),
+            Ok((Container::DwarFS, 64))
+        ));
+    }
+
+    #[test]
+    fn detects_bare_images() {
+        assert!(matches!(
+            detect_bytes(b"hsqs"),
+            Ok((Container::SquashFS, 0))
+        ));
+        assert!(matches!(detect_bytes(&erofs()), Ok((Container::Erofs, 0))));
+        assert!(matches!(
+            detect_bytes(b"DWARFS\x02\x05"),
+            Ok((Container::DwarFS, 0))
+        ));
+    }
+
+    #[test]
+    fn files_without_a_filesystem_are_explained() {
+        let message = |contents: &[u8]| detect_bytes(contents).err().unwrap().to_string();
+        assert!(message(&elf()).ends_with("is an ELF without an embedded filesystem"));
+        assert!(message(b"plain text")
+            .ends_with("is neither an AppImage nor a squashfs, EROFS or DwarFS image"));
+    }
+}
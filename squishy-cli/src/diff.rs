@@ -0,0 +1,120 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use squishy::{EntryKind, SquashFS, SquashFSEntry};
+
+use crate::{
+    appimage::Result, cli::HashAlgorithm, hash::digest, json::print_json, utils::open_squashfs,
+};
+
+/// A difference between the two images, as printed by `diff`
+#[derive(Serialize)]
+struct DiffJson {
+    path: PathBuf,
+    change: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<&'static str>,
+}
+
+/// Lists the attributes that differ between the two entries, comparing file
+/// contents only if requested and the sizes are equal
+fn changed_fields(
+    old_squashfs: &SquashFS,
+    old: &SquashFSEntry,
+    new_squashfs: &SquashFS,
+    new: &SquashFSEntry,
+    content: bool,
+) -> Result<Vec<&'static str>> {
+    let target = |entry: &SquashFSEntry| match &entry.kind {
+        EntryKind::Symlink(target) => Some(target.clone()),
+        _ => None,
+    };
+    let mut fields = Vec::new();
+    if old.kind.name() != new.kind.name() {
+        fields.push("type");
+    }
+    if old.size != new.size {
+        fields.push("size");
+    }
+    if old.mode() != new.mode() {
+        fields.push("mode");
+    }
+    if target(old) != target(new) {
+        fields.push("target");
+    }
+    if content
+        && fields.is_empty()
+        && matches!(old.kind, EntryKind::File(_))
+        && digest(old_squashfs, old, HashAlgorithm::Sha256)?
+            != digest(new_squashfs, new, HashAlgorithm::Sha256)?
+    {
+        fields.push("content");
+    }
+    Ok(fields)
+}
+
+/// Prints the entries added, removed and changed from the old image to the new one
+pub fn diff<P: AsRef<Path>>(old: &P, new: &P, content: bool, json: bool) -> Result<()> {
+    let old_squashfs = open_squashfs(old, None)?;
+    let new_squashfs = open_squashfs(new, None)?;
+    let mut old_entries: BTreeMap<PathBuf, SquashFSEntry> = old_squashfs
+        .entries()
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
+
+    let mut diffs = Vec::new();
+    for new_entry in new_squashfs.entries() {
+        let path = new_entry.path.clone();
+        let (change, fields) = match old_entries.remove(&path) {
+            None => ("added", Vec::new()),
+            Some(old_entry) => {
+                let fields = changed_fields(
+                    &old_squashfs,
+                    &old_entry,
+                    &new_squashfs,
+                    &new_entry,
+                    content,
+                )?;
+                if fields.is_empty() {
+                    continue;
+                }
+                ("changed", fields)
+            }
+        };
+        diffs.push(DiffJson {
+            path,
+            change,
+            fields,
+        });
+    }
+    diffs.extend(old_entries.into_keys().map(|path| DiffJson {
+        path,
+        change: "removed",
+        fields: Vec::new(),
+    }));
+    diffs.sort_by(|a, b| a.path.cmp(&b.path));
+
+    if json {
+        print_json(&diffs);
+        return Ok(());
+    }
+
+    let count = |change| diffs.iter().filter(|diff| diff.change == change).count();
+    for diff in &diffs {
+        match diff.change {
+            "added" => println!("+ {}", diff.path.display()),
+            "removed" => println!("- {}", diff.path.display()),
+            _ => println!("~ {} ({})", diff.path.display(), diff.fields.join(", ")),
+        }
+    }
+    println!(
+        "{} added, {} removed, {} changed",
+        count("added"),
+        count("removed"),
+        count("changed")
+    );
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+use indicatif::HumanBytes;
+use serde::Serialize;
+use squishy::EntryKind;
+
+use crate::{appimage::Result, json::print_json, utils::open_squashfs};
+
+/// Size of a directory, as printed by `du`
+#[derive(Default, Serialize)]
+struct DirUsage {
+    path: PathBuf,
+    size: u64,
+    compressed: u64,
+}
+
+/// Prints the uncompressed and compressed size of each directory up to the
+/// depth, largest first. Sizes include all nested files.
+pub fn du<P: AsRef<Path>>(
+    file: &P,
+    offset: Option<u64>,
+    depth: Option<usize>,
+    json: bool,
+) -> Result<()> {
+    let squashfs = open_squashfs(file, offset)?;
+    let compressed_sizes = squashfs.compressed_sizes();
+
+    let mut usage: HashMap<PathBuf, DirUsage> = HashMap::new();
+    for entry in squashfs.entries() {
+        let EntryKind::File(_) = entry.kind else {
+            continue;
+        };
+        let compressed = compressed_sizes.get(&entry.path).copied().unwrap_or(0);
+
+        let components: Vec<Component> = entry
+            .path
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        // every ancestor directory, from the root down to the parent of the file
+        let ancestors = components.len().saturating_sub(1);
+        for level in 0..=ancestors.min(depth.unwrap_or(usize::MAX)) {
+            let dir = Path::new("/").join(components[..level].iter().collect::<PathBuf>());
+            let dir_usage = usage.entry(dir.clone()).or_insert_with(|| DirUsage {
+                path: dir,
+                ..Default::default()
+            });
+            dir_usage.size += entry.size as u64;
+            dir_usage.compressed += compressed;
+        }
+    }
+
+    let mut usage: Vec<DirUsage> = usage.into_values().collect();
+    usage.sort_by_key(|dir| (Reverse(dir.size), dir.path.clone()));
+
+    if json {
+        print_json(&usage);
+        return Ok(());
+    }
+    for dir in usage {
+        println!(
+            "{:>12} {:>12}  {}",
+            HumanBytes(dir.size).to_string(),
+            HumanBytes(dir.compressed).to_string(),
+            dir.path.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::Serialize;
+use squishy::{elf::ElfInfo, filter::PathFilter, EntryKind, SquashFSEntry};
+use tracing::{error, info};
+
+use crate::{appimage::Result, json::print_json, utils::open_squashfs};
+
+/// An ELF binary inside the image, as printed by `elf-scan`
+#[derive(Serialize)]
+struct ElfJson {
+    path: PathBuf,
+    #[serde(flatten)]
+    info: ElfInfo,
+}
+
+/// Prints the architecture, type and linking of every ELF binary among the
+/// file entries matching the filter, and a summary. Malformed binaries are
+/// reported and skipped.
+pub fn elf_scan<P: AsRef<Path>>(
+    file: &P,
+    offset: Option<u64>,
+    filter: &PathFilter,
+    json: bool,
+) -> Result<()> {
+    let squashfs = open_squashfs(file, offset)?;
+    let entries: Vec<SquashFSEntry> = squashfs
+        .entries()
+        .filter(|entry| matches!(entry.kind, EntryKind::File(_)) && filter.matches(&entry.path))
+        .collect();
+    let scanned: Vec<(PathBuf, squishy::Result<Option<ElfInfo>>)> = entries
+        .into_par_iter()
+        .map(|entry| {
+            let info = squashfs.elf_info(&entry);
+            (entry.path, info)
+        })
+        .collect();
+
+    let mut binaries = Vec::new();
+    for (path, info) in scanned {
+        match info {
+            Ok(Some(info)) => binaries.push(ElfJson { path, info }),
+            Ok(None) => {}
+            Err(e) => error!("Failed to inspect {}: {}", path.display(), e),
+        }
+    }
+
+    if json {
+        print_json(&binaries);
+        return Ok(());
+    }
+
+    let mut architectures: BTreeMap<String, usize> = BTreeMap::new();
+    for binary in &binaries {
+        let info = &binary.info;
+        *architectures
+            .entry(info.architecture.to_string())
+            .or_default() += 1;
+
+        let mut details = Vec::new();
+        if let Some(interpreter) = &info.interpreter {
+            details.push(format!("interpreter {}", interpreter));
+        }
+        if let Some(rpath) = &info.rpath {
+            details.push(format!("rpath {}", rpath));
+        }
+        if let Some(runpath) = &info.runpath {
+            details.push(format!("runpath {}", runpath));
+        }
+        println!(
+            "{:<8} {:<13} {:<7}  {}{}",
+            info.architecture.to_string(),
+            info.kind.to_string(),
+            if info.is_static { "static" } else { "dynamic" },
+            binary.path.display(),
+            if details.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", details.join(", "))
+            }
+        );
+    }
+
+    let statics = binaries
+        .iter()
+        .filter(|binary| binary.info.is_static)
+        .count();
+    let architectures: Vec<String> = architectures
+        .iter()
+        .map(|(architecture, count)| format!("{} {}", count, architecture))
+        .collect();
+    info!(
+        "{} ELF binaries, {} static, {} dynamic{}",
+        binaries.len(),
+        statics,
+        binaries.len() - statics,
+        if architectures.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", architectures.join(", "))
+        }
+    );
+    Ok(())
+}
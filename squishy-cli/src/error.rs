@@ -0,0 +1,90 @@
+use std::{io, path::PathBuf};
+
+use squishy::error::SquishyError;
+use thiserror::Error;
+
+/// Exit codes of the CLI
+pub mod exit_code {
+    /// Any error not covered by a more specific code
+    pub const FAILURE: i32 = 1;
+    /// An input file or an entry inside the image doesn't exist
+    pub const NOT_FOUND: i32 = 2;
+    /// The image, a pattern, an embedded file or the configuration couldn't be parsed
+    pub const PARSE_ERROR: i32 = 3;
+    /// Extraction finished, but some entries couldn't be written
+    pub const PARTIAL_EXTRACTION: i32 = 4;
+    /// Verification found corrupted entries
+    pub const CORRUPTED: i32 = 5;
+    /// Lint found problems in the image
+    pub const LINT_FAILED: i32 = 6;
+}
+
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error("Input file not found: {}", .0.display())]
+    InputNotFound(PathBuf),
+
+    #[error("No {0} found.")]
+    EntryNotFound(String),
+
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+
+    #[error(transparent)]
+    Squishy(#[from] SquishyError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Extraction finished with {0} errors")]
+    PartialExtraction(u64),
+
+    #[error("Image is damaged, {0} corrupted entries")]
+    Corrupted(usize),
+
+    #[error("Lint found {0} problems")]
+    LintFailed(usize),
+}
+
+impl CliError {
+    /// Returns true if the details were already printed as part of the command output
+    pub fn is_reported(&self) -> bool {
+        matches!(
+            self,
+            CliError::PartialExtraction(_) | CliError::Corrupted(_) | CliError::LintFailed(_)
+        )
+    }
+
+    /// Returns the process exit code for the error
+    pub fn exit_code(&self) -> i32 {
+        let io_kind = match self {
+            CliError::Squishy(e) => e.io_error(),
+            CliError::Io(e) => Some(e),
+            _ => None,
+        }
+        .map(io::Error::kind);
+        match self {
+            CliError::InputNotFound(_)
+            | CliError::EntryNotFound(_)
+            | CliError::Squishy(SquishyError::FileNotFound(_)) => exit_code::NOT_FOUND,
+            _ if io_kind == Some(io::ErrorKind::NotFound) => exit_code::NOT_FOUND,
+            CliError::Config(_)
+            | CliError::Squishy(
+                SquishyError::NoSquashFsFound
+                | SquishyError::InvalidSquashFS(_)
+                | SquishyError::NoErofsFound
+                | SquishyError::NoDwarFsFound
+                | SquishyError::NoFilesystemFound
+                | SquishyError::InvalidErofs(_)
+                | SquishyError::InvalidElf(_)
+                | SquishyError::InvalidPattern(_)
+                | SquishyError::Image(_),
+            ) => exit_code::PARSE_ERROR,
+            _ if io_kind == Some(io::ErrorKind::InvalidData) => exit_code::PARSE_ERROR,
+            CliError::PartialExtraction(_) => exit_code::PARTIAL_EXTRACTION,
+            CliError::Corrupted(_) => exit_code::CORRUPTED,
+            CliError::LintFailed(_) => exit_code::LINT_FAILED,
+            _ => exit_code::FAILURE,
+        }
+    }
+}
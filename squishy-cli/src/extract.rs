@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use squishy::unpack::{ExtractedEntry, UnpackAction, UnpackOptions};
+use tracing::info;
+
+use crate::{
+    appimage::Result,
+    cli::ManifestArgs,
+    json::{print_json, WrittenJson},
+    utils::{format_planned, open_squashfs},
+};
+
+/// Extracts the entries selected by the options into the output directory,
+/// keeping the verified files of the earlier extraction if resuming
+pub fn extract<P: AsRef<Path>, Q: AsRef<Path>>(
+    file: &P,
+    offset: Option<u64>,
+    output_dir: Q,
+    options: &UnpackOptions,
+    manifest: &ManifestArgs,
+    previous: Option<&[ExtractedEntry]>,
+    json: bool,
+) -> Result<()> {
+    let squashfs = open_squashfs(file, offset)?;
+    let unpacked = match previous {
+        Some(previous) => squashfs.unpack_resume(output_dir, previous, options)?,
+        None => squashfs.unpack_to(output_dir, options)?,
+    }
+    .entries;
+    if options.dry_run {
+        if json {
+            print_json(&unpacked);
+        } else {
+            for entry in unpacked {
+                info!(
+                    "{}",
+                    format_planned(entry.action, &entry.source, &entry.destination)
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    let written: Vec<WrittenJson> = unpacked
+        .iter()
+        .filter(|entry| entry.action != UnpackAction::Skip)
+        .map(|entry| WrittenJson::new(&entry.source, &entry.destination))
+        .collect();
+    manifest.write(&written)?;
+    if json {
+        print_json(&written);
+    } else {
+        let kept = unpacked
+            .iter()
+            .filter(|entry| entry.action == UnpackAction::Keep)
+            .count();
+        if kept > 0 {
+            info!("Kept {} files verified from the earlier extraction", kept);
+        }
+        for entry in unpacked
+            .iter()
+            .filter(|entry| matches!(entry.action, UnpackAction::Create | UnpackAction::Overwrite))
+        {
+            info!(
+                "Wrote {} to {}",
+                entry.source.display(),
+                entry.destination.display()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use regex::bytes::{Regex, RegexBuilder};
+use serde::Serialize;
+use squishy::{error::SquishyError, filter::PathFilter, EntryKind, SquashFS, SquashFSEntry};
+
+use crate::{appimage::Result, json::print_json, utils::open_squashfs};
+
+/// Number of leading bytes checked for NUL to detect binary files, like grep
+const BINARY_PROBE_LEN: usize = 8192;
+
+/// A single match, as printed by `grep`
+#[derive(Serialize)]
+struct GrepMatch {
+    path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    offset: usize,
+    text: String,
+}
+
+/// Searches the contents of the file entries matching the path filter for the
+/// pattern, printing `path:line:text` for text files and the match offset for
+/// binary files (searched only with `binary`)
+pub fn grep<P: AsRef<Path>>(
+    file: &P,
+    offset: Option<u64>,
+    pattern: &str,
+    filter: &PathFilter,
+    ignore_case: bool,
+    binary: bool,
+    json: bool,
+) -> Result<()> {
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| SquishyError::InvalidPattern(e.to_string()))?;
+    let squashfs = open_squashfs(file, offset)?;
+
+    let entries: Vec<SquashFSEntry> = squashfs
+        .entries()
+        .filter(|entry| matches!(entry.kind, EntryKind::File(_)) && filter.matches(&entry.path))
+        .collect();
+    let matches: Vec<Vec<GrepMatch>> = entries
+        .into_par_iter()
+        .map(|entry| search_entry(&squashfs, &entry, &regex, binary))
+        .collect::<Result<_>>()?;
+    let matches = matches.into_iter().flatten();
+
+    if json {
+        print_json(&matches.collect::<Vec<_>>());
+        return Ok(());
+    }
+    for m in matches {
+        match m.line {
+            Some(line) => println!("{}:{}:{}", m.path.display(), line, m.text),
+            None => println!(
+                "Binary file {} matches at offset {}: {}",
+                m.path.display(),
+                m.offset,
+                m.text
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Finds the matches within a single file
+fn search_entry(
+    squashfs: &SquashFS,
+    entry: &SquashFSEntry,
+    regex: &Regex,
+    binary: bool,
+) -> Result<Vec<GrepMatch>> {
+    let contents = squashfs.read_entry(entry)?;
+    let is_binary = contents[..contents.len().min(BINARY_PROBE_LEN)].contains(&0);
+    if is_binary && !binary {
+        return Ok(Vec::new());
+    }
+
+    if is_binary {
+        return Ok(regex
+            .find_iter(&contents)
+            .map(|m| GrepMatch {
+                path: entry.path.clone(),
+                line: None,
+                offset: m.start(),
+                text: String::from_utf8_lossy(m.as_bytes()).into_owned(),
+            })
+            .collect());
+    }
+
+    let mut offset = 0;
+    let mut matches = Vec::new();
+    for (index, line) in contents.split(|&b| b == b'\n').enumerate() {
+        if regex.is_match(line) {
+            matches.push(GrepMatch {
+                path: entry.path.clone(),
+                line: Some(index + 1),
+                offset,
+                text: String::from_utf8_lossy(line).into_owned(),
+            });
+        }
+        offset += line.len() + 1;
+    }
+    Ok(matches)
+}
@@ -0,0 +1,81 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+};
+
+use sha2::{Digest, Sha256};
+use squishy::{SquashFS, SquashFSEntry};
+
+use crate::{appimage::Result, cli::HashAlgorithm};
+
+/// Feeds everything written into the digest
+struct DigestWriter<D>(D);
+
+impl<D: Digest> Write for DigestWriter<D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Formats the bytes as lowercase hex
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Computes the hex digest of the file entry, without extracting it
+pub fn digest(
+    squashfs: &SquashFS,
+    entry: &SquashFSEntry,
+    algorithm: HashAlgorithm,
+) -> Result<String> {
+    squashfs.digest(entry, algorithm.into())
+}
+
+/// Computes the hex SHA-256 digest of a file on disk
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut writer = DigestWriter(Sha256::new());
+    io::copy(&mut File::open(path)?, &mut writer)?;
+    Ok(to_hex(&writer.0.finalize()))
+}
+
+/// Passes everything written on to the writer, feeding it into a SHA-256
+/// digest and counting the bytes on the way
+pub struct Sha256Writer<W> {
+    writer: W,
+    digest: Sha256,
+    size: u64,
+}
+
+impl<W: Write> Sha256Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Sha256Writer {
+            writer,
+            digest: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    /// Returns the writer, the hex digest and the number of bytes written
+    pub fn finish(self) -> (W, String, u64) {
+        (self.writer, to_hex(&self.digest.finalize()), self.size)
+    }
+}
+
+impl<W: Write> Write for Sha256Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.digest.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,72 @@
+use std::{fs::File, io::Read, path::Path};
+
+use serde::Serialize;
+
+use crate::{appimage::Result, json::print_json, utils::open_squashfs};
+
+/// Information about a squashfs image or AppImage, as printed by `info`
+#[derive(Serialize)]
+struct ImageInfo {
+    container: &'static str,
+    filesystem: &'static str,
+    offset: u64,
+    compression: String,
+    block_size: u32,
+    files: u64,
+    directories: u64,
+    symlinks: u64,
+    other: u64,
+    total_size: u64,
+    image_size: u64,
+}
+
+/// Detects the container type from the ELF magic at the start of the file
+fn container_type<P: AsRef<Path>>(file: &P) -> Result<&'static str> {
+    let mut magic = [0u8; 4];
+    let is_elf = File::open(file)?.read_exact(&mut magic).is_ok() && magic == *b"\x7fELF";
+    Ok(if is_elf { "appimage" } else { "raw" })
+}
+
+/// Prints information about the image, as a table or as JSON
+pub fn info<P: AsRef<Path>>(file: &P, offset: Option<u64>, json: bool) -> Result<()> {
+    let container = container_type(file)?;
+    let squashfs = open_squashfs(file, offset)?;
+    let stats = squashfs.stats();
+
+    let info = ImageInfo {
+        container,
+        filesystem: "squashfs",
+        offset: squashfs.offset(),
+        compression: format!("{:?}", squashfs.compressor()).to_lowercase(),
+        block_size: squashfs.block_size(),
+        files: stats.files,
+        directories: stats.directories,
+        symlinks: stats.symlinks,
+        other: stats.other,
+        total_size: stats.total_size,
+        image_size: file.as_ref().metadata()?.len(),
+    };
+
+    if json {
+        print_json(&info);
+        return Ok(());
+    }
+
+    let rows = [
+        ("Container", info.container.to_string()),
+        ("Filesystem", info.filesystem.to_string()),
+        ("Offset", info.offset.to_string()),
+        ("Compression", info.compression),
+        ("Block size", info.block_size.to_string()),
+        ("Files", info.files.to_string()),
+        ("Directories", info.directories.to_string()),
+        ("Symlinks", info.symlinks.to_string()),
+        ("Other", info.other.to_string()),
+        ("Total size", info.total_size.to_string()),
+        ("Image size", info.image_size.to_string()),
+    ];
+    for (key, value) in rows {
+        println!("{:<12} {}", format!("{}:", key), value);
+    }
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use squishy::appimage::FoundEntry;
+
+/// An entry chosen by one of the AppImage finders
+#[derive(Serialize)]
+pub struct FoundJson {
+    pub path: PathBuf,
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_from: Option<PathBuf>,
+}
+
+impl From<&FoundEntry<'_>> for FoundJson {
+    fn from(found: &FoundEntry<'_>) -> Self {
+        Self {
+            path: found.entry.path.clone(),
+            source: found.source.to_string(),
+            resolved_from: found.resolved_from.clone(),
+        }
+    }
+}
+
+/// A file written to disk
+#[derive(Serialize)]
+pub struct WrittenJson {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+}
+
+impl WrittenJson {
+    pub fn new(source: &Path, destination: &Path) -> Self {
+        Self {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+        }
+    }
+}
+
+/// Prints the value as pretty JSON to stdout
+pub fn print_json<T: Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("Failed to serialize JSON: {}", e),
+    }
+}
+
+/// Prints an error and its exit code as a JSON object to stdout
+pub fn print_error_json(error: &str, code: i32) {
+    print_json(&serde_json::json!({ "error": error, "code": code }));
+}
@@ -0,0 +1,51 @@
+use std::path::Path;
+
+use squishy::lint::{LargeFile, Linter};
+use tracing::info;
+
+use crate::{error::CliError, json::print_json, utils::open_squashfs};
+
+/// Runs the built-in rules, except the disabled ones, on every entry of the
+/// image, failing if any of them finds a problem
+pub fn lint<P: AsRef<Path>>(
+    file: &P,
+    offset: Option<u64>,
+    max_size: u64,
+    disable: &[String],
+    json: bool,
+) -> Result<(), CliError> {
+    let squashfs = open_squashfs(file, offset)?;
+    let mut linter = Linter::default();
+    linter.add(LargeFile {
+        max_size: max_size << 20,
+    });
+    for rule in disable {
+        linter.remove(rule);
+    }
+    let report = linter.lint(&squashfs)?;
+
+    if json {
+        print_json(&report);
+    } else {
+        for finding in &report.findings {
+            println!(
+                "{:<7}  {:<14}  {}: {}",
+                finding.severity.to_string(),
+                finding.rule,
+                finding.path.display(),
+                finding.message
+            );
+        }
+        info!(
+            "Checked {} entries, {} problems",
+            report.entries,
+            report.findings.len()
+        );
+    }
+
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(CliError::LintFailed(report.findings.len()))
+    }
+}
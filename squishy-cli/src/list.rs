@@ -0,0 +1,124 @@
+use std::{cmp::Reverse, path::Path};
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::Serialize;
+use squishy::{EntryKind, SquashFSEntry};
+
+use crate::{
+    appimage::Result,
+    cli::{EntryType, SortKey},
+    json::print_json,
+    utils::{format_mode, format_mtime, open_squashfs},
+};
+
+/// Checks whether the entry is of the requested type
+fn matches_type(entry: &SquashFSEntry, entry_type: EntryType) -> bool {
+    matches!(
+        (entry_type, &entry.kind),
+        (EntryType::F, EntryKind::File(_))
+            | (EntryType::D, EntryKind::Directory)
+            | (EntryType::L, EntryKind::Symlink(_))
+    )
+}
+
+/// An entry with its sniffed MIME type, as printed by `list --mime`
+#[derive(Serialize)]
+struct MimeJson<'e> {
+    #[serde(flatten)]
+    entry: &'e SquashFSEntry<'e>,
+    mime: Option<&'static str>,
+}
+
+/// Lists the entries of the image, in `ls -l` style, as bare paths or as JSON,
+/// optionally with the MIME type sniffed from the contents of files
+pub fn list<P: AsRef<Path>>(
+    file: &P,
+    offset: Option<u64>,
+    sort: Option<SortKey>,
+    entry_type: Option<EntryType>,
+    paths_only: bool,
+    mime: bool,
+    json: bool,
+) -> Result<()> {
+    let squashfs = open_squashfs(file, offset)?;
+    let mut entries: Vec<SquashFSEntry> = squashfs
+        .entries()
+        .filter(|entry| entry_type.is_none_or(|t| matches_type(entry, t)))
+        .collect();
+
+    match sort {
+        Some(SortKey::Name) => entries.sort_by(|a, b| a.path.cmp(&b.path)),
+        Some(SortKey::Size) => entries.sort_by_key(|entry| Reverse(entry.size)),
+        None => {}
+    }
+
+    let mimes: Vec<Option<&str>> = if mime {
+        entries
+            .par_iter()
+            .map(|entry| squashfs.mime(entry))
+            .collect::<squishy::Result<_>>()?
+    } else {
+        Vec::new()
+    };
+
+    if json && mime {
+        if paths_only {
+            let paths: Vec<_> = entries
+                .iter()
+                .zip(&mimes)
+                .map(|(entry, mime)| serde_json::json!({ "path": entry.path, "mime": mime }))
+                .collect();
+            print_json(&paths);
+        } else {
+            let entries: Vec<_> = entries
+                .iter()
+                .zip(&mimes)
+                .map(|(entry, mime)| MimeJson { entry, mime: *mime })
+                .collect();
+            print_json(&entries);
+        }
+        return Ok(());
+    }
+    if json {
+        if paths_only {
+            let paths: Vec<_> = entries.iter().map(|entry| &entry.path).collect();
+            print_json(&paths);
+        } else {
+            print_json(&entries);
+        }
+        return Ok(());
+    }
+
+    for (i, entry) in entries.iter().enumerate() {
+        let mime = match mimes.get(i) {
+            Some(mime) => format!("{:<32} ", mime.unwrap_or("-")),
+            None => String::new(),
+        };
+        if paths_only && mime.is_empty() {
+            println!("{}", entry.path.display());
+            continue;
+        }
+        if paths_only {
+            println!("{}: {}", entry.path.display(), mime.trim_end());
+            continue;
+        }
+
+        let target = match &entry.kind {
+            EntryKind::Symlink(target) => format!(" -> {}", target.display()),
+            _ => String::new(),
+        };
+        println!(
+            "{} {:>3} {:>5} {:>5} {:>10} {} {}{}{}",
+            format_mode(entry),
+            entry.nlink,
+            entry.uid(),
+            entry.gid(),
+            entry.size,
+            format_mtime(entry.mtime()),
+            mime,
+            entry.path.display(),
+            target
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::Path,
+    sync::Mutex,
+};
+
+use tracing::Level;
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    fmt::{self, writer::MakeWriterExt},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    Layer,
+};
+
+/// Where and how log events are written
+pub struct LogOptions<'a> {
+    /// Number of `-v` flags: 0 for info, 1 for debug, 2 or more for trace
+    pub verbosity: u8,
+    /// Hide events on the console, they are still written to the log file
+    pub quiet: bool,
+    /// Format events as JSON lines
+    pub json: bool,
+    /// File to append events to, with timestamps
+    pub file: Option<&'a Path>,
+}
+
+fn level(verbosity: u8) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// Events of squishy at the level, and of dependencies (e.g. backhand) at the
+/// fallback level unless everything is traced
+fn targets(level: LevelFilter, fallback: LevelFilter) -> Targets {
+    let fallback = if level == LevelFilter::TRACE {
+        level
+    } else {
+        fallback
+    };
+    Targets::new()
+        .with_target("squishy", level)
+        .with_default(fallback)
+}
+
+/// Installs the global subscriber. Info and lower events go to stdout, warnings
+/// and errors to stderr, as plain messages. The log file gets at least debug
+/// events of squishy and info events of its dependencies. If the log file
+/// can't be opened, the console logger is still installed so the error can be
+/// reported.
+pub fn init(options: &LogOptions) -> io::Result<()> {
+    let console_filter = if options.quiet {
+        Targets::new()
+    } else {
+        targets(level(options.verbosity), LevelFilter::WARN)
+    };
+    let console_writer = io::stderr.with_max_level(Level::WARN).or_else(io::stdout);
+    let console = fmt::layer()
+        .without_time()
+        .with_target(false)
+        .with_level(false)
+        .with_ansi(false)
+        .with_writer(console_writer);
+    let console = if options.json {
+        console.json().with_filter(console_filter).boxed()
+    } else {
+        console.with_filter(console_filter).boxed()
+    };
+
+    let file = options
+        .file
+        .map(|path| OpenOptions::new().create(true).append(true).open(path))
+        .transpose();
+    let (file, result) = match file {
+        Ok(file) => (file, Ok(())),
+        Err(e) => (None, Err(e)),
+    };
+    let file_layer = file.map(|file: File| {
+        let file_filter = targets(
+            level(options.verbosity).max(LevelFilter::DEBUG),
+            LevelFilter::INFO,
+        );
+        let layer = fmt::layer()
+            .with_target(false)
+            .with_ansi(false)
+            .with_writer(Mutex::new(file));
+        if options.json {
+            layer.json().with_filter(file_filter).boxed()
+        } else {
+            layer.with_filter(file_filter).boxed()
+        }
+    });
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(file_layer)
+        .init();
+    result
+}
@@ -1,217 +1,879 @@
 use std::{
-    fs::{self, Permissions},
-    os::unix::{self, fs::PermissionsExt},
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
 };
 
-use appimage::extract_file;
-use clap::Parser;
-use cli::Args;
-use rayon::iter::ParallelIterator;
+use appimage::{describe_found, extract_file, format_found, write_file_to, AppImageReport};
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use cli::{AppImageAction, Args, SortKey};
+use config::Config;
+use detect::Container;
+use error::CliError;
+use indicatif::HumanBytes;
+use json::{print_error_json, print_json, FoundJson, WrittenJson};
+use logging::LogOptions;
+use progress::ExtractProgress;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use squishy::{
-    appimage::{get_offset, AppImage},
+    appimage::{create::CreateOptions, AppImage, FoundEntry, IconSearchOptions, XdgDirs},
+    erofs::Erofs,
     error::SquishyError,
-    EntryKind, SquashFS,
+    filter::PathFilter,
+    pack::{PackOptions, RecompressOptions},
+    unpack::{
+        check_path, ErrorPolicy, SkipReason, UnpackAction, UnpackOptions, UnpackedEntry,
+        UnpackedKind,
+    },
+    SquashFSEntry,
 };
+use stdin::StdinFile;
+use tracing::{error, info};
+use utils::{format_planned, open_squashfs};
 
 mod appimage;
+#[cfg(feature = "browse")]
+mod browse;
+mod cat;
+mod checksum;
 mod cli;
+mod completions;
+mod config;
+mod convert;
+mod dedup;
+mod detect;
+mod diff;
+mod du;
+mod elf_scan;
+mod error;
+mod extract;
+mod grep;
+mod hash;
+mod info;
+mod json;
+mod lint;
+mod list;
+mod logging;
+mod manifest;
+#[cfg(feature = "fuse")]
+mod mount;
+mod offset_cache;
+mod pack;
+mod progress;
+mod stdin;
+mod tree;
+mod utils;
+mod verify;
 
-macro_rules! log {
-    ($quiet:expr, $($arg:tt)*) => {
-        if !$quiet {
-            println!($($arg)*);
+/// Logs an error and records it in the JSON report
+macro_rules! report_error {
+    ($report:expr, $($arg:tt)*) => {{
+        let message = format!($($arg)*);
+        error!("{}", message);
+        $report.errors.push(message);
+    }};
+}
+
+/// Finds an entry of the AppImage
+type Finder = for<'a> fn(&'a AppImage) -> Option<FoundEntry<'a>>;
+/// Selects the report field of a finder
+type ReportSlot = fn(&mut AppImageReport) -> &mut Option<FoundJson>;
+
+fn main() {
+    let mut args = Args::parse();
+    let config = Config::load().and_then(|config| config.apply(&mut args));
+    let json = args.json;
+
+    // free-form logs are suppressed in favour of the JSON output
+    let logging = logging::init(&LogOptions {
+        verbosity: args.verbose,
+        quiet: args.quiet || args.json,
+        json: args.log_json,
+        file: args.log_file.as_deref(),
+    })
+    .map_err(|e| CliError::Config(format!("can't open log file: {}", e)));
+
+    if let Err(e) = logging.and(config).and_then(|_| run(args)) {
+        if json {
+            // keep stdout a single JSON document
+            if !e.is_reported() {
+                print_error_json(&e.to_string(), e.exit_code());
+            }
+        } else {
+            error!("{}", e);
         }
-    };
+        std::process::exit(e.exit_code());
+    }
 }
 
-macro_rules! elog {
-    ($quiet:expr, $($arg:tt)*) => {
-        if !$quiet {
-            eprintln!($($arg)*);
+fn run(mut args: Args) -> Result<(), CliError> {
+    // Free-form logs are suppressed in favour of the JSON output
+    let quiet = args.quiet || args.json;
+
+    args.no_parallel |= args.sorted;
+    let jobs = if args.no_parallel { Some(1) } else { args.jobs };
+    if let Some(jobs) = jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .map_err(std::io::Error::other)?;
+    }
+
+    let stdin = match args.command.file_mut() {
+        Some(file) if file.as_os_str() == "-" => {
+            let stdin = StdinFile::buffer()?;
+            *file = stdin.path().to_path_buf();
+            Some(stdin)
         }
+        _ => None,
     };
-}
 
-fn main() {
-    let args = Args::parse();
+    // buffered stdin gets a new file every time
+    if !args.no_cache && stdin.is_none() {
+        offset_cache::init();
+    }
+
+    if let Some(file) = args.command.file().filter(|file| !file.exists()) {
+        return Err(CliError::InputNotFound(file.to_path_buf()));
+    }
+
+    // `x` is `unsquashfs -w` on whatever container the file turns out to be
+    if let cli::Commands::X {
+        file,
+        output,
+        overwrite,
+        permissions,
+        paths,
+        manifest,
+        dry_run,
+        to_stdout_tar,
+    } = args.command
+    {
+        // nothing is logged, stdout is the archive
+        if to_stdout_tar {
+            return Ok(convert::write_tar_stdout(&file, None)?);
+        }
+        let (container, offset) = detect::detect(&file)?;
+        if container == Container::DwarFS {
+            return Err(SquishyError::InvalidSquashFS(format!(
+                "{} is a DwarFS image, which is not supported",
+                file.display()
+            ))
+            .into());
+        }
+        info!("Detected {}, filesystem at offset {}", container, offset);
+        if container == Container::Erofs {
+            if manifest.manifest.is_some() {
+                return Err(SquishyError::Unsupported("manifests of EROFS images".into()).into());
+            }
+            let erofs = Erofs::from_path_with_offset(&file, offset)?;
+            let options = paths.apply(UnpackOptions {
+                overwrite: overwrite.policy(),
+                permissions: permissions.options(),
+                dry_run,
+                ..UnpackOptions::default()
+            });
+            let output = output.unwrap_or_else(|| "squashfs-root".into());
+            let unpacked = erofs.unpack_to(&output, &options)?.entries;
+            if args.json {
+                print_json(&unpacked);
+            } else if dry_run {
+                for entry in &unpacked {
+                    info!(
+                        "{}",
+                        format_planned(entry.action, &entry.source, &entry.destination)
+                    );
+                }
+            } else {
+                let skipped = unpacked
+                    .iter()
+                    .filter(|entry| entry.action == UnpackAction::Skip)
+                    .count();
+                info!(
+                    "Extracted {} entries to {}, skipped {}",
+                    unpacked.len() - skipped,
+                    output.display(),
+                    skipped
+                );
+            }
+            return Ok(());
+        }
+        args.command = cli::Commands::Unsquashfs {
+            file,
+            offset: Some(offset),
+            write: Some(Some(output.unwrap_or_else(|| "squashfs-root".into()))),
+            overwrite,
+            permissions,
+            paths,
+            manifest,
+            dry_run,
+            to_stdout_tar: false,
+        };
+    }
 
     match args.command {
         cli::Commands::AppImage {
+            action: Some(action),
+            ..
+        } => {
+            let AppImageAction::Create {
+                appdir,
+                output,
+                runtime,
+                comp,
+                block_size,
+                update_info,
+                signature,
+                signing_key,
+            } = *action;
+            let options = CreateOptions {
+                pack: PackOptions {
+                    compressor: comp.into(),
+                    block_size,
+                    ..PackOptions::default()
+                },
+                update_info,
+                signature: signature.map(std::fs::read).transpose()?,
+                signing_key: signing_key.map(std::fs::read).transpose()?,
+            };
+            pack::create(&appdir, &output, &runtime, options, args.json)?;
+        }
+        cli::Commands::AppImage {
+            action: None,
             offset,
             filter,
+            exclude,
             file,
             icon,
+            icon_paths,
             desktop,
             appstream,
+            all,
+            license,
+            find: find_patterns,
+            info,
+            dump_runtime,
+            integrate,
             write,
             original_name,
             copy_permissions,
+            dry_run,
+            format,
+            print0,
+            output,
+            stdout,
         } => {
-            if file.exists() {
-                let appimage = match AppImage::new(filter.as_deref(), &file, offset) {
-                    Ok(appimage) => appimage,
+            let file = file.expect("clap requires the file without a subcommand");
+            let selected = [desktop, icon, appstream].iter().filter(|s| **s).count();
+            if (output.is_some() || stdout) && (all || selected != 1) {
+                Args::command()
+                    .name(env!("CARGO_BIN_NAME"))
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "--output and --stdout need exactly one of --icon, --desktop or --appstream",
+                    )
+                    .exit();
+            }
+            let format = format.or_else(|| print0.then(|| "{path}".to_owned()));
+            let filter = PathFilter::new(&filter, &exclude)?;
+            let mut appimage = offset_cache::open_cached(
+                &file,
+                offset,
+                |offset| AppImage::new(filter.clone(), &file, offset),
+                AppImage::offset,
+            )?;
+            if !icon_paths.is_empty() {
+                appimage.set_icon_search(IconSearchOptions {
+                    roots: icon_paths,
+                    ..IconSearchOptions::default()
+                });
+            }
+
+            let write_path = match write {
+                Some(Some(path)) => Some(path),
+                Some(None) => Some(std::env::current_dir()?),
+                None => None,
+            };
+
+            // the temporary file name is meaningless as output name
+            let output_name = if original_name || stdin.is_some() {
+                None
+            } else {
+                file.file_name()
+            };
+
+            let mut report = AppImageReport::default();
+            let mut write_errors = 0;
+
+            if info {
+                report.offset = Some(appimage.offset());
+                info!("Offset: {}", appimage.offset());
+                match appimage.architecture() {
+                    Ok(arch) => {
+                        info!("Architecture: {}", arch);
+                        report.architecture = Some(arch.to_string());
+                    }
                     Err(e) => {
-                        elog!(args.quiet, "{}", e);
-                        std::process::exit(-1);
+                        report_error!(report, "Failed to detect architecture: {}", e)
                     }
-                };
-
-                let write_path = if let Some(write) = write {
-                    if let Some(path) = write {
-                        Some(path)
-                    } else {
-                        Some(std::env::current_dir().unwrap())
+                }
+                match appimage.version() {
+                    Some(version) => {
+                        info!("Version: {} (from {})", version.value, version.source);
+                        report.version_source = Some(version.source.to_string());
+                        report.version = Some(version.value);
                     }
-                } else {
-                    None
-                };
+                    None => report_error!(report, "No version found."),
+                }
+                match appimage.runtime_kind() {
+                    Ok(kind) => {
+                        info!("Runtime: {}", kind);
+                        report.runtime = Some(kind.to_string());
+                    }
+                    Err(e) => {
+                        report_error!(report, "Failed to detect runtime: {}", e)
+                    }
+                }
+            }
+            if let Some(ref dump_path) = dump_runtime {
+                match File::create(dump_path)
+                    .map_err(SquishyError::from)
+                    .and_then(|mut output| appimage.write_runtime(&mut output))
+                {
+                    Ok(size) => {
+                        info!("Wrote runtime ({} bytes) to {}", size, dump_path.display());
+                        report.runtime_dump = Some(dump_path.clone());
+                    }
+                    Err(e) => report_error!(report, "Failed to dump runtime: {}", e),
+                }
+            }
+            if integrate && stdin.is_some() {
+                report_error!(report, "Can't integrate an AppImage read from stdin.");
+            } else if integrate {
+                match XdgDirs::from_env().map(|xdg_dirs| appimage.integrate(&xdg_dirs)) {
+                    Some(Ok(integration)) => {
+                        info!("Installed desktop file: {}", integration.desktop.display());
+                        for icon in &integration.icons {
+                            info!("Installed icon: {}", icon.display());
+                        }
+                        report.integrated_desktop = Some(integration.desktop);
+                        report.integrated_icons = integration.icons;
+                    }
+                    Some(Err(e)) => report_error!(report, "Failed to integrate: {}", e),
+                    None => {
+                        report_error!(report, "Couldn't determine XDG data directory.")
+                    }
+                }
+            }
 
-                let output_name = if original_name {
-                    None
-                } else {
-                    file.file_name()
+            let finders: [(bool, &str, &str, Finder, ReportSlot); 3] = [
+                (
+                    desktop || all,
+                    "Desktop file",
+                    "desktop",
+                    |appimage| appimage.find_desktop(),
+                    |report| &mut report.desktop,
+                ),
+                (
+                    icon || all,
+                    "Icon",
+                    "icon",
+                    |appimage| appimage.find_icon(),
+                    |report| &mut report.icon,
+                ),
+                (
+                    appstream || all,
+                    "Appstream file",
+                    "appstream",
+                    |appimage| appimage.find_appstream(),
+                    |report| &mut report.appstream,
+                ),
+            ];
+            let mut selections = Vec::new();
+            for (enabled, label, kind, find, slot) in finders {
+                if !enabled {
+                    continue;
+                }
+                let Some(found) = find(&appimage) else {
+                    if stdout || output.is_some() {
+                        return Err(CliError::EntryNotFound(label.to_lowercase()));
+                    }
+                    report_error!(report, "No {} found.", label.to_lowercase());
+                    continue;
                 };
+                selections.push((label, kind, found, Some(slot)));
+            }
+            if license {
+                let licenses = appimage.find_licenses();
+                if licenses.is_empty() {
+                    report_error!(report, "No license files found.");
+                }
+                for found in licenses {
+                    selections.push(("License", "license", found, None));
+                }
+            }
+            if !find_patterns.is_empty() {
+                let matches = appimage.find_matching(&find_patterns)?;
+                if matches.is_empty() {
+                    report_error!(
+                        report,
+                        "No files matching {} found.",
+                        find_patterns.join(", ")
+                    );
+                }
+                for found in matches {
+                    selections.push(("Match", "match", found, None));
+                }
+            }
 
-                if desktop {
-                    if let Some(desktop) = appimage.find_desktop() {
-                        if let Some(ref write_path) = write_path {
-                            extract_file(
-                                &appimage.squashfs,
-                                &desktop,
-                                write_path,
-                                output_name,
-                                copy_permissions,
+            for (label, kind, found, slot) in selections {
+                if stdout {
+                    let mut writer = io::stdout().lock();
+                    let result = appimage
+                        .squashfs
+                        .copy_entry_to(&found.entry, &mut writer)
+                        .and_then(|_| Ok(writer.flush()?));
+                    match result {
+                        Err(SquishyError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => {}
+                        Ok(_) => {}
+                        Err(e) => {
+                            write_errors += 1;
+                            report_error!(
+                                report,
+                                "Failed to write {}: {}",
+                                found.entry.path.display(),
+                                e
                             )
-                            .unwrap();
-                        } else {
-                            log!(args.quiet, "Desktop file: {}", desktop.path.display());
                         }
-                    } else {
-                        elog!(args.quiet, "No desktop file found.");
-                    };
-                }
-                if icon {
-                    if let Some(icon) = appimage.find_icon() {
-                        if let Some(ref write_path) = write_path {
-                            extract_file(
-                                &appimage.squashfs,
-                                &icon,
-                                write_path,
-                                output_name,
-                                copy_permissions,
-                            )
-                            .unwrap();
-                        } else {
-                            log!(args.quiet, "Icon: {}", icon.path.display());
+                    }
+                } else if write_path.is_some() || output.is_some() {
+                    let result = match (&output, &write_path) {
+                        (Some(output), _) => write_file_to(
+                            &appimage,
+                            &found.entry,
+                            output.clone(),
+                            copy_permissions,
+                            dry_run,
+                        ),
+                        // matches and licenses keep their path, symlinks are written in place of the link
+                        (None, Some(write_path)) if slot.is_none() => {
+                            let path = found.resolved_from.as_ref().unwrap_or(&found.entry.path);
+                            let output_path =
+                                write_path.join(path.strip_prefix("/").unwrap_or(path));
+                            check_path(write_path, path, &output_path).and_then(|_| {
+                                write_file_to(
+                                    &appimage,
+                                    &found.entry,
+                                    output_path,
+                                    copy_permissions,
+                                    dry_run,
+                                )
+                            })
                         }
-                    } else {
-                        elog!(args.quiet, "No icon found.");
+                        (None, Some(write_path)) => extract_file(
+                            &appimage,
+                            &found.entry,
+                            write_path,
+                            output_name,
+                            copy_permissions,
+                            dry_run,
+                        ),
+                        (None, None) => Ok(None),
                     };
-                }
-                if appstream {
-                    if let Some(appstream) = appimage.find_appstream() {
-                        if let Some(ref write_path) = write_path {
-                            extract_file(
-                                &appimage.squashfs,
-                                &appstream,
-                                write_path,
-                                output_name,
-                                copy_permissions,
+                    match result {
+                        Ok(Some((output_path, action))) if dry_run => {
+                            info!(
+                                "{}",
+                                format_planned(action, &found.entry.path, &output_path)
+                            );
+                            report.planned.push(UnpackedEntry {
+                                source: found.entry.path.clone(),
+                                destination: output_path,
+                                kind: UnpackedKind::File,
+                                action,
+                            });
+                        }
+                        Ok(Some((output_path, _))) => {
+                            info!(
+                                "Wrote {} to {}",
+                                found.entry.path.display(),
+                                output_path.display()
+                            );
+                            report
+                                .written
+                                .push(WrittenJson::new(&found.entry.path, &output_path));
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            write_errors += 1;
+                            report_error!(
+                                report,
+                                "Failed to write {}: {}",
+                                found.entry.path.display(),
+                                e
                             )
-                            .unwrap();
-                        } else {
-                            log!(args.quiet, "Appstream file: {}", appstream.path.display());
                         }
-                    } else {
-                        elog!(args.quiet, "No appstream file found.");
-                    };
+                    }
+                } else if let Some(ref template) = format {
+                    if !quiet {
+                        print!(
+                            "{}{}",
+                            format_found(template, kind, &found),
+                            if print0 { '\0' } else { '\n' }
+                        );
+                    }
+                } else {
+                    info!("{}: {}", label, describe_found(&found));
                 }
+
+                match slot {
+                    Some(slot) => *slot(&mut report) = Some(FoundJson::from(&found)),
+                    None if kind == "license" => report.licenses.push(FoundJson::from(&found)),
+                    None => report.matches.push(FoundJson::from(&found)),
+                }
+            }
+
+            if args.json && !stdout {
+                print_json(&report);
+            }
+            if write_errors > 0 {
+                return Err(CliError::PartialExtraction(write_errors));
             }
         }
+        cli::Commands::X { .. } => unreachable!("x is dispatched to unsquashfs above"),
         cli::Commands::Unsquashfs {
             offset,
             file,
             write,
+            overwrite,
+            permissions,
+            paths,
+            manifest,
+            dry_run,
+            to_stdout_tar,
         } => {
-            let write_path = if let Some(write) = write {
-                if let Some(path) = write {
-                    fs::create_dir_all(&path).unwrap();
+            if to_stdout_tar {
+                return Ok(convert::write_tar_stdout(&file, offset)?);
+            }
+            let paths = paths.apply(UnpackOptions::default());
+            let write_path = match write {
+                Some(Some(path)) => {
+                    if !dry_run {
+                        fs::create_dir_all(&path)?;
+                    }
                     Some(path)
-                } else {
-                    Some(std::env::current_dir().unwrap())
                 }
+                Some(None) => Some(std::env::current_dir()?),
+                None => None,
+            };
+
+            // AppImages are opened at the end of the runtime ELF, anything
+            // else falls back to scanning for the squashfs magic
+            let squashfs = open_squashfs(&file, offset).map_err(|e| match (offset, e) {
+                (Some(offset), SquishyError::InvalidSquashFS(reason)) => {
+                    SquishyError::InvalidSquashFS(format!(
+                        "No valid squashfs at offset {}: {}. Try providing a valid offset.",
+                        offset, reason
+                    ))
+                }
+                (None, SquishyError::NoSquashFsFound) => SquishyError::InvalidSquashFS(format!(
+                    "Couldn't find squashfs in {}, it's neither an AppImage nor contains a squashfs \
+                     magic. Try providing a valid offset.",
+                    file.display()
+                )),
+                (_, e) => e,
+            })?;
+
+            let entries: Vec<SquashFSEntry> = if args.sorted {
+                squashfs.entries_sorted().collect()
             } else {
-                None
+                squashfs.entries().collect()
             };
 
-            let offset = offset.unwrap_or(get_offset(&file).unwrap());
-            let squashfs = SquashFS::from_path_with_offset(&file, offset)
-                .map_err(|_| {
-                    SquishyError::InvalidSquashFS(
-                        "Couldn't find squashfs. Try providing valid offset.".to_owned(),
-                    )
-                })
-                .unwrap();
-
-            squashfs.par_entries().for_each(|entry| {
-                if let Some(output_dir) = &write_path {
-                    let file_path = entry.path.strip_prefix("/").unwrap_or(&entry.path);
-                    let output_path = output_dir.join(file_path);
-                    fs::create_dir_all(output_path.parent().unwrap()).unwrap();
-
-                    match entry.kind {
-                        EntryKind::File(basic_file) => {
-                            if output_path.exists() {
-                                return;
-                            }
-                            let _ = squashfs.write_file_with_permissions(
-                                basic_file,
-                                &output_path,
-                                entry.header,
-                            );
-                            log!(
-                                args.quiet,
-                                "Wrote {} to {}",
-                                entry.path.display(),
-                                output_path.display()
-                            );
-                        }
-                        EntryKind::Directory => {
-                            if output_path.exists() {
-                                return;
-                            }
-                            fs::create_dir_all(&output_path).unwrap();
-                            fs::set_permissions(
-                                &output_path,
-                                Permissions::from_mode(u32::from(entry.header.permissions)),
-                            )
-                            .unwrap();
-                            log!(
-                                args.quiet,
-                                "Wrote {} to {}",
-                                entry.path.display(),
-                                output_path.display()
-                            );
-                        }
-                        EntryKind::Symlink(e) => {
-                            if output_path.exists() {
-                                return;
-                            }
-                            let original_path = e.strip_prefix("/").unwrap_or(&e);
-                            let _ = unix::fs::symlink(original_path, &output_path);
-                            log!(
-                                args.quiet,
-                                "Wrote {} to {}",
-                                entry.path.display(),
-                                output_path.display()
-                            );
-                        }
-                        _ => {}
-                    };
+            let Some(output_dir) = write_path else {
+                if args.json {
+                    print_json(&entries);
+                } else if args.no_parallel {
+                    entries.iter().for_each(|entry| {
+                        info!("{}", entry.path.display());
+                    });
                 } else {
-                    log!(args.quiet, "{}", entry.path.display());
+                    entries.par_iter().for_each(|entry| {
+                        info!("{}", entry.path.display());
+                    });
                 }
+                return Ok(());
+            };
+
+            let options = UnpackOptions {
+                overwrite: overwrite.policy(),
+                permissions: permissions.options(),
+                dry_run,
+                on_error: ErrorPolicy::Collect,
+                ..paths
+            };
+            let report = squashfs.unpack_to(&output_dir, &options)?;
+            if dry_run {
+                if args.json {
+                    print_json(&report.entries);
+                } else {
+                    for entry in &report.entries {
+                        info!(
+                            "{}",
+                            format_planned(entry.action, &entry.source, &entry.destination)
+                        );
+                    }
+                }
+                return Ok(());
+            }
+
+            let sizes: HashMap<&Path, u64> = entries
+                .iter()
+                .map(|entry| (entry.path.as_path(), u64::from(entry.size)))
+                .collect();
+            let skipped = report
+                .skipped
+                .iter()
+                .filter(|entry| entry.reason != SkipReason::Stripped)
+                .count();
+            let total = report.entries.len() + report.errors.len() + skipped;
+            let progress = ExtractProgress::new(total as u64, quiet);
+            let mut written = Vec::new();
+            for entry in &report.entries {
+                if matches!(entry.action, UnpackAction::Create | UnpackAction::Overwrite) {
+                    let size = match entry.kind {
+                        UnpackedKind::File => sizes.get(entry.source.as_path()).copied(),
+                        _ => None,
+                    };
+                    progress.written(
+                        entry.kind,
+                        size.unwrap_or(0),
+                        &entry.source,
+                        &entry.destination,
+                    );
+                    written.push(WrittenJson::new(&entry.source, &entry.destination));
+                }
+            }
+            (0..skipped).for_each(|_| progress.skipped());
+            for error in &report.errors {
+                progress.error(&error.source, &error.error);
+            }
+
+            let summary = progress.finish();
+            manifest.write(&written)?;
+            if args.json {
+                print_json(&serde_json::json!({ "written": written, "summary": summary }));
+            } else {
+                info!(
+                    "Extracted {} files, {} directories, {} symlinks ({}), skipped {}, {} errors",
+                    summary.files,
+                    summary.directories,
+                    summary.symlinks,
+                    HumanBytes(summary.bytes),
+                    summary.skipped,
+                    summary.errors
+                );
+            }
+            if summary.errors > 0 {
+                return Err(CliError::PartialExtraction(summary.errors));
+            }
+        }
+        cli::Commands::List {
+            file,
+            offset,
+            sort,
+            entry_type,
+            paths_only,
+            mime,
+        } => {
+            let sort = sort.or(args.sorted.then_some(SortKey::Name));
+            list::list(&file, offset, sort, entry_type, paths_only, mime, args.json)?;
+        }
+        cli::Commands::Extract {
+            file,
+            patterns,
+            output,
+            offset,
+            strip_components,
+            overwrite,
+            permissions,
+            paths,
+            manifest,
+            resume,
+            dry_run,
+        } => {
+            let options = paths.apply(UnpackOptions {
+                filter: PathFilter::new(&patterns, std::iter::empty::<&str>())?,
+                strip_components,
+                overwrite: overwrite.policy(),
+                permissions: permissions.options(),
+                dry_run,
+                ..UnpackOptions::default()
             });
+            let output = output.unwrap_or_else(|| PathBuf::from("."));
+            let previous = resume.as_deref().map(manifest::read).transpose()?;
+            extract::extract(
+                &file,
+                offset,
+                &output,
+                &options,
+                &manifest,
+                previous.as_deref(),
+                args.json,
+            )?;
+        }
+        cli::Commands::Cat { file, path, offset } => {
+            cat::cat(&file, offset, &path)?;
+        }
+        cli::Commands::Info { file, offset } => {
+            info::info(&file, offset, args.json)?;
+        }
+        cli::Commands::Tree {
+            file,
+            path,
+            offset,
+            depth,
+            size,
+        } => tree::tree(
+            &file,
+            offset,
+            path.as_deref(),
+            &tree::TreeOptions { depth, size },
+        )?,
+        cli::Commands::Checksum {
+            file,
+            patterns,
+            offset,
+            algo,
+        } => {
+            let filter = PathFilter::new(&patterns, std::iter::empty::<&str>())?;
+            checksum::checksum(&file, offset, &filter, algo, args.json)?;
+        }
+        cli::Commands::ElfScan {
+            file,
+            patterns,
+            offset,
+        } => {
+            let filter = PathFilter::new(&patterns, std::iter::empty::<&str>())?;
+            elf_scan::elf_scan(&file, offset, &filter, args.json)?;
+        }
+        #[cfg(feature = "fuse")]
+        cli::Commands::Mount {
+            file,
+            mountpoint,
+            offset,
+            allow_other,
+        } => {
+            mount::mount(&file, offset, &mountpoint, allow_other)?;
+        }
+        #[cfg(feature = "browse")]
+        cli::Commands::Browse {
+            file,
+            offset,
+            output,
+        } => {
+            let output = match output {
+                Some(output) => output,
+                None => std::env::current_dir()?,
+            };
+            browse::browse(&file, offset, output)?;
         }
+        cli::Commands::Pack {
+            dir,
+            output,
+            comp,
+            block_size,
+            appimage_runtime,
+        } => {
+            let options = PackOptions {
+                compressor: comp.into(),
+                block_size,
+                ..PackOptions::default()
+            };
+            pack::pack(
+                &dir,
+                &output,
+                appimage_runtime.as_deref(),
+                options,
+                args.json,
+            )?;
+        }
+        cli::Commands::Repack {
+            file,
+            output,
+            comp,
+            level,
+            block_size,
+            offset,
+        } => {
+            let options = RecompressOptions {
+                compressor: comp.into(),
+                level,
+                block_size,
+            };
+            pack::repack(&file, offset, &output, &options, args.json)?;
+        }
+        cli::Commands::Convert {
+            file,
+            patterns,
+            output,
+            zip,
+            oci_layer,
+            offset,
+        } => match (zip, output) {
+            (Some(zip), _) => {
+                let filter = PathFilter::new(&patterns, std::iter::empty::<&str>())?;
+                convert::convert_zip(&file, offset, &zip, &filter)?
+            }
+            (None, Some(output)) => convert::convert(&file, offset, &output, oci_layer, args.json)?,
+            (None, None) => unreachable!("clap requires --output without --zip"),
+        },
+        cli::Commands::Diff { old, new, content } => {
+            if !new.exists() {
+                return Err(CliError::InputNotFound(new));
+            }
+            diff::diff(&old, &new, content, args.json)?;
+        }
+        cli::Commands::DedupReport { images, algo } => {
+            if let Some(image) = images.iter().skip(1).find(|image| !image.exists()) {
+                return Err(CliError::InputNotFound(image.clone()));
+            }
+            dedup::dedup_report(&images, algo, args.json)?;
+        }
+        cli::Commands::Du {
+            file,
+            offset,
+            depth,
+        } => du::du(&file, offset, depth, args.json)?,
+        cli::Commands::Grep {
+            file,
+            pattern,
+            path,
+            offset,
+            ignore_case,
+            binary,
+        } => {
+            let filter = PathFilter::new(&path, std::iter::empty::<&str>())?;
+            grep::grep(
+                &file,
+                offset,
+                &pattern,
+                &filter,
+                ignore_case,
+                binary,
+                args.json,
+            )?;
+        }
+        cli::Commands::Verify { file, offset } => verify::verify(&file, offset, args.json)?,
+        cli::Commands::Lint {
+            file,
+            max_size,
+            disable,
+            offset,
+        } => lint::lint(&file, offset, max_size, &disable, args.json)?,
+        cli::Commands::Completions { shell } => completions::completions(shell)?,
+        cli::Commands::Manpage => completions::manpage()?,
     }
+    Ok(())
 }
@@ -0,0 +1,223 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use squishy::unpack::ExtractedEntry;
+
+use crate::{
+    appimage::Result,
+    cli::{ManifestArgs, ManifestFormat},
+    hash::file_sha256,
+    json::WrittenJson,
+};
+
+/// An extracted entry as recorded in the manifest, described by what is on
+/// disk after extraction
+#[derive(Serialize)]
+struct ManifestEntry {
+    source: PathBuf,
+    destination: PathBuf,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    size: u64,
+    /// Permission bits in octal, e.g. `0755`
+    mode: String,
+    /// Only set for regular files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+impl ManifestEntry {
+    fn new(written: &WrittenJson) -> Result<Self> {
+        let metadata = fs::symlink_metadata(&written.destination)?;
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_file() {
+            "file"
+        } else if file_type.is_dir() {
+            "directory"
+        } else if file_type.is_symlink() {
+            "symlink"
+        } else if file_type.is_fifo() || file_type.is_socket() {
+            "special"
+        } else {
+            "other"
+        };
+        let sha256 = if file_type.is_file() {
+            Some(file_sha256(&written.destination)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            source: written.source.clone(),
+            destination: written.destination.clone(),
+            kind,
+            size: if file_type.is_file() {
+                metadata.len()
+            } else {
+                0
+            },
+            mode: format!("{:04o}", metadata.permissions().mode() & 0o7777),
+            sha256,
+        })
+    }
+}
+
+/// An entry as read back from a manifest, see `read`
+#[derive(Deserialize)]
+struct ManifestRecord {
+    source: PathBuf,
+    destination: PathBuf,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    sha256: Option<String>,
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+fn write_csv<W: Write>(mut writer: W, entries: &[ManifestEntry]) -> Result<()> {
+    writeln!(writer, "source,destination,type,size,mode,sha256")?;
+    for entry in entries {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{}",
+            csv_field(&entry.source.to_string_lossy()),
+            csv_field(&entry.destination.to_string_lossy()),
+            entry.kind,
+            entry.size,
+            entry.mode,
+            entry.sha256.as_deref().unwrap_or("")
+        )?;
+    }
+    Ok(writer.flush()?)
+}
+
+/// Splits CSV written by `write_csv` into rows of fields, unquoting them
+fn read_csv(contents: &str) -> Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "unterminated quote in CSV").into());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Reads the records of a CSV manifest, skipping its header
+fn read_csv_records(contents: &str) -> Result<Vec<ManifestRecord>> {
+    read_csv(contents)?
+        .into_iter()
+        .skip(1)
+        .map(|row| match row.as_slice() {
+            [source, destination, kind, _, _, sha256] => Ok(ManifestRecord {
+                source: source.into(),
+                destination: destination.into(),
+                kind: kind.clone(),
+                sha256: (!sha256.is_empty()).then(|| sha256.clone()),
+            }),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected 6 fields in manifest row, found {}", row.len()),
+            )
+            .into()),
+        })
+        .collect()
+}
+
+/// Returns the manifest format matching the file extension
+fn format_of(path: &Path) -> ManifestFormat {
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+    {
+        ManifestFormat::Csv
+    } else {
+        ManifestFormat::Json
+    }
+}
+
+/// Reads the files recorded in a manifest written with `--manifest`, in the
+/// format matching its extension, to resume the extraction
+pub fn read(path: &Path) -> Result<Vec<ExtractedEntry>> {
+    let contents = fs::read_to_string(path)?;
+    let records = match format_of(path) {
+        ManifestFormat::Json => {
+            serde_json::from_str::<Vec<ManifestRecord>>(&contents).map_err(io::Error::from)?
+        }
+        ManifestFormat::Csv => read_csv_records(&contents)?,
+    };
+    Ok(records
+        .into_iter()
+        .filter(|record| record.kind == "file")
+        .map(|record| ExtractedEntry {
+            source: record.source,
+            destination: record.destination,
+            sha256: record.sha256,
+        })
+        .collect())
+}
+
+impl ManifestArgs {
+    /// Returns the requested format, or the one matching the file extension
+    fn format(&self, path: &Path) -> ManifestFormat {
+        self.manifest_format.unwrap_or_else(|| format_of(path))
+    }
+
+    /// Writes the manifest of the written entries, sorted by source path, if
+    /// one was requested
+    pub fn write(&self, written: &[WrittenJson]) -> Result<()> {
+        let Some(path) = &self.manifest else {
+            return Ok(());
+        };
+        let mut entries = written
+            .par_iter()
+            .map(ManifestEntry::new)
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort_by(|a, b| a.source.cmp(&b.source));
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        match self.format(path) {
+            ManifestFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, &entries).map_err(io::Error::from)?;
+                writeln!(writer)?;
+                writer.flush()?;
+            }
+            ManifestFormat::Csv => write_csv(writer, &entries)?,
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::Path;
+
+use squishy::{
+    appimage::get_offset,
+    fuse::{
+        fuser::{Config, MountOption, SessionACL},
+        SquashFuse,
+    },
+    IoTuning, SquashFS,
+};
+use tracing::info;
+
+use crate::appimage::Result;
+
+/// Mounts the image read-only at the mountpoint, blocking until it is unmounted
+pub fn mount(file: &Path, offset: Option<u64>, mountpoint: &Path, allow_other: bool) -> Result<()> {
+    let offset = offset.or_else(|| get_offset(file).ok());
+    // opened from the path, so reads decompress only the blocks they cover
+    let squashfs = SquashFS::from_path_with_tuning(&file, offset, IoTuning::default())?;
+
+    let mut config = Config::default();
+    config.mount_options = vec![
+        MountOption::RO,
+        MountOption::FSName(file.display().to_string()),
+        MountOption::Subtype("squishy".to_owned()),
+    ];
+    if allow_other {
+        config.acl = SessionACL::All;
+        config.mount_options.push(MountOption::AutoUnmount);
+    }
+
+    info!(
+        "Mounting {} at {}, unmount with `fusermount -u {}`",
+        file.display(),
+        mountpoint.display(),
+        mountpoint.display()
+    );
+    SquashFuse::new(squashfs).mount(mountpoint, &config)
+}
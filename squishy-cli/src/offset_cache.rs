@@ -0,0 +1,147 @@
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::{Mutex, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+use squishy::cache::{CacheKey, OffsetCache};
+use tracing::debug;
+
+/// The cache used by the commands, unset with `--no-cache`
+static CACHE: OnceLock<JsonOffsetCache> = OnceLock::new();
+
+/// A cached offset, as stored in the cache file
+#[derive(Serialize, Deserialize)]
+struct Record {
+    path: PathBuf,
+    size: u64,
+    mtime: u64,
+    offset: u64,
+}
+
+/// Offsets of the images opened before, kept in `squishy/offsets.json` in
+/// the XDG cache directory. Each insertion rewrites the file.
+pub struct JsonOffsetCache {
+    file: PathBuf,
+    offsets: Mutex<HashMap<CacheKey, u64>>,
+}
+
+impl JsonOffsetCache {
+    /// Loads the cache file, starting over if it's missing or unreadable
+    fn load(file: PathBuf) -> Self {
+        let records: Vec<Record> = fs::read(&file)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        let offsets = records
+            .into_iter()
+            .map(|record| {
+                let key = CacheKey {
+                    path: record.path,
+                    size: record.size,
+                    mtime: record.mtime,
+                };
+                (key, record.offset)
+            })
+            .collect();
+        Self {
+            file,
+            offsets: Mutex::new(offsets),
+        }
+    }
+
+    /// Writes the offsets of files that still exist to the cache file
+    fn save(&self, offsets: &HashMap<CacheKey, u64>) -> io::Result<()> {
+        let records: Vec<_> = offsets
+            .iter()
+            .filter(|(key, _)| key.path.exists())
+            .map(|(key, &offset)| Record {
+                path: key.path.clone(),
+                size: key.size,
+                mtime: key.mtime,
+                offset,
+            })
+            .collect();
+
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // replaced atomically, so concurrent runs never read half a file
+        let temp = self.file.with_extension(format!("json.{}", process::id()));
+        fs::write(&temp, serde_json::to_vec(&records)?)?;
+        fs::rename(&temp, &self.file).inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+    }
+}
+
+impl OffsetCache for JsonOffsetCache {
+    fn get(&self, key: &CacheKey) -> Option<u64> {
+        self.offsets.lock().ok()?.get(key).copied()
+    }
+
+    fn insert(&self, key: CacheKey, offset: u64) {
+        let Ok(mut offsets) = self.offsets.lock() else {
+            return;
+        };
+        if offsets.insert(key, offset) == Some(offset) {
+            return;
+        }
+        if let Err(e) = self.save(&offsets) {
+            debug!("Couldn't write {}: {}", self.file.display(), e);
+        }
+    }
+}
+
+/// Returns the path of the cache file in the XDG cache directory
+fn cache_path() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|dir| dir.join("squishy").join("offsets.json"))
+}
+
+/// Enables the offset cache for the rest of the run
+pub fn init() {
+    if let Some(file) = cache_path() {
+        let _ = CACHE.set(JsonOffsetCache::load(file));
+    }
+}
+
+/// Opens `file` at the cached offset unless an offset is given. Without
+/// either, or if the cached offset doesn't work, `open` detects the offset,
+/// and the one it found is cached.
+///
+/// # Arguments
+/// * `file` - The image
+/// * `offset` - The offset given by the user
+/// * `open` - Opens the image at the offset, or detects it when None
+/// * `found` - Returns the offset of the opened image
+pub fn open_cached<T, E>(
+    file: &Path,
+    offset: Option<u64>,
+    open: impl Fn(Option<u64>) -> Result<T, E>,
+    found: impl Fn(&T) -> u64,
+) -> Result<T, E> {
+    let Some(cache) = CACHE.get().filter(|_| offset.is_none()) else {
+        return open(offset);
+    };
+    let key = CacheKey::new(file).ok();
+
+    if let Some(cached) = key.as_ref().and_then(|key| cache.get(key)) {
+        debug!("Using cached offset {} for {}", cached, file.display());
+        if let Ok(image) = open(Some(cached)) {
+            return Ok(image);
+        }
+    }
+
+    let image = open(None)?;
+    if let Some(key) = key {
+        cache.insert(key, found(&image));
+    }
+    Ok(image)
+}
@@ -0,0 +1,196 @@
+use std::{
+    fs::{self, File, Permissions},
+    io::{self, Read},
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use indicatif::HumanBytes;
+use serde::Serialize;
+use squishy::{
+    appimage::create::{create_appimage, CreateOptions},
+    error::SquishyError,
+    pack::{pack_dir, PackOptions, PackStats, RecompressOptions},
+};
+use tracing::info;
+
+use crate::{appimage::Result, json::print_json, utils::open_squashfs};
+
+/// Results of `pack`, printed with `--json`
+#[derive(Serialize)]
+struct PackJson<'a> {
+    #[serde(flatten)]
+    stats: &'a PackStats,
+    runtime_size: u64,
+}
+
+/// Parses a block size like `131072`, `128K` or `1M`
+pub fn parse_block_size(value: &str) -> std::result::Result<u32, String> {
+    let (digits, multiplier) = match value.char_indices().last() {
+        Some((index, 'K' | 'k')) => (&value[..index], 1 << 10),
+        Some((index, 'M' | 'm')) => (&value[..index], 1 << 20),
+        _ => (value, 1),
+    };
+    digits
+        .parse::<u32>()
+        .ok()
+        .and_then(|size| size.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid block size: {}", value))
+}
+
+/// Returns `$SOURCE_DATE_EPOCH` for reproducible images, or the current time
+fn image_mtime() -> u32 {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_secs() as u32)
+        })
+}
+
+/// Creates the output with `write`, removing it again if writing fails so no
+/// truncated image is left behind
+pub fn write_image<T>(output: &Path, write: impl FnOnce(&mut File) -> Result<T>) -> Result<T> {
+    let mut file = File::create(output)?;
+    match write(&mut file) {
+        Ok(value) => Ok(value),
+        Err(e) => {
+            drop(file);
+            let _ = fs::remove_file(output);
+            Err(e)
+        }
+    }
+}
+
+/// Results of `repack`, printed with `--json`
+#[derive(Serialize)]
+struct RepackJson {
+    runtime_size: u64,
+    /// Size of the source image, without the runtime
+    original_size: u64,
+    image_size: u64,
+}
+
+fn print_stats(stats: &PackStats, runtime_size: u64, output: &Path, json: bool) {
+    if json {
+        print_json(&PackJson {
+            stats,
+            runtime_size,
+        });
+    } else {
+        info!(
+            "Packed {} files, {} directories, {} symlinks into {} ({})",
+            stats.files,
+            stats.directories,
+            stats.symlinks,
+            output.display(),
+            HumanBytes(runtime_size + stats.image_size)
+        );
+    }
+}
+
+/// Packs the directory into a squashfs image, optionally prefixed with an
+/// AppImage runtime to produce an executable AppImage
+pub fn pack(
+    dir: &Path,
+    output: &Path,
+    runtime: Option<&Path>,
+    mut options: PackOptions,
+    json: bool,
+) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(SquishyError::FileNotFound(dir.to_path_buf()));
+    }
+    options.mtime = image_mtime();
+
+    let (runtime_size, stats) = write_image(output, |file| {
+        let runtime_size = match runtime {
+            Some(runtime) => io::copy(&mut File::open(runtime)?, file)?,
+            None => 0,
+        };
+        Ok((runtime_size, pack_dir(dir, file, runtime_size, &options)?))
+    })?;
+    if runtime.is_some() {
+        fs::set_permissions(output, Permissions::from_mode(0o755))?;
+    }
+
+    print_stats(&stats, runtime_size, output, json);
+    Ok(())
+}
+
+/// Builds an AppImage from the AppDir with the runtime, filling in the update
+/// information and signature sections of the runtime
+pub fn create(
+    appdir: &Path,
+    output: &Path,
+    runtime: &Path,
+    mut options: CreateOptions,
+    json: bool,
+) -> Result<()> {
+    for path in [appdir, runtime] {
+        if !path.exists() {
+            return Err(SquishyError::FileNotFound(path.to_path_buf()));
+        }
+    }
+    options.pack.mtime = image_mtime();
+
+    let runtime = fs::read(runtime)?;
+    let stats = write_image(output, |file| {
+        create_appimage(appdir, &runtime, file, &options)
+    })?;
+    fs::set_permissions(output, Permissions::from_mode(0o755))?;
+
+    print_stats(&stats, runtime.len() as u64, output, json);
+    Ok(())
+}
+
+/// Writes the squashfs image, or the payload of the AppImage after copying its
+/// runtime, again with other compression settings
+pub fn repack(
+    file: &Path,
+    offset: Option<u64>,
+    output: &Path,
+    options: &RecompressOptions,
+    json: bool,
+) -> Result<()> {
+    // creating the output would truncate the image while it's read
+    if fs::canonicalize(output).ok() == Some(fs::canonicalize(file)?) {
+        return Err(SquishyError::Pack(format!(
+            "{} is the image being repacked",
+            output.display()
+        )));
+    }
+
+    let squashfs = open_squashfs(&file, offset)?;
+    let runtime_size = squashfs.offset();
+    let metadata = fs::metadata(file)?;
+    let original_size = metadata.len().saturating_sub(runtime_size);
+
+    let image_size = write_image(output, |image| {
+        io::copy(&mut File::open(file)?.take(runtime_size), image)?;
+        squashfs.recompress(image, runtime_size, options)
+    })?;
+    if runtime_size > 0 {
+        fs::set_permissions(output, metadata.permissions())?;
+    }
+
+    if json {
+        print_json(&RepackJson {
+            runtime_size,
+            original_size,
+            image_size,
+        });
+    } else {
+        info!(
+            "Repacked {} into {}, {} to {}",
+            file.display(),
+            output.display(),
+            HumanBytes(original_size),
+            HumanBytes(image_size)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use squishy::unpack::UnpackedKind;
+use tracing::{debug, error, Level};
+
+/// Counts of the entries handled during extraction
+#[derive(Debug, Default, Serialize)]
+pub struct ExtractSummary {
+    pub files: u64,
+    pub directories: u64,
+    pub symlinks: u64,
+    pub skipped: u64,
+    pub errors: u64,
+    pub bytes: u64,
+}
+
+/// Tracks extraction progress, drawing a progress bar on stderr.
+/// Safe to share between the extraction threads.
+pub struct ExtractProgress {
+    bar: ProgressBar,
+    files: AtomicU64,
+    directories: AtomicU64,
+    symlinks: AtomicU64,
+    skipped: AtomicU64,
+    errors: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl ExtractProgress {
+    /// Creates the progress tracker. The bar is hidden with `quiet`, per-entry
+    /// lines are logged at debug level.
+    pub fn new(total: u64, quiet: bool) -> Self {
+        let bar = if quiet {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(total).with_style(
+                ProgressStyle::with_template("{bar:40} {pos}/{len} entries, {msg}")
+                    .unwrap_or_else(|_| ProgressStyle::default_bar()),
+            )
+        };
+
+        Self {
+            bar,
+            files: AtomicU64::new(0),
+            directories: AtomicU64::new(0),
+            symlinks: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a written entry
+    pub fn written(&self, kind: UnpackedKind, size: u64, source: &Path, destination: &Path) {
+        let counter = match kind {
+            UnpackedKind::File => &self.files,
+            UnpackedKind::Directory => &self.directories,
+            UnpackedKind::Symlink => &self.symlinks,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        let bytes = self.bytes.fetch_add(size, Ordering::Relaxed) + size;
+
+        if tracing::enabled!(Level::DEBUG) {
+            self.bar
+                .suspend(|| debug!("Wrote {} to {}", source.display(), destination.display()));
+        }
+        self.bar
+            .set_message(format!("{} written", HumanBytes(bytes)));
+        self.bar.inc(1);
+    }
+
+    /// Records an entry that was not extracted
+    pub fn skipped(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+        self.bar.inc(1);
+    }
+
+    /// Records an entry that failed to extract
+    pub fn error(&self, source: &Path, error: impl std::fmt::Display) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        self.bar
+            .suspend(|| error!("Failed to write {}: {}", source.display(), error));
+        self.bar.inc(1);
+    }
+
+    /// Clears the progress bar and returns the final counts
+    pub fn finish(self) -> ExtractSummary {
+        self.bar.finish_and_clear();
+        ExtractSummary {
+            files: self.files.into_inner(),
+            directories: self.directories.into_inner(),
+            symlinks: self.symlinks.into_inner(),
+            skipped: self.skipped.into_inner(),
+            errors: self.errors.into_inner(),
+            bytes: self.bytes.into_inner(),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Stdin buffered to a temporary file, since images are read with random
+/// access. The file is removed when dropped.
+pub struct StdinFile {
+    path: PathBuf,
+}
+
+impl StdinFile {
+    /// Copies stdin into a new temporary file
+    pub fn buffer() -> io::Result<Self> {
+        let path = env::temp_dir().join(format!("squishy-stdin-{}", process::id()));
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+
+        let stdin_file = Self { path };
+        io::copy(&mut io::stdin().lock(), &mut file)?;
+        Ok(stdin_file)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for StdinFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+use indicatif::HumanBytes;
+use squishy::{error::SquishyError, EntryKind, SquashFSEntry};
+
+use crate::{appimage::Result, utils::open_squashfs};
+
+/// Options of the `tree` rendering
+pub struct TreeOptions {
+    /// Maximum depth below the start directory
+    pub depth: Option<usize>,
+    /// Whether to annotate files with their size
+    pub size: bool,
+}
+
+/// Counts of the printed entries
+#[derive(Default)]
+struct TreeCounts {
+    directories: usize,
+    files: usize,
+}
+
+/// Prints the filesystem below the start path as an ASCII tree
+pub fn tree<P: AsRef<Path>>(
+    file: &P,
+    offset: Option<u64>,
+    start: Option<&Path>,
+    options: &TreeOptions,
+) -> Result<()> {
+    let squashfs = open_squashfs(file, offset)?;
+    let start = Path::new("/").join(
+        start
+            .unwrap_or(Path::new("/"))
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect::<PathBuf>(),
+    );
+    if !matches!(
+        squashfs.find(&start).map(|entry| entry.kind),
+        Some(EntryKind::Directory)
+    ) {
+        return Err(SquishyError::FileNotFound(start));
+    }
+
+    let mut children: HashMap<PathBuf, Vec<SquashFSEntry>> = HashMap::new();
+    for entry in squashfs.entries() {
+        if entry.path == start || !entry.path.starts_with(&start) {
+            continue;
+        }
+        if let Some(parent) = entry.path.parent() {
+            children
+                .entry(parent.to_path_buf())
+                .or_default()
+                .push(entry);
+        }
+    }
+
+    println!("{}", start.display());
+    let mut counts = TreeCounts::default();
+    print_children(&children, &start, "", 1, options, &mut counts);
+    println!(
+        "\n{} directories, {} files",
+        counts.directories, counts.files
+    );
+    Ok(())
+}
+
+/// Recursively prints the children of the directory
+fn print_children(
+    children: &HashMap<PathBuf, Vec<SquashFSEntry>>,
+    dir: &Path,
+    prefix: &str,
+    depth: usize,
+    options: &TreeOptions,
+    counts: &mut TreeCounts,
+) {
+    if options.depth.is_some_and(|max| depth > max) {
+        return;
+    }
+    let Some(entries) = children.get(dir) else {
+        return;
+    };
+
+    for (index, entry) in entries.iter().enumerate() {
+        let last = index + 1 == entries.len();
+        let name = entry
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let size = if options.size && matches!(entry.kind, EntryKind::File(_)) {
+            format!("[{}] ", HumanBytes(entry.size as u64))
+        } else {
+            String::new()
+        };
+        let target = match &entry.kind {
+            EntryKind::Symlink(target) => format!(" -> {}", target.display()),
+            _ => String::new(),
+        };
+        println!(
+            "{}{}{}{}{}",
+            prefix,
+            if last { "└── " } else { "├── " },
+            size,
+            name,
+            target
+        );
+
+        if let EntryKind::Directory = entry.kind {
+            counts.directories += 1;
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            print_children(children, &entry.path, &prefix, depth + 1, options, counts);
+        } else {
+            counts.files += 1;
+        }
+    }
+}
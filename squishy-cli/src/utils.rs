@@ -0,0 +1,107 @@
+use std::path::Path;
+
+use squishy::{appimage::get_offset, unpack::UnpackAction, EntryKind, SquashFS, SquashFSEntry};
+
+use crate::{appimage::Result, offset_cache};
+
+/// Opens a squashfs image or AppImage. Without an explicit offset, the offset
+/// is derived from the ELF header for AppImages, or found by scanning for the
+/// squashfs magic otherwise, unless the offset of the file is cached.
+pub fn open_squashfs<P: AsRef<Path>>(file: &P, offset: Option<u64>) -> Result<SquashFS<'_>> {
+    offset_cache::open_cached(
+        file.as_ref(),
+        offset,
+        |offset| match offset.or_else(|| get_offset(file).ok()) {
+            Some(offset) => SquashFS::from_path_with_offset(file, offset),
+            None => SquashFS::from_path(file),
+        },
+        SquashFS::offset,
+    )
+}
+
+/// Formats the entry type and permissions like `ls -l`, e.g. `drwxr-xr-x`
+pub fn format_mode(entry: &SquashFSEntry) -> String {
+    let mode = entry.mode();
+    let file_type = match entry.kind {
+        EntryKind::File(_) => '-',
+        EntryKind::Directory => 'd',
+        EntryKind::Symlink(_) => 'l',
+        EntryKind::Unknown => '?',
+    };
+
+    let bit = |mask: u32, c: char| if mode & mask != 0 { c } else { '-' };
+    let special = |exec: u32, special: u32, set: char| match (mode & exec != 0, mode & special != 0)
+    {
+        (true, true) => set,
+        (false, true) => set.to_ascii_uppercase(),
+        (true, false) => 'x',
+        (false, false) => '-',
+    };
+
+    [
+        file_type,
+        bit(0o400, 'r'),
+        bit(0o200, 'w'),
+        special(0o100, 0o4000, 's'),
+        bit(0o040, 'r'),
+        bit(0o020, 'w'),
+        special(0o010, 0o2000, 's'),
+        bit(0o004, 'r'),
+        bit(0o002, 'w'),
+        special(0o001, 0o1000, 't'),
+    ]
+    .iter()
+    .collect()
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DD HH:MM` (UTC)
+pub fn format_mtime(mtime: u32) -> String {
+    let days = (mtime / 86400) as i64;
+    let seconds = mtime % 86400;
+
+    // civil date from days since epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
+/// Describes what a dry run would do with an entry
+pub fn format_planned(action: UnpackAction, source: &Path, destination: &Path) -> String {
+    match action {
+        UnpackAction::Create => format!(
+            "Would create {} from {}",
+            destination.display(),
+            source.display()
+        ),
+        UnpackAction::Overwrite => format!(
+            "Would overwrite {} with {}",
+            destination.display(),
+            source.display()
+        ),
+        UnpackAction::Skip => format!(
+            "Conflict: {} exists, would skip {}",
+            destination.display(),
+            source.display()
+        ),
+        UnpackAction::Keep => format!(
+            "Would keep {}, already extracted from {}",
+            destination.display(),
+            source.display()
+        ),
+    }
+}
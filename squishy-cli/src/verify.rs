@@ -0,0 +1,40 @@
+use std::path::Path;
+
+use indicatif::HumanBytes;
+use tracing::{error, info, warn};
+
+use crate::{error::CliError, json::print_json, utils::open_squashfs};
+
+/// Checks the image by decompressing every file, failing if any entry is corrupted
+pub fn verify<P: AsRef<Path>>(file: &P, offset: Option<u64>, json: bool) -> Result<(), CliError> {
+    let squashfs = open_squashfs(file, offset)?;
+    let report = squashfs.verify();
+
+    if json {
+        print_json(&report);
+    } else {
+        for entry in &report.corrupted {
+            error!(
+                "Corrupted {} at offset {}: {}",
+                entry.path.display(),
+                entry.offset,
+                entry.error
+            );
+        }
+        for issue in &report.path_issues {
+            warn!("{}: {}", issue.path.display(), issue.kind);
+        }
+        info!(
+            "Verified {} entries ({}), {} corrupted",
+            report.entries,
+            HumanBytes(report.bytes),
+            report.corrupted.len()
+        );
+    }
+
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(CliError::Corrupted(report.corrupted.len()))
+    }
+}
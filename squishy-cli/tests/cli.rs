@@ -0,0 +1,366 @@
+//! Runs the squishy binary on images packed by `squishy pack`, checking what
+//! ends up on disk
+
+use std::{
+    fs::{self, File},
+    io::Read,
+    os::unix::fs::{symlink, PermissionsExt},
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use flate2::read::GzDecoder;
+use tempfile::TempDir;
+
+/// Runs squishy with the arguments, without the offset cache
+fn squishy<I, S>(args: I) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    Command::new(env!("CARGO_BIN_EXE_squishy"))
+        .arg("--no-cache")
+        .args(args)
+        .env_remove("SQUISHY_JOBS")
+        .env_remove("SQUISHY_JSON")
+        .env_remove("SQUISHY_QUIET")
+        .output()
+        .unwrap()
+}
+
+/// Runs squishy, failing the test with its output if it doesn't succeed
+fn run<I, S>(args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let output = squishy(args);
+    assert!(
+        output.status.success(),
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// The AppDir packed into the images of the tests
+fn appdir(dir: &Path) -> PathBuf {
+    let root = dir.join("AppDir");
+    fs::create_dir_all(root.join("usr/bin")).unwrap();
+    fs::create_dir_all(root.join("usr/share/metainfo")).unwrap();
+    fs::write(
+        root.join("app.desktop"),
+        "[Desktop Entry]\nType=Application\nName=App\nExec=app\nIcon=app\n",
+    )
+    .unwrap();
+    fs::write(root.join("app.png"), b"\x89PNG\r\n\x1a\nicon").unwrap();
+    fs::write(root.join("usr/bin/app"), "#!/bin/sh\necho app\n").unwrap();
+    fs::set_permissions(root.join("usr/bin/app"), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        root.join("usr/share/metainfo/app.appdata.xml"),
+        "<?xml version=\"1.0\"?>\n<component/>\n",
+    )
+    .unwrap();
+    symlink("usr/bin/app", root.join("AppRun")).unwrap();
+    root
+}
+
+/// A runtime of just an ELF header and one empty section header, so the
+/// image starts right after it at 128
+fn runtime(dir: &Path) -> PathBuf {
+    let mut elf = vec![0; 128];
+    elf[..7].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+    elf[0x28..0x30].copy_from_slice(&64u64.to_le_bytes());
+    elf[0x34..0x36].copy_from_slice(&64u16.to_le_bytes());
+    elf[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+    elf[0x3c..0x3e].copy_from_slice(&1u16.to_le_bytes());
+    let path = dir.join("runtime");
+    fs::write(&path, elf).unwrap();
+    path
+}
+
+/// Packs the AppDir into a squashfs image and an AppImage
+fn images() -> (TempDir, PathBuf, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let root = appdir(dir.path());
+    let image = dir.path().join("app.squashfs");
+    run([Path::new("pack"), &root, Path::new("-o"), &image]);
+    let appimage = dir.path().join("app.AppImage");
+    run([
+        Path::new("pack"),
+        &root,
+        Path::new("-o"),
+        &appimage,
+        Path::new("--appimage-runtime"),
+        &runtime(dir.path()),
+    ]);
+    (dir, image, appimage)
+}
+
+/// Checks that the directory holds the extracted AppDir
+fn assert_extracted(root: &Path) {
+    assert_eq!(
+        fs::read_to_string(root.join("usr/bin/app")).unwrap(),
+        "#!/bin/sh\necho app\n"
+    );
+    let mode = fs::metadata(root.join("usr/bin/app"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o755);
+    assert_eq!(
+        fs::read_link(root.join("AppRun")).unwrap(),
+        Path::new("usr/bin/app")
+    );
+    assert!(root.join("usr/share/metainfo/app.appdata.xml").is_file());
+}
+
+/// An EROFS image with 4 KiB blocks holding `/hello`, its root directory
+/// stored in block 2 and its inodes in block 1
+fn erofs_image() -> Vec<u8> {
+    const BLOCK: usize = 4096;
+    let mut image = vec![0; BLOCK * 3];
+    let sb = &mut image[1024..1024 + 128];
+    sb[..4].copy_from_slice(&0xE0F5_E1E2_u32.to_le_bytes());
+    sb[12] = 12;
+    sb[40..44].copy_from_slice(&1_u32.to_le_bytes());
+
+    // `.`, `..` and `hello`, names after the 12 byte entries
+    let mut dir = vec![0; 36];
+    for (i, (nid, name)) in [(0_u64, "."), (0, ".."), (2, "hello")].iter().enumerate() {
+        let start = dir.len() as u16;
+        dir[i * 12..i * 12 + 8].copy_from_slice(&nid.to_le_bytes());
+        dir[i * 12 + 8..i * 12 + 10].copy_from_slice(&start.to_le_bytes());
+        dir.extend_from_slice(name.as_bytes());
+    }
+    image[BLOCK * 2..BLOCK * 2 + dir.len()].copy_from_slice(&dir);
+
+    // compact inodes: the root with its data in a block, the file inline
+    for (nid, layout, mode, size, block) in [
+        (0, 0_u16, 0o040755_u16, dir.len(), 2_u32),
+        (2, 2, 0o100644, 5, 0),
+    ] {
+        let pos = BLOCK + nid * 32;
+        let inode = &mut image[pos..pos + 32];
+        inode[0..2].copy_from_slice(&(layout << 1).to_le_bytes());
+        inode[4..6].copy_from_slice(&mode.to_le_bytes());
+        inode[6..8].copy_from_slice(&1_u16.to_le_bytes());
+        inode[8..12].copy_from_slice(&(size as u32).to_le_bytes());
+        inode[16..20].copy_from_slice(&block.to_le_bytes());
+    }
+    let data = BLOCK + 2 * 32 + 32;
+    image[data..data + 5].copy_from_slice(b"hello");
+    image
+}
+
+#[test]
+fn x_extracts_squashfs_images_and_appimages() {
+    let (dir, image, appimage) = images();
+    for (file, output) in [(&image, "image-root"), (&appimage, "appimage-root")] {
+        let output = dir.path().join(output);
+        run([Path::new("x"), file, Path::new("-o"), &output]);
+        assert_extracted(&output);
+    }
+    // the output directory before the file
+    let output = dir.path().join("flags-first");
+    run([Path::new("x"), Path::new("-o"), &output, &appimage]);
+    assert_extracted(&output);
+}
+
+#[test]
+fn x_extracts_erofs_images() {
+    let dir = tempfile::tempdir().unwrap();
+    let image = dir.path().join("image.erofs");
+    fs::write(&image, erofs_image()).unwrap();
+    let output = dir.path().join("root");
+    run([Path::new("x"), &image, Path::new("-o"), &output]);
+    assert_eq!(fs::read(output.join("hello")).unwrap(), b"hello");
+}
+
+#[test]
+fn x_refuses_dwarfs_and_missing_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let image = dir.path().join("image.dwarfs");
+    let mut data = b"DWARFS\x02\x05".to_vec();
+    data.resize(4096, 0);
+    fs::write(&image, data).unwrap();
+
+    let output = squishy([Path::new("x"), &image, Path::new("-o"), dir.path()]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("DwarFS image, which is not supported")
+    );
+
+    let output = squishy([Path::new("x"), &dir.path().join("missing")]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn unsquashfs_writes_the_image_and_a_manifest() {
+    let (dir, image, _) = images();
+    let output = dir.path().join("root");
+    let manifest = dir.path().join("manifest.json");
+    run([
+        Path::new("unsquashfs"),
+        &image,
+        Path::new("-w"),
+        &output,
+        Path::new("--manifest"),
+        &manifest,
+    ]);
+    assert_extracted(&output);
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest).unwrap()).unwrap();
+    let app = manifest
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["source"] == "/usr/bin/app")
+        .unwrap();
+    assert_eq!(app["type"], "file");
+    assert_eq!(app["mode"], "0755");
+    assert_eq!(app["size"], 20);
+
+    // without -w, entries are only listed
+    let listed = run([Path::new("unsquashfs"), &image]);
+    assert!(listed.lines().any(|line| line.ends_with("/usr/bin/app")));
+}
+
+#[test]
+fn extract_resume_keeps_verified_files() {
+    let (dir, image, _) = images();
+    let output = dir.path().join("root");
+    let manifest = dir.path().join("manifest.json");
+    run([
+        Path::new("extract"),
+        &image,
+        Path::new("-o"),
+        &output,
+        Path::new("--manifest"),
+        &manifest,
+    ]);
+    assert_extracted(&output);
+
+    // an interrupted extraction: one file cut short, one never written
+    fs::write(output.join("usr/bin/app"), "#!/bin/sh\n").unwrap();
+    fs::remove_file(output.join("app.desktop")).unwrap();
+
+    let log = run([
+        Path::new("extract"),
+        &image,
+        Path::new("-o"),
+        &output,
+        Path::new("--resume"),
+        &manifest,
+    ]);
+    assert_extracted(&output);
+    assert!(output.join("app.desktop").is_file());
+    assert!(log.contains("Wrote /usr/bin/app"), "{}", log);
+    assert!(log.contains("Wrote /app.desktop"), "{}", log);
+    assert!(!log.contains("Wrote /app.png"), "{}", log);
+    assert!(log.contains("Kept "), "{}", log);
+}
+
+#[test]
+fn convert_writes_tar_and_zip_archives() {
+    let (dir, _, appimage) = images();
+
+    let archive = dir.path().join("app.tar.gz");
+    run([Path::new("convert"), &appimage, Path::new("-o"), &archive]);
+    let mut tar = tar::Archive::new(GzDecoder::new(File::open(&archive).unwrap()));
+    let mut found = Vec::new();
+    for entry in tar.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let path = entry.path().unwrap().into_owned();
+        if path == Path::new("usr/bin/app") {
+            assert_eq!(entry.header().mode().unwrap() & 0o777, 0o755);
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, "#!/bin/sh\necho app\n");
+        }
+        if path == Path::new("AppRun") {
+            assert_eq!(
+                entry.link_name().unwrap().unwrap(),
+                Path::new("usr/bin/app")
+            );
+        }
+        found.push(path);
+    }
+    for path in ["app.desktop", "usr/bin/app", "AppRun", "usr/share/metainfo"] {
+        assert!(found.contains(&PathBuf::from(path)), "{}", path);
+    }
+
+    let zip = dir.path().join("app.zip");
+    run([
+        Path::new("convert"),
+        &appimage,
+        Path::new("*.desktop"),
+        Path::new("*.png"),
+        Path::new("--zip"),
+        &zip,
+    ]);
+    let zip = zip::ZipArchive::new(File::open(&zip).unwrap()).unwrap();
+    let mut names: Vec<String> = zip
+        .file_names()
+        .map(|name| name.unwrap().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["app.desktop", "app.png"]);
+}
+
+#[test]
+fn appimage_writes_found_files() {
+    let (dir, _, appimage) = images();
+    let write = dir.path().join("written");
+    run([
+        Path::new("appimage"),
+        &appimage,
+        Path::new("--desktop"),
+        Path::new("--icon"),
+        Path::new("--appstream"),
+        Path::new("--write"),
+        &write,
+    ]);
+    // named after the AppImage, keeping the extension
+    assert!(fs::read_to_string(write.join("app.AppImage.desktop"))
+        .unwrap()
+        .contains("Name=App"));
+    assert_eq!(
+        fs::read(write.join("app.AppImage.png")).unwrap(),
+        b"\x89PNG\r\n\x1a\nicon"
+    );
+    assert!(write.join("app.AppImage.appdata.xml").is_file());
+
+    let original = dir.path().join("original");
+    run([
+        Path::new("appimage"),
+        &appimage,
+        Path::new("--desktop"),
+        Path::new("--original-name"),
+        Path::new("--write"),
+        &original,
+    ]);
+    assert!(original.join("app.desktop").is_file());
+
+    let output = dir.path().join("out/icon.png");
+    run([
+        Path::new("appimage"),
+        &appimage,
+        Path::new("--icon"),
+        Path::new("--output"),
+        &output,
+    ]);
+    assert_eq!(fs::read(&output).unwrap(), b"\x89PNG\r\n\x1a\nicon");
+
+    // --output takes exactly one file
+    let refused = squishy([
+        Path::new("appimage"),
+        &appimage,
+        Path::new("--all"),
+        Path::new("--output"),
+        &output,
+    ]);
+    assert_eq!(refused.status.code(), Some(2));
+}
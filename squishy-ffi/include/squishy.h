@@ -0,0 +1,134 @@
+#ifndef SQUISHY_H
+#define SQUISHY_H
+
+/* Generated with cbindgen from squishy-ffi, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Files of an AppImage `squishy_appimage_find` looks for
+typedef enum SquishyAppImageFile {
+  SQUISHY_APP_IMAGE_FILE_DESKTOP = 0,
+  SQUISHY_APP_IMAGE_FILE_ICON = 1,
+  SQUISHY_APP_IMAGE_FILE_APP_STREAM = 2,
+} SquishyAppImageFile;
+
+// Type of an entry
+typedef enum SquishyEntryKind {
+  SQUISHY_ENTRY_KIND_FILE = 0,
+  SQUISHY_ENTRY_KIND_DIRECTORY = 1,
+  SQUISHY_ENTRY_KIND_SYMLINK = 2,
+  SQUISHY_ENTRY_KIND_OTHER = 3,
+} SquishyEntryKind;
+
+// An opened SquashFS image or AppImage. DwarFS and EROFS images aren't
+// exposed through the C API.
+typedef struct SquishyImage SquishyImage;
+
+// An entry of the image, as listed by `squishy_list`
+typedef struct SquishyEntry {
+  // Absolute path inside the image
+  char *path;
+  enum SquishyEntryKind kind;
+  uint32_t mode;
+  uint32_t uid;
+  uint32_t gid;
+  uint64_t size;
+  // Seconds since the Unix epoch
+  uint32_t mtime;
+  // Target of a symlink, null otherwise
+  char *target;
+} SquishyEntry;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns the message of the last error on this thread, or null if there was
+// none. The string is valid until the next failing call on this thread.
+const char *squishy_last_error(void);
+
+// Opens a SquashFS image or AppImage. The offset of the filesystem is taken
+// from the ELF header for AppImages and found by scanning otherwise. DwarFS
+// images fail with the unsupported error code.
+//
+// # Safety
+// `path` must be a NUL terminated string and `image` must be valid for writes.
+int squishy_open(const char *path, struct SquishyImage **image);
+
+// Opens a SquashFS image at the offset within the file.
+//
+// # Safety
+// `path` must be a NUL terminated string and `image` must be valid for writes.
+int squishy_open_with_offset(const char *path, uint64_t offset, struct SquishyImage **image);
+
+// Closes an image opened with `squishy_open`. Null is ignored.
+//
+// # Safety
+// `image` must come from `squishy_open` and not be used afterwards.
+void squishy_close(struct SquishyImage *image);
+
+// Stores the offset of the filesystem within the file in `offset`.
+//
+// # Safety
+// `image` must be a valid image and `offset` must be valid for writes.
+int squishy_offset(const struct SquishyImage *image, uint64_t *offset);
+
+// Lists all entries of the image. Release the array with `squishy_list_free`.
+//
+// # Safety
+// `image` must be a valid image, `entries` and `len` must be valid for writes.
+int squishy_list(const struct SquishyImage *image, struct SquishyEntry **entries, size_t *len);
+
+// Releases the entries returned by `squishy_list`.
+//
+// # Safety
+// `entries` and `len` must come from `squishy_list`.
+void squishy_list_free(struct SquishyEntry *entries, size_t len);
+
+// Reads the contents of a file in the image. Release the data with
+// `squishy_bytes_free`.
+//
+// # Safety
+// `image` must be a valid image, `path` a NUL terminated string, `data` and
+// `len` must be valid for writes.
+int squishy_read_file(const struct SquishyImage *image,
+                      const char *path,
+                      uint8_t **data,
+                      size_t *len);
+
+// Releases the data returned by `squishy_read_file`.
+//
+// # Safety
+// `data` and `len` must come from `squishy_read_file`.
+void squishy_bytes_free(uint8_t *data, size_t len);
+
+// Extracts all entries of the image into the directory, with the
+// permissions from the image. Existing files are kept.
+//
+// # Safety
+// `image` must be a valid image and `dest` a NUL terminated string.
+int squishy_extract(const struct SquishyImage *image, const char *dest);
+
+// Finds the desktop entry, icon or AppStream metadata of an AppImage the
+// way the CLI does, returning its path inside the image for
+// `squishy_read_file`. Release the path with `squishy_string_free`.
+//
+// # Safety
+// `path` must be a NUL terminated string and `entry_path` valid for writes.
+int squishy_appimage_find(const char *path, enum SquishyAppImageFile file, char **entry_path);
+
+// Releases a string returned by the library. Null is ignored.
+//
+// # Safety
+// `string` must come from the library and not be used afterwards.
+void squishy_string_free(char *string);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SQUISHY_H */
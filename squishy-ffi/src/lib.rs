@@ -0,0 +1,471 @@
+//! C bindings for squishy. Functions return 0 on success or the code of the
+//! error kind (see `squishy::error::ErrorKind::code`), with the message
+//! available from `squishy_last_error` on the same thread. Everything
+//! allocated by the library must be released with the matching `_free`
+//! function.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    fs::File,
+    io::{self, BufReader},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    ptr,
+};
+
+use squishy::{
+    appimage::{get_offset, AppImage},
+    detect::{detect_filesystem_type, FilesystemType},
+    error::SquishyError,
+    filter::PathFilter,
+    unpack::UnpackOptions,
+    EntryKind, SquashFS,
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Stores the message of the error for `squishy_last_error` and returns its code
+fn set_error(error: &SquishyError) -> c_int {
+    let message = CString::new(error.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    c_int::from(error.code())
+}
+
+/// Reports a null pointer argument as an invalid input error
+fn null_argument(name: &str) -> c_int {
+    set_error(&SquishyError::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} is null", name),
+    )))
+}
+
+/// Runs the closure, turning its error into a status code
+fn status(f: impl FnOnce() -> Result<(), SquishyError>) -> c_int {
+    match f() {
+        Ok(()) => 0,
+        Err(e) => set_error(&e),
+    }
+}
+
+/// Converts a C string argument to a path
+///
+/// # Safety
+/// `path` must be null or point to a NUL terminated string
+unsafe fn path_arg(path: *const c_char) -> Result<PathBuf, SquishyError> {
+    if path.is_null() {
+        return Err(SquishyError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "path is null",
+        )));
+    }
+    let bytes = CStr::from_ptr(path).to_bytes();
+    Ok(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+/// Converts a path to an owned C string, to be released with `squishy_string_free`
+fn path_to_c(path: &Path) -> *mut c_char {
+    CString::new(path.as_os_str().as_bytes()).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// An opened SquashFS image or AppImage. DwarFS and EROFS images aren't
+/// exposed through the C API.
+pub struct SquishyImage {
+    squashfs: SquashFS<'static>,
+}
+
+/// Type of an entry
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SquishyEntryKind {
+    File = 0,
+    Directory = 1,
+    Symlink = 2,
+    Other = 3,
+}
+
+/// An entry of the image, as listed by `squishy_list`
+#[repr(C)]
+pub struct SquishyEntry {
+    /// Absolute path inside the image
+    pub path: *mut c_char,
+    pub kind: SquishyEntryKind,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    /// Seconds since the Unix epoch
+    pub mtime: u32,
+    /// Target of a symlink, null otherwise
+    pub target: *mut c_char,
+}
+
+/// Files of an AppImage `squishy_appimage_find` looks for
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SquishyAppImageFile {
+    Desktop = 0,
+    Icon = 1,
+    AppStream = 2,
+}
+
+/// Returns the message of the last error on this thread, or null if there was
+/// none. The string is valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn squishy_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Opens a SquashFS image or AppImage. The offset of the filesystem is taken
+/// from the ELF header for AppImages and found by scanning otherwise. DwarFS
+/// images fail with the unsupported error code.
+///
+/// # Safety
+/// `path` must be a NUL terminated string and `image` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn squishy_open(path: *const c_char, image: *mut *mut SquishyImage) -> c_int {
+    if image.is_null() {
+        return null_argument("image");
+    }
+    status(|| {
+        let path = path_arg(path)?;
+        let offset = get_offset(&path).ok();
+        if offset.is_none() {
+            refuse_dwarfs(&path)?;
+        }
+        *image = open(&path, offset)?;
+        Ok(())
+    })
+}
+
+/// Opens a SquashFS image at the offset within the file.
+///
+/// # Safety
+/// `path` must be a NUL terminated string and `image` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn squishy_open_with_offset(
+    path: *const c_char,
+    offset: u64,
+    image: *mut *mut SquishyImage,
+) -> c_int {
+    if image.is_null() {
+        return null_argument("image");
+    }
+    status(|| {
+        *image = open(&path_arg(path)?, Some(offset))?;
+        Ok(())
+    })
+}
+
+/// Fails with an unsupported error if the file holds a DwarFS image, which
+/// would otherwise be reported as missing a SquashFS filesystem
+fn refuse_dwarfs(path: &Path) -> Result<(), SquishyError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    match detect_filesystem_type(&mut reader) {
+        Ok((FilesystemType::DwarFS, _)) => Err(SquishyError::Unsupported(
+            "DwarFS images can't be opened through the C API".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn open(path: &Path, offset: Option<u64>) -> Result<*mut SquishyImage, SquishyError> {
+    let file = File::open(path)?;
+    let squashfs = SquashFS::new(BufReader::new(file), offset)?;
+    Ok(Box::into_raw(Box::new(SquishyImage { squashfs })))
+}
+
+/// Closes an image opened with `squishy_open`. Null is ignored.
+///
+/// # Safety
+/// `image` must come from `squishy_open` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn squishy_close(image: *mut SquishyImage) {
+    if !image.is_null() {
+        drop(Box::from_raw(image));
+    }
+}
+
+/// Stores the offset of the filesystem within the file in `offset`.
+///
+/// # Safety
+/// `image` must be a valid image and `offset` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn squishy_offset(image: *const SquishyImage, offset: *mut u64) -> c_int {
+    if image.is_null() || offset.is_null() {
+        return null_argument("image or offset");
+    }
+    *offset = (*image).squashfs.offset();
+    0
+}
+
+/// Lists all entries of the image. Release the array with `squishy_list_free`.
+///
+/// # Safety
+/// `image` must be a valid image, `entries` and `len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn squishy_list(
+    image: *const SquishyImage,
+    entries: *mut *mut SquishyEntry,
+    len: *mut usize,
+) -> c_int {
+    if image.is_null() || entries.is_null() || len.is_null() {
+        return null_argument("image, entries or len");
+    }
+    let list = (*image)
+        .squashfs
+        .entries()
+        .map(|entry| {
+            let (kind, target) = match &entry.kind {
+                EntryKind::File(_) => (SquishyEntryKind::File, ptr::null_mut()),
+                EntryKind::Directory => (SquishyEntryKind::Directory, ptr::null_mut()),
+                EntryKind::Symlink(target) => (SquishyEntryKind::Symlink, path_to_c(target)),
+                EntryKind::Unknown => (SquishyEntryKind::Other, ptr::null_mut()),
+            };
+            SquishyEntry {
+                path: path_to_c(&entry.path),
+                kind,
+                mode: entry.mode(),
+                uid: entry.uid(),
+                gid: entry.gid(),
+                size: u64::from(entry.size),
+                mtime: entry.mtime(),
+                target,
+            }
+        })
+        .collect::<Box<[_]>>();
+    *len = list.len();
+    *entries = Box::into_raw(list).cast();
+    0
+}
+
+/// Releases the entries returned by `squishy_list`.
+///
+/// # Safety
+/// `entries` and `len` must come from `squishy_list`.
+#[no_mangle]
+pub unsafe extern "C" fn squishy_list_free(entries: *mut SquishyEntry, len: usize) {
+    if entries.is_null() {
+        return;
+    }
+    let list = Box::from_raw(ptr::slice_from_raw_parts_mut(entries, len));
+    for entry in list.iter() {
+        squishy_string_free(entry.path);
+        squishy_string_free(entry.target);
+    }
+}
+
+/// Reads the contents of a file in the image. Release the data with
+/// `squishy_bytes_free`.
+///
+/// # Safety
+/// `image` must be a valid image, `path` a NUL terminated string, `data` and
+/// `len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn squishy_read_file(
+    image: *const SquishyImage,
+    path: *const c_char,
+    data: *mut *mut u8,
+    len: *mut usize,
+) -> c_int {
+    if image.is_null() || data.is_null() || len.is_null() {
+        return null_argument("image, data or len");
+    }
+    status(|| {
+        let contents = (*image)
+            .squashfs
+            .read_file(path_arg(path)?)?
+            .into_boxed_slice();
+        *len = contents.len();
+        *data = Box::into_raw(contents).cast();
+        Ok(())
+    })
+}
+
+/// Releases the data returned by `squishy_read_file`.
+///
+/// # Safety
+/// `data` and `len` must come from `squishy_read_file`.
+#[no_mangle]
+pub unsafe extern "C" fn squishy_bytes_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// Extracts all entries of the image into the directory, with the
+/// permissions from the image. Existing files are kept.
+///
+/// # Safety
+/// `image` must be a valid image and `dest` a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn squishy_extract(image: *const SquishyImage, dest: *const c_char) -> c_int {
+    if image.is_null() {
+        return null_argument("image");
+    }
+    status(|| {
+        (*image)
+            .squashfs
+            .unpack_to(path_arg(dest)?, &UnpackOptions::default())?;
+        Ok(())
+    })
+}
+
+/// Finds the desktop entry, icon or AppStream metadata of an AppImage the
+/// way the CLI does, returning its path inside the image for
+/// `squishy_read_file`. Release the path with `squishy_string_free`.
+///
+/// # Safety
+/// `path` must be a NUL terminated string and `entry_path` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn squishy_appimage_find(
+    path: *const c_char,
+    file: SquishyAppImageFile,
+    entry_path: *mut *mut c_char,
+) -> c_int {
+    if entry_path.is_null() {
+        return null_argument("entry_path");
+    }
+    status(|| {
+        let path = path_arg(path)?;
+        let appimage = AppImage::new(PathFilter::default(), &path, None)?;
+        let (found, name) = match file {
+            SquishyAppImageFile::Desktop => (appimage.find_desktop(), "desktop file"),
+            SquishyAppImageFile::Icon => (appimage.find_icon(), "icon"),
+            SquishyAppImageFile::AppStream => (appimage.find_appstream(), "appstream file"),
+        };
+        let found = found.ok_or_else(|| SquishyError::FileNotFound(PathBuf::from(name)))?;
+        *entry_path = path_to_c(&found.entry.path);
+        Ok(())
+    })
+}
+
+/// Releases a string returned by the library. Null is ignored.
+///
+/// # Safety
+/// `string` must come from the library and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn squishy_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, slice};
+
+    use squishy::pack::{pack_dir, PackOptions};
+
+    use super::*;
+
+    fn c_path(path: &Path) -> CString {
+        CString::new(path.as_os_str().as_bytes()).unwrap()
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(squishy_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn null_arguments_are_refused() {
+        let mut offset = 0;
+        let mut image = ptr::null_mut();
+        unsafe {
+            assert_eq!(squishy_offset(ptr::null(), &mut offset), 5);
+            assert!(last_error().ends_with("image or offset is null"));
+            assert_eq!(squishy_open(ptr::null(), &mut image), 5);
+            assert!(last_error().ends_with("path is null"));
+            assert!(image.is_null());
+            assert_eq!(squishy_extract(ptr::null(), ptr::null()), 5);
+            squishy_close(ptr::null_mut());
+            squishy_list_free(ptr::null_mut(), 0);
+            squishy_bytes_free(ptr::null_mut(), 0);
+            squishy_string_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn missing_and_dwarfs_files_fail_with_their_codes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut image = ptr::null_mut();
+
+        let missing = c_path(&dir.path().join("missing.squashfs"));
+        assert_eq!(unsafe { squishy_open(missing.as_ptr(), &mut image) }, 2);
+        assert!(image.is_null());
+
+        let dwarfs = dir.path().join("image.dwarfs");
+        let mut contents = b"DWARFS".to_vec();
+        contents.extend_from_slice(&[2, 5]);
+        contents.resize(64, 0);
+        fs::write(&dwarfs, contents).unwrap();
+        let dwarfs = c_path(&dwarfs);
+        assert_eq!(unsafe { squishy_open(dwarfs.as_ptr(), &mut image) }, 4);
+        assert!(last_error().contains("DwarFS"));
+        assert!(image.is_null());
+    }
+
+    #[test]
+    fn opens_lists_reads_and_extracts() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir_all(source.join("bin")).unwrap();
+        fs::write(source.join("bin/hello"), b"hello").unwrap();
+        std::os::unix::fs::symlink("bin/hello", source.join("link")).unwrap();
+        let path = dir.path().join("image.squashfs");
+        pack_dir(
+            &source,
+            File::create(&path).unwrap(),
+            0,
+            &PackOptions::default(),
+        )
+        .unwrap();
+
+        let mut image = ptr::null_mut();
+        assert_eq!(
+            unsafe { squishy_open(c_path(&path).as_ptr(), &mut image) },
+            0
+        );
+        let mut offset = u64::MAX;
+        assert_eq!(unsafe { squishy_offset(image, &mut offset) }, 0);
+        assert_eq!(offset, 0);
+
+        let (mut entries, mut len) = (ptr::null_mut(), 0);
+        assert_eq!(unsafe { squishy_list(image, &mut entries, &mut len) }, 0);
+        let listed: Vec<(String, SquishyEntryKind)> =
+            unsafe { slice::from_raw_parts(entries, len) }
+                .iter()
+                .map(|entry| {
+                    let path = unsafe { CStr::from_ptr(entry.path) };
+                    (path.to_string_lossy().into_owned(), entry.kind)
+                })
+                .collect();
+        unsafe { squishy_list_free(entries, len) };
+        assert!(listed.contains(&("/bin/hello".to_string(), SquishyEntryKind::File)));
+        assert!(listed.contains(&("/link".to_string(), SquishyEntryKind::Symlink)));
+
+        let (mut data, mut len) = (ptr::null_mut(), 0);
+        let file = CString::new("/bin/hello").unwrap();
+        assert_eq!(
+            unsafe { squishy_read_file(image, file.as_ptr(), &mut data, &mut len) },
+            0
+        );
+        assert_eq!(unsafe { slice::from_raw_parts(data, len) }, b"hello");
+        unsafe { squishy_bytes_free(data, len) };
+
+        let missing = CString::new("/missing").unwrap();
+        assert_eq!(
+            unsafe { squishy_read_file(image, missing.as_ptr(), &mut data, &mut len) },
+            2
+        );
+
+        let dest = dir.path().join("dest");
+        assert_eq!(unsafe { squishy_extract(image, c_path(&dest).as_ptr()) }, 0);
+        assert_eq!(fs::read(dest.join("bin/hello")).unwrap(), b"hello");
+        unsafe { squishy_close(image) };
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use squishy::{
+    pack::{pack_dir, PackOptions},
+    unpack::UnpackOptions,
+    Compressor, SquashFS,
+};
+
+const WORDS: &[&str] = &[
+    "squashfs",
+    "block",
+    "inode",
+    "fragment",
+    "directory",
+    "symlink",
+    "xattr",
+    "compressed",
+    "AppImage",
+    "runtime",
+    "desktop",
+    "icon",
+    "offset",
+    "superblock",
+    "metadata",
+    "table",
+];
+
+/// Writes `size` bytes of compressible text to the file, the same for every run
+fn write_text(path: &Path, size: usize, seed: u64) {
+    let mut writer = BufWriter::new(File::create(path).unwrap());
+    let mut state = seed;
+    let mut written = 0;
+    while written < size {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let word = WORDS[(state >> 33) as usize % WORDS.len()];
+        let digits = (state >> 16) as u16;
+        let line = format!("{} {} ", word, digits);
+        writer.write_all(line.as_bytes()).unwrap();
+        written += line.len();
+    }
+}
+
+/// Builds a tree of many small files and a few large ones, and packs it
+fn build_image(dir: &Path, compressor: Compressor) -> (PathBuf, u64) {
+    let source = dir.join("source");
+    let mut total = 0;
+    for i in 0..8 {
+        let sub = source.join(format!("dir{}", i));
+        fs::create_dir_all(&sub).unwrap();
+        for j in 0..64 {
+            let size = 512 + (i * 64 + j) * 97 % 16384;
+            write_text(
+                &sub.join(format!("file{}.txt", j)),
+                size,
+                (i * 64 + j) as u64,
+            );
+            total += size as u64;
+        }
+    }
+    for i in 0..4 {
+        let size = 8 << 20;
+        write_text(&source.join(format!("large{}.bin", i)), size, 1000 + i);
+        total += size as u64;
+    }
+
+    let image = dir.join(format!("{:?}.squashfs", compressor).to_lowercase());
+    let options = PackOptions {
+        compressor,
+        ..PackOptions::default()
+    };
+    pack_dir(&source, File::create(&image).unwrap(), 0, &options).unwrap();
+    (image, total)
+}
+
+fn extract(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("squishy-bench-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    for compressor in [Compressor::Xz, Compressor::Zstd, Compressor::Gzip] {
+        let (image, total) = build_image(&dir, compressor);
+        let squashfs = SquashFS::from_path(&image).unwrap();
+        let name = format!("{:?}", compressor).to_lowercase();
+
+        let mut group = c.benchmark_group(name);
+        group.sample_size(10);
+        group.throughput(Throughput::Bytes(total));
+
+        let output = dir.join("output");
+        group.bench_function("unpack_to", |b| {
+            b.iter_batched(
+                || {
+                    let _ = fs::remove_dir_all(&output);
+                },
+                |_| {
+                    squashfs
+                        .unpack_to(&output, &UnpackOptions::default())
+                        .unwrap()
+                },
+                BatchSize::PerIteration,
+            )
+        });
+
+        group.throughput(Throughput::Bytes(8 << 20));
+        group.bench_function("read_file", |b| {
+            b.iter(|| squashfs.read_file("/large0.bin").unwrap())
+        });
+        group.finish();
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+criterion_group!(benches, extract);
+criterion_main!(benches);
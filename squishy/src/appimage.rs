@@ -1,18 +1,63 @@
 use std::{
+    collections::{HashMap, HashSet},
+    fmt,
     fs::File,
-    io::{Read, Seek, SeekFrom},
-    path::Path,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::OnceLock,
 };
 
-use goblin::elf::Elf;
-use rayon::iter::ParallelIterator;
+use goblin::{
+    container::Ctx,
+    elf::{header::header64, section_header::SHT_NOBITS, Elf, ProgramHeader, SectionHeader},
+};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{
+    detect,
+    error::SquishyError,
+    filter::{self, PathFilter},
+    permissions::SpecialPermissions,
+    reader::ReadSeek,
+    EntryKind, SquashFS, SquashFSEntry, SQUASHFS_MAGIC,
+};
+
+pub mod arch;
+#[cfg(feature = "fs")]
+pub mod create;
+pub mod desktop;
+#[cfg(feature = "erofs")]
+mod erofs;
+mod harvest;
+#[cfg(feature = "fs")]
+mod integrate;
+pub mod runtime;
+mod scan;
+#[cfg(feature = "image")]
+mod thumbnail;
+pub mod version;
+#[cfg(feature = "zsync")]
+pub mod zsync;
 
-use crate::{error::SquishyError, EntryKind, SquashFS, SquashFSEntry};
+#[cfg(feature = "erofs")]
+pub use erofs::ErofsAppImage;
+pub use harvest::Harvest;
+#[cfg(feature = "fs")]
+pub use integrate::{Integration, XdgDirs};
+pub use scan::{is_appimage, par_scan_directory, scan_directory, AppImageSummary};
+
+use desktop::{locale_variants, DesktopEntry};
 
 pub type Result<T> = std::result::Result<T, SquishyError>;
 
 /// Get offset for AppImage. This is used by default if no offset is provided.
 ///
+/// The offset is the end of the runtime ELF, computed from the section and
+/// program header tables (32/64-bit, little/big endian). If no SquashFS magic
+/// is found at that offset, the file is scanned for the magic instead.
+/// Bare SquashFS images, starting with the magic, have the offset 0 without
+/// parsing them as ELF.
+///
 /// # Arguments
 /// * `path` - Path to the appimage file.
 ///
@@ -20,194 +65,895 @@ pub type Result<T> = std::result::Result<T, SquishyError>;
 /// Offset of the appimage, or an error if it fails to parse Elf
 pub fn get_offset<P: AsRef<Path>>(path: P) -> std::io::Result<u64> {
     let mut file = File::open(path)?;
+    let mut magic = [0u8; 4];
+    if file.read_exact(&mut magic).is_ok() && magic == SQUASHFS_MAGIC {
+        return Ok(0);
+    }
+    file.rewind()?;
+    let offset = elf_end_offset(&mut file)?;
+
+    let mut magic = [0u8; 4];
+    file.seek(SeekFrom::Start(offset))?;
+    if file.read_exact(&mut magic).is_ok() && magic == SQUASHFS_MAGIC {
+        return Ok(offset);
+    }
+
+    file.rewind()?;
+    detect::find_squashfs_offset(&mut BufReader::new(file))
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::NotFound, e))
+}
+
+/// Computes the end of the ELF image, i.e. the largest end offset of the ELF
+/// header tables, sections and segments.
+///
+/// # Arguments
+/// * `file` - The ELF file
+///
+/// # Returns
+/// The end offset of the ELF, or an error if it fails to parse Elf
+pub(crate) fn elf_end_offset<R: Read + Seek>(file: &mut R) -> std::io::Result<u64> {
+    let invalid_data = |e| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
 
-    let mut elf_header_raw = [0; 64];
+    let mut elf_header_raw = [0; header64::SIZEOF_EHDR];
     file.read_exact(&mut elf_header_raw)?;
 
-    let section_table_offset = u64::from_le_bytes(elf_header_raw[40..48].try_into().unwrap());
-    let section_count = u16::from_le_bytes(elf_header_raw[60..62].try_into().unwrap());
+    let header = Elf::parse_header(&elf_header_raw).map_err(invalid_data)?;
+    let ctx = Ctx::new(
+        header.container().map_err(invalid_data)?,
+        header.endianness().map_err(invalid_data)?,
+    );
 
-    let section_table_size = section_count as u64 * 64;
-    let required_bytes = section_table_offset + section_table_size;
+    let section_table_size = header.e_shentsize as u64 * header.e_shnum as u64;
+    let program_table_size = header.e_phentsize as u64 * header.e_phnum as u64;
+    let mut end = end_of(header.e_shoff, section_table_size)?
+        .max(end_of(header.e_phoff, program_table_size)?);
 
-    let mut header_data = vec![0; required_bytes as usize];
-    file.seek(SeekFrom::Start(0))?;
-    file.read_exact(&mut header_data)?;
+    if header.e_shnum > 0 {
+        let table = read_at(file, header.e_shoff, section_table_size)?;
+        let sections =
+            SectionHeader::parse(&table, 0, header.e_shnum as usize, ctx).map_err(invalid_data)?;
+        end = sections
+            .iter()
+            .filter(|section| section.sh_type != SHT_NOBITS)
+            .try_fold(end, |end, section| {
+                Ok::<_, std::io::Error>(end.max(end_of(section.sh_offset, section.sh_size)?))
+            })?;
+    }
 
-    let elf = Elf::parse(&header_data)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    if header.e_phnum > 0 {
+        let table = read_at(file, header.e_phoff, program_table_size)?;
+        let segments =
+            ProgramHeader::parse(&table, 0, header.e_phnum as usize, ctx).map_err(invalid_data)?;
+        end = segments.iter().try_fold(end, |end, segment| {
+            Ok::<_, std::io::Error>(end.max(end_of(segment.p_offset, segment.p_filesz)?))
+        })?;
+    }
 
-    let section_table_end =
-        elf.header.e_shoff + (elf.header.e_shentsize as u64 * elf.header.e_shnum as u64);
+    Ok(end)
+}
 
-    let last_section_end = elf
-        .section_headers
-        .last()
-        .map(|section| section.sh_offset + section.sh_size)
-        .unwrap_or(0);
+/// Returns the end of the range of `size` bytes at `offset`, or an error if it
+/// overflows
+fn end_of(offset: u64, size: u64) -> std::io::Result<u64> {
+    offset.checked_add(size).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "ELF offset out of range")
+    })
+}
+
+/// Reads `len` bytes starting at `offset`, checking that they are inside the
+/// file before allocating
+fn read_at<R: Read + Seek>(file: &mut R, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+    let file_len = file.seek(SeekFrom::End(0))?;
+    if end_of(offset, len)? > file_len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "ELF table past the end of the file",
+        ));
+    }
+    let mut data = vec![0; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
 
-    Ok(section_table_end.max(last_section_end))
+/// The EntrySource enum describes how an entry was selected by the AppImage finders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum EntrySource {
+    /// Icon named by the `Icon=` key of the desktop file
+    DesktopIcon,
+    /// `.DirIcon` at the AppImage root
+    DirIcon,
+    /// Icon in one of the icon search roots, /usr/share/icons and
+    /// /usr/share/pixmaps by default
+    IconTheme,
+    /// Largest png icon in any path
+    FallbackPng,
+    /// Svg icon in any path
+    FallbackSvg,
+    /// First entry matching the searched file type
+    Search,
+}
+
+impl fmt::Display for EntrySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self {
+            EntrySource::DesktopIcon => "desktop Icon key",
+            EntrySource::DirIcon => "DirIcon",
+            EntrySource::IconTheme => "icon theme",
+            EntrySource::FallbackPng => "fallback png",
+            EntrySource::FallbackSvg => "fallback svg",
+            EntrySource::Search => "search",
+        };
+        write!(f, "{}", source)
+    }
+}
+
+/// The FoundEntry struct represents an entry selected by the AppImage finders,
+/// along with how it was found.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FoundEntry<'a> {
+    /// The selected entry. Symlinks are already resolved to their target.
+    pub entry: SquashFSEntry<'a>,
+    /// How the entry was selected
+    pub source: EntrySource,
+    /// Path of the symlink the entry was resolved from, if any
+    pub resolved_from: Option<PathBuf>,
+}
+
+/// The IconSearchOptions struct configures where `AppImage::find_icon` looks
+/// for icons and which formats it prefers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IconSearchOptions {
+    /// Directories searched for icons before the whole image, e.g. a custom
+    /// prefix like `/opt/app/icons`. Icons in them are also preferred when
+    /// picking the icon named by the desktop file.
+    pub roots: Vec<PathBuf>,
+    /// Icon file extensions, most preferred first. Other files are ignored in
+    /// the roots and for the icon named by the desktop file.
+    pub extensions: Vec<String>,
+}
+
+impl Default for IconSearchOptions {
+    fn default() -> Self {
+        Self {
+            roots: vec![
+                PathBuf::from("/usr/share/icons"),
+                PathBuf::from("/usr/share/pixmaps"),
+            ],
+            extensions: vec!["png".to_string(), "svg".to_string(), "xpm".to_string()],
+        }
+    }
+}
+
+impl IconSearchOptions {
+    /// Returns the rank of the extension of the path, higher for more
+    /// preferred ones, or None if it isn't an icon extension
+    fn extension_rank(&self, path: &Path) -> Option<usize> {
+        let extension = path.extension()?.as_encoded_bytes();
+        let index = self
+            .extensions
+            .iter()
+            .position(|preferred| preferred.as_bytes().eq_ignore_ascii_case(extension))?;
+        Some(self.extensions.len() - index)
+    }
+
+    /// Checks whether the path is inside one of the roots. The leading `/`
+    /// of roots is optional.
+    fn in_roots(&self, path: &Path) -> bool {
+        self.roots
+            .iter()
+            .any(|root| path.starts_with(Path::new("/").join(root)))
+    }
+}
+
+/// The FoundCandidate struct is one of several entries matching a finder, e.g.
+/// one of the desktop files of an AppImage bundling several applications.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FoundCandidate<'a> {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub found: FoundEntry<'a>,
+    /// Whether the entry is the one the single finder returns
+    pub primary: bool,
+}
+
+impl FoundEntry<'_> {
+    /// Returns the path the entry was found at, the symlink if it was resolved
+    fn placed_path(&self) -> &Path {
+        self.resolved_from.as_deref().unwrap_or(&self.entry.path)
+    }
+}
+
+/// The IndexedEntry struct holds what the AppImage finders match on for a
+/// single entry, so the entries are walked only once per AppImage.
+#[derive(Debug)]
+struct IndexedEntry {
+    path: PathBuf,
+    /// The path in lowercase, for matching file names and extensions. Bytes
+    /// that aren't valid UTF-8 are kept as they are.
+    lowercase: Vec<u8>,
+    size: u32,
+    directory: bool,
+    /// Whether the path matches the filter of the AppImage
+    filtered: bool,
+}
+
+/// Marks the first of the entries with the highest rank as the primary one.
+/// Of entries resolving to the same file, only the highest ranked is kept, so
+/// a symlink at the AppImage root isn't listed twice.
+fn mark_primary<'a>(
+    entries: Vec<FoundEntry<'a>>,
+    rank: impl Fn(&FoundEntry) -> usize,
+) -> Vec<FoundCandidate<'a>> {
+    let ranks: Vec<usize> = entries.iter().map(rank).collect();
+    let mut best: HashMap<&Path, usize> = HashMap::new();
+    for (index, found) in entries.iter().enumerate() {
+        best.entry(&found.entry.path)
+            .and_modify(|kept| {
+                if ranks[index] > ranks[*kept] {
+                    *kept = index;
+                }
+            })
+            .or_insert(index);
+    }
+    let kept: HashSet<usize> = best.into_values().collect();
+    let primary = kept
+        .iter()
+        .max_by_key(|index| (ranks[**index], std::cmp::Reverse(**index)))
+        .copied();
+
+    entries
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| kept.contains(index))
+        .map(|(index, found)| FoundCandidate {
+            found,
+            primary: Some(index) == primary,
+        })
+        .collect()
+}
+
+/// Returns the primary one of the candidates
+fn primary(candidates: Vec<FoundCandidate<'_>>) -> Option<FoundEntry<'_>> {
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.primary)
+        .map(|candidate| candidate.found)
 }
 
 pub struct AppImage<'a> {
-    filter: Option<&'a str>,
+    filter: PathFilter,
+    path: &'a Path,
+    offset: u64,
+    read_limit: u64,
+    icon_search: IconSearchOptions,
+    /// Entries the finders match on, built on first use
+    index: OnceLock<Vec<IndexedEntry>>,
     pub squashfs: SquashFS<'a>,
 }
 
 impl<'a> AppImage<'a> {
-    /// Creates a new AppImage instance
+    /// Creates a new AppImage instance. AppImages carrying EROFS instead of
+    /// SquashFS are opened with `ErofsAppImage`.
     ///
     /// # Arguments
     ///
-    /// * `filter` - Filter to apply
+    /// * `filter` - Include/exclude patterns to apply
     /// * `path` - Path to AppImage
     /// * `offset` - Offset to seek to
     pub fn new<P: AsRef<Path>>(
-        filter: Option<&'a str>,
+        filter: PathFilter,
         path: &'a P,
         offset: Option<u64>,
     ) -> Result<Self> {
-        let offset = offset.unwrap_or(get_offset(path)?);
+        let offset = match offset {
+            Some(offset) => offset,
+            None => get_offset(path)?,
+        };
         let squashfs = SquashFS::from_path_with_offset(path, offset).map_err(|_| {
             SquishyError::InvalidSquashFS(
                 "Couldn't find squashfs. Try providing valid offset.".to_owned(),
             )
         })?;
-        Ok(AppImage { filter, squashfs })
+        Ok(AppImage {
+            filter,
+            path: path.as_ref(),
+            offset,
+            read_limit: u64::MAX,
+            icon_search: IconSearchOptions::default(),
+            index: OnceLock::new(),
+            squashfs,
+        })
+    }
+
+    /// Limits the size of files read into memory by `read_entry` and the
+    /// helpers built on it, e.g. `desktop_entry` or `render_icon_png`. Larger files
+    /// fail with `SquishyError::FileTooLarge`. Unlimited by default.
+    ///
+    /// # Arguments
+    /// * `limit` - The largest file size to read, or None for no limit
+    pub fn set_read_limit(&mut self, limit: Option<u64>) {
+        self.read_limit = limit.unwrap_or(u64::MAX);
+    }
+
+    /// Sets where `find_icon`, and the helpers built on it like
+    /// `render_icon_png`, look for icons and which formats they prefer.
+    ///
+    /// # Arguments
+    /// * `options` - The roots and extensions to search
+    pub fn set_icon_search(&mut self, options: IconSearchOptions) {
+        self.icon_search = options;
+    }
+
+    /// Returns the path of the AppImage file
+    pub fn path(&self) -> &Path {
+        self.path
+    }
+
+    /// Returns the offset of the SquashFS filesystem within the AppImage
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Looks up a single entry by its path, ignoring the filter.
+    /// The leading `/` is optional, e.g. `usr/bin/foo`.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the entry within the AppImage
+    ///
+    /// # Returns
+    /// The entry, or None if there is no entry at the path
+    pub fn find<P: AsRef<Path>>(&self, path: P) -> Option<SquashFSEntry<'_>> {
+        self.squashfs.find(path)
+    }
+
+    /// Checks whether an entry exists at the path, ignoring the filter.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the entry within the AppImage
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.squashfs.exists(path)
+    }
+
+    /// Lists the entries with setuid, setgid or sticky bits, or file
+    /// capabilities, ignoring the filter. See `SquashFS::special_permission_entries`.
+    ///
+    /// # Returns
+    /// The entries, or an error if the capabilities of an entry are malformed
+    pub fn special_permission_entries(&self) -> Result<Vec<SpecialPermissions>> {
+        self.squashfs.special_permission_entries()
+    }
+
+    /// Reads the contents of the entry, following symlinks, unless it is
+    /// larger than the read limit
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to read
+    ///
+    /// # Returns
+    /// The contents of the file, or an error if the entry doesn't resolve to a file
+    pub fn read_entry(&self, entry: &SquashFSEntry) -> Result<Vec<u8>> {
+        match self.resolve_entry(entry)? {
+            Some(resolved) => self
+                .squashfs
+                .read_entry_with_limit(&resolved, self.read_limit),
+            None => self.squashfs.read_entry_with_limit(entry, self.read_limit),
+        }
+    }
+
+    /// Opens the entry for reading and seeking, following symlinks, e.g. to
+    /// hand an in-image archive or image to crates that take `Read + Seek`
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to open
+    ///
+    /// # Returns
+    /// A reader of the file contents, or an error if the entry doesn't resolve to a file
+    pub fn open_entry<'s>(&'s self, entry: &SquashFSEntry<'s>) -> Result<Box<dyn ReadSeek + 's>> {
+        let resolved = self.resolve_entry(entry)?;
+        let reader = self
+            .squashfs
+            .open_entry(resolved.as_ref().unwrap_or(entry))?;
+        Ok(Box::new(reader))
+    }
+
+    /// Writes the contents of the entry to the destination path, following symlinks
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to write
+    /// * `dest` - The destination path to write the file to
+    ///
+    /// # Returns
+    /// An empty result, or an error if the entry doesn't resolve to a file or can't be written
+    #[cfg(feature = "fs")]
+    pub fn write_entry<P: AsRef<Path>>(&self, entry: &SquashFSEntry, dest: P) -> Result<()> {
+        let resolved = self.resolve_entry(entry)?;
+        let entry = resolved.as_ref().unwrap_or(entry);
+        match entry.kind {
+            EntryKind::File(basic_file) => self.squashfs.write_file(basic_file, dest),
+            _ => Err(SquishyError::FileNotFound(entry.path.clone())),
+        }
+    }
+
+    /// Writes the contents of the entry to the destination path with the entry's
+    /// permissions, following symlinks
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to write
+    /// * `dest` - The destination path to write the file to
+    ///
+    /// # Returns
+    /// An empty result, or an error if the entry doesn't resolve to a file or can't be written
+    #[cfg(feature = "fs")]
+    pub fn write_entry_with_permissions<P: AsRef<Path>>(
+        &self,
+        entry: &SquashFSEntry,
+        dest: P,
+    ) -> Result<()> {
+        let resolved = self.resolve_entry(entry)?;
+        let entry = resolved.as_ref().unwrap_or(entry);
+        match entry.kind {
+            EntryKind::File(basic_file) => {
+                self.squashfs
+                    .write_file_with_permissions(basic_file, dest, entry.header)
+            }
+            _ => Err(SquishyError::FileNotFound(entry.path.clone())),
+        }
+    }
+
+    /// Resolves the entry if it is a symlink
+    ///
+    /// # Returns
+    /// The symlink target entry, None if the entry is not a symlink, or an error
+    /// if the symlink is dangling or cyclic
+    fn resolve_entry(&self, entry: &SquashFSEntry) -> Result<Option<SquashFSEntry<'_>>> {
+        match &entry.kind {
+            EntryKind::Symlink(target) => self
+                .squashfs
+                .resolve_symlink(entry)?
+                .map(Some)
+                .ok_or_else(|| SquishyError::FileNotFound(target.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the index of the entries the finders match on, walking the
+    /// entries on first use only
+    fn index(&self) -> &[IndexedEntry] {
+        self.index.get_or_init(|| {
+            self.squashfs
+                .entries()
+                .map(|entry| IndexedEntry {
+                    lowercase: filter::lowercase(entry.path.as_os_str()),
+                    size: entry.size,
+                    directory: matches!(entry.kind, EntryKind::Directory),
+                    filtered: self.filter_path(&entry.path),
+                    path: entry.path,
+                })
+                .collect()
+        })
+    }
+
+    /// Looks up the entry of the index in the image
+    fn indexed_entry(&self, indexed: &IndexedEntry) -> Option<SquashFSEntry<'_>> {
+        self.squashfs.find(&indexed.path)
     }
 
     /// Find icon in AppImage, filtered
     /// It looks for icon in order:
+    /// - Icon named by the desktop file `Icon=` key
     /// - DirIcon at AppImage root
-    /// - Largest png icon in /usr/share/icons
-    /// - Largest svg icon in /usr/share/icons
+    /// - Largest icon in the search roots, of the most preferred extension
+    ///   found, see `set_icon_search`
     /// - Largest png icon in any path
     /// - Largest svg icon in any path
     ///
     /// # Returns
-    /// A SquashFS entry to the icon, if found
-    pub fn find_icon(&self) -> Option<SquashFSEntry> {
-        let icon = self
-            .search_diricon()
-            .or_else(|| self.find_largest_icon_path())
-            .or_else(|| self.find_png_icon())
-            .or_else(|| self.find_svg_icon());
+    /// The found icon entry, if any
+    pub fn find_icon(&self) -> Option<FoundEntry<'_>> {
+        self.find_icon_with(self.desktop_entry().as_ref())
+    }
 
-        if let Some(icon) = &icon {
-            if let EntryKind::Symlink(_) = icon.kind {
-                let final_entry = self.squashfs.resolve_symlink(icon).unwrap();
-                return final_entry;
-            }
+    /// Find icon, matching the `Icon=` key of the already parsed desktop
+    /// file, see `find_icon`
+    fn find_icon_with(&self, desktop: Option<&DesktopEntry>) -> Option<FoundEntry<'_>> {
+        let (icon, source) = desktop
+            .and_then(|desktop| self.find_desktop_icon(desktop))
+            .map(|entry| (entry, EntrySource::DesktopIcon))
+            .or_else(|| {
+                self.search_diricon()
+                    .map(|entry| (entry, EntrySource::DirIcon))
+            })
+            .or_else(|| {
+                self.find_largest_icon_path()
+                    .map(|entry| (entry, EntrySource::IconTheme))
+            })
+            .or_else(|| {
+                self.find_png_icon()
+                    .map(|entry| (entry, EntrySource::FallbackPng))
+            })
+            .or_else(|| {
+                self.find_svg_icon()
+                    .map(|entry| (entry, EntrySource::FallbackSvg))
+            })?;
+
+        self.found_entry(self.indexed_entry(icon)?, source)
+    }
+
+    /// Wraps the entry into a FoundEntry, resolving symlinks
+    ///
+    /// # Arguments
+    /// * `entry` - The selected entry
+    /// * `source` - How the entry was selected
+    ///
+    /// # Returns
+    /// The found entry, or None if the symlink couldn't be resolved
+    fn found_entry<'b>(
+        &'b self,
+        entry: SquashFSEntry<'b>,
+        source: EntrySource,
+    ) -> Option<FoundEntry<'b>> {
+        if let EntryKind::Symlink(_) = entry.kind {
+            let resolved = self.squashfs.resolve_symlink(&entry).ok().flatten()?;
+            return Some(FoundEntry {
+                entry: resolved,
+                source,
+                resolved_from: Some(entry.path),
+            });
         }
-        icon
+        Some(FoundEntry {
+            entry,
+            source,
+            resolved_from: None,
+        })
+    }
+
+    /// Find icon named by the `Icon=` key of the desktop file, filtered
+    /// Icons in the search roots are preferred, then preferred extensions,
+    /// then larger files.
+    ///
+    /// # Returns
+    /// The indexed entry of the icon, if found
+    fn find_desktop_icon(&self, desktop: &DesktopEntry) -> Option<&IndexedEntry> {
+        let icon = desktop.get("Icon")?;
+        let name = filter::lowercase(Path::new(icon).file_name()?);
+
+        self.index()
+            .par_iter()
+            .filter(|entry| {
+                let path = &entry.path;
+                let file_name = path.file_name().map(filter::lowercase);
+                let file_stem = path.file_stem().map(filter::lowercase);
+                let is_image = self.icon_search.extension_rank(path).is_some();
+
+                entry.filtered
+                    && is_image
+                    && (file_name.as_ref() == Some(&name) || file_stem.as_ref() == Some(&name))
+            })
+            .max_by_key(|entry| {
+                (
+                    self.icon_search.in_roots(&entry.path),
+                    self.icon_search.extension_rank(&entry.path),
+                    entry.size,
+                )
+            })
     }
 
     /// Find DirIcon at AppImage root
     ///
     /// # Returns
-    /// A SquashFS entry to the icon, if found
-    fn search_diricon(&self) -> Option<SquashFSEntry> {
-        self.squashfs
-            .par_entries()
-            .find_first(|entry| entry.path.to_string_lossy() == "/.DirIcon")
+    /// The indexed entry of the icon, if found
+    fn search_diricon(&self) -> Option<&IndexedEntry> {
+        self.index()
+            .par_iter()
+            .find_first(|entry| entry.path == Path::new("/.DirIcon"))
     }
 
     /// Helper method to filter paths
     ///
     /// # Returns
     /// boolean stating if the path matches the filter
-    fn filter_path(&self, path: &str) -> bool {
-        self.filter
-            .as_ref()
-            .map_or(true, |filter| path.contains(filter))
+    fn filter_path(&self, path: &Path) -> bool {
+        self.filter.matches(path)
     }
 
-    /// Find largest png (preferred) or svg icon in /usr/share/icons, filtered
+    /// Find the largest icon in the search roots, of the most preferred
+    /// extension found, filtered
     ///
     /// # Returns
-    /// A SquashFS entry to the icon, if found
-    fn find_largest_icon_path(&self) -> Option<SquashFSEntry> {
-        let png_entries = self.squashfs.par_entries().filter(|entry| {
-            let path = entry.path.to_string_lossy().to_lowercase();
-            path.starts_with("/usr/share/icons/")
-                && self.filter_path(&path)
-                && path.ends_with(".png")
-        });
-
-        if let Some(entry) = png_entries.max_by_key(|entry| entry.size) {
-            return Some(entry);
-        }
-
-        self.squashfs.par_entries().find_first(|entry| {
-            let path = entry.path.to_string_lossy().to_lowercase();
-            path.starts_with("/usr/share/icons")
-                && self.filter_path(&path)
-                && path.ends_with(".svg")
-        })
+    /// The indexed entry of the icon, if found
+    fn find_largest_icon_path(&self) -> Option<&IndexedEntry> {
+        self.index()
+            .par_iter()
+            .filter(|entry| {
+                !entry.directory && entry.filtered && self.icon_search.in_roots(&entry.path)
+            })
+            .filter_map(|entry| {
+                let rank = self.icon_search.extension_rank(&entry.path)?;
+                Some((rank, entry))
+            })
+            .max_by_key(|(rank, entry)| (*rank, entry.size))
+            .map(|(_, entry)| entry)
     }
 
     /// Find largest png icon in AppImage, filtered
     ///
     /// # Returns
-    /// A SquashFS entry to the icon, if found
-    fn find_png_icon(&self) -> Option<SquashFSEntry> {
-        let png_entries = self.squashfs.par_entries().filter(|entry| {
-            let p = entry.path.to_string_lossy().to_lowercase();
-            self.filter_path(&p) && p.ends_with(".png")
-        });
-        if let Some(entry) = png_entries.max_by_key(|entry| entry.size) {
-            return Some(entry);
-        }
-        None
+    /// The indexed entry of the icon, if found
+    fn find_png_icon(&self) -> Option<&IndexedEntry> {
+        self.index()
+            .par_iter()
+            .filter(|entry| entry.filtered && entry.lowercase.ends_with(b".png"))
+            .max_by_key(|entry| entry.size)
     }
 
     /// Find largest svg icon in AppImage, filtered
     ///
     /// # Returns
-    /// A SquashFS entry to the icon, if found
-    fn find_svg_icon(&self) -> Option<SquashFSEntry> {
-        self.squashfs.par_entries().find_first(|entry| {
-            let path = entry.path.to_string_lossy().to_lowercase();
-            self.filter_path(&path) && path.ends_with(".svg")
-        })
+    /// The indexed entry of the icon, if found
+    fn find_svg_icon(&self) -> Option<&IndexedEntry> {
+        self.index()
+            .par_iter()
+            .find_first(|entry| entry.filtered && entry.lowercase.ends_with(b".svg"))
     }
 
-    /// Find desktop file in AppImage, filtered
+    /// Find the primary desktop file in AppImage, filtered. See `find_desktops`.
     ///
     /// # Returns
-    /// A SquashFS entry to the desktop file, if found
-    pub fn find_desktop(&self) -> Option<SquashFSEntry> {
-        let desktop = self.squashfs.par_entries().find_first(|entry| {
-            let path = entry.path.to_string_lossy().to_lowercase();
-            self.filter_path(&path) && path.ends_with(".desktop")
-        });
+    /// The found desktop file entry, if any
+    pub fn find_desktop(&self) -> Option<FoundEntry<'_>> {
+        primary(self.find_desktops())
+    }
 
-        if let Some(desktop) = &desktop {
-            if let EntryKind::Symlink(_) = desktop.kind {
-                let final_entry = self.squashfs.resolve_symlink(desktop).unwrap();
-                return final_entry;
+    /// Find every desktop file in AppImage, filtered, e.g. of AppImages
+    /// bundling several applications. The primary one is the desktop file at
+    /// the AppImage root, as the AppImage spec requires, else one in
+    /// /usr/share/applications, else the first by path.
+    ///
+    /// # Returns
+    /// The desktop files, by path
+    pub fn find_desktops(&self) -> Vec<FoundCandidate<'_>> {
+        let desktops = self.find_all(|path| path.ends_with(b".desktop"));
+        mark_primary(desktops, |found| {
+            let path = found.placed_path();
+            if path.parent() == Some(Path::new("/")) {
+                2
+            } else if path.starts_with("/usr/share/applications") {
+                1
+            } else {
+                0
             }
-        }
-        desktop
+        })
+    }
+
+    /// Resolves every file or symlink whose lowercase path matches, filtered
+    fn find_all(&self, matches: impl Fn(&[u8]) -> bool) -> Vec<FoundEntry<'_>> {
+        self.index()
+            .iter()
+            .filter(|entry| !entry.directory && entry.filtered && matches(&entry.lowercase))
+            .filter_map(|entry| self.found_entry(self.indexed_entry(entry)?, EntrySource::Search))
+            .collect()
     }
 
-    /// Find appstream file in AppImage (appdata.xml | metainfo.xml)
+    /// Reads and parses the desktop file found by `find_desktop`
     ///
     /// # Returns
-    /// A SquashFS entry to the appstream, if found
-    pub fn find_appstream(&self) -> Option<SquashFSEntry> {
-        let appstream = self.squashfs.par_entries().find_first(|entry| {
-            let path = entry.path.to_string_lossy().to_lowercase();
-            self.filter_path(&path)
-                && (path.ends_with("appdata.xml") || path.ends_with("metainfo.xml"))
+    /// The parsed desktop entry, if found and readable
+    pub fn desktop_entry(&self) -> Option<DesktopEntry> {
+        let desktop = self.find_desktop()?;
+        let contents = self.read_text(&desktop)?;
+        Some(DesktopEntry::parse(&contents))
+    }
+
+    /// Reads the appstream file found by `find_appstream`
+    ///
+    /// # Returns
+    /// The contents of the appstream file, if found and readable
+    pub(crate) fn appstream_contents(&self) -> Option<String> {
+        let appstream = self.find_appstream()?;
+        self.read_text(&appstream)
+    }
+
+    /// Reads the found file as text, replacing invalid UTF-8
+    fn read_text(&self, found: &FoundEntry) -> Option<String> {
+        let contents = self.read_entry(&found.entry).ok()?;
+        Some(String::from_utf8_lossy(&contents).into_owned())
+    }
+
+    /// Find the primary appstream file in AppImage (appdata.xml | metainfo.xml),
+    /// filtered. See `find_appstreams`.
+    ///
+    /// # Returns
+    /// The found appstream entry, if any
+    pub fn find_appstream(&self) -> Option<FoundEntry<'_>> {
+        primary(self.find_appstreams())
+    }
+
+    /// Find every appstream file in AppImage, filtered. The primary one is
+    /// named after the primary desktop file, e.g. `org.app.metainfo.xml` for
+    /// `org.app.desktop`, then in /usr/share/metainfo, then in
+    /// /usr/share/appdata, then the first by path.
+    ///
+    /// # Returns
+    /// The appstream files, by path
+    pub fn find_appstreams(&self) -> Vec<FoundCandidate<'_>> {
+        self.find_appstreams_with(|| self.find_desktop())
+    }
+
+    /// Find every appstream file, looking up the primary desktop file with
+    /// `desktop` only if there are several, see `find_appstreams`
+    fn find_appstreams_with<'b>(
+        &'b self,
+        desktop: impl FnOnce() -> Option<FoundEntry<'b>>,
+    ) -> Vec<FoundCandidate<'b>> {
+        let appstreams =
+            self.find_all(|path| path.ends_with(b"appdata.xml") || path.ends_with(b"metainfo.xml"));
+        if appstreams.len() < 2 {
+            return mark_primary(appstreams, |_| 0);
+        }
+
+        let desktop_id = desktop().and_then(|desktop| {
+            let name = filter::lowercase(desktop.entry.path.file_name()?);
+            Some(name.strip_suffix(b".desktop")?.to_vec())
         });
+        mark_primary(appstreams, |found| {
+            let name = found
+                .entry
+                .path
+                .file_name()
+                .map(filter::lowercase)
+                .unwrap_or_default();
+            let id = name
+                .strip_suffix(b".appdata.xml")
+                .or_else(|| name.strip_suffix(b".metainfo.xml"));
+            let named = desktop_id.is_some() && id == desktop_id.as_deref();
+            let path = found.placed_path();
+            let location = if path.starts_with("/usr/share/metainfo") {
+                2
+            } else if path.starts_with("/usr/share/appdata") {
+                1
+            } else {
+                0
+            };
+            usize::from(named) * 4 + location
+        })
+    }
+
+    /// Find the license files in AppImage, filtered: files named `LICENSE*`,
+    /// `LICENCE*` or `COPYING*` anywhere, and every file in /usr/share/licenses.
+    /// Symlinks are resolved, and files linked from several paths are listed
+    /// once.
+    ///
+    /// # Returns
+    /// The license files, by path
+    pub fn find_licenses(&self) -> Vec<FoundEntry<'_>> {
+        let mut seen = HashSet::new();
+        self.find_all(|path| {
+            let name = path.rsplit(|&byte| byte == b'/').next().unwrap_or_default();
+            path.starts_with(b"/usr/share/licenses/")
+                || [b"license", b"licence", b"copying"]
+                    .iter()
+                    .any(|prefix| name.starts_with(*prefix))
+        })
+        .into_iter()
+        .filter(|found| {
+            matches!(found.entry.kind, EntryKind::File(_)) && seen.insert(found.entry.path.clone())
+        })
+        .collect()
+    }
+
+    /// Find all files matching the patterns in AppImage, filtered.
+    /// Patterns are substrings or globs, like the filter patterns.
+    ///
+    /// # Arguments
+    /// * `patterns` - Patterns of which at least one must match
+    ///
+    /// # Returns
+    /// The matching files in image order, with symlinks resolved and dangling
+    /// symlinks skipped, or an error if a pattern is invalid
+    pub fn find_matching<I>(&self, patterns: I) -> Result<Vec<FoundEntry<'_>>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let patterns = PathFilter::new(patterns, std::iter::empty::<&str>())?;
+        Ok(self
+            .index()
+            .iter()
+            .filter(|entry| !entry.directory && entry.filtered && patterns.matches(&entry.path))
+            .filter_map(|entry| self.found_entry(self.indexed_entry(entry)?, EntrySource::Search))
+            .filter(|found| matches!(found.entry.kind, EntryKind::File(_)))
+            .collect())
+    }
+
+    /// Find appstream file for the locale in AppImage, filtered
+    /// Locale specific files are recognized by the locale in the file name
+    /// (e.g. `app.de_DE.metainfo.xml`) or as a directory (e.g. `de/app.metainfo.xml`).
+    /// Falls back to `find_appstream` if there is no locale specific file.
+    ///
+    /// # Arguments
+    /// * `locale` - The locale, e.g. `de_DE`
+    ///
+    /// # Returns
+    /// The found appstream entry, if any
+    pub fn find_appstream_for_locale(&self, locale: &str) -> Option<FoundEntry<'_>> {
+        let appstreams: Vec<&IndexedEntry> = self
+            .index()
+            .par_iter()
+            .filter(|entry| {
+                entry.filtered
+                    && (entry.lowercase.ends_with(b"appdata.xml")
+                        || entry.lowercase.ends_with(b"metainfo.xml"))
+            })
+            .collect();
+
+        for variant in locale_variants(locale) {
+            let variant = variant.to_lowercase();
+            let dotted = format!(".{}.", variant);
+            let localized = appstreams.iter().find(|entry| {
+                let path = &entry.path;
+                let in_file_name = path.file_name().is_some_and(|name| {
+                    filter::contains(&filter::lowercase(name), dotted.as_bytes())
+                });
+                let in_dir = path.parent().is_some_and(|dir| {
+                    dir.components()
+                        .any(|c| filter::lowercase(c.as_os_str()) == variant.as_bytes())
+                });
+                in_file_name || in_dir
+            });
 
-        if let Some(appstream) = &appstream {
-            if let EntryKind::Symlink(_) = appstream.kind {
-                let final_entry = self.squashfs.resolve_symlink(appstream).unwrap();
-                return final_entry;
+            if let Some(entry) = localized {
+                return self.found_entry(self.indexed_entry(entry)?, EntrySource::Search);
             }
         }
-        appstream
+
+        self.find_appstream()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::{Cursor, ErrorKind};
+
+    use super::*;
+
+    /// Builds a little endian ELF64 header with the table offsets and counts
+    pub(crate) fn elf_header(phoff: u64, phnum: u16, shoff: u64, shnum: u16) -> Vec<u8> {
+        let mut header = vec![0; header64::SIZEOF_EHDR];
+        header[..7].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+        header[0x20..0x28].copy_from_slice(&phoff.to_le_bytes());
+        header[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+        header[0x34..0x36].copy_from_slice(&64u16.to_le_bytes());
+        header[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+        header[0x38..0x3a].copy_from_slice(&phnum.to_le_bytes());
+        header[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        header[0x3c..0x3e].copy_from_slice(&shnum.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn end_of_header_only_elf() {
+        let mut file = Cursor::new(elf_header(0, 0, 0, 0));
+        assert_eq!(elf_end_offset(&mut file).unwrap(), 0);
+    }
+
+    #[test]
+    fn overflowing_table_offset_is_invalid() {
+        let mut file = Cursor::new(elf_header(0, 0, u64::MAX - 8, 1));
+        let err = elf_end_offset(&mut file).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn table_past_the_end_is_invalid() {
+        let mut file = Cursor::new(elf_header(64, u16::MAX, 0, 0));
+        let err = elf_end_offset(&mut file).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
     }
 }
@@ -0,0 +1,49 @@
+use std::{fs::File, io::Read};
+
+use goblin::elf::{header, Elf};
+
+pub use crate::elf::Architecture;
+
+use super::{AppImage, Result};
+use crate::error::{IoContext, SquishyError};
+
+impl AppImage<'_> {
+    /// Detects the architecture of the AppImage from the ELF machine type of the
+    /// runtime, falling back to the `<arch>` tag of the appstream file if the
+    /// machine type is not recognized.
+    ///
+    /// # Returns
+    /// The detected architecture, or an error if the runtime ELF header can't be read
+    pub fn architecture(&self) -> Result<Architecture> {
+        self.architecture_from(|| self.appstream_contents())
+    }
+
+    /// Detects the architecture, reading the appstream file with
+    /// `appstream` only if the machine type is not recognized
+    pub(crate) fn architecture_from(
+        &self,
+        appstream: impl FnOnce() -> Option<String>,
+    ) -> Result<Architecture> {
+        let mut file = File::open(self.path).context("open", self.path)?;
+        let mut header_raw = [0u8; header::header64::SIZEOF_EHDR];
+        file.read_exact(&mut header_raw)?;
+
+        let header = Elf::parse_header(&header_raw)
+            .map_err(|e| SquishyError::InvalidSquashFS(format!("Invalid ELF header: {}", e)))?;
+        let arch = Architecture::from_elf_machine(header.e_machine);
+
+        if let Architecture::Other(_) = arch {
+            if let Some(arch) = appstream().as_deref().and_then(appstream_architecture) {
+                return Ok(arch);
+            }
+        }
+        Ok(arch)
+    }
+}
+
+/// Reads the architecture from the `<arch>` tag of the appstream file
+fn appstream_architecture(contents: &str) -> Option<Architecture> {
+    let start = contents.find("<arch>")? + "<arch>".len();
+    let end = start + contents[start..].find("</arch>")?;
+    Architecture::from_name(&contents[start..end])
+}
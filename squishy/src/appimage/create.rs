@@ -0,0 +1,199 @@
+use std::{
+    fs,
+    io::{Seek, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use goblin::elf::Elf;
+
+pub use super::runtime::{SIGNATURE_SECTION, SIGNING_KEY_SECTION, UPDATE_INFO_SECTION};
+use super::{desktop::DesktopEntry, Result};
+use crate::{
+    error::{IoContext, SquishyError},
+    pack::{pack_dir, PackOptions, PackStats},
+};
+
+/// Extensions tried for the icon named in the desktop entry, in order
+const ICON_EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+
+/// The AppDir struct holds the paths `validate_appdir` found in an AppDir.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AppDir {
+    pub app_run: PathBuf,
+    /// The desktop entry in the top-level directory
+    pub desktop: PathBuf,
+    /// The icon named by the desktop entry, or `.DirIcon`
+    pub icon: PathBuf,
+}
+
+/// The CreateOptions struct controls how `create_appimage` builds an AppImage.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CreateOptions {
+    /// Compression, block size and modification time of the payload
+    pub pack: PackOptions,
+    /// Written to the `.upd_info` section of the runtime
+    pub update_info: Option<String>,
+    /// Written to the `.sha256_sig` section of the runtime
+    pub signature: Option<Vec<u8>>,
+    /// Written to the `.sig_key` section of the runtime
+    pub signing_key: Option<Vec<u8>>,
+}
+
+fn invalid_appdir(appdir: &Path, reason: &str) -> SquishyError {
+    SquishyError::Pack(format!(
+        "{} is not a valid AppDir: {}",
+        appdir.display(),
+        reason
+    ))
+}
+
+/// Checks that the directory has what an AppImage needs at its root: an
+/// executable `AppRun`, a single desktop entry and the icon it names.
+///
+/// # Arguments
+/// * `appdir` - The directory to check
+///
+/// # Returns
+/// The paths of the required files, or an error describing the first problem
+pub fn validate_appdir<P: AsRef<Path>>(appdir: P) -> Result<AppDir> {
+    let appdir = appdir.as_ref();
+    if !appdir.is_dir() {
+        return Err(invalid_appdir(appdir, "not a directory"));
+    }
+
+    let app_run = appdir.join("AppRun");
+    match fs::metadata(&app_run) {
+        Ok(metadata) if metadata.is_file() && metadata.permissions().mode() & 0o111 != 0 => {}
+        Ok(_) => return Err(invalid_appdir(appdir, "AppRun is not an executable file")),
+        Err(_) => return Err(invalid_appdir(appdir, "AppRun is missing")),
+    }
+
+    let mut desktops = fs::read_dir(appdir)
+        .context("read directory", appdir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "desktop") && path.is_file())
+        .collect::<Vec<_>>();
+    let desktop = match desktops.len() {
+        1 => desktops.remove(0),
+        0 => {
+            return Err(invalid_appdir(
+                appdir,
+                "no desktop entry in the top-level directory",
+            ))
+        }
+        _ => {
+            return Err(invalid_appdir(
+                appdir,
+                "more than one desktop entry in the top-level directory",
+            ))
+        }
+    };
+
+    let entry = DesktopEntry::parse(&fs::read_to_string(&desktop).context("read", &desktop)?);
+    let icon_name = entry
+        .get("Icon")
+        .filter(|icon| !icon.is_empty())
+        .ok_or_else(|| invalid_appdir(appdir, "desktop entry has no Icon key"))?;
+
+    let icon = ICON_EXTENSIONS
+        .iter()
+        .map(|ext| appdir.join(format!("{}.{}", icon_name, ext)))
+        .chain([appdir.join(icon_name), appdir.join(".DirIcon")])
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            invalid_appdir(
+                appdir,
+                &format!("icon {} not found in the top-level directory", icon_name),
+            )
+        })?;
+
+    Ok(AppDir {
+        app_run,
+        desktop,
+        icon,
+    })
+}
+
+/// Writes data into a section of a runtime ELF, zero-filling the rest of the
+/// section. The runtime reserves these sections so they can be filled in
+/// without relinking.
+///
+/// # Arguments
+/// * `runtime` - The runtime ELF
+/// * `section` - Name of the section, e.g. `UPDATE_INFO_SECTION`
+/// * `data` - The bytes to write
+///
+/// # Returns
+/// An error if the runtime has no such section or the data doesn't fit
+pub fn embed_section(runtime: &mut [u8], section: &str, data: &[u8]) -> Result<()> {
+    let elf = Elf::parse(runtime)
+        .map_err(|e| SquishyError::InvalidSquashFS(format!("Invalid runtime ELF: {}", e)))?;
+    let header = elf
+        .section_headers
+        .iter()
+        .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(section))
+        .ok_or_else(|| SquishyError::Pack(format!("runtime has no {} section", section)))?;
+
+    let start = header.sh_offset as usize;
+    let size = header.sh_size as usize;
+    if data.len() > size {
+        return Err(SquishyError::Pack(format!(
+            "{} bytes don't fit into the {} section of {} bytes",
+            data.len(),
+            section,
+            size
+        )));
+    }
+    let target = runtime
+        .get_mut(start..start + size)
+        .ok_or_else(|| SquishyError::Pack(format!("{} section is out of bounds", section)))?;
+    target.fill(0);
+    target[..data.len()].copy_from_slice(data);
+    Ok(())
+}
+
+/// Builds an AppImage from an AppDir: the runtime, with the update information
+/// and signature sections filled in, followed by a SquashFS image of the AppDir.
+///
+/// # Arguments
+/// * `appdir` - The AppDir to pack, checked with `validate_appdir` first
+/// * `runtime` - The runtime ELF to prepend
+/// * `writer` - Where the AppImage is written
+/// * `options` - Payload options and section contents
+///
+/// # Returns
+/// Counts of the packed entries and the payload size, or an error if the
+/// AppDir is invalid or the sections can't be written
+pub fn create_appimage<P, W>(
+    appdir: P,
+    runtime: &[u8],
+    mut writer: W,
+    options: &CreateOptions,
+) -> Result<PackStats>
+where
+    P: AsRef<Path>,
+    W: Write + Seek,
+{
+    let appdir = appdir.as_ref();
+    validate_appdir(appdir)?;
+
+    let mut runtime = runtime.to_vec();
+    let sections = [
+        (
+            UPDATE_INFO_SECTION,
+            options.update_info.as_ref().map(|info| info.as_bytes()),
+        ),
+        (SIGNATURE_SECTION, options.signature.as_deref()),
+        (SIGNING_KEY_SECTION, options.signing_key.as_deref()),
+    ];
+    for (section, data) in sections {
+        if let Some(data) = data {
+            embed_section(&mut runtime, section, data)?;
+        }
+    }
+
+    writer.write_all(&runtime)?;
+    pack_dir(appdir, writer, runtime.len() as u64, &options.pack)
+}
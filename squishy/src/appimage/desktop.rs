@@ -0,0 +1,184 @@
+use std::fmt;
+
+/// The main group of a desktop file
+pub const DESKTOP_ENTRY_GROUP: &str = "Desktop Entry";
+
+/// A single `[Group]` of a desktop file with its key/value pairs in file order.
+#[derive(Debug, Clone)]
+struct Group {
+    name: String,
+    entries: Vec<(String, String)>,
+}
+
+/// The DesktopEntry struct is a minimal parser for freedesktop.org desktop files.
+/// Comments and blank lines are not preserved.
+#[derive(Debug, Clone, Default)]
+pub struct DesktopEntry {
+    groups: Vec<Group>,
+}
+
+impl DesktopEntry {
+    /// Parses a desktop file. Lines that are neither group headers nor
+    /// key/value pairs are ignored.
+    ///
+    /// # Arguments
+    /// * `content` - Contents of the desktop file
+    pub fn parse(content: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                groups.push(Group {
+                    name: name.to_string(),
+                    entries: Vec::new(),
+                });
+                continue;
+            }
+
+            if let (Some(group), Some((key, value))) = (groups.last_mut(), line.split_once('=')) {
+                group
+                    .entries
+                    .push((key.trim().to_string(), value.trim().to_string()));
+            }
+        }
+
+        Self { groups }
+    }
+
+    /// Gets a value from the `[Desktop Entry]` group.
+    ///
+    /// # Arguments
+    /// * `key` - The key to look up, e.g. `Icon`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_in(DESKTOP_ENTRY_GROUP, key)
+    }
+
+    /// Gets a localized value from the `[Desktop Entry]` group, e.g. `Name[de]`.
+    /// Locale variants are tried in the order of the desktop entry specification
+    /// (`lang_COUNTRY@MODIFIER`, `lang_COUNTRY`, `lang@MODIFIER`, `lang`),
+    /// falling back to the unlocalized key.
+    ///
+    /// # Arguments
+    /// * `key` - The key to look up, e.g. `Name` or `Comment`
+    /// * `locale` - The locale, e.g. `de_DE` or `de_DE.UTF-8`
+    pub fn get_localized(&self, key: &str, locale: &str) -> Option<&str> {
+        locale_variants(locale)
+            .iter()
+            .find_map(|variant| self.get(&format!("{}[{}]", key, variant)))
+            .or_else(|| self.get(key))
+    }
+
+    /// Gets a value from the specified group.
+    ///
+    /// # Arguments
+    /// * `group` - Name of the group, without brackets
+    /// * `key` - The key to look up
+    pub fn get_in(&self, group: &str, key: &str) -> Option<&str> {
+        self.groups
+            .iter()
+            .find(|g| g.name == group)?
+            .entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Sets a value in the `[Desktop Entry]` group, replacing an existing value
+    /// or appending the key if it doesn't exist yet.
+    ///
+    /// # Arguments
+    /// * `key` - The key to set
+    /// * `value` - The new value
+    pub fn set(&mut self, key: &str, value: &str) {
+        let group = match self
+            .groups
+            .iter()
+            .position(|g| g.name == DESKTOP_ENTRY_GROUP)
+        {
+            Some(index) => &mut self.groups[index],
+            None => {
+                self.groups.insert(
+                    0,
+                    Group {
+                        name: DESKTOP_ENTRY_GROUP.to_string(),
+                        entries: Vec::new(),
+                    },
+                );
+                &mut self.groups[0]
+            }
+        };
+
+        match group.entries.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => group.entries.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// Replaces the value of the key in every group that has it, e.g. `Exec`
+    /// in `[Desktop Entry]` and every `[Desktop Action ...]` group.
+    ///
+    /// # Arguments
+    /// * `key` - The key to replace
+    /// * `replace` - Returns the new value for the old one
+    pub fn replace_all(&mut self, key: &str, mut replace: impl FnMut(&str) -> String) {
+        for group in &mut self.groups {
+            for (k, v) in &mut group.entries {
+                if k == key {
+                    *v = replace(v);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for DesktopEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, group) in self.groups.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[{}]", group.name)?;
+            for (key, value) in &group.entries {
+                writeln!(f, "{}={}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the locale variants to try for a locale, most specific first.
+/// The encoding part (e.g. `.UTF-8`) is ignored.
+///
+/// # Arguments
+/// * `locale` - The locale, e.g. `de_DE.UTF-8@euro`
+pub fn locale_variants(locale: &str) -> Vec<String> {
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale, None),
+    };
+    let locale = locale.split('.').next().unwrap_or(locale);
+    let (lang, country) = match locale.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (locale, None),
+    };
+
+    let mut variants = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        variants.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = country {
+        variants.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        variants.push(format!("{}@{}", lang, modifier));
+    }
+    if !lang.is_empty() {
+        variants.push(lang.to_string());
+    }
+    variants
+}
@@ -0,0 +1,234 @@
+//! AppImages carrying an EROFS filesystem instead of SquashFS
+
+use std::{fs::File, io::BufReader, path::Path};
+
+#[cfg(feature = "fs")]
+use crate::unpack::{UnpackOptions, UnpackReport};
+use crate::{
+    detect::{detect_filesystem_type, FilesystemType},
+    erofs::{Erofs, ErofsEntry, ErofsEntryKind},
+    error::{IoContext, SquishyError},
+    filter::PathFilter,
+};
+
+use super::Result;
+
+/// The ErofsAppImage struct is an AppImage whose runtime is followed by an
+/// EROFS filesystem. Entries are looked up, read and unpacked as with
+/// `AppImage`; the finders for desktop files, icons and appstream metadata
+/// are only available on SquashFS AppImages.
+pub struct ErofsAppImage<'a> {
+    filter: PathFilter,
+    path: &'a Path,
+    read_limit: u64,
+    pub erofs: Erofs<'a>,
+}
+
+impl<'a> ErofsAppImage<'a> {
+    /// Creates a new ErofsAppImage instance
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Include/exclude patterns to apply
+    /// * `path` - Path to AppImage
+    /// * `offset` - Offset of the EROFS filesystem, detected as by
+    ///   `detect::detect_filesystem_type` if not given
+    pub fn new<P: AsRef<Path>>(
+        filter: PathFilter,
+        path: &'a P,
+        offset: Option<u64>,
+    ) -> Result<Self> {
+        let offset = match offset {
+            Some(offset) => offset,
+            None => {
+                let file = File::open(path).context("open", path)?;
+                match detect_filesystem_type(&mut BufReader::new(file))? {
+                    (FilesystemType::Erofs, offset) => offset,
+                    _ => return Err(SquishyError::NoErofsFound),
+                }
+            }
+        };
+        Ok(ErofsAppImage {
+            filter,
+            path: path.as_ref(),
+            read_limit: u64::MAX,
+            erofs: Erofs::from_path_with_offset(path, offset)?,
+        })
+    }
+
+    /// Limits the size of files read into memory by `read_entry`. Larger
+    /// files fail with `SquishyError::FileTooLarge`. Unlimited by default.
+    ///
+    /// # Arguments
+    /// * `limit` - The largest file size to read, or None for no limit
+    pub fn set_read_limit(&mut self, limit: Option<u64>) {
+        self.read_limit = limit.unwrap_or(u64::MAX);
+    }
+
+    /// Returns the path of the AppImage file
+    pub fn path(&self) -> &Path {
+        self.path
+    }
+
+    /// Returns the offset of the EROFS filesystem within the AppImage
+    pub fn offset(&self) -> u64 {
+        self.erofs.offset()
+    }
+
+    /// Returns the entries matching the filter, sorted by path
+    pub fn entries(&self) -> impl Iterator<Item = ErofsEntry> + '_ {
+        let filter = &self.filter;
+        self.erofs
+            .entries()
+            .filter(move |entry| filter.matches(&entry.path))
+    }
+
+    /// Looks up a single entry by its path, ignoring the filter.
+    /// The leading `/` is optional, e.g. `usr/bin/foo`.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the entry within the AppImage
+    ///
+    /// # Returns
+    /// The entry, or None if there is no entry at the path
+    pub fn find<P: AsRef<Path>>(&self, path: P) -> Option<ErofsEntry> {
+        self.erofs.find(path)
+    }
+
+    /// Checks whether an entry exists at the path, ignoring the filter.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the entry within the AppImage
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.erofs.exists(path)
+    }
+
+    /// Reads the contents of the entry, following symlinks, unless it is
+    /// larger than the read limit
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to read
+    ///
+    /// # Returns
+    /// The contents of the file, or an error if the entry doesn't resolve to a file
+    pub fn read_entry(&self, entry: &ErofsEntry) -> Result<Vec<u8>> {
+        let resolved = match &entry.kind {
+            ErofsEntryKind::Symlink(target) => Some(
+                self.erofs
+                    .resolve_symlink(entry)?
+                    .ok_or_else(|| SquishyError::FileNotFound(target.clone()))?,
+            ),
+            _ => None,
+        };
+        self.erofs
+            .read_entry_with_limit(resolved.as_ref().unwrap_or(entry), self.read_limit)
+    }
+
+    /// Extracts the entries selected by the options into the destination
+    /// directory, see `Erofs::unpack_to`. The filter of the AppImage isn't
+    /// applied, select the entries with the options.
+    ///
+    /// # Arguments
+    /// * `dest` - The directory to extract into, created if missing
+    /// * `options` - Selects and maps the extracted entries
+    ///
+    /// # Returns
+    /// The report of what was done with every entry, or an error if an entry
+    /// can't be read or written and `on_error` aborts
+    #[cfg(feature = "fs")]
+    pub fn unpack_to<P: AsRef<Path>>(
+        &self,
+        dest: P,
+        options: &UnpackOptions,
+    ) -> Result<UnpackReport> {
+        self.erofs.unpack_to(dest, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+    use crate::{appimage::tests::elf_header, erofs::tests::image};
+
+    /// Writes an AppImage of a bare ELF header followed by the EROFS image at
+    /// 4 KiB
+    fn appimage(dir: &Path) -> PathBuf {
+        let mut contents = elf_header(0, 0, 0, 0);
+        contents.resize(4096, 0);
+        contents.extend_from_slice(&image());
+        let path = dir.join("test.AppImage");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn finds_and_reads_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = appimage(dir.path());
+        let mut appimage = ErofsAppImage::new(PathFilter::default(), &path, None).unwrap();
+        assert_eq!(appimage.offset(), 4096);
+        assert!(appimage.exists("hello"));
+
+        let link = appimage.find("/link").unwrap();
+        assert_eq!(appimage.read_entry(&link).unwrap(), b"hello");
+        appimage.set_read_limit(Some(4));
+        assert!(matches!(
+            appimage.read_entry(&link),
+            Err(SquishyError::FileTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn entries_are_filtered() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = appimage(dir.path());
+        let filter = PathFilter::new(Vec::<&str>::new(), ["link"]).unwrap();
+        let appimage = ErofsAppImage::new(filter, &path, None).unwrap();
+        let paths: Vec<PathBuf> = appimage.entries().map(|entry| entry.path).collect();
+        assert_eq!(paths, ["/", "/bin", "/hello"].map(PathBuf::from));
+        // lookups ignore the filter
+        assert!(appimage.exists("/link"));
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn unpacks_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = appimage(dir.path());
+        let appimage = ErofsAppImage::new(PathFilter::default(), &path, None).unwrap();
+        let dest = dir.path().join("root");
+        appimage
+            .unpack_to(&dest, &UnpackOptions::default())
+            .unwrap();
+        assert_eq!(fs::read(dest.join("hello")).unwrap(), b"hello");
+        assert_eq!(
+            fs::read_link(dest.join("link")).unwrap(),
+            Path::new("hello")
+        );
+    }
+
+    #[test]
+    fn open_detects_erofs_appimages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = appimage(dir.path());
+        let image = crate::open(&path).unwrap();
+        assert!(matches!(image, crate::Image::ErofsAppImage(_)));
+        assert_eq!(image.kind(), FilesystemType::Erofs);
+        assert_eq!(image.read_file("/hello").unwrap(), b"hello");
+    }
+
+    #[test]
+    fn squashfs_appimages_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.AppImage");
+        let mut contents = elf_header(0, 0, 0, 0);
+        contents.extend_from_slice(b"hsqs");
+        fs::write(&path, contents).unwrap();
+        assert!(matches!(
+            ErofsAppImage::new(PathFilter::default(), &path, None),
+            Err(SquishyError::NoErofsFound)
+        ));
+    }
+}
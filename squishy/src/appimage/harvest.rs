@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use super::{
+    arch::Architecture, desktop::DesktopEntry, primary, version::Version, AppImage, EntrySource,
+};
+
+/// The Harvest struct holds the assets `AppImage::harvest` collects from an
+/// AppImage.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Harvest {
+    /// Contents of the icon found by `find_icon`, not serialized
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub icon: Option<Vec<u8>>,
+    /// Path of the icon within the AppImage, symlinks resolved
+    pub icon_path: Option<PathBuf>,
+    /// How the icon was selected
+    pub icon_source: Option<EntrySource>,
+    /// Contents of the primary desktop file
+    pub desktop: Option<String>,
+    /// Contents of the primary appstream file
+    pub appstream: Option<String>,
+    /// Version detected as by `AppImage::version`
+    pub version: Option<Version>,
+    /// Architecture detected as by `AppImage::architecture`
+    pub arch: Option<Architecture>,
+    /// Update information from the runtime, see `AppImage::update_info`
+    pub update_info: Option<String>,
+}
+
+impl AppImage<'_> {
+    /// Collects the icon, desktop file, appstream file, version, architecture
+    /// and update information in one go. Unlike calling the single finders,
+    /// the desktop and appstream files are read only once.
+    /// Assets that are missing or can't be read are left out.
+    ///
+    /// # Returns
+    /// The collected assets
+    pub fn harvest(&self) -> Harvest {
+        let desktop = self.find_desktop();
+        let desktop_contents = desktop.as_ref().and_then(|found| self.read_text(found));
+        let desktop_entry = desktop_contents.as_deref().map(DesktopEntry::parse);
+
+        let appstream =
+            primary(self.find_appstreams_with(|| desktop)).and_then(|found| self.read_text(&found));
+
+        let icon = self.find_icon_with(desktop_entry.as_ref());
+        let icon_contents = icon
+            .as_ref()
+            .and_then(|found| self.read_entry(&found.entry).ok());
+
+        Harvest {
+            icon: icon_contents,
+            icon_path: icon.as_ref().map(|found| found.entry.path.clone()),
+            icon_source: icon.as_ref().map(|found| found.source),
+            version: self.version_from(desktop_entry.as_ref(), || appstream.clone()),
+            arch: self.architecture_from(|| appstream.clone()).ok(),
+            update_info: self.update_info().ok().flatten(),
+            desktop: desktop_contents,
+            appstream,
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use super::{desktop::DesktopEntry, AppImage, Result};
+use crate::error::{IoContext, SquishyError};
+
+/// Prefix of the desktop and icon names installed by `integrate`
+const INTEGRATION_PREFIX: &str = "squishy_";
+
+/// The XdgDirs struct holds the XDG base directories used for desktop integration.
+#[derive(Debug, Clone)]
+pub struct XdgDirs {
+    /// Base directory for user data files, usually `~/.local/share`
+    pub data_home: PathBuf,
+}
+
+impl XdgDirs {
+    /// Creates XdgDirs from `$XDG_DATA_HOME`, falling back to `$HOME/.local/share`.
+    ///
+    /// # Returns
+    /// The XDG directories, or None if neither variable is set
+    pub fn from_env() -> Option<Self> {
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))?;
+        Some(Self { data_home })
+    }
+
+    /// Directory for desktop files
+    pub fn applications_dir(&self) -> PathBuf {
+        self.data_home.join("applications")
+    }
+
+    /// Directory of the hicolor icon theme
+    pub fn icons_dir(&self) -> PathBuf {
+        self.data_home.join("icons/hicolor")
+    }
+}
+
+/// The Integration struct lists the files installed by `integrate`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Integration {
+    /// Path of the installed desktop file
+    pub desktop: PathBuf,
+    /// Paths of the installed icons
+    pub icons: Vec<PathBuf>,
+}
+
+impl AppImage<'_> {
+    /// Integrates the AppImage into the desktop by installing its desktop file
+    /// and icon into the XDG directories. The desktop file `Exec`, `TryExec` and
+    /// `Icon` keys are rewritten to point to the AppImage and the installed icon.
+    ///
+    /// # Arguments
+    /// * `xdg_dirs` - The XDG directories to install into
+    ///
+    /// # Returns
+    /// The installed files, or an error if there is no desktop file or writing fails
+    pub fn integrate(&self, xdg_dirs: &XdgDirs) -> Result<Integration> {
+        let desktop = self
+            .find_desktop()
+            .ok_or_else(|| SquishyError::FileNotFound(PathBuf::from("*.desktop")))?;
+        let stem = desktop
+            .entry
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let id = format!("{}{}", INTEGRATION_PREFIX, stem);
+
+        let appimage_path = fs::canonicalize(self.path).context("resolve", self.path)?;
+        let contents = self.read_entry(&desktop.entry)?;
+        let mut desktop_entry = DesktopEntry::parse(&String::from_utf8_lossy(&contents));
+
+        rewrite_execs(&mut desktop_entry, &appimage_path);
+
+        let mut icons = Vec::new();
+        if let Some(icon) = self.find_icon() {
+            let data = self.read_entry(&icon.entry)?;
+            let dest = icon_destination(xdg_dirs, &icon.entry.path, &data, &id);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent).context("create directory", parent)?;
+            }
+            fs::write(&dest, data).context("write", &dest)?;
+            desktop_entry.set("Icon", &id);
+            icons.push(dest);
+        }
+
+        let applications_dir = xdg_dirs.applications_dir();
+        fs::create_dir_all(&applications_dir).context("create directory", &applications_dir)?;
+        let desktop_path = applications_dir.join(format!("{}.desktop", id));
+        fs::write(&desktop_path, desktop_entry.to_string()).context("write", &desktop_path)?;
+
+        Ok(Integration {
+            desktop: desktop_path,
+            icons,
+        })
+    }
+}
+
+/// Points `Exec` and `TryExec` of the main group and of every action group to
+/// the AppImage, adding `Exec` to the main group if it has none
+fn rewrite_execs(desktop_entry: &mut DesktopEntry, appimage_path: &Path) {
+    desktop_entry.replace_all("Exec", |exec| rewrite_exec(exec, appimage_path));
+    if desktop_entry.get("Exec").is_none() {
+        desktop_entry.set("Exec", &quote_exec_arg(appimage_path));
+    }
+    let try_exec = appimage_path.to_string_lossy();
+    desktop_entry.replace_all("TryExec", |_| try_exec.to_string());
+    desktop_entry.set("TryExec", &try_exec);
+}
+
+/// Replaces the program of an `Exec` value with the AppImage path, keeping the arguments
+fn rewrite_exec(exec: &str, appimage_path: &Path) -> String {
+    let exec = exec.trim();
+    let args = if let Some(rest) = exec.strip_prefix('"') {
+        rest.split_once('"').map(|(_, args)| args).unwrap_or("")
+    } else {
+        exec.split_once(char::is_whitespace)
+            .map(|(_, args)| args)
+            .unwrap_or("")
+    };
+
+    let program = quote_exec_arg(appimage_path);
+    match args.trim() {
+        "" => program,
+        args => format!("{} {}", program, args),
+    }
+}
+
+/// Quotes a path for use in an `Exec` value
+fn quote_exec_arg(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let escaped = path
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('`', "\\`")
+        .replace('$', "\\$");
+    format!("\"{}\"", escaped)
+}
+
+/// Determines where to install the icon in the hicolor theme
+fn icon_destination(xdg_dirs: &XdgDirs, icon_path: &Path, data: &[u8], id: &str) -> PathBuf {
+    let extension = icon_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| "png".to_string());
+
+    let size_dir = match extension.as_str() {
+        "svg" | "svgz" => "scalable".to_string(),
+        _ => icon_path
+            .components()
+            .filter_map(|c| c.as_os_str().to_str())
+            .find(|c| is_size_dir(c))
+            .map(str::to_string)
+            .or_else(|| png_size(data).map(|(w, h)| format!("{}x{}", w, h)))
+            .unwrap_or_else(|| "256x256".to_string()),
+    };
+
+    xdg_dirs
+        .icons_dir()
+        .join(size_dir)
+        .join("apps")
+        .join(format!("{}.{}", id, extension))
+}
+
+/// Checks whether the path component is an icon size directory like `48x48`
+fn is_size_dir(component: &str) -> bool {
+    component.split_once('x').is_some_and(|(w, h)| {
+        !w.is_empty()
+            && w.chars().all(|c| c.is_ascii_digit())
+            && w == h.split('@').next().unwrap_or("")
+    })
+}
+
+/// Reads width and height from the PNG IHDR chunk
+fn png_size(data: &[u8]) -> Option<(u32, u32)> {
+    if data.len() < 24 || &data[..8] != b"\x89PNG\r\n\x1a\n" {
+        return None;
+    }
+    let width = u32::from_be_bytes(data[16..20].try_into().ok()?);
+    let height = u32::from_be_bytes(data[20..24].try_into().ok()?);
+    Some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn execs_of_actions_are_rewritten() {
+        let mut desktop_entry = DesktopEntry::parse(
+            "[Desktop Entry]\nName=App\nExec=app %U\nTryExec=app\nActions=new;\n\n\
+             [Desktop Action new]\nName=New Window\nExec=\"/usr/bin/app\" --new-window\n",
+        );
+        rewrite_execs(&mut desktop_entry, Path::new("/opt/My App.AppImage"));
+
+        assert_eq!(
+            desktop_entry.get("Exec"),
+            Some("\"/opt/My App.AppImage\" %U")
+        );
+        assert_eq!(desktop_entry.get("TryExec"), Some("/opt/My App.AppImage"));
+        assert_eq!(
+            desktop_entry.get_in("Desktop Action new", "Exec"),
+            Some("\"/opt/My App.AppImage\" --new-window")
+        );
+        assert_eq!(
+            desktop_entry.get_in("Desktop Action new", "Name"),
+            Some("New Window")
+        );
+    }
+
+    #[test]
+    fn missing_exec_is_added() {
+        let mut desktop_entry = DesktopEntry::parse("[Desktop Entry]\nName=App\n");
+        rewrite_execs(&mut desktop_entry, Path::new("/opt/app.AppImage"));
+
+        assert_eq!(desktop_entry.get("Exec"), Some("\"/opt/app.AppImage\""));
+        assert_eq!(desktop_entry.get("TryExec"), Some("/opt/app.AppImage"));
+    }
+}
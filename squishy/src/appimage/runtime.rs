@@ -0,0 +1,108 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{Read, Write},
+};
+
+use goblin::elf::Elf;
+
+use super::{AppImage, Result};
+use crate::error::{IoContext, SquishyError};
+
+/// Runtime section holding the update information, e.g. `zsync|https://...`
+pub const UPDATE_INFO_SECTION: &str = ".upd_info";
+/// Runtime section holding the detached signature of the AppImage digest
+pub const SIGNATURE_SECTION: &str = ".sha256_sig";
+/// Runtime section holding the public key matching the signature
+pub const SIGNING_KEY_SECTION: &str = ".sig_key";
+
+/// The RuntimeKind enum distinguishes the AppImage runtime flavours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum RuntimeKind {
+    /// Statically linked runtime (type2-runtime), doesn't need libfuse on the host
+    Static,
+    /// Dynamically linked legacy runtime (AppImageKit), needs libfuse2 on the host
+    Legacy,
+}
+
+impl fmt::Display for RuntimeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeKind::Static => write!(f, "static"),
+            RuntimeKind::Legacy => write!(f, "legacy"),
+        }
+    }
+}
+
+impl AppImage<'_> {
+    /// Reads the runtime ELF, i.e. all bytes before the filesystem offset
+    ///
+    /// # Returns
+    /// The runtime bytes, or an error if the AppImage can't be read
+    pub fn runtime_bytes(&self) -> Result<Vec<u8>> {
+        let file = File::open(self.path).context("open", self.path)?;
+        let mut runtime = Vec::with_capacity(self.offset as usize);
+        file.take(self.offset).read_to_end(&mut runtime)?;
+        Ok(runtime)
+    }
+
+    /// Writes the runtime ELF to the writer
+    ///
+    /// # Arguments
+    /// * `writer` - The writer to copy the runtime to
+    ///
+    /// # Returns
+    /// The number of bytes written, or an error if reading or writing fails
+    pub fn write_runtime<W: Write>(&self, writer: &mut W) -> Result<u64> {
+        let file = File::open(self.path).context("open", self.path)?;
+        Ok(std::io::copy(&mut file.take(self.offset), writer)?)
+    }
+
+    /// Detects whether the runtime is the static or the legacy (dynamically linked) runtime
+    ///
+    /// # Returns
+    /// The runtime kind, or an error if the runtime is not a valid ELF
+    pub fn runtime_kind(&self) -> Result<RuntimeKind> {
+        let runtime = self.runtime_bytes()?;
+        let elf = Elf::parse(&runtime)
+            .map_err(|e| SquishyError::InvalidSquashFS(format!("Invalid runtime ELF: {}", e)))?;
+
+        if elf.interpreter.is_none() && elf.dynamic.is_none() {
+            Ok(RuntimeKind::Static)
+        } else {
+            Ok(RuntimeKind::Legacy)
+        }
+    }
+
+    /// Reads the update information from the `.upd_info` section of the
+    /// runtime, e.g. `zsync|https://example.com/App.AppImage.zsync`
+    ///
+    /// # Returns
+    /// The update information, None if the section is missing or empty, or an
+    /// error if the runtime is not a valid ELF
+    pub fn update_info(&self) -> Result<Option<String>> {
+        let runtime = self.runtime_bytes()?;
+        let elf = Elf::parse(&runtime)
+            .map_err(|e| SquishyError::InvalidSquashFS(format!("Invalid runtime ELF: {}", e)))?;
+        let Some(header) = elf
+            .section_headers
+            .iter()
+            .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(UPDATE_INFO_SECTION))
+        else {
+            return Ok(None);
+        };
+
+        let start = header.sh_offset as usize;
+        let data = runtime
+            .get(start..start + header.sh_size as usize)
+            .unwrap_or_default();
+        let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        let info = String::from_utf8_lossy(&data[..end]).trim().to_string();
+        Ok((!info.is_empty()).then_some(info))
+    }
+}
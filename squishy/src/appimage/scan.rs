@@ -0,0 +1,138 @@
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::{arch::Architecture, desktop::DesktopEntry, version::Version, AppImage, Result};
+use crate::{error::IoContext, filter::PathFilter};
+
+/// Magic bytes of type 2 AppImages, stored at offset 8 of the ELF header
+const APPIMAGE_MAGIC: [u8; 3] = [b'A', b'I', 0x02];
+
+/// The AppImageSummary struct holds the metadata collected for a single AppImage
+/// by `scan_directory`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AppImageSummary {
+    /// Path of the AppImage file
+    pub path: PathBuf,
+    /// Name from the desktop file, or the file stem if there is none
+    pub name: String,
+    /// Contents of the icon found by `find_icon`, not serialized
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub icon: Option<Vec<u8>>,
+    /// Contents of the desktop file
+    pub desktop: Option<String>,
+    /// Version detected by `AppImage::version`
+    pub version: Option<Version>,
+    /// Detected architecture
+    pub arch: Option<Architecture>,
+}
+
+impl AppImageSummary {
+    /// Collects the summary for a single AppImage.
+    ///
+    /// # Arguments
+    /// * `path` - Path to the AppImage
+    ///
+    /// # Returns
+    /// The summary, or an error if the AppImage can't be opened
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let appimage = AppImage::new(PathFilter::default(), &path, None)?;
+
+        let harvest = appimage.harvest();
+        let desktop_entry = harvest.desktop.as_deref().map(DesktopEntry::parse);
+
+        let name = desktop_entry
+            .as_ref()
+            .and_then(|entry| entry.get("Name"))
+            .map(str::to_string)
+            .or_else(|| {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            path: path.to_path_buf(),
+            name,
+            icon: harvest.icon,
+            desktop: harvest.desktop,
+            version: harvest.version,
+            arch: harvest.arch,
+        })
+    }
+}
+
+/// Checks whether the file is a type 2 AppImage by its magic bytes,
+/// or by the `.AppImage` extension.
+///
+/// # Arguments
+/// * `path` - Path to the file
+pub fn is_appimage<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("appimage"))
+    {
+        return true;
+    }
+
+    let mut header = [0u8; 11];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok()
+        && header[8..] == APPIMAGE_MAGIC
+}
+
+/// Lists the AppImage files in the directory (not recursive)
+fn appimage_paths(dir: &Path) -> impl Iterator<Item = Result<PathBuf>> {
+    let (entries, error) = match fs::read_dir(dir) {
+        Ok(entries) => (Some(entries), None),
+        Err(e) => (None, Some(Err(e).context("read directory", dir))),
+    };
+    let dir = dir.to_path_buf();
+
+    error.into_iter().chain(
+        entries
+            .into_iter()
+            .flatten()
+            .filter_map(move |entry| match entry {
+                Ok(entry) => {
+                    let path = entry.path();
+                    (path.is_file() && is_appimage(&path)).then_some(Ok(path))
+                }
+                Err(e) => Some(Err(e).context("read directory", &dir)),
+            }),
+    )
+}
+
+/// Scans a directory for AppImages and collects a summary for each of them.
+///
+/// # Arguments
+/// * `dir` - The directory to scan (not recursive)
+///
+/// # Returns
+/// An iterator over the summaries, with an error for each file that couldn't be processed
+pub fn scan_directory<P: AsRef<Path>>(dir: P) -> impl Iterator<Item = Result<AppImageSummary>> {
+    appimage_paths(dir.as_ref()).map(|path| path.and_then(AppImageSummary::from_path))
+}
+
+/// Scans a directory for AppImages and collects a summary for each of them in parallel.
+///
+/// # Arguments
+/// * `dir` - The directory to scan (not recursive)
+///
+/// # Returns
+/// A parallel iterator over the summaries, with an error for each file that couldn't be processed
+pub fn par_scan_directory<P: AsRef<Path>>(
+    dir: P,
+) -> impl ParallelIterator<Item = Result<AppImageSummary>> {
+    appimage_paths(dir.as_ref())
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|path| path.and_then(AppImageSummary::from_path))
+}
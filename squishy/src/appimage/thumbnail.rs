@@ -0,0 +1,73 @@
+use std::io::Cursor;
+
+use image::{imageops::FilterType, ImageFormat};
+use resvg::{tiny_skia, usvg};
+
+use super::{AppImage, Result};
+use crate::error::SquishyError;
+
+impl AppImage<'_> {
+    /// Renders the icon found by `find_icon` as a PNG of the requested size.
+    /// SVG icons are rasterized, raster icons are rescaled. The aspect ratio is
+    /// preserved, so the longer side of the result equals `size`.
+    ///
+    /// # Arguments
+    /// * `size` - Size of the longer side of the thumbnail in pixels
+    ///
+    /// # Returns
+    /// The PNG bytes, None if no icon is found, or an error if the icon can't be decoded
+    pub fn render_icon_png(&self, size: u32) -> Result<Option<Vec<u8>>> {
+        let Some(icon) = self.find_icon() else {
+            return Ok(None);
+        };
+        let data = self.read_entry(&icon.entry)?;
+
+        let is_svg = icon
+            .entry
+            .path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"))
+            || data.trim_ascii_start().starts_with(b"<");
+
+        if is_svg {
+            render_svg(&data, size).map(Some)
+        } else {
+            render_raster(&data, size).map(Some)
+        }
+    }
+}
+
+/// Rasterizes SVG data to a PNG
+fn render_svg(data: &[u8], size: u32) -> Result<Vec<u8>> {
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default())
+        .map_err(|e| SquishyError::Image(e.to_string()))?;
+
+    let svg_size = tree.size();
+    let scale = size as f32 / svg_size.width().max(svg_size.height());
+    let width = ((svg_size.width() * scale).round() as u32).max(1);
+    let height = ((svg_size.height() * scale).round() as u32).max(1);
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| SquishyError::Image(format!("Invalid size {}x{}", width, height)))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    pixmap
+        .encode_png()
+        .map_err(|e| SquishyError::Image(e.to_string()))
+}
+
+/// Rescales raster image data to a PNG
+fn render_raster(data: &[u8], size: u32) -> Result<Vec<u8>> {
+    let image = image::load_from_memory(data).map_err(|e| SquishyError::Image(e.to_string()))?;
+    let image = image.resize(size, size, FilterType::Lanczos3);
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| SquishyError::Image(e.to_string()))?;
+    Ok(png)
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+
+use super::{desktop::DesktopEntry, AppImage};
+
+/// The VersionSource enum describes where the version of an AppImage was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum VersionSource {
+    /// `X-AppImage-Version` key of the desktop file
+    Desktop,
+    /// First `<release>` of the appstream file
+    #[cfg_attr(feature = "serde", serde(rename = "appstream"))]
+    AppStream,
+    /// Version-like part of the AppImage file name
+    Filename,
+}
+
+impl fmt::Display for VersionSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionSource::Desktop => write!(f, "desktop file"),
+            VersionSource::AppStream => write!(f, "appstream"),
+            VersionSource::Filename => write!(f, "filename"),
+        }
+    }
+}
+
+/// The Version struct holds the detected version of an AppImage and its source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Version {
+    pub value: String,
+    pub source: VersionSource,
+}
+
+impl AppImage<'_> {
+    /// Detects the application version. It looks for the version in order:
+    /// - `X-AppImage-Version` key of the desktop file
+    /// - First `<release version="...">` of the appstream file
+    /// - Version-like part of the file name, e.g. `App-1.2.3-x86_64.AppImage`
+    ///
+    /// # Returns
+    /// The version and where it was found, if any
+    pub fn version(&self) -> Option<Version> {
+        self.version_from(self.desktop_entry().as_ref(), || self.appstream_contents())
+    }
+
+    /// Detects the application version from the already parsed desktop file,
+    /// reading the appstream file with `appstream` only if needed, see `version`
+    pub(crate) fn version_from(
+        &self,
+        desktop: Option<&DesktopEntry>,
+        appstream: impl FnOnce() -> Option<String>,
+    ) -> Option<Version> {
+        let from_desktop = || {
+            desktop?
+                .get("X-AppImage-Version")
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+        let from_appstream = || appstream_release_version(&appstream()?);
+        let from_filename = || filename_version(&self.path.file_stem()?.to_string_lossy());
+
+        from_desktop()
+            .map(|value| (value, VersionSource::Desktop))
+            .or_else(|| from_appstream().map(|value| (value, VersionSource::AppStream)))
+            .or_else(|| from_filename().map(|value| (value, VersionSource::Filename)))
+            .map(|(value, source)| Version { value, source })
+    }
+}
+
+/// Extracts the version attribute of the first `<release>` tag
+fn appstream_release_version(contents: &str) -> Option<String> {
+    let start = contents.find("<release ")?;
+    let end = start + contents[start..].find('>')?;
+    let tag = &contents[start..end];
+
+    let attr = tag.find("version=")? + "version=".len();
+    let quote = tag[attr..]
+        .chars()
+        .next()
+        .filter(|c| *c == '"' || *c == '\'')?;
+    let value = &tag[attr + 1..];
+    let value = &value[..value.find(quote)?];
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Finds a version-like part (digits with dots, optionally prefixed by `v`)
+/// in a file name split on `-` and `_`
+fn filename_version(stem: &str) -> Option<String> {
+    stem.split(['-', '_'])
+        .map(|part| part.strip_prefix(['v', 'V']).unwrap_or(part))
+        .find(|part| {
+            part.starts_with(|c: char| c.is_ascii_digit())
+                && part.contains('.')
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '+')
+        })
+        .map(str::to_string)
+}
@@ -0,0 +1,110 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+};
+
+use md4::{Digest, Md4};
+
+use super::{AppImage, Result};
+use crate::error::IoContext;
+
+/// The Chunk struct holds the zsync checksums of a single block of the AppImage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Chunk {
+    /// Index of the block
+    pub index: u64,
+    /// Offset of the block in the file
+    pub offset: u64,
+    /// Number of bytes of the file in this block. Only the last block can be
+    /// shorter than the block size, it is zero padded for the checksums.
+    pub len: usize,
+    /// zsync rolling checksum, `a` in the high and `b` in the low 16 bits
+    pub rsum: u32,
+    /// MD4 strong checksum
+    pub checksum: [u8; 16],
+}
+
+/// Computes the zsync rolling checksum of a block
+///
+/// # Arguments
+/// * `block` - The block data
+///
+/// # Returns
+/// The checksum with `a` in the high and `b` in the low 16 bits
+pub fn rsum(block: &[u8]) -> u32 {
+    let len = block.len();
+    let (a, b) = block
+        .iter()
+        .enumerate()
+        .fold((0u16, 0u16), |(a, b), (i, &byte)| {
+            (
+                a.wrapping_add(byte as u16),
+                b.wrapping_add(((len - i) as u16).wrapping_mul(byte as u16)),
+            )
+        });
+    ((a as u32) << 16) | b as u32
+}
+
+/// The Chunks struct is an iterator over the zsync checksums of an AppImage.
+pub struct Chunks {
+    reader: BufReader<File>,
+    block_size: usize,
+    index: u64,
+    buf: Vec<u8>,
+}
+
+impl Iterator for Chunks {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buf.clear();
+        let read = match (&mut self.reader)
+            .take(self.block_size as u64)
+            .read_to_end(&mut self.buf)
+        {
+            Ok(0) => return None,
+            Ok(read) => read,
+            Err(e) => return Some(Err(e.into())),
+        };
+        self.buf.resize(self.block_size, 0);
+
+        let chunk = Chunk {
+            index: self.index,
+            offset: self.index * self.block_size as u64,
+            len: read,
+            rsum: rsum(&self.buf),
+            checksum: Md4::digest(&self.buf).into(),
+        };
+        self.index += 1;
+        Some(Ok(chunk))
+    }
+}
+
+impl AppImage<'_> {
+    /// Splits the AppImage file into blocks and computes the zsync rolling and
+    /// strong checksums of each block, as needed for a zsync index.
+    ///
+    /// # Arguments
+    /// * `block_size` - Size of the blocks, zsync uses a power of two like 2048
+    ///
+    /// # Returns
+    /// An iterator over the chunks, or an error if the file can't be opened
+    /// or the block size is zero
+    pub fn chunks(&self, block_size: usize) -> Result<Chunks> {
+        if block_size == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "block size must be non-zero",
+            )
+            .into());
+        }
+
+        Ok(Chunks {
+            reader: BufReader::new(File::open(self.path).context("open", self.path)?),
+            block_size,
+            index: 0,
+            buf: Vec::with_capacity(block_size),
+        })
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    ffi::OsStr,
+    io::{self, Write},
+    os::unix::ffi::OsStrExt,
+    path::Path,
+};
+
+use backhand::InnerNode;
+use tar::{Builder, EntryType, Header};
+
+#[cfg(feature = "erofs")]
+use crate::erofs::{Erofs, ErofsEntryKind};
+use crate::{Result, SquashFS};
+
+/// Entries of tar archives are padded to blocks of this size
+const BLOCK_SIZE: u64 = 512;
+
+/// Longest name and link target of ustar headers
+const NAME_SIZE: usize = 100;
+
+#[cfg(feature = "erofs")]
+const S_IFIFO: u32 = 0o010000;
+#[cfg(feature = "erofs")]
+const S_IFCHR: u32 = 0o020000;
+#[cfg(feature = "erofs")]
+const S_IFBLK: u32 = 0o060000;
+
+/// Creates a ustar header with the metadata of an entry
+fn header(kind: EntryType, mode: u32, uid: u32, gid: u32, mtime: u64) -> Header {
+    let mut header = Header::new_ustar();
+    header.set_entry_type(kind);
+    header.set_mode(mode & 0o7777);
+    header.set_uid(u64::from(uid));
+    header.set_gid(u64::from(gid));
+    header.set_mtime(mtime);
+    header.set_size(0);
+    header
+}
+
+/// Sets the major and minor number of a device node, from the Linux
+/// encoding used by both SquashFS and EROFS
+fn set_device(header: &mut Header, device: u32) -> io::Result<()> {
+    header.set_device_major((device >> 8) & 0xfff)?;
+    header.set_device_minor((device & 0xff) | ((device >> 12) & 0xfff00))
+}
+
+/// Encodes a PAX extended header record, `<length> <key>=<value>\n`, where
+/// the length counts its own digits
+fn pax_record(key: &str, value: &[u8]) -> Vec<u8> {
+    let rest = key.len() + value.len() + 3;
+    let mut length = rest;
+    while rest + length.to_string().len() != length {
+        length = rest + length.to_string().len();
+    }
+
+    let mut record = format!("{length} {key}=").into_bytes();
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
+/// Appends the header of an entry, without its data. Paths and link targets
+/// too long for the ustar fields are stored in a PAX extended header before
+/// it, and cut short in the header itself.
+fn append_header<W: Write>(
+    builder: &mut Builder<W>,
+    header: &mut Header,
+    path: &Path,
+    link: Option<&OsStr>,
+) -> io::Result<()> {
+    let mut records = Vec::new();
+    if header.set_path(path).is_err() {
+        records.extend(pax_record("path", path.as_os_str().as_bytes()));
+        let name = path.file_name().unwrap_or_default().as_bytes();
+        header.set_path(OsStr::from_bytes(&name[..name.len().min(NAME_SIZE)]))?;
+    }
+    if let Some(link) = link {
+        let link = link.as_bytes();
+        if header.set_link_name_literal(link).is_err() {
+            records.extend(pax_record("linkpath", link));
+            header.set_link_name_literal(&link[..NAME_SIZE])?;
+        }
+    }
+
+    if !records.is_empty() {
+        let mut extended = Header::new_ustar();
+        extended.set_entry_type(EntryType::XHeader);
+        extended.set_path("PaxHeaders")?;
+        extended.set_mode(0o644);
+        extended.set_mtime(header.mtime()?);
+        extended.set_size(records.len() as u64);
+        extended.set_cksum();
+        builder.append(&extended, records.as_slice())?;
+    }
+
+    // without data, only the header is written
+    header.set_cksum();
+    builder.append(header, io::empty())
+}
+
+/// Pads the data of a file written after its header to a whole block,
+/// checking that it has the size stated in the header
+fn finish_data<W: Write>(builder: &mut Builder<W>, copied: u64, size: u64) -> io::Result<()> {
+    if copied != size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "file data shorter than its size",
+        ));
+    }
+    let padding = (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE;
+    builder
+        .get_mut()
+        .write_all(&[0; BLOCK_SIZE as usize][..padding as usize])
+}
+
+impl SquashFS<'_> {
+    /// Writes all the entries as a POSIX (ustar) tar archive to the writer,
+    /// keeping modes, owners, modification times, symlink targets and device
+    /// nodes. Paths are relative to the root of the image and files are
+    /// streamed a block at a time. Sockets can't be stored in tar archives
+    /// and are left out.
+    ///
+    /// # Arguments
+    /// * `writer` - The writer to write the archive to.
+    ///
+    /// # Returns
+    /// An empty result, or an error if an entry cannot be read or written.
+    pub fn write_tar<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        let mut builder = Builder::new(writer);
+        for node in self.reader.files() {
+            // the root itself isn't stored
+            let path = node.fullpath.strip_prefix("/").unwrap_or(&node.fullpath);
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+
+            let mut header = header(
+                EntryType::Regular,
+                u32::from(node.header.permissions),
+                node.header.uid,
+                node.header.gid,
+                u64::from(node.header.mtime),
+            );
+            match &node.inner {
+                InnerNode::File(file) => {
+                    let size = u64::from(file.basic.file_size);
+                    header.set_size(size);
+                    append_header(&mut builder, &mut header, path, None)?;
+                    let copied = self.copy_blocks(&file.basic, builder.get_mut())?;
+                    finish_data(&mut builder, copied, size)?;
+                }
+                InnerNode::Dir(_) => {
+                    header.set_entry_type(EntryType::Directory);
+                    append_header(&mut builder, &mut header, path, None)?;
+                }
+                InnerNode::Symlink(symlink) => {
+                    header.set_entry_type(EntryType::Symlink);
+                    append_header(
+                        &mut builder,
+                        &mut header,
+                        path,
+                        Some(symlink.link.as_os_str()),
+                    )?;
+                }
+                InnerNode::CharacterDevice(device) => {
+                    header.set_entry_type(EntryType::Char);
+                    set_device(&mut header, device.device_number)?;
+                    append_header(&mut builder, &mut header, path, None)?;
+                }
+                InnerNode::BlockDevice(device) => {
+                    header.set_entry_type(EntryType::Block);
+                    set_device(&mut header, device.device_number)?;
+                    append_header(&mut builder, &mut header, path, None)?;
+                }
+                InnerNode::NamedPipe => {
+                    header.set_entry_type(EntryType::Fifo);
+                    append_header(&mut builder, &mut header, path, None)?;
+                }
+                InnerNode::Socket => {}
+            }
+        }
+        builder.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "erofs")]
+impl Erofs<'_> {
+    /// Writes all the entries as a POSIX (ustar) tar archive to the writer,
+    /// like `SquashFS::write_tar`.
+    ///
+    /// # Arguments
+    /// * `writer` - The writer to write the archive to.
+    ///
+    /// # Returns
+    /// An empty result, or an error if an entry cannot be read or written.
+    pub fn write_tar<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        let mut builder = Builder::new(writer);
+        for entry in self.entries() {
+            let path = entry.path.strip_prefix("/").unwrap_or(&entry.path);
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+
+            let mut header = header(
+                EntryType::Regular,
+                entry.mode(),
+                entry.uid(),
+                entry.gid(),
+                entry.mtime(),
+            );
+            match &entry.kind {
+                ErofsEntryKind::File => {
+                    header.set_size(entry.size);
+                    append_header(&mut builder, &mut header, path, None)?;
+                    let copied = self.copy_entry_to(&entry, builder.get_mut())?;
+                    finish_data(&mut builder, copied, entry.size)?;
+                }
+                ErofsEntryKind::Directory => {
+                    header.set_entry_type(EntryType::Directory);
+                    append_header(&mut builder, &mut header, path, None)?;
+                }
+                ErofsEntryKind::Symlink(target) => {
+                    // without the `/` prepended to the stored target
+                    let target = target.as_os_str().as_bytes();
+                    let target = OsStr::from_bytes(target.strip_prefix(b"/").unwrap_or(target));
+                    header.set_entry_type(EntryType::Symlink);
+                    append_header(&mut builder, &mut header, path, Some(target))?;
+                }
+                ErofsEntryKind::Unknown => {
+                    let kind = match entry.file_type() {
+                        S_IFCHR => EntryType::Char,
+                        S_IFBLK => EntryType::Block,
+                        S_IFIFO => EntryType::Fifo,
+                        // sockets
+                        _ => continue,
+                    };
+                    header.set_entry_type(kind);
+                    if kind != EntryType::Fifo {
+                        set_device(&mut header, entry.rdev())?;
+                    }
+                    append_header(&mut builder, &mut header, path, None)?;
+                }
+            }
+        }
+        builder.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "fs", feature = "erofs"))]
+    use std::{io::Read, path::PathBuf};
+
+    #[cfg(any(feature = "fs", feature = "erofs"))]
+    use tar::Archive;
+
+    use super::*;
+
+    /// An entry read back from an archive
+    #[cfg(any(feature = "fs", feature = "erofs"))]
+    #[derive(Debug, PartialEq, Eq)]
+    struct Entry {
+        path: PathBuf,
+        kind: EntryType,
+        mode: u32,
+        link: Option<PathBuf>,
+        data: Vec<u8>,
+    }
+
+    #[cfg(any(feature = "fs", feature = "erofs"))]
+    fn read_tar(archive: &[u8]) -> Vec<Entry> {
+        Archive::new(archive)
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).unwrap();
+                Entry {
+                    path: entry.path().unwrap().into_owned(),
+                    kind: entry.header().entry_type(),
+                    mode: entry.header().mode().unwrap(),
+                    link: entry.link_name().unwrap().map(|link| link.into_owned()),
+                    data,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pax_records_count_their_own_length() {
+        assert_eq!(pax_record("path", b"a"), b"9 path=a\n");
+        // 98 bytes without the length, whose 2 digits would make it 100
+        let record = pax_record("path", &[b'a'; 91]);
+        assert_eq!(record.len(), 101);
+        assert!(record.starts_with(b"101 path=a"));
+    }
+
+    #[test]
+    fn devices_use_the_linux_encoding() {
+        let mut header = header(EntryType::Char, 0o620, 0, 5, 0);
+        set_device(&mut header, (4 << 8) | 0x01 | (0x12300 << 12)).unwrap();
+        assert_eq!(header.device_major().unwrap(), Some(4));
+        assert_eq!(header.device_minor().unwrap(), Some(0x12301));
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn squashfs_entries_modes_and_symlinks_are_kept() {
+        use crate::testing::{image, Node};
+
+        let long = format!("{}/file", "d".repeat(120));
+        let data = image(&[
+            Node::File("bin/tool", b"#!/bin/sh\n"),
+            Node::Symlink("tool", "bin/tool"),
+            Node::File(&long, b"long"),
+        ]);
+        let mut squashfs = SquashFS::from_bytes(&data, None).unwrap();
+        for node in squashfs.reader.root.nodes.iter_mut() {
+            if node.fullpath == Path::new("/bin/tool") {
+                node.header.permissions = 0o4755;
+            }
+        }
+
+        let mut archive = Vec::new();
+        squashfs.write_tar(&mut archive).unwrap();
+        let entries = read_tar(&archive);
+        let find = |path: &str| {
+            entries
+                .iter()
+                .find(|entry| entry.path == Path::new(path))
+                .unwrap()
+        };
+
+        assert!(entries.iter().all(|entry| entry.path.is_relative()));
+        assert_eq!(find("bin").kind, EntryType::Directory);
+        let tool = find("bin/tool");
+        assert_eq!((tool.kind, tool.mode), (EntryType::Regular, 0o4755));
+        assert_eq!(tool.data, b"#!/bin/sh\n");
+        let link = find("tool");
+        assert_eq!(link.kind, EntryType::Symlink);
+        assert_eq!(link.link.as_deref(), Some(Path::new("bin/tool")));
+        assert_eq!(find(&long).data, b"long");
+    }
+
+    #[cfg(feature = "erofs")]
+    #[test]
+    fn erofs_entries_modes_and_symlinks_are_kept() {
+        let data = crate::erofs::tests::image();
+        let erofs = Erofs::from_bytes(&data, None).unwrap();
+        let mut archive = Vec::new();
+        erofs.write_tar(&mut archive).unwrap();
+
+        let entry = |path: &str, kind, mode, link: Option<&str>, data: &[u8]| Entry {
+            path: PathBuf::from(path),
+            kind,
+            mode,
+            link: link.map(PathBuf::from),
+            data: data.to_vec(),
+        };
+        assert_eq!(
+            read_tar(&archive),
+            [
+                entry("bin", EntryType::Directory, 0o700, None, b""),
+                entry("hello", EntryType::Regular, 0o644, None, b"hello"),
+                entry("link", EntryType::Symlink, 0o777, Some("hello"), b""),
+            ]
+        );
+
+        let mut archive = Archive::new(archive.as_slice());
+        let bin = archive.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(bin.header().uid().unwrap(), 70_000);
+        assert_eq!(bin.header().gid().unwrap(), 80_000);
+        assert_eq!(bin.header().mtime().unwrap(), 42);
+    }
+}
@@ -0,0 +1,232 @@
+use std::{fs::File, io, os::unix::fs::FileExt};
+
+use backhand::{BasicFile, DataSize};
+
+use crate::{decompress, SquashFS};
+
+pub(crate) fn corrupt(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A data block of a file: where it's stored in the image, its stored size and
+/// its size when decompressed
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Block {
+    pub(crate) start: u64,
+    pub(crate) size: DataSize,
+    pub(crate) len: usize,
+}
+
+impl SquashFS<'_> {
+    /// Returns the data blocks of the file, without the tail in a fragment
+    pub(crate) fn blocks(&self, file: &BasicFile) -> Vec<Block> {
+        let block_size = u64::from(self.reader.block_size);
+        let file_size = u64::from(file.file_size);
+
+        // the blocks are stored back to back from blocks_start
+        let mut position = self.offset + u64::from(file.blocks_start);
+        file.block_sizes
+            .iter()
+            .enumerate()
+            .map(|(index, &size)| {
+                let start = position;
+                position += u64::from(size.size());
+                let len = (file_size.saturating_sub(index as u64 * block_size)).min(block_size);
+                Block {
+                    start,
+                    size,
+                    len: len as usize,
+                }
+            })
+            .collect()
+    }
+
+    /// Reads and decompresses a single block, of `len` bytes when decompressed
+    /// unless it's a fragment. Blocks stored with size 0 are sparse.
+    pub(crate) fn read_block(
+        &self,
+        image: &File,
+        start: u64,
+        size: DataSize,
+        len: usize,
+    ) -> io::Result<Vec<u8>> {
+        if size.size() == 0 {
+            return Ok(vec![0; len]);
+        }
+        let mut raw = vec![0; size.size() as usize];
+        image.read_exact_at(&mut raw, start)?;
+        if size.uncompressed() {
+            return Ok(raw);
+        }
+
+        let mut block = Vec::with_capacity(self.reader.block_size as usize);
+        decompress::decompress(&raw, &mut block, self.reader.compressor)
+            .map_err(|e| corrupt(&e.to_string()))?;
+        Ok(block)
+    }
+
+    /// Reads and decompresses the fragment holding the tail of the file that
+    /// is shorter than a block. The tail starts at `block_offset`.
+    pub(crate) fn read_fragment(&self, image: &File, file: &BasicFile) -> io::Result<Vec<u8>> {
+        let fragment = self
+            .reader
+            .fragments
+            .as_ref()
+            .and_then(|fragments| fragments.get(file.frag_index as usize))
+            .ok_or_else(|| corrupt("missing fragment"))?;
+        self.read_block(
+            image,
+            self.offset + fragment.start,
+            fragment.size,
+            self.reader.block_size as usize,
+        )
+    }
+}
+
+/// The BlockReader struct reads ranges of a file a block at a time from the
+/// image file, decompressing only the blocks a read covers and keeping the
+/// last one for the next read. It doesn't borrow the image, so it can be kept
+/// per open file.
+#[cfg(feature = "fuse")]
+pub(crate) struct BlockReader {
+    file: BasicFile,
+    blocks: Vec<Block>,
+    /// The last block read by index, the fragment being the one past the blocks
+    cached: Option<(usize, Vec<u8>)>,
+}
+
+#[cfg(feature = "fuse")]
+impl BlockReader {
+    pub(crate) fn new(squashfs: &SquashFS, file: &BasicFile) -> Self {
+        Self {
+            blocks: squashfs.blocks(file),
+            file: file.clone(),
+            cached: None,
+        }
+    }
+
+    /// Reads up to `buf.len()` bytes at the offset, fewer only at the end of
+    /// the file
+    ///
+    /// # Arguments
+    /// * `squashfs` - The image the file was opened from
+    /// * `image` - The image file the SquashFS was opened from
+    /// * `offset` - Position in the file to read at
+    /// * `buf` - Buffer to read into
+    ///
+    /// # Returns
+    /// The number of bytes read, or an error if a block can't be read
+    pub(crate) fn read_at(
+        &mut self,
+        squashfs: &SquashFS,
+        image: &File,
+        mut offset: u64,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        let block_size = u64::from(squashfs.block_size());
+        let file_size = u64::from(self.file.file_size);
+        let mut read = 0;
+
+        while read < buf.len() && offset < file_size {
+            let index = (offset / block_size) as usize;
+            let mut start = (offset % block_size) as usize;
+            if index >= self.blocks.len() {
+                start += self.file.block_offset as usize;
+            }
+            let data = self.block(squashfs, image, index)?;
+            let available = data
+                .get(start..)
+                .ok_or_else(|| corrupt("fragment shorter than the file"))?;
+            let len = available
+                .len()
+                .min(buf.len() - read)
+                .min((file_size - offset) as usize);
+            if len == 0 {
+                return Err(corrupt("fragment shorter than the file"));
+            }
+            buf[read..read + len].copy_from_slice(&available[..len]);
+            read += len;
+            offset += len as u64;
+        }
+        Ok(read)
+    }
+
+    /// Returns the decompressed block, reading it unless it's the cached one
+    fn block(&mut self, squashfs: &SquashFS, image: &File, index: usize) -> io::Result<&[u8]> {
+        if !matches!(&self.cached, Some((cached, _)) if *cached == index) {
+            let data = match self.blocks.get(index) {
+                Some(block) => {
+                    let data = squashfs.read_block(image, block.start, block.size, block.len)?;
+                    if data.len() != block.len {
+                        return Err(corrupt("data block of the wrong size"));
+                    }
+                    data
+                }
+                None => squashfs.read_fragment(image, &self.file)?,
+            };
+            self.cached = Some((index, data));
+        }
+        Ok(self
+            .cached
+            .as_ref()
+            .map(|(_, data)| data.as_slice())
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(all(test, feature = "fs", feature = "rayon"))]
+mod tests {
+    use std::{fs, io::Read};
+
+    use crate::{
+        testing::{image, Node},
+        EntryKind, SquashFS,
+    };
+
+    #[test]
+    fn parallel_and_sequential_reads_match() {
+        // ten blocks of the default block size and a tail in a fragment, with
+        // a sparse block in the middle
+        let mut contents: Vec<u8> = (0..10 * 128 * 1024 + 1000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        contents[4 * 128 * 1024..5 * 128 * 1024].fill(0);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.squashfs");
+        fs::write(&path, image(&[Node::File("big", &contents)])).unwrap();
+
+        let squashfs = SquashFS::from_path(&path).unwrap();
+        let entry = squashfs.find("/big").unwrap();
+        let EntryKind::File(file) = entry.kind else {
+            panic!("not a file");
+        };
+        assert!(file.block_sizes.len() >= crate::parallel::PARALLEL_MIN_BLOCKS);
+
+        let mut sequential = Vec::new();
+        squashfs
+            .reader
+            .file(file)
+            .reader()
+            .read_to_end(&mut sequential)
+            .unwrap();
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let mut parallel = Vec::new();
+        let copied = pool
+            .install(|| {
+                squashfs.copy_blocks_parallel(squashfs.image.as_ref().unwrap(), file, &mut parallel)
+            })
+            .unwrap();
+
+        assert_eq!(copied, contents.len() as u64);
+        assert_eq!(parallel, sequential);
+        assert_eq!(parallel, contents);
+        assert_eq!(
+            pool.install(|| squashfs.read_file("/big")).unwrap(),
+            contents
+        );
+    }
+}
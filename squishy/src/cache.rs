@@ -0,0 +1,75 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+/// Identifies a file for caching its SquashFS offset. Replacing or modifying
+/// the file changes its size or modification time, so stale offsets are
+/// never looked up.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CacheKey {
+    /// The canonical path of the file
+    pub path: PathBuf,
+    /// The size of the file in bytes
+    pub size: u64,
+    /// The modification time in nanoseconds since the Unix epoch
+    pub mtime: u64,
+}
+
+impl CacheKey {
+    /// Creates the key of the file at `path` from its metadata
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = fs::canonicalize(path)?;
+        let metadata = fs::metadata(&path)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |mtime| {
+                u64::try_from(mtime.as_nanos()).unwrap_or(u64::MAX)
+            });
+        Ok(Self {
+            path,
+            size: metadata.len(),
+            mtime,
+        })
+    }
+}
+
+/// The OffsetCache trait remembers where the SquashFS data starts in files,
+/// so that opening the same file again doesn't scan for the magic.
+pub trait OffsetCache {
+    /// Returns the cached offset of the file, if any
+    fn get(&self, key: &CacheKey) -> Option<u64>;
+
+    /// Remembers the offset of the file
+    fn insert(&self, key: CacheKey, offset: u64);
+}
+
+/// An OffsetCache that lives as long as the process
+#[derive(Debug, Default)]
+pub struct MemoryOffsetCache {
+    offsets: Mutex<HashMap<CacheKey, u64>>,
+}
+
+impl MemoryOffsetCache {
+    /// Creates an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OffsetCache for MemoryOffsetCache {
+    fn get(&self, key: &CacheKey) -> Option<u64> {
+        self.offsets.lock().ok()?.get(key).copied()
+    }
+
+    fn insert(&self, key: CacheKey, offset: u64) {
+        if let Ok(mut offsets) = self.offsets.lock() {
+            offsets.insert(key, offset);
+        }
+    }
+}
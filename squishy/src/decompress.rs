@@ -0,0 +1,241 @@
+use backhand::{
+    compression::{CompressionAction, Compressor, DefaultCompressor},
+    kind::{Kind, LE_V4_0},
+    BackhandError, FilesystemCompressor,
+};
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use std::cell::RefCell;
+
+/// Decompresses the blocks of images read by squishy. backhand sets up a new
+/// decoder for every block, this keeps the zlib and zstd decoders of each
+/// thread across blocks and files, and feeds xz blocks to the decoder directly
+/// instead of through an intermediate reader.
+///
+/// Blocks are decompressed into the room backhand reserves for them, the
+/// block size for data and 8 KiB for metadata. Blocks that decompress to
+/// more are corrupt, so the output never grows.
+struct Decompressor;
+
+static DECOMPRESSOR: Decompressor = Decompressor;
+
+#[cfg(feature = "gzip")]
+thread_local! {
+    static INFLATE: RefCell<flate2::Decompress> = RefCell::new(flate2::Decompress::new(true));
+}
+
+#[cfg(feature = "zstd")]
+thread_local! {
+    static ZSTD: RefCell<Option<zstd::bulk::Decompressor<'static>>> = const { RefCell::new(None) };
+}
+
+/// The kind of little endian SquashFS 4.0 images, using the decompressor
+pub(crate) fn kind() -> Kind {
+    Kind::new_with_const(&DECOMPRESSOR, LE_V4_0)
+}
+
+/// Decompresses a single block, into the room reserved in `out`
+pub(crate) fn decompress(
+    bytes: &[u8],
+    out: &mut Vec<u8>,
+    compressor: Compressor,
+) -> Result<(), BackhandError> {
+    DECOMPRESSOR.decompress(bytes, out, compressor)
+}
+
+/// Memory the xz decoder may use. Dictionaries of SquashFS images are at most
+/// one block, 1 MiB, so this only rejects crafted blocks.
+#[cfg(feature = "xz")]
+const XZ_MEMORY_LIMIT: u64 = 64 << 20;
+
+#[cfg(feature = "gzip")]
+fn inflate(bytes: &[u8], out: &mut Vec<u8>) -> Result<(), BackhandError> {
+    use flate2::{FlushDecompress, Status};
+
+    INFLATE.with_borrow_mut(|decoder| {
+        decoder.reset(true);
+        loop {
+            let (consumed, produced) = (decoder.total_in(), out.len());
+            let input = &bytes[consumed as usize..];
+            match decoder
+                .decompress_vec(input, out, FlushDecompress::Finish)
+                .map_err(std::io::Error::from)?
+            {
+                Status::StreamEnd => return Ok(()),
+                _ if decoder.total_in() > consumed || out.len() > produced => {}
+                // truncated, or larger than the room for the block
+                _ => return Err(BackhandError::CorruptedOrInvalidSquashfs),
+            }
+        }
+    })
+}
+
+#[cfg(feature = "xz")]
+fn unxz(bytes: &[u8], out: &mut Vec<u8>) -> Result<(), BackhandError> {
+    use xz2::stream::{Action, Status, Stream};
+
+    let mut stream =
+        Stream::new_stream_decoder(XZ_MEMORY_LIMIT, 0).map_err(std::io::Error::from)?;
+    loop {
+        let (consumed, produced) = (stream.total_in(), out.len());
+        let input = &bytes[consumed as usize..];
+        match stream
+            .process_vec(input, out, Action::Finish)
+            .map_err(std::io::Error::from)?
+        {
+            Status::StreamEnd => return Ok(()),
+            _ if stream.total_in() > consumed || out.len() > produced => {}
+            // truncated, or larger than the room for the block
+            _ => return Err(BackhandError::CorruptedOrInvalidSquashfs),
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn unzstd(bytes: &[u8], out: &mut Vec<u8>) -> Result<(), BackhandError> {
+    // zstd decodes no input to no output, while a compressed block is never empty
+    if bytes.is_empty() {
+        return Err(BackhandError::CorruptedOrInvalidSquashfs);
+    }
+    ZSTD.with_borrow_mut(|decoder| {
+        let decoder = match decoder {
+            Some(decoder) => decoder,
+            None => decoder.insert(zstd::bulk::Decompressor::new()?),
+        };
+        decoder.decompress_to_buffer(bytes, out)?;
+        Ok(())
+    })
+}
+
+impl CompressionAction for Decompressor {
+    fn decompress(
+        &self,
+        bytes: &[u8],
+        out: &mut Vec<u8>,
+        compressor: Compressor,
+    ) -> Result<(), BackhandError> {
+        match compressor {
+            #[cfg(feature = "gzip")]
+            Compressor::Gzip => inflate(bytes, out),
+            #[cfg(feature = "xz")]
+            Compressor::Xz => unxz(bytes, out),
+            #[cfg(feature = "zstd")]
+            Compressor::Zstd => unzstd(bytes, out),
+            _ => DefaultCompressor.decompress(bytes, out, compressor),
+        }
+    }
+
+    fn compress(
+        &self,
+        bytes: &[u8],
+        fc: FilesystemCompressor,
+        block_size: u32,
+    ) -> Result<Vec<u8>, BackhandError> {
+        DefaultCompressor.compress(bytes, fc, block_size)
+    }
+}
+
+#[cfg(all(test, any(feature = "gzip", feature = "xz", feature = "zstd")))]
+mod tests {
+    #[cfg(any(feature = "gzip", feature = "xz"))]
+    use std::io::Write;
+
+    use super::*;
+
+    /// Text that compresses well, so the compressed block is much smaller
+    /// than the output
+    fn data() -> Vec<u8> {
+        (0..2000u32)
+            .flat_map(|i| format!("line {}\n", i % 97).into_bytes())
+            .collect()
+    }
+
+    /// Compresses the data with the codec SquashFS uses for the compressor
+    fn compress(data: &[u8], compressor: Compressor) -> Vec<u8> {
+        match compressor {
+            #[cfg(feature = "gzip")]
+            Compressor::Gzip => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            #[cfg(feature = "xz")]
+            Compressor::Xz => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            #[cfg(feature = "zstd")]
+            Compressor::Zstd => zstd::bulk::compress(data, 3).unwrap(),
+            _ => unreachable!("no encoder for {:?}", compressor),
+        }
+    }
+
+    fn compressors() -> Vec<Compressor> {
+        vec![
+            #[cfg(feature = "gzip")]
+            Compressor::Gzip,
+            #[cfg(feature = "xz")]
+            Compressor::Xz,
+            #[cfg(feature = "zstd")]
+            Compressor::Zstd,
+        ]
+    }
+
+    #[test]
+    fn round_trips_each_codec() {
+        let data = data();
+        for compressor in compressors() {
+            let compressed = compress(&data, compressor);
+            // twice, so the decoders kept by the thread are reused
+            for _ in 0..2 {
+                let mut out = Vec::with_capacity(data.len());
+                decompress(&compressed, &mut out, compressor).unwrap();
+                assert_eq!(out, data, "{:?}", compressor);
+            }
+        }
+    }
+
+    #[test]
+    fn output_past_the_reserved_room_is_rejected() {
+        let data = data();
+        for compressor in compressors() {
+            let compressed = compress(&data, compressor);
+            let mut out = Vec::with_capacity(data.len() / 2);
+            assert!(
+                decompress(&compressed, &mut out, compressor).is_err(),
+                "{:?}",
+                compressor
+            );
+            assert!(out.len() <= data.len() / 2, "{:?}", compressor);
+        }
+    }
+
+    #[test]
+    fn corrupt_input_is_an_error() {
+        let data = data();
+        for compressor in compressors() {
+            let compressed = compress(&data, compressor);
+            let truncated = &compressed[..compressed.len() / 2];
+            let mut garbage = compressed.clone();
+            garbage[..8].copy_from_slice(b"garbage!");
+            for (name, bytes) in [
+                ("truncated", truncated),
+                ("garbage", &garbage),
+                ("empty", &[]),
+            ] {
+                let mut out = Vec::with_capacity(data.len());
+                assert!(
+                    decompress(bytes, &mut out, compressor).is_err(),
+                    "{:?} {}",
+                    compressor,
+                    name
+                );
+            }
+            // a failed block doesn't break the next one
+            let mut out = Vec::with_capacity(data.len());
+            decompress(&compressed, &mut out, compressor).unwrap();
+            assert_eq!(out, data);
+        }
+    }
+}
@@ -0,0 +1,439 @@
+//! Finding filesystems in any reader by their magic bytes, e.g. to probe files
+//! without opening them as a `SquashFS`
+
+use std::{
+    fmt,
+    io::{Read, Seek, SeekFrom},
+};
+
+#[cfg(feature = "erofs")]
+use std::io::BufReader;
+
+#[cfg(feature = "appimage")]
+use crate::appimage::elf_end_offset;
+#[cfg(feature = "erofs")]
+use crate::erofs::Erofs;
+use crate::{error::SquishyError, Result, SQUASHFS_MAGIC};
+
+/// Magic bytes at the start of a DwarFS image
+const DWARFS_MAGIC: [u8; 6] = *b"DWARFS";
+
+/// Bytes `detect_filesystem_type` scans for filesystems neither at the start
+/// of the reader nor after an ELF
+const SCAN_LIMIT: u64 = 64 << 20;
+
+/// The FilesystemType enum represents the filesystems squishy can find in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum FilesystemType {
+    SquashFS,
+    Erofs,
+    /// Found by its magic only, squishy can't read DwarFS images
+    DwarFS,
+}
+
+impl fmt::Display for FilesystemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilesystemType::SquashFS => write!(f, "squashfs"),
+            FilesystemType::Erofs => write!(f, "erofs"),
+            FilesystemType::DwarFS => write!(f, "dwarfs"),
+        }
+    }
+}
+
+/// Scans up to `limit` bytes for the magic bytes from the current position,
+/// returning the position of the first match
+fn scan<R: Read + Seek>(reader: &mut R, magic: &[u8], limit: u64) -> Result<Option<u64>> {
+    let start = reader.stream_position()?;
+    let end = start.saturating_add(limit);
+    let mut base = start;
+    let mut buf = vec![0_u8; 64 * 1024];
+    let mut carry = 0;
+
+    loop {
+        let room = end
+            .saturating_sub(base + carry as u64)
+            .min((buf.len() - carry) as u64) as usize;
+        let read = reader.read(&mut buf[carry..carry + room])?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let filled = carry + read;
+        if let Some(pos) = buf[..filled]
+            .windows(magic.len())
+            .position(|window| window == magic)
+        {
+            return Ok(Some(base + pos as u64));
+        }
+        // keep the tail in case the magic spans two reads
+        carry = filled.min(magic.len() - 1);
+        buf.copy_within(filled - carry..filled, 0);
+        base += (filled - carry) as u64;
+    }
+}
+
+/// Scans for the magic bytes, then seeks back to where the scan started
+fn find_magic<R: Read + Seek>(reader: &mut R, magic: &[u8], limit: u64) -> Result<Option<u64>> {
+    let start = reader.stream_position()?;
+    let found = scan(reader, magic, limit)?;
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(found)
+}
+
+/// Finds the offset of the SquashFS data, scanning from the current position
+/// of the reader for the magic bytes. The reader is left at that position.
+///
+/// # Arguments
+/// * `reader` - The reader of the file, e.g. an image or an AppImage
+///
+/// # Returns
+/// The offset of the SquashFS data within the reader, or an error if the
+/// magic bytes are not found.
+pub fn find_squashfs_offset<R: Read + Seek>(reader: &mut R) -> Result<u64> {
+    find_magic(reader, &SQUASHFS_MAGIC, u64::MAX)?.ok_or(SquishyError::NoSquashFsFound)
+}
+
+/// Finds the offset of the EROFS data, scanning from the current position of
+/// the reader for a valid superblock. The reader is left at that position.
+///
+/// # Arguments
+/// * `reader` - The reader of the file, e.g. an image or an AppImage
+///
+/// # Returns
+/// The offset of the EROFS data within the reader, or an error if no
+/// superblock is found.
+#[cfg(feature = "erofs")]
+pub fn find_erofs_offset<R: Read + Seek>(reader: &mut R) -> Result<u64> {
+    find_erofs_within(reader, u64::MAX)
+}
+
+/// Scans up to `limit` bytes for EROFS data, then seeks back to where the
+/// scan started
+#[cfg(feature = "erofs")]
+fn find_erofs_within<R: Read + Seek>(reader: &mut R, limit: u64) -> Result<u64> {
+    let start = reader.stream_position()?;
+    let found = Erofs::find_offset(&mut BufReader::new(&mut *reader), limit);
+    reader.seek(SeekFrom::Start(start))?;
+    found
+}
+
+/// Finds the offset of a DwarFS image, scanning from the current position of
+/// the reader for the magic bytes. The reader is left at that position.
+///
+/// # Arguments
+/// * `reader` - The reader of the file, e.g. an image or an AppImage
+///
+/// # Returns
+/// The offset of the DwarFS image within the reader, or an error if the
+/// magic bytes are not found.
+pub fn find_dwarfs_offset<R: Read + Seek>(reader: &mut R) -> Result<u64> {
+    find_magic(reader, &DWARFS_MAGIC, u64::MAX)?.ok_or(SquishyError::NoDwarFsFound)
+}
+
+/// Keeps the offset found, or None if there is no EROFS data
+#[cfg(feature = "erofs")]
+fn found(result: Result<u64>) -> Result<Option<u64>> {
+    match result {
+        Ok(offset) => Ok(Some(offset)),
+        Err(SquishyError::NoErofsFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Finds the filesystem starting first in the reader, scanning from its
+/// current position. EROFS is only looked for with the `erofs` feature. The
+/// reader is left at that position.
+///
+/// # Arguments
+/// * `reader` - The reader of the file, e.g. an image or an AppImage
+///
+/// # Returns
+/// The type and offset of the filesystem, or an error if there is none.
+pub fn detect_any<R: Read + Seek>(reader: &mut R) -> Result<(FilesystemType, u64)> {
+    detect_within(reader, u64::MAX)
+}
+
+/// Finds the filesystem starting first within `limit` bytes of the current
+/// position
+fn detect_within<R: Read + Seek>(reader: &mut R, limit: u64) -> Result<(FilesystemType, u64)> {
+    #[cfg_attr(not(feature = "erofs"), allow(unused_mut))]
+    let mut candidates = vec![
+        (
+            FilesystemType::SquashFS,
+            find_magic(reader, &SQUASHFS_MAGIC, limit)?,
+        ),
+        (
+            FilesystemType::DwarFS,
+            find_magic(reader, &DWARFS_MAGIC, limit)?,
+        ),
+    ];
+    #[cfg(feature = "erofs")]
+    candidates.push((
+        FilesystemType::Erofs,
+        found(find_erofs_within(reader, limit))?,
+    ));
+
+    candidates
+        .into_iter()
+        .filter_map(|(kind, offset)| Some((kind, offset?)))
+        .min_by_key(|(_, offset)| *offset)
+        .ok_or(SquishyError::NoFilesystemFound)
+}
+
+/// Returns the type of the filesystem starting right at the offset
+fn type_at<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<Option<FilesystemType>> {
+    let mut magic = Vec::with_capacity(DWARFS_MAGIC.len());
+    reader.seek(SeekFrom::Start(offset))?;
+    reader
+        .by_ref()
+        .take(DWARFS_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+
+    if magic.starts_with(&SQUASHFS_MAGIC) {
+        return Ok(Some(FilesystemType::SquashFS));
+    }
+    if magic.starts_with(&DWARFS_MAGIC) {
+        return Ok(Some(FilesystemType::DwarFS));
+    }
+    #[cfg(feature = "erofs")]
+    if Erofs::has_superblock(&mut BufReader::new(&mut *reader), offset) {
+        return Ok(Some(FilesystemType::Erofs));
+    }
+    Ok(None)
+}
+
+/// Detects the filesystem in the reader and its offset from the start of the
+/// reader. Tries in turn the start of the reader, the end of the ELF it starts
+/// with as in AppImages, and a scan of the first 64 MiB for the filesystem
+/// starting first. ELFs are only looked at with the `appimage` feature, EROFS
+/// only with the `erofs` feature. The reader is left at its position.
+///
+/// # Arguments
+/// * `reader` - The reader of the file, e.g. an image or an AppImage
+///
+/// # Returns
+/// The type and offset of the filesystem, or an error if there is none.
+pub fn detect_filesystem_type<R: Read + Seek>(reader: &mut R) -> Result<(FilesystemType, u64)> {
+    let position = reader.stream_position()?;
+    let detected = detect_from_start(reader);
+    reader.seek(SeekFrom::Start(position))?;
+    detected
+}
+
+/// Detects the filesystem, see `detect_filesystem_type`
+fn detect_from_start<R: Read + Seek>(reader: &mut R) -> Result<(FilesystemType, u64)> {
+    #[cfg_attr(not(feature = "appimage"), allow(unused_mut))]
+    let mut offsets = vec![0];
+    #[cfg(feature = "appimage")]
+    {
+        reader.rewind()?;
+        if let Ok(end) = elf_end_offset(reader) {
+            offsets.push(end);
+        }
+    }
+
+    for offset in offsets {
+        if let Some(kind) = type_at(reader, offset)? {
+            return Ok((kind, offset));
+        }
+    }
+    reader.rewind()?;
+    detect_within(reader, SCAN_LIMIT)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    #[cfg(feature = "appimage")]
+    use crate::appimage::tests::elf_header;
+    #[cfg(feature = "erofs")]
+    use crate::erofs::tests::image;
+
+    /// Returns `len` zero bytes with the bytes placed at their offsets
+    fn file(len: usize, placed: &[(usize, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut data = vec![0; len];
+        for (offset, bytes) in placed {
+            data[*offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        Cursor::new(data)
+    }
+
+    /// An ELF whose only section header ends at 128, with a DwarFS magic in
+    /// the padding of its identification bytes to show the ELF isn't scanned
+    #[cfg(feature = "appimage")]
+    fn elf() -> Vec<u8> {
+        let mut elf = elf_header(0, 0, 64, 1);
+        elf[9..15].copy_from_slice(&DWARFS_MAGIC);
+        elf.resize(128, 0);
+        elf
+    }
+
+    #[test]
+    fn finds_magics_from_the_position() {
+        let mut reader = file(4096, &[(100, b"hsqs"), (300, b"DWARFS"), (500, b"hsqs")]);
+        assert_eq!(find_squashfs_offset(&mut reader).unwrap(), 100);
+        assert_eq!(reader.position(), 0);
+        assert_eq!(find_dwarfs_offset(&mut reader).unwrap(), 300);
+
+        reader.set_position(101);
+        assert_eq!(find_squashfs_offset(&mut reader).unwrap(), 500);
+        assert_eq!(reader.position(), 101);
+
+        reader.set_position(301);
+        assert!(matches!(
+            find_dwarfs_offset(&mut reader),
+            Err(SquishyError::NoDwarFsFound)
+        ));
+        reader.set_position(501);
+        assert!(matches!(
+            find_squashfs_offset(&mut reader),
+            Err(SquishyError::NoSquashFsFound)
+        ));
+    }
+
+    #[test]
+    fn finds_magics_spanning_two_reads() {
+        let mut reader = file(
+            200_000,
+            &[(64 * 1024 - 2, b"hsqs"), (128 * 1024 - 3, b"DWARFS")],
+        );
+        assert_eq!(find_squashfs_offset(&mut reader).unwrap(), 64 * 1024 - 2);
+        assert_eq!(find_dwarfs_offset(&mut reader).unwrap(), 128 * 1024 - 3);
+    }
+
+    #[test]
+    fn detect_any_finds_the_first_filesystem() {
+        let mut reader = file(4096, &[(200, b"DWARFS"), (900, b"hsqs")]);
+        assert_eq!(
+            detect_any(&mut reader).unwrap(),
+            (FilesystemType::DwarFS, 200)
+        );
+        reader.set_position(201);
+        assert_eq!(
+            detect_any(&mut reader).unwrap(),
+            (FilesystemType::SquashFS, 900)
+        );
+        assert!(matches!(
+            detect_any(&mut file(4096, &[])),
+            Err(SquishyError::NoFilesystemFound)
+        ));
+    }
+
+    #[test]
+    fn scans_within_the_limit() {
+        let mut reader = file(4096, &[(1000, b"hsqs")]);
+        assert!(matches!(
+            detect_within(&mut reader, 1000),
+            Err(SquishyError::NoFilesystemFound)
+        ));
+        assert_eq!(
+            detect_within(&mut reader, 1004).unwrap(),
+            (FilesystemType::SquashFS, 1000)
+        );
+    }
+
+    #[test]
+    fn detects_filesystems_at_the_start() {
+        let mut reader = file(4096, &[(0, b"hsqs"), (100, b"DWARFS")]);
+        reader.set_position(7);
+        assert_eq!(
+            detect_filesystem_type(&mut reader).unwrap(),
+            (FilesystemType::SquashFS, 0)
+        );
+        assert_eq!(reader.position(), 7);
+
+        let mut reader = file(4096, &[(0, b"DWARFS"), (100, b"hsqs")]);
+        assert_eq!(
+            detect_filesystem_type(&mut reader).unwrap(),
+            (FilesystemType::DwarFS, 0)
+        );
+    }
+
+    #[test]
+    fn scans_for_filesystems_elsewhere() {
+        let mut reader = file(4096, &[(1000, b"hsqs"), (2000, b"DWARFS")]);
+        assert_eq!(
+            detect_filesystem_type(&mut reader).unwrap(),
+            (FilesystemType::SquashFS, 1000)
+        );
+        let mut reader = file(4096, &[(1000, b"DWARFS")]);
+        assert_eq!(
+            detect_filesystem_type(&mut reader).unwrap(),
+            (FilesystemType::DwarFS, 1000)
+        );
+        assert!(matches!(
+            detect_filesystem_type(&mut file(4096, &[])),
+            Err(SquishyError::NoFilesystemFound)
+        ));
+    }
+
+    #[cfg(feature = "appimage")]
+    #[test]
+    fn filesystems_at_the_end_of_the_elf_come_first() {
+        for (magic, kind) in [
+            (&b"hsqs"[..], FilesystemType::SquashFS),
+            (&DWARFS_MAGIC[..], FilesystemType::DwarFS),
+        ] {
+            let mut data = elf();
+            data.extend_from_slice(magic);
+            data.resize(4096, 0);
+            assert_eq!(
+                detect_filesystem_type(&mut Cursor::new(data)).unwrap(),
+                (kind, 128)
+            );
+        }
+
+        // without a filesystem at its end, the ELF is scanned
+        let mut data = elf();
+        data.resize(4096, 0);
+        assert_eq!(
+            detect_filesystem_type(&mut Cursor::new(data)).unwrap(),
+            (FilesystemType::DwarFS, 9)
+        );
+    }
+
+    #[cfg(feature = "erofs")]
+    #[test]
+    fn detects_erofs() {
+        assert_eq!(
+            detect_filesystem_type(&mut Cursor::new(image())).unwrap(),
+            (FilesystemType::Erofs, 0)
+        );
+
+        let mut data = vec![0; 4096];
+        data.extend_from_slice(&image());
+        let mut reader = Cursor::new(data);
+        assert_eq!(find_erofs_offset(&mut reader).unwrap(), 4096);
+        assert_eq!(reader.position(), 0);
+        assert_eq!(
+            detect_filesystem_type(&mut reader).unwrap(),
+            (FilesystemType::Erofs, 4096)
+        );
+
+        // a SquashFS magic before the EROFS data starts first
+        reader.get_mut()[200..204].copy_from_slice(b"hsqs");
+        assert_eq!(
+            detect_any(&mut reader).unwrap(),
+            (FilesystemType::SquashFS, 200)
+        );
+        assert!(matches!(
+            find_erofs_offset(&mut file(8192, &[])),
+            Err(SquishyError::NoErofsFound)
+        ));
+    }
+
+    #[cfg(all(feature = "appimage", feature = "erofs"))]
+    #[test]
+    fn detects_erofs_at_the_end_of_the_elf() {
+        let mut data = elf();
+        data.extend_from_slice(&image());
+        assert_eq!(
+            detect_filesystem_type(&mut Cursor::new(data)).unwrap(),
+            (FilesystemType::Erofs, 128)
+        );
+    }
+}
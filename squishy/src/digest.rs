@@ -0,0 +1,218 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+    path::PathBuf,
+};
+#[cfg(feature = "fs")]
+use std::{fs::File, path::Path};
+
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use sha2::{Digest, Sha256};
+
+use crate::{EntryKind, Result, SquashFS, SquashFSEntry};
+
+/// The HashAlgorithm enum selects the digest computed over file contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+/// Feeds everything written into the digest
+struct DigestWriter<D>(D);
+
+impl<D: Digest> Write for DigestWriter<D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Formats the bytes as lowercase hex
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Computes the hex SHA-256 digest of a file on disk
+#[cfg(feature = "fs")]
+pub(crate) fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut writer = DigestWriter(Sha256::new());
+    io::copy(&mut File::open(path)?, &mut writer)?;
+    Ok(to_hex(&writer.0.finalize()))
+}
+
+/// The DuplicateFile struct locates one copy of duplicated contents.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DuplicateFile {
+    /// Index of the image in the slice given to `DedupReport::new`
+    pub image: usize,
+    /// Path of the file within the image
+    pub path: PathBuf,
+}
+
+/// The DuplicateGroup struct describes contents found in more than one image.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DuplicateGroup {
+    /// Hex digest of the contents
+    pub digest: String,
+    /// Size of the contents in bytes
+    pub size: u64,
+    /// Every file with the contents, in image order
+    pub files: Vec<DuplicateFile>,
+    /// Bytes saved by storing the contents once instead of once per image
+    pub savings: u64,
+}
+
+/// The DedupReport struct holds the results of `DedupReport::new`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DedupReport {
+    /// Number of non-empty files hashed across all images
+    pub files: u64,
+    /// Bytes stored when every image keeps its own copy of each of its
+    /// distinct contents, as a SquashFS image does
+    pub bytes: u64,
+    /// Bytes stored when every distinct content is kept once overall
+    pub unique_bytes: u64,
+    /// Contents found in more than one image, largest savings first
+    pub duplicates: Vec<DuplicateGroup>,
+}
+
+impl DedupReport {
+    /// Hashes every file of the images and groups the contents shared between
+    /// them. Duplicates within a single image are only counted once, since
+    /// SquashFS images already store them once. Empty files are left out.
+    ///
+    /// # Arguments
+    /// * `images` - The images to compare.
+    /// * `algorithm` - The digest identifying equal contents.
+    ///
+    /// # Returns
+    /// The report, or an error if a file cannot be read.
+    pub fn new(images: &[SquashFS], algorithm: HashAlgorithm) -> Result<Self> {
+        // files and images of each content, by digest and size
+        let mut contents: BTreeMap<(String, u64), (Vec<DuplicateFile>, BTreeSet<usize>)> =
+            BTreeMap::new();
+        let mut report = DedupReport::default();
+
+        for (image, squashfs) in images.iter().enumerate() {
+            let mut seen = BTreeSet::new();
+            for (path, size, digest) in squashfs.file_digests(algorithm, true)? {
+                report.files += 1;
+                if seen.insert((digest.clone(), size)) {
+                    report.bytes += size;
+                }
+                let (files, images) = contents.entry((digest, size)).or_default();
+                files.push(DuplicateFile { image, path });
+                images.insert(image);
+            }
+        }
+
+        for ((digest, size), (files, images)) in contents {
+            report.unique_bytes += size;
+            if images.len() > 1 {
+                report.duplicates.push(DuplicateGroup {
+                    digest,
+                    size,
+                    files,
+                    savings: size * (images.len() as u64 - 1),
+                });
+            }
+        }
+        report
+            .duplicates
+            .sort_by(|a, b| b.savings.cmp(&a.savings).then(a.digest.cmp(&b.digest)));
+        Ok(report)
+    }
+
+    /// Returns the bytes saved by storing every distinct content once overall.
+    pub fn savings(&self) -> u64 {
+        self.bytes - self.unique_bytes
+    }
+}
+
+impl SquashFS<'_> {
+    /// Computes the hex digest of the contents of a file entry, without
+    /// extracting it.
+    ///
+    /// # Arguments
+    /// * `entry` - The file entry to hash.
+    /// * `algorithm` - The digest to compute.
+    ///
+    /// # Returns
+    /// The lowercase hex digest, or an error if the entry isn't a file or
+    /// cannot be read.
+    pub fn digest(&self, entry: &SquashFSEntry, algorithm: HashAlgorithm) -> Result<String> {
+        match algorithm {
+            HashAlgorithm::Sha256 => {
+                let mut writer = DigestWriter(Sha256::new());
+                self.copy_entry_to(entry, &mut writer)?;
+                Ok(to_hex(&writer.0.finalize()))
+            }
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                self.copy_entry_to(entry, &mut hasher)?;
+                Ok(hasher.finalize().to_hex().to_string())
+            }
+        }
+    }
+
+    /// Hashes every file and returns the paths of those whose contents have
+    /// the digest, e.g. to check whether a known build of a library is
+    /// bundled in an AppImage. Hard links and copies all match.
+    ///
+    /// # Arguments
+    /// * `algorithm` - The algorithm of the digest.
+    /// * `digest` - The hex digest to look for, in either case.
+    ///
+    /// # Returns
+    /// The paths of the matching files sorted, or an error if a file cannot
+    /// be read.
+    pub fn find_by_digest(&self, algorithm: HashAlgorithm, digest: &str) -> Result<Vec<PathBuf>> {
+        let mut paths: Vec<PathBuf> = self
+            .file_digests(algorithm, false)?
+            .into_iter()
+            .filter(|(_, _, found)| found.eq_ignore_ascii_case(digest.trim()))
+            .map(|(path, _, _)| path)
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Returns the path, size and digest of every file, hashed in parallel
+    /// with the rayon feature
+    fn file_digests(
+        &self,
+        algorithm: HashAlgorithm,
+        skip_empty: bool,
+    ) -> Result<Vec<(PathBuf, u64, String)>> {
+        let files: Vec<SquashFSEntry> = self
+            .entries()
+            .filter(|entry| {
+                matches!(entry.kind, EntryKind::File(_)) && !(skip_empty && entry.size == 0)
+            })
+            .collect();
+
+        #[cfg(feature = "rayon")]
+        let files = files.into_par_iter();
+        #[cfg(not(feature = "rayon"))]
+        let files = files.into_iter();
+
+        files
+            .map(|entry| {
+                let digest = self.digest(&entry, algorithm)?;
+                Ok((entry.path, u64::from(entry.size), digest))
+            })
+            .collect()
+    }
+}
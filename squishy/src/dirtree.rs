@@ -0,0 +1,229 @@
+//! Creating extracted paths relative to open directories, so every entry of a
+//! deep tree isn't resolved again from the destination, and a directory
+//! swapped for a symlink during extraction is never followed
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs::{self, File},
+    io,
+    os::{
+        fd::{AsFd, BorrowedFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+};
+
+use rustix::fs::{self as rfs, AtFlags, FileType, Mode, OFlags};
+
+use crate::{
+    error::{IoContext, SquishyError},
+    Result,
+};
+
+/// The DirTree struct keeps the destination of an extraction open, along with
+/// the directories leading to the one used last. Paths are relative to the
+/// destination and made of normal components only, see
+/// `UnpackOptions::destination`.
+pub(crate) struct DirTree {
+    /// The destination directory
+    root: OwnedFd,
+    /// Path of the destination, for errors
+    dest: PathBuf,
+    /// Directories from the destination down to the one used last, by name
+    open: Vec<(OsString, OwnedFd)>,
+    /// Follow directories that are symlinks, see `UnpackOptions::insecure_paths`
+    follow: bool,
+}
+
+/// Splits the relative path into its parent and its name
+pub(crate) fn split(relative: &Path) -> (&Path, &OsStr) {
+    (
+        relative.parent().unwrap_or(Path::new("")),
+        relative.file_name().unwrap_or_default(),
+    )
+}
+
+/// Removes the directory named `name` in `parent` with everything in it,
+/// without following symlinks
+pub(crate) fn remove_dir_all_at(parent: BorrowedFd<'_>, name: &OsStr) -> io::Result<()> {
+    let flags = OFlags::RDONLY | OFlags::DIRECTORY | OFlags::NOFOLLOW | OFlags::CLOEXEC;
+    let dir = rfs::openat(parent, name, flags, Mode::empty())?;
+    let mut children = Vec::new();
+    for child in rfs::Dir::read_from(&dir)? {
+        let child = child?;
+        let child_name = child.file_name().to_bytes();
+        if child_name != b"." && child_name != b".." {
+            children.push((OsStr::from_bytes(child_name).to_owned(), child.file_type()));
+        }
+    }
+
+    for (child, file_type) in children {
+        let file_type = match file_type {
+            FileType::Unknown => FileType::from_raw_mode(
+                rfs::statat(&dir, &child, AtFlags::SYMLINK_NOFOLLOW)?.st_mode,
+            ),
+            file_type => file_type,
+        };
+        if file_type == FileType::Directory {
+            remove_dir_all_at(dir.as_fd(), &child)?;
+        } else {
+            rfs::unlinkat(&dir, &child, AtFlags::empty())?;
+        }
+    }
+    Ok(rfs::unlinkat(parent, name, AtFlags::REMOVEDIR)?)
+}
+
+impl DirTree {
+    /// Opens the destination, creating it if missing
+    ///
+    /// # Arguments
+    /// * `dest` - The directory to extract into
+    /// * `follow` - Whether directories that are symlinks are followed
+    pub(crate) fn create(dest: &Path, follow: bool) -> Result<Self> {
+        fs::create_dir_all(dest).context("create directory", dest)?;
+        let flags = OFlags::RDONLY | OFlags::DIRECTORY | OFlags::CLOEXEC;
+        let root = rfs::open(dest, flags, Mode::empty())
+            .map_err(io::Error::from)
+            .context("open", dest)?;
+        Ok(Self::new(root, dest, follow))
+    }
+
+    /// Keeps the open destination
+    ///
+    /// # Arguments
+    /// * `root` - The directory to extract into
+    /// * `dest` - Path of the directory, for errors
+    /// * `follow` - Whether directories that are symlinks are followed
+    pub(crate) fn new(root: OwnedFd, dest: &Path, follow: bool) -> Self {
+        Self {
+            root,
+            dest: dest.to_path_buf(),
+            open: Vec::new(),
+            follow,
+        }
+    }
+
+    /// Returns the directory at the relative path, creating missing ones.
+    /// The directories shared with the path of the previous call are reused.
+    ///
+    /// # Arguments
+    /// * `relative` - Path of the directory within the destination
+    /// * `entry` - Path of the entry being extracted, for errors
+    pub(crate) fn dir(&mut self, relative: &Path, entry: &Path) -> Result<BorrowedFd<'_>> {
+        let names: Vec<&OsStr> = relative.iter().collect();
+        let kept = self
+            .open
+            .iter()
+            .zip(&names)
+            .take_while(|((open, _), name)| open == *name)
+            .count();
+        self.open.truncate(kept);
+
+        for (depth, name) in names.iter().enumerate().skip(kept) {
+            let parent = self
+                .open
+                .last()
+                .map_or(self.root.as_fd(), |(_, fd)| fd.as_fd());
+            let path: PathBuf = names[..=depth].iter().collect();
+            let fd = self.open_child(parent, name, true, &path, entry)?;
+            self.open.push((name.to_os_string(), fd));
+        }
+        Ok(self
+            .open
+            .last()
+            .map_or(self.root.as_fd(), |(_, fd)| fd.as_fd()))
+    }
+
+    /// Opens the existing directory at the relative path on its own, without
+    /// touching the directories kept by `dir`, e.g. from several threads
+    ///
+    /// # Arguments
+    /// * `relative` - Path of the directory within the destination
+    /// * `entry` - Path of the entry being extracted, for errors
+    pub(crate) fn open_dir(&self, relative: &Path, entry: &Path) -> Result<OwnedFd> {
+        let mut dir = self.root.try_clone().context("open", &self.dest)?;
+        let mut path = PathBuf::new();
+        for name in relative {
+            path.push(name);
+            let child = self.open_child(dir.as_fd(), name, false, &path, entry)?;
+            dir = child;
+        }
+        Ok(dir)
+    }
+
+    /// Opens the directory in the parent, creating it first if asked
+    fn open_child(
+        &self,
+        parent: BorrowedFd<'_>,
+        name: &OsStr,
+        create: bool,
+        path: &Path,
+        entry: &Path,
+    ) -> Result<OwnedFd> {
+        let path = self.dest.join(path);
+        if create {
+            match rfs::mkdirat(parent, name, Mode::from_raw_mode(0o777)) {
+                Ok(()) | Err(rustix::io::Errno::EXIST) => {}
+                Err(e) => return Err(io::Error::from(e)).context("create directory", &path),
+            }
+        }
+
+        let mut flags = OFlags::RDONLY | OFlags::DIRECTORY | OFlags::CLOEXEC;
+        if !self.follow {
+            flags |= OFlags::NOFOLLOW;
+        }
+        rfs::openat(parent, name, flags, Mode::empty()).or_else(|e| {
+            let symlink = rfs::statat(parent, name, AtFlags::SYMLINK_NOFOLLOW)
+                .is_ok_and(|stat| FileType::from_raw_mode(stat.st_mode) == FileType::Symlink);
+            if symlink && !self.follow {
+                return Err(SquishyError::UnsafePath {
+                    path: entry.to_path_buf(),
+                    reason: "a parent directory is a symlink",
+                });
+            }
+            Err(io::Error::from(e)).context("open", &path)
+        })
+    }
+
+    /// Creates the file at the relative path, truncating an existing one but
+    /// never following a symlink. The directory of the previous file is reused
+    /// if it is the same.
+    ///
+    /// # Arguments
+    /// * `last` - The directory of the previous file, updated for the next one
+    /// * `relative` - Path of the file within the destination
+    /// * `entry` - Path of the entry being extracted, for errors
+    pub(crate) fn create_file(
+        &self,
+        last: &mut Option<(PathBuf, OwnedFd)>,
+        relative: &Path,
+        entry: &Path,
+    ) -> Result<File> {
+        let (parent, name) = split(relative);
+        let (_, dir) = match last.take() {
+            Some((cached, fd)) if cached == parent => last.insert((cached, fd)),
+            _ => last.insert((parent.to_path_buf(), self.open_dir(parent, entry)?)),
+        };
+
+        let flags =
+            OFlags::WRONLY | OFlags::CREATE | OFlags::TRUNC | OFlags::NOFOLLOW | OFlags::CLOEXEC;
+        rfs::openat(dir.as_fd(), name, flags, Mode::from_raw_mode(0o666))
+            .map(File::from)
+            .map_err(io::Error::from)
+            .context("create", self.dest.join(relative))
+    }
+
+    /// Creates the symlink at the relative path
+    ///
+    /// # Arguments
+    /// * `relative` - Path of the symlink within the destination
+    /// * `target` - Target of the symlink
+    /// * `entry` - Path of the entry being extracted, for errors
+    pub(crate) fn symlink(&mut self, relative: &Path, target: &Path, entry: &Path) -> Result<()> {
+        let (parent, name) = split(relative);
+        let dir = self.dir(parent, entry)?;
+        rfs::symlinkat(target, dir, name)
+            .map_err(io::Error::from)
+            .context("create symlink", self.dest.join(relative))
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    fmt,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+use goblin::{
+    container::{Container, Ctx},
+    elf::{
+        dynamic::{Dynamic, DF_1_PIE, DT_NEEDED, DT_RPATH, DT_RUNPATH},
+        header::{self, ELFMAG, ET_CORE, ET_DYN, ET_EXEC, ET_REL},
+        program_header::{ProgramHeader, PT_DYNAMIC, PT_INTERP, PT_LOAD},
+        Elf,
+    },
+};
+
+use crate::{error::SquishyError, EntryKind, Result, SquashFS, SquashFSEntry};
+
+/// Largest program header table, interpreter path, dynamic section or string
+/// table read from a binary
+const MAX_TABLE_SIZE: u64 = 16 << 20;
+
+/// The Architecture enum represents the CPU architecture a binary or an AppImage is built for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Architecture {
+    X86,
+    X86_64,
+    Arm,
+    Aarch64,
+    #[cfg_attr(feature = "serde", serde(rename = "riscv64"))]
+    RiscV64,
+    /// Unrecognized ELF machine type
+    Other(u16),
+}
+
+impl Architecture {
+    /// Maps an ELF `e_machine` value to an architecture.
+    pub fn from_elf_machine(machine: u16) -> Self {
+        match machine {
+            header::EM_386 => Architecture::X86,
+            header::EM_X86_64 => Architecture::X86_64,
+            header::EM_ARM => Architecture::Arm,
+            header::EM_AARCH64 => Architecture::Aarch64,
+            header::EM_RISCV => Architecture::RiscV64,
+            other => Architecture::Other(other),
+        }
+    }
+
+    /// Parses an architecture name as used in AppImage file names and AppStream metadata.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "i386" | "i686" | "x86" => Some(Architecture::X86),
+            "x86_64" | "amd64" | "x86-64" => Some(Architecture::X86_64),
+            "arm" | "armhf" | "armv7" | "armv7l" => Some(Architecture::Arm),
+            "aarch64" | "arm64" => Some(Architecture::Aarch64),
+            "riscv64" => Some(Architecture::RiscV64),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Architecture::X86 => write!(f, "i686"),
+            Architecture::X86_64 => write!(f, "x86_64"),
+            Architecture::Arm => write!(f, "armhf"),
+            Architecture::Aarch64 => write!(f, "aarch64"),
+            Architecture::RiscV64 => write!(f, "riscv64"),
+            Architecture::Other(machine) => write!(f, "unknown ({})", machine),
+        }
+    }
+}
+
+/// The ElfKind enum tells executables, libraries and other ELF files apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ElfKind {
+    /// Executable, including position independent ones
+    Executable,
+    /// Shared library
+    SharedObject,
+    /// Object file
+    Relocatable,
+    /// Core dump
+    Core,
+    /// Unrecognized ELF file type
+    Other(u16),
+}
+
+impl fmt::Display for ElfKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfKind::Executable => write!(f, "executable"),
+            ElfKind::SharedObject => write!(f, "shared object"),
+            ElfKind::Relocatable => write!(f, "relocatable"),
+            ElfKind::Core => write!(f, "core"),
+            ElfKind::Other(kind) => write!(f, "unknown ({})", kind),
+        }
+    }
+}
+
+/// The ElfInfo struct describes how an ELF binary is built and linked.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ElfInfo {
+    /// Machine type of the binary
+    pub architecture: Architecture,
+    /// Whether the binary uses the 64-bit ELF class
+    pub is_64bit: bool,
+    /// File type of the binary
+    pub kind: ElfKind,
+    /// Dynamic loader requested by the binary (`PT_INTERP`)
+    pub interpreter: Option<String>,
+    /// Libraries the binary is linked against (`DT_NEEDED`)
+    pub needed: Vec<String>,
+    /// Library search path (`DT_RPATH`), searched before `LD_LIBRARY_PATH`
+    pub rpath: Option<String>,
+    /// Library search path (`DT_RUNPATH`), searched after `LD_LIBRARY_PATH`
+    pub runpath: Option<String>,
+    /// Whether the binary needs neither a dynamic loader nor any library
+    pub is_static: bool,
+}
+
+fn invalid_elf<E: fmt::Display>(e: E) -> SquishyError {
+    SquishyError::InvalidElf(e.to_string())
+}
+
+/// Reads `size` bytes at the offset, refusing tables larger than `MAX_TABLE_SIZE`
+fn read_at<R: Read + Seek + ?Sized>(reader: &mut R, offset: u64, size: u64) -> Result<Vec<u8>> {
+    if size > MAX_TABLE_SIZE {
+        return Err(invalid_elf(format!(
+            "{} bytes at offset {} is too large for an ELF table",
+            size, offset
+        )));
+    }
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut bytes = vec![0; size as usize];
+    reader.read_exact(&mut bytes).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => {
+            invalid_elf(format!("table at offset {} ends past the file", offset))
+        }
+        _ => e.into(),
+    })?;
+    Ok(bytes)
+}
+
+/// Returns the string up to the first NUL byte
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Reads the ELF header, program headers and dynamic section of a binary,
+/// seeking to each of them instead of reading the whole file.
+///
+/// # Arguments
+/// * `reader` - The reader positioned anywhere in the binary.
+///
+/// # Returns
+/// The description of the binary, None if it doesn't start with the ELF
+/// magic bytes, or an error if it is malformed or cannot be read.
+pub fn read_elf<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Option<ElfInfo>> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut header_raw = Vec::with_capacity(header::header64::SIZEOF_EHDR);
+    reader
+        .take(header::header64::SIZEOF_EHDR as u64)
+        .read_to_end(&mut header_raw)?;
+    if !header_raw.starts_with(ELFMAG) {
+        return Ok(None);
+    }
+
+    let header = Elf::parse_header(&header_raw).map_err(invalid_elf)?;
+    let container = header.container().map_err(invalid_elf)?;
+    let ctx = Ctx::new(container, header.endianness().map_err(invalid_elf)?);
+
+    let phdrs_raw = read_at(
+        reader,
+        header.e_phoff,
+        u64::from(header.e_phnum) * u64::from(header.e_phentsize),
+    )?;
+    let phdrs = ProgramHeader::parse(&phdrs_raw, 0, usize::from(header.e_phnum), ctx)
+        .map_err(invalid_elf)?;
+
+    let interpreter = match phdrs.iter().find(|phdr| phdr.p_type == PT_INTERP) {
+        Some(phdr) => Some(c_string(&read_at(reader, phdr.p_offset, phdr.p_filesz)?)),
+        None => None,
+    };
+
+    let mut info = ElfInfo {
+        architecture: Architecture::from_elf_machine(header.e_machine),
+        is_64bit: container == Container::Big,
+        kind: match header.e_type {
+            ET_EXEC => ElfKind::Executable,
+            ET_DYN => ElfKind::SharedObject,
+            ET_REL => ElfKind::Relocatable,
+            ET_CORE => ElfKind::Core,
+            other => ElfKind::Other(other),
+        },
+        is_static: false,
+        interpreter,
+        needed: Vec::new(),
+        rpath: None,
+        runpath: None,
+    };
+
+    let mut is_pie = false;
+    if let Some(dynamic_phdr) = phdrs.iter().find(|phdr| phdr.p_type == PT_DYNAMIC) {
+        // Only the dynamic section is read, so it starts at offset 0. The
+        // loadable segments map addresses in it, like DT_STRTAB, to offsets.
+        let dynamic_raw = read_at(reader, dynamic_phdr.p_offset, dynamic_phdr.p_filesz)?;
+        let mut dynamic_phdrs = vec![ProgramHeader {
+            p_offset: 0,
+            ..dynamic_phdr.clone()
+        }];
+        dynamic_phdrs.extend(phdrs.iter().filter(|phdr| phdr.p_type == PT_LOAD).cloned());
+
+        if let Some(dynamic) =
+            Dynamic::parse(&dynamic_raw, &dynamic_phdrs, ctx).map_err(invalid_elf)?
+        {
+            is_pie = dynamic.info.flags_1 & DF_1_PIE != 0;
+            let strtab_raw = read_at(
+                reader,
+                dynamic.info.strtab as u64,
+                dynamic.info.strsz as u64,
+            )?;
+            for entry in &dynamic.dyns {
+                let Some(value) = strtab_raw.get(entry.d_val as usize..).map(c_string) else {
+                    continue;
+                };
+                match entry.d_tag {
+                    DT_NEEDED => info.needed.push(value),
+                    DT_RPATH => info.rpath = Some(value),
+                    DT_RUNPATH => info.runpath = Some(value),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // position independent executables are shared objects with a loader or the PIE flag
+    if info.kind == ElfKind::SharedObject && (info.interpreter.is_some() || is_pie) {
+        info.kind = ElfKind::Executable;
+    }
+    info.is_static = info.interpreter.is_none() && info.needed.is_empty();
+    Ok(Some(info))
+}
+
+impl SquashFS<'_> {
+    /// Inspects a file entry as an ELF binary, decompressing only the parts
+    /// of it that describe how it is linked.
+    ///
+    /// # Arguments
+    /// * `entry` - The file entry to inspect.
+    ///
+    /// # Returns
+    /// The description of the binary, None if the entry isn't a file or not
+    /// an ELF binary, or an error if it is malformed or cannot be read.
+    pub fn elf_info(&self, entry: &SquashFSEntry) -> Result<Option<ElfInfo>> {
+        if !matches!(entry.kind, EntryKind::File(_)) {
+            return Ok(None);
+        }
+        read_elf(&mut self.open_entry(entry)?)
+    }
+}
@@ -0,0 +1,1088 @@
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    fs::File,
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    error::{IoContext, SquishyError},
+    lookup_path, EntryStats, IoTuning, Result,
+};
+
+/// Magic number at the start of the EROFS superblock
+const EROFS_MAGIC: [u8; 4] = 0xE0F5_E1E2_u32.to_le_bytes();
+
+/// The superblock follows 1 KiB left to boot loaders
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const SUPERBLOCK_SIZE: usize = 128;
+
+/// Incompatible features up to big directories and xattr prefixes, which
+/// don't change how uncompressed files are stored. Compressed files and chunks
+/// on extra devices are refused when they are read.
+const SUPPORTED_FEATURES: u32 = 0x1ff;
+
+/// Inode numbers address 32 byte slots of the metadata area
+const INODE_SLOT_SIZE: u64 = 32;
+
+const LAYOUT_FLAT_PLAIN: u8 = 0;
+const LAYOUT_COMPRESSED_FULL: u8 = 1;
+const LAYOUT_FLAT_INLINE: u8 = 2;
+const LAYOUT_COMPRESSED_COMPACT: u8 = 3;
+const LAYOUT_CHUNK_BASED: u8 = 4;
+
+const CHUNK_BITS_MASK: u32 = 0x1f;
+const CHUNK_INDEXES: u32 = 0x20;
+/// Block address of chunks that are holes
+const NULL_ADDRESS: u32 = u32::MAX;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+
+/// Symlink targets are limited to PATH_MAX
+const MAX_LINK_SIZE: u64 = 4096;
+
+/// The most memory reserved up front for reading a file, the size comes from the image
+const MAX_PREALLOCATION: u64 = 16 << 20;
+
+trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// The Erofs struct reads an EROFS filesystem, like SquashFS does for SquashFS.
+/// Files stored uncompressed, inline or in chunks are supported, reading a
+/// compressed file fails with `SquishyError::Unsupported`.
+pub struct Erofs<'a> {
+    reader: Mutex<Box<dyn ReadSeek + Send + 'a>>,
+    offset: u64,
+    superblock: Superblock,
+    /// Every entry, sorted by path
+    entries: Vec<ErofsEntry>,
+    tuning: IoTuning,
+}
+
+/// The fields of the superblock needed to read the filesystem
+#[derive(Debug, Clone, Copy)]
+struct Superblock {
+    block_size: u64,
+    root_nid: u64,
+    /// Modification time of inodes without their own
+    build_time: u64,
+    /// Start of the metadata area holding the inodes
+    meta_start: u64,
+}
+
+/// An on-disk inode, compact or extended
+#[derive(Debug, Clone, Copy)]
+struct Inode {
+    nid: u64,
+    layout: u8,
+    mode: u32,
+    nlink: u32,
+    uid: u32,
+    gid: u32,
+    mtime: u64,
+    size: u64,
+    /// The block address, or the chunk format of chunk based files
+    raw: u32,
+    /// Position after the inode and its xattrs, where inline data and chunk indexes are
+    tail: u64,
+}
+
+/// The ErofsEntry struct represents a single entry within the EROFS filesystem.
+#[derive(Debug, Clone)]
+pub struct ErofsEntry {
+    pub path: PathBuf,
+    /// Size of files, 0 for other entries
+    pub size: u64,
+    pub kind: ErofsEntryKind,
+    inode: Inode,
+}
+
+impl ErofsEntry {
+    /// Returns the permission bits of the entry.
+    pub fn mode(&self) -> u32 {
+        self.inode.mode & 0o7777
+    }
+
+    /// Returns the owner user id of the entry.
+    pub fn uid(&self) -> u32 {
+        self.inode.uid
+    }
+
+    /// Returns the owner group id of the entry.
+    pub fn gid(&self) -> u32 {
+        self.inode.gid
+    }
+
+    /// Returns the modification time of the entry as seconds since the Unix
+    /// epoch. Compact inodes have the build time of the image.
+    pub fn mtime(&self) -> u64 {
+        self.inode.mtime
+    }
+
+    /// Returns the inode number (nid) of the entry, shared by hard links to
+    /// the same inode.
+    pub fn inode(&self) -> u64 {
+        self.inode.nid
+    }
+
+    /// Returns the number of hard links to the inode of the entry.
+    pub fn nlink(&self) -> u32 {
+        self.inode.nlink
+    }
+
+    /// Returns the file type bits of the mode
+    #[cfg(feature = "tar")]
+    pub(crate) fn file_type(&self) -> u32 {
+        self.inode.mode & S_IFMT
+    }
+
+    /// Returns the device number of device nodes, in the Linux encoding
+    #[cfg(feature = "tar")]
+    pub(crate) fn rdev(&self) -> u32 {
+        self.inode.raw
+    }
+}
+
+/// Serializes the entry like `SquashFSEntry`
+#[cfg(feature = "serde")]
+impl serde::Serialize for ErofsEntry {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut entry = serializer.serialize_struct("ErofsEntry", 10)?;
+        entry.serialize_field("path", &self.path)?;
+        entry.serialize_field("type", self.kind.name())?;
+        entry.serialize_field("mode", &self.mode())?;
+        entry.serialize_field("uid", &self.uid())?;
+        entry.serialize_field("gid", &self.gid())?;
+        entry.serialize_field("size", &self.size)?;
+        entry.serialize_field("mtime", &self.mtime())?;
+        entry.serialize_field("inode", &self.inode())?;
+        entry.serialize_field("nlink", &self.nlink())?;
+        match &self.kind {
+            ErofsEntryKind::Symlink(target) => entry.serialize_field("target", target)?,
+            _ => entry.skip_field("target")?,
+        }
+        entry.end()
+    }
+}
+
+/// The ErofsEntryKind enum represents the types of entries in the EROFS filesystem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErofsEntryKind {
+    File,
+    Directory,
+    /// The target, with a `/` prepended as in `EntryKind::Symlink`
+    Symlink(PathBuf),
+    Unknown,
+}
+
+impl ErofsEntryKind {
+    /// Returns the name of the kind: `file`, `directory`, `symlink` or `other`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErofsEntryKind::File => "file",
+            ErofsEntryKind::Directory => "directory",
+            ErofsEntryKind::Symlink(_) => "symlink",
+            ErofsEntryKind::Unknown => "other",
+        }
+    }
+}
+
+fn le16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn le32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn le64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// Returns the position of the chunk index entry of the size, failing
+/// instead of overflowing
+fn chunk_index(tail: u64, index: u64, size: u64) -> Result<u64> {
+    tail.checked_next_multiple_of(size)
+        .zip(index.checked_mul(size))
+        .and_then(|(start, entry)| start.checked_add(entry))
+        .ok_or_else(|| SquishyError::InvalidErofs(format!("chunk {} out of range", index)))
+}
+
+/// Adds an offset taken from the image, failing instead of overflowing
+fn offset(base: u64, offset: u64) -> Result<u64> {
+    base.checked_add(offset)
+        .ok_or_else(|| SquishyError::InvalidErofs(format!("offset {:#x} out of range", offset)))
+}
+
+impl Superblock {
+    fn parse(raw: &[u8; SUPERBLOCK_SIZE]) -> Result<Self> {
+        if raw[..4] != EROFS_MAGIC {
+            return Err(SquishyError::NoErofsFound);
+        }
+        let block_bits = raw[12];
+        if !(9..=16).contains(&block_bits) {
+            return Err(SquishyError::InvalidErofs(format!(
+                "invalid block size 2^{}",
+                block_bits
+            )));
+        }
+        let unsupported = le32(raw, 80) & !SUPPORTED_FEATURES;
+        if unsupported != 0 {
+            return Err(SquishyError::Unsupported(format!(
+                "EROFS features {:#x}",
+                unsupported
+            )));
+        }
+
+        let block_size = 1 << block_bits;
+        Ok(Self {
+            block_size,
+            root_nid: u64::from(le16(raw, 14)),
+            build_time: le64(raw, 24),
+            meta_start: u64::from(le32(raw, 40)) * block_size,
+        })
+    }
+}
+
+/// Splits a directory block into the names and inode numbers of its entries,
+/// or returns None if the block is malformed
+fn dirents(block: &[u8]) -> Option<Vec<(&[u8], u64)>> {
+    // names follow the 12 byte entries, so the first name tells their count
+    let count = usize::from(le16(block.get(..12)?, 8)) / 12;
+    if count == 0 {
+        return None;
+    }
+    (0..count)
+        .map(|i| {
+            let dirent = block.get(i * 12..i * 12 + 12)?;
+            let start = usize::from(le16(dirent, 8));
+            let name = if i + 1 < count {
+                block.get(start..usize::from(le16(block.get(..i * 12 + 24)?, i * 12 + 20)))?
+            } else {
+                // the last name ends at the end of the block or its padding
+                let name = block.get(start..)?;
+                name.split(|&b| b == 0).next()?
+            };
+            Some((name, le64(dirent, 0)))
+        })
+        .collect()
+}
+
+impl<'a> Erofs<'a> {
+    /// Creates a new Erofs instance from a BufReader.
+    ///
+    /// # Arguments
+    /// * `reader` - A BufReader that provides access to the EROFS data.
+    /// * `offset` - Offset of the EROFS data, found automatically if not given
+    ///
+    /// # Returns
+    /// An Erofs instance if the EROFS data is found and valid, or an error if it is not.
+    pub fn new<R>(mut reader: BufReader<R>, offset: Option<u64>) -> Result<Self>
+    where
+        R: Read + Seek + Send + 'a,
+    {
+        let (offset, superblock) = match offset {
+            Some(offset) => (offset, Self::read_superblock(&mut reader, offset)?),
+            None => Self::find_erofs(&mut reader, u64::MAX)?,
+        };
+        let mut erofs = Self {
+            reader: Mutex::new(Box::new(reader)),
+            offset,
+            superblock,
+            entries: Vec::new(),
+            tuning: IoTuning::default(),
+        };
+        erofs.entries = erofs.walk()?;
+        Ok(erofs)
+    }
+
+    /// Creates a new Erofs instance from a file path. Tries to find offset automatically.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the EROFS image or AppImage.
+    ///
+    /// # Returns
+    /// An Erofs instance if the EROFS data is found and valid, or an error if it is not.
+    pub fn from_path<P: AsRef<Path>>(path: &'a P) -> Result<Self> {
+        Erofs::from_path_with_tuning(path, None, IoTuning::default())
+    }
+
+    /// Creates a new Erofs instance from a file path.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the EROFS image or AppImage.
+    /// * `offset` - Offset of the EROFS data within the file
+    ///
+    /// # Returns
+    /// An Erofs instance if the EROFS data is found and valid, or an error if it is not.
+    pub fn from_path_with_offset<P: AsRef<Path>>(path: &'a P, offset: u64) -> Result<Self> {
+        Erofs::from_path_with_tuning(path, Some(offset), IoTuning::default())
+    }
+
+    /// Creates a new Erofs instance from a file path, reading and writing
+    /// with the buffer sizes of the tuning.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the EROFS image or AppImage.
+    /// * `offset` - Offset of the EROFS data, found automatically if not given
+    /// * `tuning` - The buffer sizes
+    ///
+    /// # Returns
+    /// An Erofs instance if the EROFS data is found and valid, or an error if it is not.
+    pub fn from_path_with_tuning<P: AsRef<Path>>(
+        path: &'a P,
+        offset: Option<u64>,
+        tuning: IoTuning,
+    ) -> Result<Self> {
+        let file = File::open(path).context("open", path)?;
+        let mut erofs = Erofs::new(BufReader::with_capacity(tuning.read_buf, file), offset)?;
+        erofs.tuning = tuning;
+        Ok(erofs)
+    }
+
+    /// Sets the buffer sizes. The read buffer is only used when opening an
+    /// image from a path, see `from_path_with_tuning`.
+    ///
+    /// # Arguments
+    /// * `tuning` - The buffer sizes
+    pub fn set_io_tuning(&mut self, tuning: IoTuning) {
+        self.tuning = tuning;
+    }
+
+    /// Returns the buffer sizes of the image.
+    pub fn io_tuning(&self) -> IoTuning {
+        self.tuning
+    }
+
+    /// Creates a new Erofs instance from the bytes of an image. Tries to find
+    /// the offset automatically if not given.
+    ///
+    /// # Arguments
+    /// * `data` - The contents of the EROFS image or AppImage.
+    /// * `offset` - Offset of the EROFS data within the bytes.
+    ///
+    /// # Returns
+    /// An Erofs instance if the EROFS data is found and valid, or an error if it is not.
+    pub fn from_bytes(data: &'a [u8], offset: Option<u64>) -> Result<Self> {
+        Erofs::new(BufReader::new(Cursor::new(data)), offset)
+    }
+
+    /// Returns the offset of the EROFS data within the file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the block size of the filesystem.
+    pub fn block_size(&self) -> u32 {
+        self.superblock.block_size as u32
+    }
+
+    /// Counts the entries of the filesystem by kind, and the total size of files.
+    pub fn stats(&self) -> EntryStats {
+        self.entries
+            .iter()
+            .fold(EntryStats::default(), |mut stats, entry| {
+                match entry.kind {
+                    ErofsEntryKind::File => {
+                        stats.files += 1;
+                        stats.total_size += entry.size;
+                    }
+                    ErofsEntryKind::Directory => stats.directories += 1,
+                    ErofsEntryKind::Symlink(_) => stats.symlinks += 1,
+                    ErofsEntryKind::Unknown => stats.other += 1,
+                }
+                stats
+            })
+    }
+
+    /// Reads and checks the superblock of the EROFS data at the offset
+    fn read_superblock<R: Read + Seek>(
+        reader: &mut BufReader<R>,
+        offset: u64,
+    ) -> Result<Superblock> {
+        let mut raw = [0; SUPERBLOCK_SIZE];
+        reader.seek(SeekFrom::Start(offset + SUPERBLOCK_OFFSET))?;
+        reader
+            .read_exact(&mut raw)
+            .map_err(|_| SquishyError::NoErofsFound)?;
+        Superblock::parse(&raw)
+    }
+
+    /// Finds the EROFS data by scanning up to `limit` bytes for a valid
+    /// superblock, e.g. behind the runtime of an AppImage.
+    fn find_erofs<R: Read + Seek>(
+        reader: &mut BufReader<R>,
+        limit: u64,
+    ) -> Result<(u64, Superblock)> {
+        let start = reader.stream_position()?;
+        let end = start.saturating_add(limit);
+        let mut base = start;
+        let mut buf = vec![0_u8; 64 * 1024];
+        let mut carry = 0;
+
+        loop {
+            let room = end
+                .saturating_sub(base + carry as u64)
+                .min((buf.len() - carry) as u64) as usize;
+            let read = reader.read(&mut buf[carry..carry + room])?;
+            if read == 0 {
+                break;
+            }
+            let filled = carry + read;
+            let candidates: Vec<u64> = buf[..filled]
+                .windows(EROFS_MAGIC.len())
+                .enumerate()
+                .filter(|(_, window)| *window == EROFS_MAGIC)
+                .map(|(pos, _)| base + pos as u64)
+                .filter(|&pos| pos >= start + SUPERBLOCK_OFFSET)
+                .collect();
+            if !candidates.is_empty() {
+                for pos in candidates {
+                    let offset = pos - SUPERBLOCK_OFFSET;
+                    if let Ok(superblock) = Self::read_superblock(reader, offset) {
+                        return Ok((offset, superblock));
+                    }
+                }
+                reader.seek(SeekFrom::Start(base + filled as u64))?;
+            }
+            // keep the tail in case the magic spans two reads
+            carry = filled.min(EROFS_MAGIC.len() - 1);
+            buf.copy_within(filled - carry..filled, 0);
+            base += (filled - carry) as u64;
+        }
+        Err(SquishyError::NoErofsFound)
+    }
+
+    /// Finds the offset of the EROFS data, see `find_erofs`
+    pub(crate) fn find_offset<R: Read + Seek>(
+        reader: &mut BufReader<R>,
+        limit: u64,
+    ) -> Result<u64> {
+        Self::find_erofs(reader, limit).map(|(offset, _)| offset)
+    }
+
+    /// Checks whether there is a valid superblock for EROFS data at the offset
+    pub(crate) fn has_superblock<R: Read + Seek>(reader: &mut BufReader<R>, offset: u64) -> bool {
+        Self::read_superblock(reader, offset).is_ok()
+    }
+
+    /// Reads bytes at the position within the EROFS data
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        let pos = offset(self.offset, pos)?;
+        let mut reader = self
+            .reader
+            .lock()
+            .map_err(|_| io::Error::other("EROFS reader poisoned"))?;
+        reader.seek(SeekFrom::Start(pos))?;
+        reader.read_exact(buf)?;
+        Ok(())
+    }
+
+    /// Reads the inode with the number
+    fn read_inode(&self, nid: u64) -> Result<Inode> {
+        let pos = nid
+            .checked_mul(INODE_SLOT_SIZE)
+            .and_then(|pos| pos.checked_add(self.superblock.meta_start))
+            .ok_or_else(|| SquishyError::InvalidErofs(format!("invalid inode number {}", nid)))?;
+
+        let mut raw = [0; 64];
+        self.read_at(pos, &mut raw[..32])?;
+        let format = le16(&raw, 0);
+        let xattr_count = u64::from(le16(&raw, 2));
+        let mode = u32::from(le16(&raw, 4));
+        let extended = format & 1 == 1;
+
+        let mut inode = Inode {
+            nid,
+            layout: (format >> 1 & 0x7) as u8,
+            mode,
+            nlink: u32::from(le16(&raw, 6)),
+            uid: u32::from(le16(&raw, 24)),
+            gid: u32::from(le16(&raw, 26)),
+            mtime: self.superblock.build_time,
+            size: u64::from(le32(&raw, 8)),
+            raw: le32(&raw, 16),
+            tail: offset(pos, 32)?,
+        };
+        if extended {
+            self.read_at(inode.tail, &mut raw[32..])?;
+            inode.size = le64(&raw, 8);
+            inode.uid = le32(&raw, 24);
+            inode.gid = le32(&raw, 28);
+            inode.mtime = le64(&raw, 32);
+            inode.nlink = le32(&raw, 44);
+            inode.tail = offset(pos, 64)?;
+        }
+        if xattr_count > 0 {
+            // a 12 byte header, then 4 byte slots
+            inode.tail = offset(inode.tail, 12 + (xattr_count - 1) * 4)?;
+        }
+        Ok(inode)
+    }
+
+    /// Returns the position of the data at `pos` within the file, or None
+    /// for holes. The data is contiguous up to the end of the block.
+    fn locate(&self, inode: &Inode, pos: u64) -> Result<Option<u64>> {
+        let block_size = self.superblock.block_size;
+        // a 32 bit block address within 64 KiB blocks always fits
+        let start = u64::from(inode.raw) * block_size;
+        match inode.layout {
+            LAYOUT_FLAT_PLAIN => offset(start, pos).map(Some),
+            LAYOUT_FLAT_INLINE => {
+                // the tail shorter than a block follows the inode
+                let blocks_end = inode.size / block_size * block_size;
+                if pos < blocks_end {
+                    offset(start, pos).map(Some)
+                } else {
+                    offset(inode.tail, pos - blocks_end).map(Some)
+                }
+            }
+            LAYOUT_CHUNK_BASED => {
+                let chunk_size = block_size << (inode.raw & CHUNK_BITS_MASK);
+                let index = pos / chunk_size;
+                let address = if inode.raw & CHUNK_INDEXES != 0 {
+                    let mut raw = [0; 8];
+                    self.read_at(chunk_index(inode.tail, index, 8)?, &mut raw)?;
+                    if le16(&raw, 2) != 0 {
+                        return Err(SquishyError::Unsupported(
+                            "EROFS chunks on extra devices".into(),
+                        ));
+                    }
+                    le32(&raw, 4)
+                } else {
+                    let mut raw = [0; 4];
+                    self.read_at(chunk_index(inode.tail, index, 4)?, &mut raw)?;
+                    le32(&raw, 0)
+                };
+                Ok((address != NULL_ADDRESS)
+                    .then(|| u64::from(address) * block_size + pos % chunk_size))
+            }
+            LAYOUT_COMPRESSED_FULL | LAYOUT_COMPRESSED_COMPACT => {
+                Err(SquishyError::Unsupported("compressed EROFS files".into()))
+            }
+            layout => Err(SquishyError::InvalidErofs(format!(
+                "invalid data layout {}",
+                layout
+            ))),
+        }
+    }
+
+    /// Passes the data of the inode to `f` a block at a time
+    fn read_blocks(&self, inode: &Inode, mut f: impl FnMut(&[u8]) -> Result<()>) -> Result<()> {
+        let block_size = self.superblock.block_size;
+        let mut buffer = vec![0; block_size as usize];
+        let mut pos = 0;
+        while pos < inode.size {
+            let len = (inode.size - pos).min(block_size - pos % block_size);
+            let block = &mut buffer[..len as usize];
+            match self.locate(inode, pos)? {
+                Some(address) => self.read_at(address, block)?,
+                None => block.fill(0),
+            }
+            f(block)?;
+            pos += len;
+        }
+        Ok(())
+    }
+
+    /// Reads up to `buf.len()` bytes of the file at the offset, fewer only at
+    /// the end of the file, returning the number of bytes read
+    #[cfg(feature = "fuse")]
+    pub(crate) fn read_entry_at(
+        &self,
+        entry: &ErofsEntry,
+        mut pos: u64,
+        buf: &mut [u8],
+    ) -> Result<usize> {
+        let inode = &entry.inode;
+        let block_size = self.superblock.block_size;
+        let mut read = 0;
+        while read < buf.len() && pos < inode.size {
+            let len = (inode.size - pos)
+                .min(block_size - pos % block_size)
+                .min((buf.len() - read) as u64);
+            let chunk = &mut buf[read..read + len as usize];
+            match self.locate(inode, pos)? {
+                Some(address) => self.read_at(address, chunk)?,
+                None => chunk.fill(0),
+            }
+            read += len as usize;
+            pos += len;
+        }
+        Ok(read)
+    }
+
+    /// Reads the entries of the directory, as names and inode numbers
+    fn read_dir(&self, path: &Path, inode: &Inode) -> Result<Vec<(Vec<u8>, u64)>> {
+        let mut children = Vec::new();
+        self.read_blocks(inode, |block| {
+            let entries = dirents(block).ok_or_else(|| {
+                SquishyError::InvalidErofs(format!("malformed directory {}", path.display()))
+            })?;
+            children.extend(
+                entries
+                    .into_iter()
+                    .filter(|(name, _)| *name != b"." && *name != b"..")
+                    .map(|(name, nid)| (name.to_vec(), nid)),
+            );
+            Ok(())
+        })?;
+        Ok(children)
+    }
+
+    /// Creates the entry of the inode, reading the target of symlinks
+    fn entry(&self, path: PathBuf, inode: Inode) -> Result<ErofsEntry> {
+        let kind = match inode.mode & S_IFMT {
+            S_IFREG => ErofsEntryKind::File,
+            S_IFDIR => ErofsEntryKind::Directory,
+            S_IFLNK => {
+                if inode.size > MAX_LINK_SIZE {
+                    return Err(SquishyError::InvalidErofs(format!(
+                        "symlink {} is too long",
+                        path.display()
+                    )));
+                }
+                let mut target = b"/".to_vec();
+                self.read_blocks(&inode, |block| {
+                    target.extend_from_slice(block);
+                    Ok(())
+                })?;
+                ErofsEntryKind::Symlink(PathBuf::from(OsStr::from_bytes(&target)))
+            }
+            _ => ErofsEntryKind::Unknown,
+        };
+        let size = match kind {
+            ErofsEntryKind::File => inode.size,
+            _ => 0,
+        };
+        Ok(ErofsEntry {
+            path,
+            size,
+            kind,
+            inode,
+        })
+    }
+
+    /// Reads every entry, starting from the root directory
+    fn walk(&self) -> Result<Vec<ErofsEntry>> {
+        let root_path = PathBuf::from("/");
+        let root = self.entry(root_path, self.read_inode(self.superblock.root_nid)?)?;
+        if root.kind != ErofsEntryKind::Directory {
+            return Err(SquishyError::InvalidErofs(
+                "the root is not a directory".into(),
+            ));
+        }
+
+        let mut visited = HashSet::from([self.superblock.root_nid]);
+        let mut pending = vec![(root.path.clone(), root.inode)];
+        let mut entries = vec![root];
+        while let Some((dir, inode)) = pending.pop() {
+            for (name, nid) in self.read_dir(&dir, &inode)? {
+                let path = dir.join(OsStr::from_bytes(&name));
+                if name.is_empty() || name.contains(&b'/') {
+                    return Err(SquishyError::InvalidErofs(format!(
+                        "invalid name in {}",
+                        dir.display()
+                    )));
+                }
+                let entry = self.entry(path, self.read_inode(nid)?)?;
+                if entry.kind == ErofsEntryKind::Directory {
+                    // directories can't be hard links, so this is a loop
+                    if !visited.insert(nid) {
+                        return Err(SquishyError::InvalidErofs(format!(
+                            "directory {} appears twice",
+                            entry.path.display()
+                        )));
+                    }
+                    pending.push((entry.path.clone(), entry.inode));
+                }
+                entries.push(entry);
+            }
+        }
+
+        // sorted for looking up entries by path
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    /// Returns an iterator over all the entries in the EROFS filesystem, sorted by path.
+    pub fn entries(&self) -> impl Iterator<Item = ErofsEntry> + '_ {
+        self.entries.iter().cloned()
+    }
+
+    /// Same as `entries`, which are always sorted by path, component by
+    /// component. Mirrors `SquashFS::entries_sorted`.
+    pub fn entries_sorted(&self) -> impl Iterator<Item = ErofsEntry> + '_ {
+        self.entries()
+    }
+
+    /// Looks up a single entry by its path. The leading `/` is optional.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the entry within the EROFS filesystem.
+    ///
+    /// # Returns
+    /// The entry, or None if there is no entry at the path or the path has a
+    /// `..` component.
+    pub fn find<P: AsRef<Path>>(&self, path: P) -> Option<ErofsEntry> {
+        let path = lookup_path(path.as_ref())?;
+        self.entries
+            .binary_search_by(|entry| entry.path.as_path().cmp(&path))
+            .ok()
+            .map(|index| self.entries[index].clone())
+    }
+
+    /// Checks whether an entry exists at the path. The leading `/` is optional.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the entry within the EROFS filesystem.
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.find(path).is_some()
+    }
+
+    /// Returns an iterator over all the entries in the EROFS filesystem
+    /// that match the provided predicate function.
+    ///
+    /// # Arguments
+    /// * `predicate` - A function that takes a &Path and returns a bool, indicating whether the entry should be included.
+    pub fn find_entries<F>(&self, predicate: F) -> impl Iterator<Item = ErofsEntry> + '_
+    where
+        F: Fn(&Path) -> bool + 'a,
+    {
+        self.entries().filter(move |entry| predicate(&entry.path))
+    }
+
+    /// Reads the contents of the specified file from the EROFS filesystem.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the file within the EROFS filesystem.
+    ///
+    /// # Returns
+    /// The contents of the file as a Vec<u8>, or an error if the file is not found.
+    pub fn read_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        self.read_file_with_limit(path, u64::MAX)
+    }
+
+    /// Reads the contents of the specified file, unless it is larger than the limit.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the file within the EROFS filesystem.
+    /// * `max_bytes` - The largest file size to read
+    ///
+    /// # Returns
+    /// The contents of the file, or an error if the file is not found or too large.
+    pub fn read_file_with_limit<P: AsRef<Path>>(&self, path: P, max_bytes: u64) -> Result<Vec<u8>> {
+        let path = path.as_ref();
+        match self.find(path) {
+            Some(entry) => self.read_entry_with_limit(&entry, max_bytes),
+            None => Err(SquishyError::FileNotFound(path.to_path_buf())),
+        }
+    }
+
+    /// Reads the contents of the specified entry from the EROFS filesystem.
+    ///
+    /// # Arguments
+    /// * `entry` - The file entry within the EROFS filesystem.
+    ///
+    /// # Returns
+    /// The contents of the file as a Vec<u8>, or an error if the entry is not a file.
+    pub fn read_entry(&self, entry: &ErofsEntry) -> Result<Vec<u8>> {
+        self.read_entry_with_limit(entry, u64::MAX)
+    }
+
+    /// Reads the contents of the specified entry, unless it is larger than the limit.
+    ///
+    /// # Arguments
+    /// * `entry` - The file entry within the EROFS filesystem.
+    /// * `max_bytes` - The largest file size to read
+    ///
+    /// # Returns
+    /// The contents of the file, or an error if the entry is not a file or too large.
+    pub fn read_entry_with_limit(&self, entry: &ErofsEntry, max_bytes: u64) -> Result<Vec<u8>> {
+        if entry.size > max_bytes {
+            return Err(SquishyError::FileTooLarge {
+                path: entry.path.clone(),
+                size: entry.size,
+                limit: max_bytes,
+            });
+        }
+        let mut contents = Vec::with_capacity(entry.size.min(MAX_PREALLOCATION) as usize);
+        self.copy_entry_to(entry, &mut contents)?;
+        Ok(contents)
+    }
+
+    /// Streams the contents of the file at the path to the writer, e.g. a
+    /// socket, a hasher or a tar builder, without buffering it in memory.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the file within the EROFS filesystem.
+    /// * `writer` - The writer to copy the contents to.
+    ///
+    /// # Returns
+    /// The number of bytes copied, or an error if there is no file at the path.
+    pub fn copy_file_to<P: AsRef<Path>, W: Write + ?Sized>(
+        &self,
+        path: P,
+        writer: &mut W,
+    ) -> Result<u64> {
+        let path = path.as_ref();
+        match self.find(path) {
+            Some(entry) => self.copy_entry_to(&entry, writer),
+            None => Err(SquishyError::FileNotFound(path.to_path_buf())),
+        }
+    }
+
+    /// Streams the contents of the specified entry to the writer, without
+    /// buffering the whole file in memory.
+    ///
+    /// # Arguments
+    /// * `entry` - The file entry within the EROFS filesystem.
+    /// * `writer` - The writer to copy the contents to.
+    ///
+    /// # Returns
+    /// The number of bytes copied, or an error if the entry is not a file.
+    pub fn copy_entry_to<W: Write + ?Sized>(
+        &self,
+        entry: &ErofsEntry,
+        writer: &mut W,
+    ) -> Result<u64> {
+        if entry.kind != ErofsEntryKind::File {
+            return Err(SquishyError::FileNotFound(entry.path.clone()));
+        }
+        self.read_blocks(&entry.inode, |block| Ok(writer.write_all(block)?))?;
+        Ok(entry.size)
+    }
+
+    /// Resolves the symlink chain starting from the specified entry,
+    /// returning the final target entry or an error if a cycle is detected.
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to resolve the symlink for.
+    ///
+    /// # Returns
+    /// The final target entry, or None if the entry is not a symlink or the
+    /// chain is dangling, or an error if a cycle is detected.
+    pub fn resolve_symlink(&self, entry: &ErofsEntry) -> Result<Option<ErofsEntry>> {
+        let ErofsEntryKind::Symlink(target) = &entry.kind else {
+            return Ok(None);
+        };
+        let mut visited = HashSet::from([entry.path.clone()]);
+        let mut target = target.clone();
+        loop {
+            if !visited.insert(target.clone()) {
+                return Err(SquishyError::SymlinkError("Cyclic symlink detected".into()));
+            }
+            match self.find(&target) {
+                Some(ErofsEntry {
+                    kind: ErofsEntryKind::Symlink(next),
+                    ..
+                }) => target = next,
+                found => return Ok(found),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const BLOCK: usize = 4096;
+    const BUILD_TIME: u64 = 1_700_000_000;
+
+    /// Writes a compact inode, with the inline data following it
+    fn compact_inode(image: &mut [u8], nid: u64, layout: u8, mode: u16, data: &[u8], raw: u32) {
+        let pos = BLOCK + nid as usize * 32;
+        let inode = &mut image[pos..pos + 32];
+        inode[0..2].copy_from_slice(&(u16::from(layout) << 1).to_le_bytes());
+        inode[4..6].copy_from_slice(&mode.to_le_bytes());
+        inode[6..8].copy_from_slice(&1_u16.to_le_bytes());
+        inode[8..12].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        inode[16..20].copy_from_slice(&raw.to_le_bytes());
+        inode[24..26].copy_from_slice(&1000_u16.to_le_bytes());
+        inode[26..28].copy_from_slice(&100_u16.to_le_bytes());
+        if layout == LAYOUT_FLAT_INLINE {
+            image[pos + 32..pos + 32 + data.len()].copy_from_slice(data);
+        }
+    }
+
+    /// Returns a directory block with the entries, names sorted
+    fn dir_block(entries: &[(&[u8], u64)]) -> Vec<u8> {
+        let mut block = vec![0; entries.len() * 12];
+        for (i, (name, nid)) in entries.iter().enumerate() {
+            let start = block.len() as u16;
+            block[i * 12..i * 12 + 8].copy_from_slice(&nid.to_le_bytes());
+            block[i * 12 + 8..i * 12 + 10].copy_from_slice(&start.to_le_bytes());
+            block.extend_from_slice(name);
+        }
+        block
+    }
+
+    /// Builds an image with `/bin` (an extended inode), `/hello` and `/link`
+    /// pointing to it
+    pub(crate) fn image() -> Vec<u8> {
+        let mut image = vec![0; BLOCK * 4];
+        let sb = &mut image[1024..1024 + SUPERBLOCK_SIZE];
+        sb[..4].copy_from_slice(&EROFS_MAGIC);
+        sb[12] = 12;
+        sb[24..32].copy_from_slice(&BUILD_TIME.to_le_bytes());
+        sb[40..44].copy_from_slice(&1_u32.to_le_bytes());
+
+        let root = dir_block(&[
+            (b".", 0),
+            (b"..", 0),
+            (b"bin", 6),
+            (b"hello", 2),
+            (b"link", 4),
+        ]);
+        image[BLOCK * 2..BLOCK * 2 + root.len()].copy_from_slice(&root);
+        compact_inode(&mut image, 0, LAYOUT_FLAT_PLAIN, 0o040755, &root, 2);
+        compact_inode(&mut image, 2, LAYOUT_FLAT_INLINE, 0o100644, b"hello", 0);
+        compact_inode(&mut image, 4, LAYOUT_FLAT_INLINE, 0o120777, b"hello", 0);
+
+        let bin = dir_block(&[(b".", 6), (b"..", 0)]);
+        image[BLOCK * 3..BLOCK * 3 + bin.len()].copy_from_slice(&bin);
+        let pos = BLOCK + 6 * 32;
+        let inode = &mut image[pos..pos + 64];
+        inode[0..2].copy_from_slice(&1_u16.to_le_bytes());
+        inode[4..6].copy_from_slice(&0o040700_u16.to_le_bytes());
+        inode[8..16].copy_from_slice(&(bin.len() as u64).to_le_bytes());
+        inode[16..20].copy_from_slice(&3_u32.to_le_bytes());
+        inode[24..28].copy_from_slice(&70_000_u32.to_le_bytes());
+        inode[28..32].copy_from_slice(&80_000_u32.to_le_bytes());
+        inode[32..40].copy_from_slice(&42_u64.to_le_bytes());
+        inode[44..48].copy_from_slice(&2_u32.to_le_bytes());
+        image
+    }
+
+    #[test]
+    fn dirents_split_names() {
+        let mut block = dir_block(&[(b".", 1), (b"..", 2), (b"name", 3)]);
+        assert_eq!(
+            dirents(&block).unwrap(),
+            [(&b"."[..], 1), (&b".."[..], 2), (&b"name"[..], 3)]
+        );
+        // the last name ends at the padding
+        block.extend_from_slice(&[0; 8]);
+        assert_eq!(dirents(&block).unwrap()[2], (&b"name"[..], 3));
+    }
+
+    #[test]
+    fn dirents_refuse_malformed_blocks() {
+        assert_eq!(dirents(&[0; 8]), None);
+        assert_eq!(dirents(&[0; 12]), None);
+        let mut block = dir_block(&[(b".", 1), (b"..", 2)]);
+        // the first name starts past the end of the block
+        block[8..10].copy_from_slice(&100_u16.to_le_bytes());
+        assert_eq!(dirents(&block), None);
+    }
+
+    #[test]
+    fn read_inode_compact_and_extended() {
+        let image = image();
+        let erofs = Erofs::from_bytes(&image, Some(0)).unwrap();
+
+        let hello = erofs.read_inode(2).unwrap();
+        assert_eq!(hello.layout, LAYOUT_FLAT_INLINE);
+        assert_eq!(hello.mode, 0o100644);
+        assert_eq!(hello.size, 5);
+        assert_eq!((hello.uid, hello.gid), (1000, 100));
+        assert_eq!(hello.mtime, BUILD_TIME);
+        assert_eq!(hello.tail, BLOCK as u64 + 2 * 32 + 32);
+
+        let bin = erofs.read_inode(6).unwrap();
+        assert_eq!(bin.mode, 0o040700);
+        assert_eq!((bin.uid, bin.gid), (70_000, 80_000));
+        assert_eq!(bin.mtime, 42);
+        assert_eq!(bin.nlink, 2);
+        assert_eq!(bin.tail, BLOCK as u64 + 6 * 32 + 64);
+
+        assert!(erofs.read_inode(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn locate_refuses_overflowing_offsets() {
+        let image = image();
+        let erofs = Erofs::from_bytes(&image, Some(0)).unwrap();
+        let mut inode = erofs.read_inode(2).unwrap();
+        inode.size = u64::MAX;
+
+        inode.layout = LAYOUT_FLAT_PLAIN;
+        inode.raw = u32::MAX;
+        assert!(matches!(
+            erofs.locate(&inode, u64::MAX - 1),
+            Err(SquishyError::InvalidErofs(_))
+        ));
+
+        inode.layout = LAYOUT_FLAT_INLINE;
+        inode.tail = u64::MAX - 2;
+        assert!(matches!(
+            erofs.locate(&inode, u64::MAX - 1),
+            Err(SquishyError::InvalidErofs(_))
+        ));
+
+        inode.layout = LAYOUT_CHUNK_BASED;
+        inode.raw = CHUNK_INDEXES;
+        assert!(matches!(
+            erofs.locate(&inode, u64::MAX - 1),
+            Err(SquishyError::InvalidErofs(_))
+        ));
+    }
+
+    #[test]
+    fn walk_reads_every_entry() {
+        let image = image();
+        let erofs = Erofs::from_bytes(&image, None).unwrap();
+        let paths: Vec<PathBuf> = erofs.entries().map(|entry| entry.path).collect();
+        assert_eq!(paths, ["/", "/bin", "/hello", "/link"].map(PathBuf::from));
+
+        assert_eq!(erofs.read_file("hello").unwrap(), b"hello");
+        let link = erofs.find("/link").unwrap();
+        assert_eq!(link.kind, ErofsEntryKind::Symlink(PathBuf::from("/hello")));
+        assert_eq!(erofs.find("/bin").unwrap().mode(), 0o700);
+        assert!(erofs.find("/bin/../hello").is_none());
+    }
+
+    #[test]
+    fn resolve_symlink_follows_and_refuses_cycles() {
+        let image = image();
+        let erofs = Erofs::from_bytes(&image, None).unwrap();
+        let link = erofs.find("/link").unwrap();
+        let hello = erofs.resolve_symlink(&link).unwrap().unwrap();
+        assert_eq!(hello.path, Path::new("/hello"));
+        assert!(erofs.resolve_symlink(&hello).unwrap().is_none());
+
+        let mut cyclic = image.clone();
+        compact_inode(&mut cyclic, 4, LAYOUT_FLAT_INLINE, 0o120777, b"link", 0);
+        let erofs = Erofs::from_bytes(&cyclic, None).unwrap();
+        let link = erofs.find("/link").unwrap();
+        assert!(matches!(
+            erofs.resolve_symlink(&link),
+            Err(SquishyError::SymlinkError(_))
+        ));
+    }
+
+    #[test]
+    fn walk_refuses_directory_loops() {
+        let mut image = image();
+        let bin = dir_block(&[(b".", 6), (b"..", 0), (b"loop", 0)]);
+        image[BLOCK * 3..BLOCK * 3 + bin.len()].copy_from_slice(&bin);
+        let pos = BLOCK + 6 * 32;
+        image[pos + 8..pos + 16].copy_from_slice(&(bin.len() as u64).to_le_bytes());
+        assert!(matches!(
+            Erofs::from_bytes(&image, Some(0)),
+            Err(SquishyError::InvalidErofs(_))
+        ));
+    }
+}
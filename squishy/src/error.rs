@@ -1,21 +1,228 @@
-use std::path::PathBuf;
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
 
 use thiserror::Error;
 
+/// Broad category of an error, stable across new `SquishyError` variants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Reading or writing failed for reasons outside the image, e.g. permissions or disk space
+    Io,
+    /// A file, an entry inside the image or the filesystem itself doesn't exist
+    NotFound,
+    /// The image or a file inside it is damaged or malformed
+    Corrupt,
+    /// The input is valid, but uses something squishy can't handle
+    Unsupported,
+    /// An argument, pattern or input directory is invalid
+    InvalidInput,
+    /// Anything else, e.g. an unexpected failure in a dependency
+    Internal,
+}
+
+impl ErrorKind {
+    /// Returns the numeric code of the kind, which never changes
+    pub fn code(self) -> u16 {
+        match self {
+            ErrorKind::Io => 1,
+            ErrorKind::NotFound => 2,
+            ErrorKind::Corrupt => 3,
+            ErrorKind::Unsupported => 4,
+            ErrorKind::InvalidInput => 5,
+            ErrorKind::Internal => 6,
+        }
+    }
+
+    /// Returns the name of the kind in snake case, e.g. `not_found`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Io => "io",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::Corrupt => "corrupt",
+            ErrorKind::Unsupported => "unsupported",
+            ErrorKind::InvalidInput => "invalid_input",
+            ErrorKind::Internal => "internal",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<io::ErrorKind> for ErrorKind {
+    fn from(kind: io::ErrorKind) -> Self {
+        match kind {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => ErrorKind::Corrupt,
+            io::ErrorKind::Unsupported => ErrorKind::Unsupported,
+            io::ErrorKind::InvalidInput => ErrorKind::InvalidInput,
+            _ => ErrorKind::Io,
+        }
+    }
+}
+
+/// The Quota enum names the extraction limit that was exceeded, with its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quota {
+    /// Total size of the extracted files, in bytes
+    TotalBytes(u64),
+    /// Number of extracted entries
+    Entries(u64),
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quota::TotalBytes(limit) => write!(f, "limit of {} bytes", limit),
+            Quota::Entries(limit) => write!(f, "limit of {} entries", limit),
+        }
+    }
+}
+
+/// Errors of squishy. New variants may be added, match on `kind()` to handle
+/// categories of errors.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum SquishyError {
     #[error("Failed to find SquashFS magic bytes in the file")]
     NoSquashFsFound,
 
+    #[error("Failed to find EROFS magic bytes in the file")]
+    NoErofsFound,
+
+    #[error("Failed to find DwarFS magic bytes in the file")]
+    NoDwarFsFound,
+
+    #[error("Failed to find a SquashFS, EROFS or DwarFS filesystem in the file")]
+    NoFilesystemFound,
+
     #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
+    Io(#[from] io::Error),
+
+    #[error("Failed to {op} {}: {source}", path.display())]
+    PathIo {
+        /// What was done with the path, e.g. `create` or `read directory`
+        op: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
 
     #[error("SquashFS error: {0}")]
     InvalidSquashFS(String),
 
+    #[error("EROFS error: {0}")]
+    InvalidErofs(String),
+
+    #[error("ELF error: {0}")]
+    InvalidElf(String),
+
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+
     #[error("Symlink error: {0}")]
     SymlinkError(String),
 
     #[error("File not found: {0}")]
     FileNotFound(PathBuf),
+
+    #[error("File {} is {size} bytes, more than the limit of {limit} bytes", path.display())]
+    FileTooLarge {
+        path: PathBuf,
+        /// Size of the file in the image
+        size: u64,
+        /// The most bytes the caller allowed to read
+        limit: u64,
+    },
+
+    #[error("Refusing to extract {}: {reason}", path.display())]
+    UnsafePath {
+        path: PathBuf,
+        /// Why the path would end up outside of the destination
+        reason: &'static str,
+    },
+
+    #[error("Extraction exceeded the {quota}, aborted after {} entries", written.len())]
+    QuotaExceeded {
+        quota: Quota,
+        /// Destinations of the entries extracted before aborting
+        written: Vec<PathBuf>,
+    },
+
+    #[error("Invalid pattern: {0}")]
+    InvalidPattern(String),
+
+    #[error("Image error: {0}")]
+    Image(String),
+
+    #[error("Pack error: {0}")]
+    Pack(String),
+}
+
+impl SquishyError {
+    /// Returns the category of the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            SquishyError::Io(e) | SquishyError::PathIo { source: e, .. } => e.kind().into(),
+            SquishyError::NoSquashFsFound
+            | SquishyError::NoErofsFound
+            | SquishyError::NoDwarFsFound
+            | SquishyError::NoFilesystemFound
+            | SquishyError::FileNotFound(_) => ErrorKind::NotFound,
+            SquishyError::InvalidSquashFS(_)
+            | SquishyError::InvalidErofs(_)
+            | SquishyError::InvalidElf(_)
+            | SquishyError::SymlinkError(_)
+            | SquishyError::Image(_) => ErrorKind::Corrupt,
+            SquishyError::Unsupported(_) => ErrorKind::Unsupported,
+            SquishyError::InvalidPattern(_)
+            | SquishyError::Pack(_)
+            | SquishyError::UnsafePath { .. }
+            | SquishyError::FileTooLarge { .. }
+            | SquishyError::QuotaExceeded { .. } => ErrorKind::InvalidInput,
+        }
+    }
+
+    /// Returns the numeric code of the error category, see `ErrorKind::code`
+    pub fn code(&self) -> u16 {
+        self.kind().code()
+    }
+
+    /// Returns the underlying IO error, whether or not it names a path
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            SquishyError::Io(e) | SquishyError::PathIo { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Adds the failed operation and the offending path to IO errors
+pub trait IoContext<T> {
+    /// Wraps the error into `SquishyError::PathIo`
+    ///
+    /// # Arguments
+    /// * `op` - What was done with the path, completing "Failed to ...", e.g. `open`
+    /// * `path` - The path the operation failed on
+    fn context<P: AsRef<Path>>(self, op: &'static str, path: P) -> Result<T, SquishyError>;
+}
+
+impl<T> IoContext<T> for io::Result<T> {
+    fn context<P: AsRef<Path>>(self, op: &'static str, path: P) -> Result<T, SquishyError> {
+        self.map_err(|source| SquishyError::PathIo {
+            op,
+            path: path.as_ref().to_path_buf(),
+            source,
+        })
+    }
 }
@@ -0,0 +1,412 @@
+use std::{ffi::OsStr, path::Path};
+
+use glob::Pattern;
+
+use crate::{error::SquishyError, Result};
+
+/// A single filter pattern. Patterns containing glob metacharacters
+/// (`*`, `?`, `[`) are matched as globs against the whole path, anything
+/// else is matched as a case-insensitive substring. Paths are matched as
+/// bytes: bytes of names that aren't valid UTF-8 are only matched by
+/// wildcards, never by a character of the pattern.
+#[derive(Debug, Clone)]
+enum FilterPattern {
+    Glob(Vec<Token>),
+    Substring(Vec<u8>),
+}
+
+impl FilterPattern {
+    fn new(pattern: &str) -> Result<Self> {
+        if pattern.contains(['*', '?', '[']) {
+            let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+            let pattern = format!("/{}", pattern);
+            // glob reports the syntax errors, the tokens are matched as bytes
+            Pattern::new(&pattern).map_err(|e| SquishyError::InvalidPattern(e.to_string()))?;
+            Ok(FilterPattern::Glob(parse(&pattern)))
+        } else {
+            Ok(FilterPattern::Substring(
+                pattern.to_lowercase().into_bytes(),
+            ))
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        match self {
+            FilterPattern::Glob(tokens) => {
+                let units = units(path.as_os_str().as_encoded_bytes());
+                matches!(match_units(tokens, &units), MatchResult::Match)
+            }
+            FilterPattern::Substring(needle) => contains(&lowercase(path.as_os_str()), needle),
+        }
+    }
+}
+
+/// Lowercases the valid UTF-8 parts of the name, keeping bytes that aren't
+/// valid UTF-8 as they are
+pub(crate) fn lowercase(name: &OsStr) -> Vec<u8> {
+    let mut lowercase = Vec::with_capacity(name.len());
+    for chunk in name.as_encoded_bytes().utf8_chunks() {
+        lowercase.extend_from_slice(chunk.valid().to_lowercase().as_bytes());
+        lowercase.extend_from_slice(chunk.invalid());
+    }
+    lowercase
+}
+
+/// Checks whether the bytes contain the needle
+pub(crate) fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty()
+        || haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+}
+
+/// A glob token, as the glob crate parses them
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnySequence,
+    AnyRecursiveSequence,
+    AnyWithin(Vec<Specifier>),
+    AnyExcept(Vec<Specifier>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Specifier {
+    Single(char),
+    Range(char, char),
+}
+
+/// A unit of a path: a character, or a byte that isn't valid UTF-8
+#[derive(Debug, Clone, Copy)]
+enum Unit {
+    Char(char),
+    Byte,
+}
+
+enum MatchResult {
+    Match,
+    SubPatternDoesntMatch,
+    EntirePatternDoesntMatch,
+}
+
+/// Splits the path into characters and the bytes that aren't valid UTF-8
+fn units(path: &[u8]) -> Vec<Unit> {
+    path.utf8_chunks()
+        .flat_map(|chunk| {
+            chunk
+                .valid()
+                .chars()
+                .map(Unit::Char)
+                .chain(chunk.invalid().iter().map(|_| Unit::Byte))
+        })
+        .collect()
+}
+
+/// Parses a pattern `glob::Pattern::new` accepted into its tokens
+fn parse(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '?' => {
+                tokens.push(Token::AnyChar);
+                i += 1;
+            }
+            '*' => {
+                let start = i;
+                while chars.get(i) == Some(&'*') {
+                    i += 1;
+                }
+                if i - start == 1 {
+                    tokens.push(Token::AnySequence);
+                } else {
+                    // `**` is a whole path component, the separator after it
+                    // is part of the token
+                    if chars.get(i) == Some(&'/') {
+                        i += 1;
+                    }
+                    if tokens.last() != Some(&Token::AnyRecursiveSequence) {
+                        tokens.push(Token::AnyRecursiveSequence);
+                    }
+                }
+            }
+            '[' if chars.get(i + 1) == Some(&'!') && i + 4 <= chars.len() => {
+                let end = i + 3 + chars[i + 3..].iter().position(|&c| c == ']').unwrap_or(0);
+                tokens.push(Token::AnyExcept(specifiers(&chars[i + 2..end])));
+                i = end + 1;
+            }
+            '[' if i + 3 <= chars.len() => {
+                let end = i + 2 + chars[i + 2..].iter().position(|&c| c == ']').unwrap_or(0);
+                tokens.push(Token::AnyWithin(specifiers(&chars[i + 1..end])));
+                i = end + 1;
+            }
+            c => {
+                tokens.push(Token::Char(c));
+                i += 1;
+            }
+        }
+    }
+    tokens
+}
+
+fn specifiers(chars: &[char]) -> Vec<Specifier> {
+    let mut specifiers = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if i + 3 <= chars.len() && chars[i + 1] == '-' {
+            specifiers.push(Specifier::Range(chars[i], chars[i + 2]));
+            i += 3;
+        } else {
+            specifiers.push(Specifier::Single(chars[i]));
+            i += 1;
+        }
+    }
+    specifiers
+}
+
+/// Matches the tokens against the units like `glob::Pattern::matches_with`
+/// does with case-insensitive matching and separators matched by wildcards
+fn match_units(tokens: &[Token], mut units: &[Unit]) -> MatchResult {
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::AnySequence | Token::AnyRecursiveSequence => {
+                let rest = &tokens[index + 1..];
+                match match_units(rest, units) {
+                    MatchResult::SubPatternDoesntMatch => {}
+                    result => return result,
+                }
+                while let Some((unit, tail)) = units.split_first() {
+                    units = tail;
+                    // `**` only moves on to the rest at the start of a component
+                    if *token == Token::AnyRecursiveSequence && !matches!(unit, Unit::Char('/')) {
+                        continue;
+                    }
+                    match match_units(rest, units) {
+                        MatchResult::SubPatternDoesntMatch => {}
+                        result => return result,
+                    }
+                }
+            }
+            _ => {
+                let Some((&unit, tail)) = units.split_first() else {
+                    return MatchResult::EntirePatternDoesntMatch;
+                };
+                units = tail;
+                let matched = match (token, unit) {
+                    (Token::AnyChar, _) => true,
+                    (Token::AnyExcept(specifiers), Unit::Char(c)) => !in_specifiers(specifiers, c),
+                    (Token::AnyExcept(_), Unit::Byte) => true,
+                    (Token::AnyWithin(specifiers), Unit::Char(c)) => in_specifiers(specifiers, c),
+                    (Token::Char(expected), Unit::Char(c)) => chars_eq(c, *expected),
+                    _ => false,
+                };
+                if !matched {
+                    return MatchResult::SubPatternDoesntMatch;
+                }
+            }
+        }
+    }
+
+    if units.is_empty() {
+        MatchResult::Match
+    } else {
+        MatchResult::SubPatternDoesntMatch
+    }
+}
+
+fn in_specifiers(specifiers: &[Specifier], c: char) -> bool {
+    specifiers.iter().any(|specifier| match *specifier {
+        Specifier::Single(expected) => chars_eq(c, expected),
+        Specifier::Range(start, end) => {
+            // like glob, ranges ignore case only between ASCII letters
+            let letters = start.is_ascii_alphabetic() && end.is_ascii_alphabetic();
+            let lower = c.to_ascii_lowercase();
+            (letters
+                && c.is_ascii()
+                && lower >= start.to_ascii_lowercase()
+                && lower <= end.to_ascii_lowercase())
+                || (c >= start && c <= end)
+        }
+    })
+}
+
+/// Compares characters ignoring the case of ASCII ones, like glob does
+fn chars_eq(a: char, b: char) -> bool {
+    if a.is_ascii() && b.is_ascii() {
+        a.eq_ignore_ascii_case(&b)
+    } else {
+        a == b
+    }
+}
+
+/// The PathFilter struct selects entries by a list of include and exclude patterns.
+/// A path matches if it matches any include pattern (or there are none)
+/// and doesn't match any exclude pattern.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include: Vec<FilterPattern>,
+    exclude: Vec<FilterPattern>,
+}
+
+impl PathFilter {
+    /// Creates a new PathFilter.
+    ///
+    /// # Arguments
+    /// * `include` - Patterns of which at least one must match
+    /// * `exclude` - Patterns of which none may match
+    ///
+    /// # Returns
+    /// A PathFilter, or an error if any of the glob patterns is invalid.
+    pub fn new<I, E>(include: I, exclude: E) -> Result<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        E: IntoIterator,
+        E::Item: AsRef<str>,
+    {
+        let include = include
+            .into_iter()
+            .map(|p| FilterPattern::new(p.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        let exclude = exclude
+            .into_iter()
+            .map(|p| FilterPattern::new(p.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { include, exclude })
+    }
+
+    /// Checks whether the path is selected by the filter.
+    ///
+    /// # Arguments
+    /// * `path` - The path to check
+    ///
+    /// # Returns
+    /// boolean stating if the path matches the filter
+    pub fn matches<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches(path)))
+            && !self.exclude.iter().any(|p| p.matches(path))
+    }
+
+    /// Returns true if the filter has no patterns and matches everything.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+    use glob::MatchOptions;
+
+    use super::*;
+
+    const GLOB_OPTIONS: MatchOptions = MatchOptions {
+        case_sensitive: false,
+        require_literal_separator: false,
+        require_literal_leading_dot: false,
+    };
+
+    fn filter(include: &[&str]) -> PathFilter {
+        PathFilter::new(include, std::iter::empty::<&str>()).unwrap()
+    }
+
+    fn non_utf8(bytes: &[u8]) -> &Path {
+        Path::new(OsStr::from_bytes(bytes))
+    }
+
+    #[test]
+    fn globs_match_like_glob() {
+        let patterns = [
+            "*.so",
+            "/usr/**/*.so",
+            "usr/lib/**",
+            "**/bin/?ash",
+            "/usr/[a-c]in/*",
+            "/usr/[!s]in/*",
+            "/usr/[]x]/*",
+            "/USR/*/LIB*.SO.[0-9]",
+            "/usr/lib/**/x",
+        ];
+        let paths = [
+            "/usr/lib/libc.so",
+            "/usr/lib/x86_64/libz.so.1",
+            "/usr/bin/bash",
+            "/usr/sbin/dash",
+            "/usr/bin/x",
+            "/usr/]/a",
+            "/usr/lib/x",
+            "/usr/lib/a/b/x",
+            "/usr/share/ÄÖ.so",
+        ];
+        for pattern in patterns {
+            let glob = Pattern::new(&format!("/{}", pattern.trim_start_matches('/'))).unwrap();
+            let filter = filter(&[pattern]);
+            for path in paths {
+                assert_eq!(
+                    filter.matches(path),
+                    glob.matches_with(path, GLOB_OPTIONS),
+                    "{pattern} against {path}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_globs_are_rejected() {
+        for pattern in ["/a/***", "/a**/b", "/a/[b"] {
+            assert!(matches!(
+                PathFilter::new([pattern], std::iter::empty::<&str>()),
+                Err(SquishyError::InvalidPattern(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn wildcards_match_non_utf8_names() {
+        let path = non_utf8(b"/usr/lib/lib\xff.so");
+        assert!(filter(&["*.so"]).matches(path));
+        assert!(filter(&["/usr/lib/lib?.so"]).matches(path));
+        assert!(filter(&["/usr/lib/lib[!a].so"]).matches(path));
+        assert!(!filter(&["/usr/lib/lib[a-z].so"]).matches(path));
+    }
+
+    #[test]
+    fn replacement_character_doesnt_match_non_utf8_names() {
+        let path = non_utf8(b"/usr/lib/lib\xff.so");
+        assert!(!filter(&["/usr/lib/lib\u{fffd}.so"]).matches(path));
+        assert!(!filter(&["/usr/lib/lib[\u{fffd}].so"]).matches(path));
+        assert!(!filter(&["lib\u{fffd}"]).matches(path));
+        assert!(filter(&["/usr/lib/lib\u{fffd}.so"]).matches("/usr/lib/lib\u{fffd}.so"));
+    }
+
+    #[test]
+    fn substrings_match_bytes_ignoring_case() {
+        let path = non_utf8(b"/usr/share/\xfe\xffDoc/README");
+        assert!(filter(&["readme"]).matches(path));
+        assert!(filter(&["doc/"]).matches(path));
+        assert!(!filter(&["\u{fffd}doc"]).matches(path));
+        assert!(filter(&["ÄRGER"]).matches("/usr/share/ärger"));
+        assert!(!filter(&["LIB"]).matches("/usr/share/doc"));
+    }
+
+    #[test]
+    fn excludes_win_over_includes() {
+        let filter = PathFilter::new(["*.so"], ["*/debug/*"]).unwrap();
+        assert!(filter.matches("/usr/lib/libc.so"));
+        assert!(!filter.matches("/usr/lib/debug/libc.so"));
+        assert!(!filter.matches(non_utf8(b"/usr/lib/debug/\x80.so")));
+    }
+
+    #[test]
+    fn lowercase_keeps_invalid_bytes() {
+        assert_eq!(
+            lowercase(OsStr::from_bytes(b"A\xffB\xc3\x84")),
+            b"a\xffb\xc3\xa4"
+        );
+    }
+}
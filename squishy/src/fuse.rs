@@ -0,0 +1,528 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, UNIX_EPOCH},
+};
+
+pub use fuser;
+use fuser::{
+    BackgroundSession, Config, Errno, FileAttr, FileHandle, FileType, Filesystem, FopenFlags,
+    Generation, INodeNo, LockOwner, OpenFlags, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty,
+    ReplyEntry, ReplyOpen, ReplyStatfs, Request,
+};
+
+#[cfg(feature = "erofs")]
+use crate::erofs::{Erofs, ErofsEntry, ErofsEntryKind};
+use crate::{blocks::BlockReader, EntryKind, Result, SquashFS};
+
+/// The image never changes while mounted, so the kernel may cache everything
+const TTL: Duration = Duration::from_secs(3600);
+
+/// The VirtualFs trait is what `SquashFuse` needs of a read-only image: all
+/// of its entries up front, and reads of the files it opens. It is
+/// implemented for `SquashFS` and, with the `erofs` feature, `Erofs`.
+pub trait VirtualFs: Send + Sync + 'static {
+    /// An open file, e.g. with the last block read
+    type File: Send + 'static;
+
+    /// Returns the block size reported to the kernel
+    fn block_size(&self) -> u32;
+
+    /// Returns all the entries, sorted by path so parents come before their
+    /// children
+    fn nodes(&self) -> Vec<VirtualNode>;
+
+    /// Opens the regular file at the path
+    ///
+    /// # Returns
+    /// The open file, or an error of kind `NotFound` if there is no file at
+    /// the path
+    fn open(&self, path: &Path) -> io::Result<Self::File>;
+
+    /// Reads up to `buf.len()` bytes of the open file at the offset, fewer
+    /// only at the end of the file
+    ///
+    /// # Returns
+    /// The number of bytes read, or an error if the data can't be read
+    fn read_at(&self, file: &mut Self::File, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+/// The VirtualNode struct is an entry of a `VirtualFs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualNode {
+    pub path: PathBuf,
+    pub kind: VirtualKind,
+    /// Size of files, 0 for other entries
+    pub size: u64,
+    /// Permission bits
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Modification time as seconds since the Unix epoch
+    pub mtime: u64,
+}
+
+/// The VirtualKind enum is the type of a `VirtualNode`. Other types, like
+/// devices, aren't exposed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VirtualKind {
+    File,
+    Directory,
+    /// The target, as stored in the image
+    Symlink(PathBuf),
+}
+
+/// An entry of the image, indexed by its inode number
+struct Inode {
+    path: PathBuf,
+    parent: u64,
+    attr: FileAttr,
+    target: Option<PathBuf>,
+    children: Vec<u64>,
+}
+
+/// An open file of a SquashFS image. Reads decompress only the blocks they
+/// cover when the image was opened from a path, else the file is
+/// decompressed up to the offset.
+pub struct SquashFSFile {
+    path: PathBuf,
+    reader: Option<BlockReader>,
+}
+
+impl VirtualFs for SquashFS<'static> {
+    type File = SquashFSFile;
+
+    fn block_size(&self) -> u32 {
+        SquashFS::block_size(self)
+    }
+
+    fn nodes(&self) -> Vec<VirtualNode> {
+        self.entries()
+            .filter_map(|entry| {
+                let kind = match &entry.kind {
+                    EntryKind::File(_) => VirtualKind::File,
+                    EntryKind::Directory => VirtualKind::Directory,
+                    EntryKind::Symlink(target) => VirtualKind::Symlink(
+                        target.strip_prefix("/").unwrap_or(target).to_path_buf(),
+                    ),
+                    EntryKind::Unknown => return None,
+                };
+                Some(VirtualNode {
+                    kind,
+                    size: u64::from(entry.size),
+                    mode: entry.mode(),
+                    uid: entry.uid(),
+                    gid: entry.gid(),
+                    mtime: u64::from(entry.mtime()),
+                    path: entry.path,
+                })
+            })
+            .collect()
+    }
+
+    fn open(&self, path: &Path) -> io::Result<SquashFSFile> {
+        let Some(EntryKind::File(file)) = self.find(path).map(|entry| entry.kind) else {
+            return Err(io::ErrorKind::NotFound.into());
+        };
+        Ok(SquashFSFile {
+            path: path.to_path_buf(),
+            reader: self.image.as_ref().map(|_| BlockReader::new(self, file)),
+        })
+    }
+
+    fn read_at(&self, file: &mut SquashFSFile, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if let (Some(reader), Some(image)) = (&mut file.reader, &self.image) {
+            return reader.read_at(self, image, offset, buf);
+        }
+        let entry = self
+            .find(&file.path)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        let mut reader = self.open_entry(&entry).map_err(io::Error::other)?;
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut read = 0;
+        while read < buf.len() {
+            match reader.read(&mut buf[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// An open file of an EROFS image
+#[cfg(feature = "erofs")]
+pub struct ErofsFile(ErofsEntry);
+
+#[cfg(feature = "erofs")]
+impl VirtualFs for Erofs<'static> {
+    type File = ErofsFile;
+
+    fn block_size(&self) -> u32 {
+        Erofs::block_size(self)
+    }
+
+    fn nodes(&self) -> Vec<VirtualNode> {
+        self.entries()
+            .filter_map(|entry| {
+                let kind = match &entry.kind {
+                    ErofsEntryKind::File => VirtualKind::File,
+                    ErofsEntryKind::Directory => VirtualKind::Directory,
+                    ErofsEntryKind::Symlink(target) => VirtualKind::Symlink(
+                        target.strip_prefix("/").unwrap_or(target).to_path_buf(),
+                    ),
+                    ErofsEntryKind::Unknown => return None,
+                };
+                Some(VirtualNode {
+                    kind,
+                    size: entry.size,
+                    mode: entry.mode(),
+                    uid: entry.uid(),
+                    gid: entry.gid(),
+                    mtime: entry.mtime(),
+                    path: entry.path,
+                })
+            })
+            .collect()
+    }
+
+    fn open(&self, path: &Path) -> io::Result<ErofsFile> {
+        match self.find(path) {
+            Some(entry) if entry.kind == ErofsEntryKind::File => Ok(ErofsFile(entry)),
+            _ => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn read_at(&self, file: &mut ErofsFile, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_entry_at(&file.0, offset, buf)
+            .map_err(io::Error::other)
+    }
+}
+
+/// The SquashFuse struct is a read-only FUSE filesystem over an image, a
+/// SquashFS one unless another `VirtualFs` is given. Files are read as the
+/// kernel asks for them. SquashFS files are read a block at a time, with the
+/// last block read kept per open file. Images opened with
+/// `SquashFS::from_path` and related constructors are read at block
+/// positions; images opened from another reader decompress each file from
+/// its start on every read.
+pub struct SquashFuse<F: VirtualFs = SquashFS<'static>> {
+    fs: F,
+    inodes: Vec<Inode>,
+    handles: Mutex<HashMap<u64, Arc<Mutex<F::File>>>>,
+    next_handle: AtomicU64,
+}
+
+impl<F: VirtualFs> SquashFuse<F> {
+    /// Creates the filesystem, indexing all entries of the image by inode number.
+    ///
+    /// # Arguments
+    /// * `fs` - The image to expose, owning its reader
+    pub fn new(fs: F) -> Self {
+        let block_size = fs.block_size();
+        let mut inodes: Vec<Inode> = Vec::new();
+        let mut by_path: HashMap<PathBuf, u64> = HashMap::new();
+
+        for node in fs.nodes() {
+            let ino = inodes.len() as u64 + 1;
+            let (kind, target) = match node.kind {
+                VirtualKind::File => (FileType::RegularFile, None),
+                VirtualKind::Directory => (FileType::Directory, None),
+                VirtualKind::Symlink(target) => (FileType::Symlink, Some(target)),
+            };
+            let size = match &target {
+                Some(target) => target.as_os_str().len() as u64,
+                None => node.size,
+            };
+            let mtime = UNIX_EPOCH + Duration::from_secs(node.mtime);
+            let parent = node
+                .path
+                .parent()
+                .and_then(|parent| by_path.get(parent).copied())
+                .unwrap_or(ino);
+
+            inodes.push(Inode {
+                path: node.path.clone(),
+                parent,
+                attr: FileAttr {
+                    ino: INodeNo(ino),
+                    size,
+                    blocks: size.div_ceil(512),
+                    atime: mtime,
+                    mtime,
+                    ctime: mtime,
+                    crtime: mtime,
+                    kind,
+                    perm: node.mode as u16,
+                    nlink: if kind == FileType::Directory { 2 } else { 1 },
+                    uid: node.uid,
+                    gid: node.gid,
+                    rdev: 0,
+                    blksize: block_size,
+                    flags: 0,
+                },
+                target,
+                children: Vec::new(),
+            });
+            if parent != ino {
+                inodes[parent as usize - 1].children.push(ino);
+            }
+            by_path.insert(node.path, ino);
+        }
+
+        Self {
+            fs,
+            inodes,
+            handles: Mutex::new(HashMap::new()),
+            next_handle: AtomicU64::new(1),
+        }
+    }
+
+    /// Mounts the filesystem at the mountpoint, blocking until it is unmounted.
+    /// The mount options should include `MountOption::RO`.
+    ///
+    /// # Arguments
+    /// * `mountpoint` - The directory to mount at
+    /// * `config` - Mount options and session settings
+    pub fn mount<P: AsRef<Path>>(self, mountpoint: P, config: &Config) -> Result<()> {
+        fuser::mount(self, mountpoint, config)?;
+        Ok(())
+    }
+
+    /// Mounts the filesystem at the mountpoint in a background thread.
+    /// The filesystem is unmounted when the returned session is dropped.
+    ///
+    /// # Arguments
+    /// * `mountpoint` - The directory to mount at
+    /// * `config` - Mount options and session settings
+    pub fn spawn_mount<P: AsRef<Path>>(
+        self,
+        mountpoint: P,
+        config: &Config,
+    ) -> Result<BackgroundSession> {
+        Ok(fuser::spawn_mount(self, mountpoint, config)?)
+    }
+
+    fn inode(&self, ino: INodeNo) -> Option<&Inode> {
+        (ino.0 as usize)
+            .checked_sub(1)
+            .and_then(|index| self.inodes.get(index))
+    }
+
+    /// Reads up to `size` bytes of the open file at the offset
+    fn read_file(&self, file: &mut F::File, offset: u64, size: u32) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; size as usize];
+        let read = self.fs.read_at(file, offset, &mut data)?;
+        data.truncate(read);
+        Ok(data)
+    }
+}
+
+impl<F: VirtualFs> Filesystem for SquashFuse<F> {
+    fn lookup(&self, _req: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+        let child = self.inode(parent).and_then(|parent| {
+            parent
+                .children
+                .iter()
+                .map(|&ino| &self.inodes[ino as usize - 1])
+                .find(|child| child.path.file_name() == Some(name))
+        });
+        match child {
+            Some(child) => reply.entry(&TTL, &child.attr, Generation(0)),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn getattr(&self, _req: &Request, ino: INodeNo, _fh: Option<FileHandle>, reply: ReplyAttr) {
+        match self.inode(ino) {
+            Some(inode) => reply.attr(&TTL, &inode.attr),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn readlink(&self, _req: &Request, ino: INodeNo, reply: ReplyData) {
+        match self.inode(ino).and_then(|inode| inode.target.as_ref()) {
+            Some(target) => reply.data(target.as_os_str().as_encoded_bytes()),
+            None => reply.error(Errno::EINVAL),
+        }
+    }
+
+    fn open(&self, _req: &Request, ino: INodeNo, _flags: OpenFlags, reply: ReplyOpen) {
+        let Some(inode) = self.inode(ino) else {
+            return reply.error(Errno::ENOENT);
+        };
+        if inode.attr.kind != FileType::RegularFile {
+            return reply.error(Errno::EISDIR);
+        }
+        let file = match self.fs.open(&inode.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return reply.error(Errno::ENOENT),
+            Err(_) => return reply.error(Errno::EIO),
+        };
+        // a poisoned lock fails the call instead of the whole mount
+        let Ok(mut handles) = self.handles.lock() else {
+            return reply.error(Errno::EIO);
+        };
+        let fh = self.next_handle.fetch_add(1, Ordering::Relaxed);
+        handles.insert(fh, Arc::new(Mutex::new(file)));
+        reply.opened(FileHandle(fh), FopenFlags::FOPEN_KEEP_CACHE);
+    }
+
+    fn read(
+        &self,
+        _req: &Request,
+        _ino: INodeNo,
+        fh: FileHandle,
+        offset: u64,
+        size: u32,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        reply: ReplyData,
+    ) {
+        // the map is only locked for the lookup, reads of other files go on
+        let handle = match self.handles.lock() {
+            Ok(handles) => handles.get(&fh.0).cloned(),
+            Err(_) => return reply.error(Errno::EIO),
+        };
+        let Some(handle) = handle else {
+            return reply.error(Errno::EBADF);
+        };
+        let Ok(mut file) = handle.lock() else {
+            return reply.error(Errno::EIO);
+        };
+        match self.read_file(&mut file, offset, size) {
+            Ok(data) => reply.data(&data),
+            Err(_) => reply.error(Errno::EIO),
+        }
+    }
+
+    fn release(
+        &self,
+        _req: &Request,
+        _ino: INodeNo,
+        fh: FileHandle,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        match self.handles.lock() {
+            Ok(mut handles) => {
+                handles.remove(&fh.0);
+                reply.ok();
+            }
+            Err(_) => reply.error(Errno::EIO),
+        }
+    }
+
+    fn readdir(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(inode) = self.inode(ino) else {
+            return reply.error(Errno::ENOENT);
+        };
+        if inode.attr.kind != FileType::Directory {
+            return reply.error(Errno::ENOTDIR);
+        }
+
+        let entries = [
+            (ino.0, FileType::Directory, OsStr::new(".")),
+            (inode.parent, FileType::Directory, OsStr::new("..")),
+        ]
+        .into_iter()
+        .chain(inode.children.iter().map(|&child| {
+            let child = &self.inodes[child as usize - 1];
+            (
+                child.attr.ino.0,
+                child.attr.kind,
+                child.path.file_name().unwrap_or_default(),
+            )
+        }));
+        for (index, (ino, kind, name)) in entries.enumerate().skip(offset as usize) {
+            // the offset of an entry is the index of the next one
+            if reply.add(INodeNo(ino), index as u64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn statfs(&self, _req: &Request, _ino: INodeNo, reply: ReplyStatfs) {
+        let block_size = self.fs.block_size();
+        let blocks = self
+            .inodes
+            .iter()
+            .map(|inode| inode.attr.size.div_ceil(u64::from(block_size)))
+            .sum();
+        reply.statfs(
+            blocks,
+            0,
+            0,
+            self.inodes.len() as u64,
+            0,
+            block_size,
+            255,
+            block_size,
+        );
+    }
+}
+
+#[cfg(all(test, feature = "erofs"))]
+mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use super::*;
+    use crate::erofs::tests::image;
+
+    fn erofs() -> Erofs<'static> {
+        Erofs::new(BufReader::new(Cursor::new(image())), None).unwrap()
+    }
+
+    #[test]
+    fn erofs_nodes_and_reads() {
+        let erofs = erofs();
+        let nodes = erofs.nodes();
+        let paths: Vec<&Path> = nodes.iter().map(|node| node.path.as_path()).collect();
+        assert_eq!(paths, ["/", "/bin", "/hello", "/link"].map(Path::new));
+        assert_eq!(nodes[3].kind, VirtualKind::Symlink(PathBuf::from("hello")));
+        assert_eq!(
+            (nodes[1].mode, nodes[1].uid, nodes[1].mtime),
+            (0o700, 70_000, 42)
+        );
+
+        let mut file = erofs.open(Path::new("/hello")).unwrap();
+        let mut buf = [0; 10];
+        assert_eq!(erofs.read_at(&mut file, 1, &mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"ello");
+        assert_eq!(erofs.read_at(&mut file, 5, &mut buf).unwrap(), 0);
+
+        let err = erofs.open(Path::new("/bin")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn inodes_form_the_tree() {
+        let fuse = SquashFuse::new(erofs());
+        let root = fuse.inode(INodeNo(1)).unwrap();
+        assert_eq!(root.children, [2, 3, 4]);
+        assert_eq!(root.parent, 1);
+
+        let link = fuse.inode(INodeNo(4)).unwrap();
+        assert_eq!(link.parent, 1);
+        assert_eq!(link.attr.kind, FileType::Symlink);
+        assert_eq!(link.attr.size, 5);
+        assert!(fuse.inode(INodeNo(0)).is_none());
+        assert!(fuse.inode(INodeNo(5)).is_none());
+    }
+}
@@ -1,38 +1,200 @@
 use std::{
-    collections::HashSet,
-    fs::{self, File, Permissions},
-    io::{BufReader, BufWriter, Read, Seek},
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs::File,
+    io::{self, BufReader, Cursor, Read, Seek, Write},
+    path::{Component, Path, PathBuf},
+};
+#[cfg(feature = "fs")]
+use std::{
+    fs::{self, Permissions},
+    io::BufWriter,
     os::unix::fs::PermissionsExt,
-    path::{Path, PathBuf},
 };
 
-use backhand::{kind::Kind, BasicFile, FilesystemReader, InnerNode, NodeHeader};
-use error::SquishyError;
+/// The backhand crate squishy reads images with, for use with `SquashFS::inner`
+pub use backhand;
+pub use backhand::compression::Compressor;
+use backhand::{BasicFile, FilesystemReader, InnerNode, Node, NodeHeader, SquashfsFileReader};
+use cache::{CacheKey, OffsetCache};
+use error::{IoContext, SquishyError};
+pub use open::{open, Image};
+use reader::EntryReader;
+#[cfg(feature = "fs")]
+use unpack::SparseWriter;
+use xattr::Xattr;
 
 #[cfg(feature = "rayon")]
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 #[cfg(feature = "appimage")]
 pub mod appimage;
+#[cfg(feature = "tar")]
+mod archive;
+#[cfg(any(feature = "rayon", feature = "fuse"))]
+mod blocks;
 
+pub mod cache;
+mod decompress;
+pub mod detect;
+#[cfg(feature = "digest")]
+pub mod digest;
+#[cfg(feature = "fs")]
+mod dirtree;
+#[cfg(feature = "elf")]
+pub mod elf;
+#[cfg(feature = "erofs")]
+pub mod erofs;
 pub mod error;
+pub mod filter;
+#[cfg(feature = "fuse")]
+pub mod fuse;
+#[cfg(feature = "lint")]
+pub mod lint;
+mod metadata;
+pub mod mime;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+mod open;
+#[cfg(feature = "fs")]
+pub mod pack;
+#[cfg(feature = "rayon")]
+mod parallel;
+pub mod permissions;
+pub mod reader;
+#[cfg(feature = "fs")]
+pub mod unpack;
+pub mod verify;
+#[cfg(feature = "vfs")]
+pub mod vfs;
+pub mod xattr;
+#[cfg(feature = "zip")]
+mod zipfile;
 
 pub type Result<T> = std::result::Result<T, SquishyError>;
 
+thread_local! {
+    /// Block buffer of `SquashFS::copy_blocks`
+    static COPY_BUFFER: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
+/// Magic bytes at the start of a (little endian, v4) SquashFS superblock
+pub(crate) const SQUASHFS_MAGIC: [u8; 4] = *b"hsqs";
+
 /// The SquashFS struct provides an interface for reading and interacting with a SquashFS filesystem.
 /// It wraps a FilesystemReader, which is responsible for reading the contents of the SquashFS file.
 pub struct SquashFS<'a> {
     reader: FilesystemReader<'a>,
+    offset: u64,
+    /// Xattrs of the entries having some, by path
+    xattrs: HashMap<PathBuf, Vec<Xattr>>,
+    /// Inode number and link count of the entries, in the order of the nodes
+    links: Vec<(u32, u32)>,
+    tuning: IoTuning,
+    /// The image file, when opened from a path, for reading blocks in parallel
+    /// and at their position
+    #[cfg(any(feature = "rayon", feature = "fuse"))]
+    image: Option<File>,
 }
 
 /// The SquashFSEntry struct represents a single file or directory entry within the SquashFS filesystem.
 /// It contains information about the path, size, and type of the entry.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SquashFSEntry<'a> {
     pub header: NodeHeader,
     pub path: PathBuf,
     pub size: u32,
     pub kind: EntryKind<'a>,
+    /// Inode number, shared by hard links to the same inode. 0 if the inode
+    /// table couldn't be read.
+    pub inode: u32,
+    /// Number of hard links to the inode. Directories count their
+    /// subdirectories and their `.` entry. 0 if the inode table couldn't be
+    /// read.
+    pub nlink: u32,
+}
+
+impl SquashFSEntry<'_> {
+    /// Returns the permission bits of the entry.
+    pub fn mode(&self) -> u32 {
+        u32::from(self.header.permissions)
+    }
+
+    /// Returns the owner user id of the entry.
+    pub fn uid(&self) -> u32 {
+        self.header.uid
+    }
+
+    /// Returns the owner group id of the entry.
+    pub fn gid(&self) -> u32 {
+        self.header.gid
+    }
+
+    /// Returns the modification time of the entry as seconds since the Unix epoch.
+    pub fn mtime(&self) -> u32 {
+        self.header.mtime
+    }
+}
+
+/// Serializes the path, type, mode, owner, size and modification time of the
+/// entry, plus the target of symlinks
+#[cfg(feature = "serde")]
+impl serde::Serialize for SquashFSEntry<'_> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut entry = serializer.serialize_struct("SquashFSEntry", 10)?;
+        entry.serialize_field("path", &self.path)?;
+        entry.serialize_field("type", self.kind.name())?;
+        entry.serialize_field("mode", &self.mode())?;
+        entry.serialize_field("uid", &self.uid())?;
+        entry.serialize_field("gid", &self.gid())?;
+        entry.serialize_field("size", &self.size)?;
+        entry.serialize_field("mtime", &self.mtime())?;
+        entry.serialize_field("inode", &self.inode)?;
+        entry.serialize_field("nlink", &self.nlink)?;
+        match &self.kind {
+            EntryKind::Symlink(target) => entry.serialize_field("target", target)?,
+            _ => entry.skip_field("target")?,
+        }
+        entry.end()
+    }
+}
+
+/// The IoTuning struct sets the sizes of the buffers images are read and
+/// extracted files are written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoTuning {
+    /// Buffer of the reader of images opened from a path, in bytes
+    pub read_buf: usize,
+    /// Buffer of extracted files, in bytes. Sparse files are written without
+    /// it, to find the holes in whole blocks.
+    pub write_buf: usize,
+}
+
+impl Default for IoTuning {
+    fn default() -> Self {
+        IoTuning {
+            read_buf: 64 << 10,
+            write_buf: 1 << 20,
+        }
+    }
+}
+
+/// The EntryStats struct summarizes the entries of a SquashFS filesystem by kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EntryStats {
+    pub files: u64,
+    pub directories: u64,
+    pub symlinks: u64,
+    pub other: u64,
+    /// Total uncompressed size of all files
+    pub total_size: u64,
 }
 
 /// The EntryKind enum represents the different types of entries that can be found in the SquashFS filesystem.
@@ -44,6 +206,82 @@ pub enum EntryKind<'a> {
     Unknown,
 }
 
+impl EntryKind<'_> {
+    /// Returns the name of the kind: `file`, `directory`, `symlink` or `other`
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntryKind::File(_) => "file",
+            EntryKind::Directory => "directory",
+            EntryKind::Symlink(_) => "symlink",
+            EntryKind::Unknown => "other",
+        }
+    }
+}
+
+/// Returns the absolute path to look an entry up at, or None if the path has a
+/// `..` component, which no entry path has. The leading `/` is optional.
+pub(crate) fn lookup_path(path: &Path) -> Option<PathBuf> {
+    let mut lookup = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => lookup.push(name),
+            Component::ParentDir => return None,
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+    Some(lookup)
+}
+
+/// Returns the indices of the direct children of `dir` among the items,
+/// which are sorted by path, skipping over the subtree of every child
+#[cfg(feature = "vfs")]
+fn child_indices<T>(items: &[T], path: impl Fn(&T) -> &Path, dir: &Path) -> Vec<usize> {
+    let mut index = items.partition_point(|item| path(item) <= dir);
+    let mut children = Vec::new();
+    while let Some(child) = items.get(index).map(&path) {
+        if child.parent() != Some(dir) {
+            break;
+        }
+        children.push(index);
+        // descendants of the child are sorted right after it
+        index += 1 + items[index + 1..].partition_point(|item| path(item).starts_with(child));
+    }
+    children
+}
+
+/// Creates a SquashFSEntry from a filesystem node and its inode number and
+/// link count.
+fn entry_from_node(
+    node: &Node<SquashfsFileReader>,
+    (inode, nlink): (u32, u32),
+) -> SquashFSEntry<'_> {
+    let size = match &node.inner {
+        InnerNode::File(file) => file.basic.file_size,
+        _ => 0,
+    };
+
+    let kind = match &node.inner {
+        InnerNode::File(file) => EntryKind::File(&file.basic),
+        InnerNode::Dir(_) => EntryKind::Directory,
+        InnerNode::Symlink(symlink) => {
+            // kept as bytes, targets needn't be valid UTF-8
+            let mut target = OsString::from("/");
+            target.push(symlink.link.as_os_str());
+            EntryKind::Symlink(PathBuf::from(target))
+        }
+        _ => EntryKind::Unknown,
+    };
+
+    SquashFSEntry {
+        header: node.header,
+        path: node.fullpath.clone(),
+        size,
+        kind,
+        inode,
+        nlink,
+    }
+}
+
 impl<'a> SquashFS<'a> {
     /// Creates a new SquashFS instance from a BufReader.
     ///
@@ -56,13 +294,44 @@ impl<'a> SquashFS<'a> {
     where
         R: Read + Seek + Send + 'a,
     {
-        let offset = offset.unwrap_or(
-            Self::find_squashfs_offset(&mut reader).map_err(|_| SquishyError::NoSquashFsFound)?,
-        );
-        let reader = FilesystemReader::from_reader_with_offset(reader, offset)
-            .map_err(|e| SquishyError::InvalidSquashFS(e.to_string()))?;
+        let offset = match offset {
+            Some(offset) => offset,
+            None => detect::find_squashfs_offset(&mut reader)
+                .map_err(|_| SquishyError::NoSquashFsFound)?,
+        };
+        // squishy walks the tables again for what backhand drops. Images it
+        // can't walk still open, without inode numbers, link counts and xattrs.
+        let mut inodes = metadata::read_inodes(&mut reader, offset).unwrap_or_default();
+        let xattrs = xattr::read_xattrs(&mut reader, offset, &inodes)?;
+        // backhand reads images at offset 0 from the current position
+        reader.rewind()?;
+        let reader =
+            FilesystemReader::from_reader_with_offset_and_kind(reader, offset, decompress::kind())
+                .map_err(|e| SquishyError::InvalidSquashFS(e.to_string()))?;
+
+        // backhand drops inode numbers and link counts, match them to its nodes
+        inodes.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        let links = reader
+            .files()
+            .map(|node| {
+                inodes
+                    .binary_search_by(|(path, _)| path.cmp(&node.fullpath))
+                    .map_or((0, 0), |index| {
+                        let inode = &inodes[index].1;
+                        (inode.number, inode.nlink)
+                    })
+            })
+            .collect();
 
-        Ok(Self { reader })
+        Ok(Self {
+            reader,
+            offset,
+            xattrs,
+            links,
+            tuning: IoTuning::default(),
+            #[cfg(any(feature = "rayon", feature = "fuse"))]
+            image: None,
+        })
     }
 
     /// Creates a new SquashFS instance from a file path. Tries to find offset automatically.
@@ -72,10 +341,33 @@ impl<'a> SquashFS<'a> {
     ///
     /// # Returns
     /// A SquashFS instance if the SquashFS data is found and valid, or an error if it is not.
-    pub fn from_path<P: AsRef<Path>>(path: &'a P) -> Result<Self> {
-        let file = File::open(path).unwrap();
-        let reader = BufReader::new(file);
-        SquashFS::new(reader, None)
+    pub fn from_path<P: AsRef<Path>>(path: &P) -> Result<Self> {
+        SquashFS::open_path(path, None, IoTuning::default())
+    }
+
+    /// Creates a new SquashFS instance from a file path, looking up the offset
+    /// in `cache` before scanning for it. The offset found is added to the cache.
+    /// A cached offset that doesn't work anymore is ignored.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the SquashFS file.
+    /// * `cache` - The cache of offsets
+    ///
+    /// # Returns
+    /// A SquashFS instance if the SquashFS data is found and valid, or an error if it is not.
+    pub fn from_path_cached<P: AsRef<Path>>(path: &P, cache: &dyn OffsetCache) -> Result<Self> {
+        let key = CacheKey::new(path).ok();
+        if let Some(offset) = key.as_ref().and_then(|key| cache.get(key)) {
+            if let Ok(squashfs) = SquashFS::from_path_with_offset(path, offset) {
+                return Ok(squashfs);
+            }
+        }
+
+        let squashfs = SquashFS::from_path(path)?;
+        if let Some(key) = key {
+            cache.insert(key, squashfs.offset);
+        }
+        Ok(squashfs)
     }
 
     /// Creates a new SquashFS instance from a file path.
@@ -86,98 +378,241 @@ impl<'a> SquashFS<'a> {
     ///
     /// # Returns
     /// A SquashFS instance if the SquashFS data is found and valid, or an error if it is not.
-    pub fn from_path_with_offset<P: AsRef<Path>>(path: &'a P, offset: u64) -> Result<Self> {
-        let file = File::open(path).unwrap();
-        let reader = BufReader::new(file);
-        SquashFS::new(reader, Some(offset))
+    pub fn from_path_with_offset<P: AsRef<Path>>(path: &P, offset: u64) -> Result<Self> {
+        SquashFS::open_path(path, Some(offset), IoTuning::default())
     }
 
-    /// Finds the starting offset of the SquashFS data within the input file.
+    /// Creates a new SquashFS instance from a file path, reading and writing
+    /// with the buffer sizes of the tuning.
     ///
     /// # Arguments
-    /// * `file` - The BufReader that provides access to the input file.
+    /// * `path` - The path to the SquashFS file.
+    /// * `offset` - Offset of the SquashFS data, found automatically if not given
+    /// * `tuning` - The buffer sizes
     ///
     /// # Returns
-    /// The starting offset of the SquashFS data, or an error if the SquashFS data is not found.
-    fn find_squashfs_offset<R>(file: &mut BufReader<R>) -> Result<u64>
-    where
-        R: Read + Seek,
-    {
-        let mut magic = [0_u8; 4];
-        let kind = Kind::from_target("le_v4_0").unwrap();
-        while file.read_exact(&mut magic).is_ok() {
-            if magic == kind.magic() {
-                let found = file.stream_position()? - magic.len() as u64;
-                file.rewind()?;
-                return Ok(found);
+    /// A SquashFS instance if the SquashFS data is found and valid, or an error if it is not.
+    pub fn from_path_with_tuning<P: AsRef<Path>>(
+        path: &P,
+        offset: Option<u64>,
+        tuning: IoTuning,
+    ) -> Result<Self> {
+        SquashFS::open_path(path, offset, tuning)
+    }
+
+    /// Opens the file, keeping a handle for reading blocks in parallel
+    fn open_path<P: AsRef<Path>>(path: &P, offset: Option<u64>, tuning: IoTuning) -> Result<Self> {
+        let file = File::open(path).context("open", path)?;
+        #[cfg(any(feature = "rayon", feature = "fuse"))]
+        let image = file.try_clone().ok();
+        let reader = BufReader::with_capacity(tuning.read_buf, file);
+        let mut squashfs = SquashFS::new(reader, offset)?;
+        squashfs.tuning = tuning;
+        #[cfg(any(feature = "rayon", feature = "fuse"))]
+        {
+            squashfs.image = image;
+        }
+        Ok(squashfs)
+    }
+
+    /// Sets the buffer sizes. The read buffer is only used when opening an
+    /// image from a path, see `from_path_with_tuning`.
+    ///
+    /// # Arguments
+    /// * `tuning` - The buffer sizes
+    pub fn set_io_tuning(&mut self, tuning: IoTuning) {
+        self.tuning = tuning;
+    }
+
+    /// Returns the buffer sizes of the image.
+    pub fn io_tuning(&self) -> IoTuning {
+        self.tuning
+    }
+
+    /// Creates a new SquashFS instance from the bytes of an image, e.g. one
+    /// loaded in a browser. Tries to find the offset automatically if not given.
+    ///
+    /// # Arguments
+    /// * `data` - The contents of the SquashFS file or AppImage.
+    /// * `offset` - Offset of the SquashFS data within the bytes.
+    ///
+    /// # Returns
+    /// A SquashFS instance if the SquashFS data is found and valid, or an error if it is not.
+    pub fn from_bytes(data: &'a [u8], offset: Option<u64>) -> Result<Self> {
+        SquashFS::new(BufReader::new(Cursor::new(data)), offset)
+    }
+
+    /// Returns the offset of the SquashFS data within the input file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the backhand reader of the image, to use backhand APIs squishy
+    /// doesn't wrap without parsing the image again.
+    pub fn inner(&self) -> &FilesystemReader<'a> {
+        &self.reader
+    }
+
+    /// Returns the backhand reader of the image, consuming the SquashFS.
+    pub fn into_inner(self) -> FilesystemReader<'a> {
+        self.reader
+    }
+
+    /// Returns the compressor used by the SquashFS filesystem.
+    pub fn compressor(&self) -> Compressor {
+        self.reader.compressor
+    }
+
+    /// Returns the data block size of the SquashFS filesystem.
+    pub fn block_size(&self) -> u32 {
+        self.reader.block_size
+    }
+
+    /// Counts the entries of the SquashFS filesystem by kind and sums up the file sizes.
+    pub fn stats(&self) -> EntryStats {
+        self.entries()
+            .fold(EntryStats::default(), |mut stats, entry| {
+                match entry.kind {
+                    EntryKind::File(_) => {
+                        stats.files += 1;
+                        stats.total_size += entry.size as u64;
+                    }
+                    EntryKind::Directory => stats.directories += 1,
+                    EntryKind::Symlink(_) => stats.symlinks += 1,
+                    EntryKind::Unknown => stats.other += 1,
+                }
+                stats
+            })
+    }
+
+    /// Estimates the compressed size of every file. Data blocks are counted as
+    /// stored, while the compressed size of a fragment shared by several file
+    /// tails is split proportionally to the tail sizes.
+    ///
+    /// # Returns
+    /// The compressed size of each file, keyed by its path
+    pub fn compressed_sizes(&self) -> HashMap<PathBuf, u64> {
+        let block_size = u64::from(self.reader.block_size);
+        let tail_size = |file: &BasicFile| {
+            (file.frag_index != u32::MAX).then(|| {
+                u64::from(file.file_size).saturating_sub(file.block_sizes.len() as u64 * block_size)
+            })
+        };
+        let files = || {
+            self.entries().filter_map(|entry| match entry.kind {
+                EntryKind::File(file) => Some((entry.path, file)),
+                _ => None,
+            })
+        };
+
+        let mut fragment_usage: HashMap<u32, u64> = HashMap::new();
+        for (_, file) in files() {
+            if let Some(tail) = tail_size(file) {
+                *fragment_usage.entry(file.frag_index).or_default() += tail;
             }
         }
-        Err(SquishyError::NoSquashFsFound)
+
+        files()
+            .map(|(path, file)| {
+                let blocks: u64 = file
+                    .block_sizes
+                    .iter()
+                    .map(|size| u64::from(size.size()))
+                    .sum();
+                let fragment = tail_size(file)
+                    .zip(self.reader.fragments.as_ref())
+                    .and_then(|(tail, fragments)| {
+                        let fragment = fragments.get(file.frag_index as usize)?;
+                        let used = fragment_usage.get(&file.frag_index).copied()?.max(1);
+                        Some(u64::from(fragment.size.size()) * tail / used)
+                    })
+                    .unwrap_or(0);
+                (path, blocks + fragment)
+            })
+            .collect()
     }
 
     /// Returns an iterator over all the entries in the SquashFS filesystem.
-    pub fn entries(&self) -> impl Iterator<Item = SquashFSEntry> + '_ {
-        self.reader.files().map(|node| {
-            let size = match &node.inner {
-                InnerNode::File(file) => file.basic.file_size,
-                _ => 0,
-            };
-
-            let kind = match &node.inner {
-                InnerNode::File(file) => EntryKind::File(&file.basic),
-                InnerNode::Dir(_) => EntryKind::Directory,
-                InnerNode::Symlink(symlink) => EntryKind::Symlink(
-                    PathBuf::from(format!("/{}", symlink.link.display())).clone(),
-                ),
-                _ => EntryKind::Unknown,
-            };
-
-            SquashFSEntry {
-                header: node.header,
-                path: node.fullpath.clone(),
-                size,
-                kind,
-            }
-        })
+    pub fn entries(&self) -> impl Iterator<Item = SquashFSEntry<'_>> + '_ {
+        self.reader
+            .files()
+            .zip(&self.links)
+            .map(|(node, links)| entry_from_node(node, *links))
+    }
+
+    /// Returns an iterator over all the entries sorted by path, component by
+    /// component, so every directory comes right before its contents. Unlike
+    /// `entries`, the order doesn't depend on how the image is read.
+    pub fn entries_sorted(&self) -> impl Iterator<Item = SquashFSEntry<'_>> + '_ {
+        let mut entries: Vec<SquashFSEntry> = self.entries().collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        entries.into_iter()
     }
 
     #[cfg(feature = "rayon")]
     /// Returns a parallel iterator over all the entries in the SquashFS filesystem.
-    pub fn par_entries(&self) -> impl ParallelIterator<Item = SquashFSEntry> + '_ {
-        self.reader
-            .files()
-            .map(|node| {
-                let size = match &node.inner {
-                    InnerNode::File(file) => file.basic.file_size,
-                    _ => 0,
-                };
-
-                let kind = match &node.inner {
-                    InnerNode::File(file) => EntryKind::File(&file.basic),
-                    InnerNode::Dir(_) => EntryKind::Directory,
-                    InnerNode::Symlink(symlink) => EntryKind::Symlink(
-                        PathBuf::from(format!("/{}", symlink.link.display())).clone(),
-                    ),
-                    _ => EntryKind::Unknown,
-                };
-
-                SquashFSEntry {
-                    header: node.header,
-                    path: node.fullpath.clone(),
-                    size,
-                    kind,
-                }
-            })
+    pub fn par_entries(&self) -> impl ParallelIterator<Item = SquashFSEntry<'_>> + '_ {
+        self.entries()
             .collect::<Vec<SquashFSEntry>>()
             .into_par_iter()
     }
 
+    /// Looks up a single entry by its path, without iterating all entries.
+    /// The leading `/` is optional.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the entry within the SquashFS filesystem.
+    ///
+    /// # Returns
+    /// The entry, or None if there is no entry at the path or the path has a
+    /// `..` component.
+    pub fn find<P: AsRef<Path>>(&self, path: P) -> Option<SquashFSEntry<'_>> {
+        let path = lookup_path(path.as_ref())?;
+
+        // nodes are kept sorted by their full path
+        let nodes = &self.reader.root.nodes;
+        nodes
+            .binary_search_by(|node| node.fullpath.as_path().cmp(&path))
+            .ok()
+            .map(|index| entry_from_node(&nodes[index], self.links[index]))
+    }
+
+    /// Returns the entries directly inside the directory at the path, found
+    /// with a range lookup in the sorted nodes
+    #[cfg(feature = "vfs")]
+    pub(crate) fn children(&self, dir: &Path) -> Vec<SquashFSEntry<'_>> {
+        let nodes = &self.reader.root.nodes;
+        child_indices(nodes, |node| node.fullpath.as_path(), dir)
+            .into_iter()
+            .map(|index| entry_from_node(&nodes[index], self.links[index]))
+            .collect()
+    }
+
+    /// Counts the entries at the path, more than one only in malformed images
+    #[cfg(feature = "lint")]
+    pub(crate) fn count_path(&self, path: &Path) -> usize {
+        let nodes = &self.reader.root.nodes;
+        let start = nodes.partition_point(|node| node.fullpath.as_path() < path);
+        nodes[start..]
+            .iter()
+            .take_while(|node| node.fullpath == path)
+            .count()
+    }
+
+    /// Checks whether an entry exists at the path. The leading `/` is optional.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the entry within the SquashFS filesystem.
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.find(path).is_some()
+    }
+
     /// Returns an iterator over all the entries in the SquashFS filesystem
     /// that match the provided predicate function.
     ///
     /// # Arguments
     /// * `predicate` - A function that takes a &Path and returns a bool, indicating whether the entry should be included.
-    pub fn find_entries<F>(&self, predicate: F) -> impl Iterator<Item = SquashFSEntry> + '_
+    pub fn find_entries<F>(&self, predicate: F) -> impl Iterator<Item = SquashFSEntry<'_>> + '_
     where
         F: Fn(&Path) -> bool + 'a,
     {
@@ -192,24 +627,143 @@ impl<'a> SquashFS<'a> {
     /// # Returns
     /// The contents of the file as a Vec<u8>, or an error if the file is not found.
     pub fn read_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        self.read_file_with_limit(path, u64::MAX)
+    }
+
+    /// Reads the contents of the specified file, unless it is larger than the
+    /// limit. The size is checked before anything is allocated, so untrusted
+    /// images can be read without running out of memory.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the file within the SquashFS filesystem.
+    /// * `max_bytes` - The largest file size to read
+    ///
+    /// # Returns
+    /// The contents of the file, or an error if the file is not found or too large.
+    pub fn read_file_with_limit<P: AsRef<Path>>(&self, path: P, max_bytes: u64) -> Result<Vec<u8>> {
         let path = path.as_ref();
+        match self.find(path) {
+            Some(entry) => self.read_entry_with_limit(&entry, max_bytes),
+            None => Err(SquishyError::FileNotFound(path.to_path_buf())),
+        }
+    }
 
-        for node in self.reader.files() {
-            if node.fullpath == path {
-                if let InnerNode::File(file) = &node.inner {
-                    let mut reader = self.reader.file(&file.basic).reader().bytes();
-                    let mut contents = Vec::new();
+    /// Reads the contents of the specified entry from the SquashFS filesystem.
+    ///
+    /// # Arguments
+    /// * `entry` - The file entry within the SquashFS filesystem.
+    ///
+    /// # Returns
+    /// The contents of the file as a Vec<u8>, or an error if the entry is not a file.
+    pub fn read_entry(&self, entry: &SquashFSEntry) -> Result<Vec<u8>> {
+        self.read_entry_with_limit(entry, u64::MAX)
+    }
 
-                    while let Some(Ok(byte)) = reader.next() {
-                        contents.push(byte);
-                    }
+    /// Reads the contents of the specified entry, unless it is larger than the limit.
+    ///
+    /// # Arguments
+    /// * `entry` - The file entry within the SquashFS filesystem.
+    /// * `max_bytes` - The largest file size to read
+    ///
+    /// # Returns
+    /// The contents of the file, or an error if the entry is not a file or too large.
+    pub fn read_entry_with_limit(&self, entry: &SquashFSEntry, max_bytes: u64) -> Result<Vec<u8>> {
+        match entry.kind {
+            EntryKind::File(basic_file) => self.read_basic_file(basic_file, &entry.path, max_bytes),
+            _ => Err(SquishyError::FileNotFound(entry.path.clone())),
+        }
+    }
 
-                    return Ok(contents);
-                }
-            }
+    fn read_basic_file(&self, file: &BasicFile, path: &Path, max_bytes: u64) -> Result<Vec<u8>> {
+        let size = u64::from(file.file_size);
+        if size > max_bytes {
+            return Err(SquishyError::FileTooLarge {
+                path: path.to_path_buf(),
+                size,
+                limit: max_bytes,
+            });
+        }
+        let mut contents = Vec::with_capacity(file.file_size as usize);
+        self.copy_blocks(file, &mut contents)?;
+        Ok(contents)
+    }
+
+    /// Opens the specified entry for reading and seeking, without loading the
+    /// whole file into memory.
+    ///
+    /// # Arguments
+    /// * `entry` - The file entry within the SquashFS filesystem.
+    ///
+    /// # Returns
+    /// A reader of the file contents, or an error if the entry is not a file.
+    pub fn open_entry<'s>(&'s self, entry: &SquashFSEntry<'s>) -> Result<EntryReader<'s, 'a>> {
+        match entry.kind {
+            EntryKind::File(basic_file) => Ok(EntryReader::new(
+                self.reader.file(basic_file),
+                u64::from(basic_file.file_size),
+            )),
+            _ => Err(SquishyError::FileNotFound(entry.path.clone())),
         }
+    }
+
+    /// Opens a SquashFS image stored as a file inside this one, e.g. a payload
+    /// bundled in an AppImage, without extracting it. The nested image is
+    /// decompressed as it is read, and seeking backward in it starts over from
+    /// the beginning, so reading large nested images this way is slow.
+    ///
+    /// # Arguments
+    /// * `entry` - The file entry holding the nested image.
+    /// * `offset` - Offset of the SquashFS data within the file, found automatically if `None`.
+    ///
+    /// # Returns
+    /// The nested SquashFS, or an error if the entry is not a file or holds no valid SquashFS.
+    pub fn open_nested<'s>(
+        &'s self,
+        entry: &SquashFSEntry<'s>,
+        offset: Option<u64>,
+    ) -> Result<SquashFS<'s>> {
+        SquashFS::new(BufReader::new(self.open_entry(entry)?), offset)
+    }
 
-        Err(SquishyError::FileNotFound(path.to_path_buf()))
+    /// Streams the contents of the file at the path to the writer, e.g. a
+    /// socket, a hasher or a tar builder, without buffering it in memory.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the file within the SquashFS filesystem.
+    /// * `writer` - The writer to copy the contents to.
+    ///
+    /// # Returns
+    /// The number of bytes copied, or an error if there is no file at the path.
+    pub fn copy_file_to<P: AsRef<Path>, W: Write + ?Sized>(
+        &self,
+        path: P,
+        writer: &mut W,
+    ) -> Result<u64> {
+        let path = path.as_ref();
+        match self.find(path) {
+            Some(entry) => self.copy_entry_to(&entry, writer),
+            None => Err(SquishyError::FileNotFound(path.to_path_buf())),
+        }
+    }
+
+    /// Streams the contents of the specified entry from the SquashFS filesystem
+    /// to the writer, without buffering the whole file in memory.
+    ///
+    /// # Arguments
+    /// * `entry` - The file entry within the SquashFS filesystem.
+    /// * `writer` - The writer to copy the contents to.
+    ///
+    /// # Returns
+    /// The number of bytes copied, or an error if the entry is not a file.
+    pub fn copy_entry_to<W: Write + ?Sized>(
+        &self,
+        entry: &SquashFSEntry,
+        writer: &mut W,
+    ) -> Result<u64> {
+        match entry.kind {
+            EntryKind::File(basic_file) => Ok(self.copy_blocks(basic_file, writer)?),
+            _ => Err(SquishyError::FileNotFound(entry.path.clone())),
+        }
     }
 
     /// Writes the contents of the specified file from the SquashFS filesystem
@@ -221,13 +775,49 @@ impl<'a> SquashFS<'a> {
     ///
     /// # Returns
     /// An empty result, or an error if the file cannot be read or written.
+    #[cfg(feature = "fs")]
     pub fn write_file<P: AsRef<Path>>(&self, file: &BasicFile, dest: P) -> Result<()> {
-        let output_file = File::create(dest)?;
-        let mut writer = BufWriter::with_capacity(file.file_size as usize, &output_file);
-        let file = self.reader.file(file);
-        let mut reader = file.reader();
-        std::io::copy(&mut reader, &mut writer)?;
-        Ok(())
+        let dest = dest.as_ref();
+        let output_file = File::create(dest).context("create", dest)?;
+        self.write_to(file, &output_file, dest, true)
+    }
+
+    /// Writes the contents of the specified file from the SquashFS filesystem
+    /// to the specified destination path, leaving blocks of zeros as holes
+    /// instead of writing them, so e.g. disk images take little disk space.
+    ///
+    /// # Arguments
+    /// * `file` - The basic file within the SquashFS filesystem.
+    /// * `dest` - The destination path to write the file to.
+    ///
+    /// # Returns
+    /// An empty result, or an error if the file cannot be read or written.
+    #[cfg(feature = "fs")]
+    pub fn write_file_sparse<P: AsRef<Path>>(&self, file: &BasicFile, dest: P) -> Result<()> {
+        let dest = dest.as_ref();
+        let output_file = File::create(dest).context("create", dest)?;
+        self.write_to(file, &output_file, dest, false)
+    }
+
+    /// Writes the contents of the file to the opened output, leaving blocks of
+    /// zeros as holes unless `dense` is set. `dest` is only used in errors.
+    #[cfg(feature = "fs")]
+    pub(crate) fn write_to(
+        &self,
+        file: &BasicFile,
+        output: &File,
+        dest: &Path,
+        dense: bool,
+    ) -> Result<()> {
+        if dense {
+            let mut writer = BufWriter::with_capacity(self.tuning.write_buf, output);
+            self.copy_blocks(file, &mut writer).context("write", dest)?;
+            writer.flush().context("write", dest)
+        } else {
+            let mut writer = SparseWriter::new(output);
+            self.copy_blocks(file, &mut writer).context("write", dest)?;
+            writer.finish().context("write", dest)
+        }
     }
 
     /// Writes the contents of the specified file from the SquashFS filesystem
@@ -240,22 +830,61 @@ impl<'a> SquashFS<'a> {
     ///
     /// # Returns
     /// An empty result, or an error if the file cannot be read or written.
+    #[cfg(feature = "fs")]
     pub fn write_file_with_permissions<P: AsRef<Path>>(
         &self,
         file: &BasicFile,
         dest: P,
         header: NodeHeader,
     ) -> Result<()> {
-        let output_file = File::create(&dest)?;
+        let dest = dest.as_ref();
+        let output_file = File::create(dest).context("create", dest)?;
         let mode = u32::from(header.permissions);
-        fs::set_permissions(dest, Permissions::from_mode(mode))?;
-        let mut writer = BufWriter::with_capacity(file.file_size as usize, &output_file);
-        let file = self.reader.file(file);
-        let mut reader = file.reader();
-        std::io::copy(&mut reader, &mut writer)?;
+        fs::set_permissions(dest, Permissions::from_mode(mode))
+            .context("set permissions of", dest)?;
+        let mut writer = BufWriter::with_capacity(self.tuning.write_buf, output_file);
+        self.copy_blocks(file, &mut writer).context("write", dest)?;
+        writer.flush().context("write", dest)?;
         Ok(())
     }
 
+    /// Copies the contents of the file to the writer a block at a time, with a
+    /// buffer kept per thread, so extracting many files allocates nothing per
+    /// file and every write is a whole block. With the `rayon` feature, large
+    /// files of images opened from a path are decompressed on all threads.
+    fn copy_blocks<W: Write + ?Sized>(&self, file: &BasicFile, writer: &mut W) -> io::Result<u64> {
+        #[cfg(feature = "rayon")]
+        if let Some(image) = &self.image {
+            if file.block_sizes.len() >= parallel::PARALLEL_MIN_BLOCKS
+                && rayon::current_num_threads() > 1
+            {
+                return self.copy_blocks_parallel(image, file, writer);
+            }
+        }
+
+        // taken out of the cell, in case the writer copies another file
+        let mut buffer = COPY_BUFFER.take();
+        buffer.resize(self.reader.block_size as usize, 0);
+
+        let mut reader = self.reader.file(file).reader();
+        let mut copied = 0;
+        let result = loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break Ok(copied),
+                Ok(read) => {
+                    if let Err(e) = writer.write_all(&buffer[..read]) {
+                        break Err(e);
+                    }
+                    copied += read as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        COPY_BUFFER.set(buffer);
+        result
+    }
+
     /// Resolves the symlink chain starting from the specified entry,
     /// returning the final target entry or an error if a cycle is detected.
     ///
@@ -264,7 +893,7 @@ impl<'a> SquashFS<'a> {
     ///
     /// # Returns
     /// The final target entry, or None if the entry is not a symlink, or an error if a cycle is detected.
-    pub fn resolve_symlink(&self, entry: &SquashFSEntry) -> Result<Option<SquashFSEntry>> {
+    pub fn resolve_symlink(&self, entry: &SquashFSEntry) -> Result<Option<SquashFSEntry<'_>>> {
         match &entry.kind {
             EntryKind::Symlink(target) => {
                 let mut visited = HashSet::new();
@@ -288,7 +917,7 @@ impl<'a> SquashFS<'a> {
         &self,
         target: &Path,
         visited: &mut HashSet<PathBuf>,
-    ) -> Result<Option<SquashFSEntry>> {
+    ) -> Result<Option<SquashFSEntry<'_>>> {
         if !visited.insert(target.to_path_buf()) {
             return Err(SquishyError::SymlinkError("Cyclic symlink detected".into()));
         }
@@ -305,3 +934,109 @@ impl<'a> SquashFS<'a> {
         }
     }
 }
+
+/// Images built from a tree of files for the tests of every module
+#[cfg(all(test, feature = "fs"))]
+pub(crate) mod testing {
+    use std::{fs, io::Cursor, os::unix};
+
+    use crate::pack::{pack_dir, PackOptions};
+
+    /// A path of the tree, relative to the root of the image
+    pub(crate) enum Node<'a> {
+        Dir(&'a str),
+        File(&'a str, &'a [u8]),
+        Symlink(&'a str, &'a str),
+    }
+
+    /// Packs the tree into an image, creating the parents of every path
+    pub(crate) fn image(tree: &[Node]) -> Vec<u8> {
+        let root = tempfile::tempdir().unwrap();
+        let create = |path: &str| {
+            let path = root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            path
+        };
+        for node in tree {
+            match node {
+                Node::Dir(path) => fs::create_dir_all(root.path().join(path)).unwrap(),
+                Node::File(path, contents) => fs::write(create(path), contents).unwrap(),
+                Node::Symlink(path, target) => unix::fs::symlink(target, create(path)).unwrap(),
+            }
+        }
+
+        let mut image = Cursor::new(Vec::new());
+        pack_dir(root.path(), &mut image, 0, &PackOptions::default()).unwrap();
+        image.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "fs")]
+    use super::testing::{image, Node};
+    use super::*;
+
+    #[test]
+    fn lookup_path_is_absolute() {
+        assert_eq!(
+            lookup_path(Path::new("usr/bin")),
+            Some(PathBuf::from("/usr/bin"))
+        );
+        assert_eq!(
+            lookup_path(Path::new("/usr/./bin/")),
+            Some(PathBuf::from("/usr/bin"))
+        );
+        assert_eq!(lookup_path(Path::new("")), Some(PathBuf::from("/")));
+    }
+
+    #[cfg(feature = "vfs")]
+    #[test]
+    fn child_indices_skip_subtrees() {
+        let paths: Vec<PathBuf> = ["/", "/a", "/a/b", "/a/b/c", "/a/d", "/a.b", "/a.b/e", "/f"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let children = |dir| child_indices(&paths, PathBuf::as_path, Path::new(dir));
+
+        assert_eq!(children("/"), [1, 5, 7]);
+        assert_eq!(children("/a"), [2, 4]);
+        assert_eq!(children("/a/b"), [3]);
+        assert_eq!(children("/a.b"), [6]);
+        assert!(children("/f").is_empty());
+        assert!(children("/missing").is_empty());
+    }
+
+    #[test]
+    fn lookup_path_rejects_parent_dirs() {
+        assert_eq!(lookup_path(Path::new("/usr/../etc/passwd")), None);
+        assert_eq!(lookup_path(Path::new("../usr")), None);
+        assert_eq!(lookup_path(Path::new("/usr/bin/..")), None);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn read_file_finds_the_entry_like_find() {
+        let data = image(&[
+            Node::File("usr/bin/foo", b"foo"),
+            Node::Symlink("usr/bin/link", "foo"),
+            Node::Dir("etc"),
+        ]);
+        let squashfs = SquashFS::from_bytes(&data, None).unwrap();
+        for path in ["usr/bin/foo", "/usr/bin/foo", "/usr/./bin/foo/"] {
+            assert!(squashfs.exists(path));
+            assert_eq!(squashfs.read_file(path).unwrap(), b"foo");
+        }
+        for path in [
+            "/usr/bin/../bin/foo",
+            "/usr/bin/bar",
+            "/usr/bin/link",
+            "/etc",
+        ] {
+            assert!(matches!(
+                squashfs.read_file(path),
+                Err(SquishyError::FileNotFound(_))
+            ));
+        }
+    }
+}
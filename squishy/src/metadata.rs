@@ -0,0 +1,509 @@
+//! Reading the SquashFS metadata backhand parses but doesn't expose, like the
+//! inode numbers and xattrs of inodes
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use backhand::compression::Compressor;
+
+use crate::{decompress::decompress, error::SquishyError, Result, SQUASHFS_MAGIC};
+
+/// Size of decompressed metadata blocks
+const METADATA_BLOCK_SIZE: usize = 8192;
+
+/// Marks metadata blocks stored uncompressed
+const METADATA_UNCOMPRESSED: u16 = 0x8000;
+
+/// Table positions of the superblock that are unset
+pub(crate) const NOT_SET: u64 = u64::MAX;
+
+/// Xattr index of inodes without xattrs
+const NO_XATTRS: u32 = u32::MAX;
+
+pub(crate) fn le16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+pub(crate) fn le32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+pub(crate) fn le64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// Adds a position taken from the image, failing instead of overflowing
+fn checked_position(base: u64, offset: u64) -> Result<u64> {
+    base.checked_add(offset).ok_or_else(|| {
+        SquishyError::InvalidSquashFS(format!("metadata position {:#x} out of range", offset))
+    })
+}
+
+/// The fields of the superblock needed to read the metadata tables
+pub(crate) struct Superblock {
+    pub(crate) compressor: Compressor,
+    pub(crate) root_inode: u64,
+    pub(crate) xattr_table: u64,
+    pub(crate) inode_table: u64,
+    pub(crate) dir_table: u64,
+}
+
+impl Superblock {
+    /// Reads the superblock at the offset of the SquashFS data
+    pub(crate) fn read<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<Self> {
+        let mut raw = [0; 96];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut raw)?;
+        if raw[..4] != SQUASHFS_MAGIC {
+            return Err(SquishyError::InvalidSquashFS(
+                "no superblock at the offset".to_string(),
+            ));
+        }
+
+        let compressor = match le16(&raw, 20) {
+            0 => Compressor::None,
+            1 => Compressor::Gzip,
+            2 => Compressor::Lzma,
+            3 => Compressor::Lzo,
+            4 => Compressor::Xz,
+            5 => Compressor::Lz4,
+            6 => Compressor::Zstd,
+            id => {
+                return Err(SquishyError::InvalidSquashFS(format!(
+                    "unknown compressor {}",
+                    id
+                )))
+            }
+        };
+        Ok(Self {
+            compressor,
+            root_inode: le64(&raw, 32),
+            xattr_table: le64(&raw, 56),
+            inode_table: le64(&raw, 64),
+            dir_table: le64(&raw, 72),
+        })
+    }
+}
+
+/// Reads the metadata blocks of a table as one stream, from a block and an
+/// offset in its decompressed data. Blocks are kept once decompressed.
+pub(crate) struct MetadataReader<'r, R> {
+    reader: &'r mut R,
+    offset: u64,
+    compressor: Compressor,
+    /// Decompressed blocks by position, with the position of the next block
+    blocks: HashMap<u64, (Vec<u8>, u64)>,
+    block: u64,
+    at: usize,
+}
+
+impl<'r, R: Read + Seek> MetadataReader<'r, R> {
+    pub(crate) fn new(reader: &'r mut R, offset: u64, compressor: Compressor) -> Self {
+        Self {
+            reader,
+            offset,
+            compressor,
+            blocks: HashMap::new(),
+            block: 0,
+            at: 0,
+        }
+    }
+
+    /// Moves to the offset in the decompressed data of the block at the
+    /// position within the SquashFS data
+    pub(crate) fn seek(&mut self, block: u64, at: usize) {
+        self.block = block;
+        self.at = at;
+    }
+
+    /// Returns the block and offset the reader points to
+    pub(crate) fn position(&self) -> (u64, usize) {
+        (self.block, self.at)
+    }
+
+    /// Moves to a reference of the upper 48 bits pointing to a block relative
+    /// to the start of the table, and the lower 16 bits to the offset in it
+    pub(crate) fn seek_ref(&mut self, table: u64, reference: u64) -> Result<()> {
+        let block = checked_position(table, reference >> 16)?;
+        self.seek(block, (reference & 0xffff) as usize);
+        Ok(())
+    }
+
+    fn load(&mut self, pos: u64) -> Result<()> {
+        if self.blocks.contains_key(&pos) {
+            return Ok(());
+        }
+
+        let mut header = [0; 2];
+        self.reader
+            .seek(SeekFrom::Start(checked_position(self.offset, pos)?))?;
+        self.reader.read_exact(&mut header)?;
+        let header = u16::from_le_bytes(header);
+        let size = header & !METADATA_UNCOMPRESSED;
+        let mut raw = vec![0; usize::from(size)];
+        self.reader.read_exact(&mut raw)?;
+
+        let data = if header & METADATA_UNCOMPRESSED != 0 {
+            raw
+        } else {
+            let mut out = Vec::with_capacity(METADATA_BLOCK_SIZE);
+            decompress(&raw, &mut out, self.compressor)
+                .map_err(|e| SquishyError::InvalidSquashFS(e.to_string()))?;
+            out
+        };
+        let next = checked_position(pos, 2 + u64::from(size))?;
+        self.blocks.insert(pos, (data, next));
+        Ok(())
+    }
+
+    /// Moves forward over bytes, without reading them yet
+    pub(crate) fn skip(&mut self, len: usize) {
+        self.at = self.at.saturating_add(len);
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16> {
+        let mut buf = [0; 2];
+        self.read_exact(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32> {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64> {
+        let mut buf = [0; 8];
+        self.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    pub(crate) fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0; len];
+        self.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub(crate) fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            self.load(self.block)?;
+            let (data, next) = &self.blocks[&self.block];
+            if self.at >= data.len() {
+                self.at -= data.len();
+                self.block = *next;
+                continue;
+            }
+            let len = buf.len().min(data.len() - self.at);
+            buf[..len].copy_from_slice(&data[self.at..self.at + len]);
+            self.at += len;
+            buf = &mut buf[len..];
+        }
+        Ok(())
+    }
+}
+
+/// Reads every inode the directory tree reaches, with its path. Hard links
+/// appear once per path.
+pub(crate) fn read_inodes<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+) -> Result<Vec<(PathBuf, Inode)>> {
+    let superblock = Superblock::read(reader, offset)?;
+    let mut metadata = MetadataReader::new(reader, offset, superblock.compressor);
+    walk_inodes(&mut metadata, &superblock)
+}
+
+/// Walks the directory tree from the root inode, returning the path and
+/// fields of every inode
+fn walk_inodes<R: Read + Seek>(
+    metadata: &mut MetadataReader<'_, R>,
+    superblock: &Superblock,
+) -> Result<Vec<(PathBuf, Inode)>> {
+    let mut inodes = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![(PathBuf::from("/"), superblock.root_inode)];
+
+    while let Some((path, reference)) = pending.pop() {
+        metadata.seek_ref(superblock.inode_table, reference)?;
+        let inode = read_inode(metadata)?;
+        let listing = inode.listing;
+        inodes.push((path.clone(), inode));
+        let Some((block, at, size)) = listing else {
+            continue;
+        };
+        if !visited.insert(reference) {
+            return Err(SquishyError::InvalidSquashFS(format!(
+                "directory loop at {}",
+                path.display()
+            )));
+        }
+
+        // the size counts 3 bytes for the . and .. entries
+        let mut remaining = size.saturating_sub(3);
+        metadata.seek(
+            checked_position(superblock.dir_table, u64::from(block))?,
+            at,
+        );
+        while remaining > 0 {
+            let count = u64::from(metadata.read_u32()?) + 1;
+            let start = metadata.read_u32()?;
+            metadata.read_u32()?;
+            remaining = remaining.saturating_sub(12);
+            for _ in 0..count {
+                let offset = metadata.read_u16()?;
+                metadata.read_u16()?;
+                metadata.read_u16()?;
+                let name_size = usize::from(metadata.read_u16()?) + 1;
+                let name = metadata.read_bytes(name_size)?;
+                remaining = remaining.saturating_sub(8 + name_size as u32);
+                pending.push((
+                    join_name(&path, &name),
+                    u64::from(start) << 16 | u64::from(offset),
+                ));
+            }
+        }
+    }
+    Ok(inodes)
+}
+
+/// Joins a name from the directory table to the path. Names are bytes, as
+/// they are on Unix.
+fn join_name(path: &Path, name: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+        path.join(OsStr::from_bytes(name))
+    }
+    #[cfg(not(unix))]
+    path.join(String::from_utf8_lossy(name).as_ref())
+}
+
+/// The fields of an inode backhand doesn't expose, and those needed to walk
+/// the tree
+pub(crate) struct Inode {
+    pub(crate) number: u32,
+    /// Number of hard links, counting subdirectories for directories
+    pub(crate) nlink: u32,
+    pub(crate) xattr_index: Option<u32>,
+    /// Block, offset and size of the listing of directories
+    listing: Option<(u32, usize, u32)>,
+}
+
+/// Reads the inode the reader points to
+fn read_inode<R: Read + Seek>(metadata: &mut MetadataReader<'_, R>) -> Result<Inode> {
+    let header = metadata.read_bytes(16)?;
+    let mut fields = Inode {
+        number: le32(&header, 12),
+        nlink: 1,
+        xattr_index: None,
+        listing: None,
+    };
+    let xattr_index = match le16(&header, 0) {
+        // basic directory
+        1 => {
+            let raw = metadata.read_bytes(16)?;
+            fields.nlink = le32(&raw, 4);
+            fields.listing = Some((
+                le32(&raw, 0),
+                usize::from(le16(&raw, 10)),
+                u32::from(le16(&raw, 8)),
+            ));
+            NO_XATTRS
+        }
+        // extended directory
+        8 => {
+            let raw = metadata.read_bytes(24)?;
+            fields.nlink = le32(&raw, 0);
+            fields.listing = Some((le32(&raw, 8), usize::from(le16(&raw, 18)), le32(&raw, 4)));
+            le32(&raw, 20)
+        }
+        // basic files always have a single link
+        2 => NO_XATTRS,
+        // extended file
+        9 => {
+            let raw = metadata.read_bytes(40)?;
+            fields.nlink = le32(&raw, 24);
+            le32(&raw, 36)
+        }
+        // basic symlinks, devices, fifos and sockets start with the link count
+        3..=7 => {
+            fields.nlink = metadata.read_u32()?;
+            NO_XATTRS
+        }
+        // extended symlink, the xattr index follows the target
+        10 => {
+            let raw = metadata.read_bytes(8)?;
+            fields.nlink = le32(&raw, 0);
+            metadata.skip(le32(&raw, 4) as usize);
+            metadata.read_u32()?
+        }
+        // extended block and character devices
+        11 | 12 => {
+            let raw = metadata.read_bytes(12)?;
+            fields.nlink = le32(&raw, 0);
+            le32(&raw, 8)
+        }
+        // extended fifos and sockets
+        13 | 14 => {
+            let raw = metadata.read_bytes(8)?;
+            fields.nlink = le32(&raw, 0);
+            le32(&raw, 4)
+        }
+        kind => {
+            return Err(SquishyError::InvalidSquashFS(format!(
+                "unknown inode type {}",
+                kind
+            )))
+        }
+    };
+    if xattr_index != NO_XATTRS {
+        fields.xattr_index = Some(xattr_index);
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Stores the data as one uncompressed metadata block
+    fn block(data: &[u8]) -> Vec<u8> {
+        let mut block = (METADATA_UNCOMPRESSED | data.len() as u16)
+            .to_le_bytes()
+            .to_vec();
+        block.extend_from_slice(data);
+        block
+    }
+
+    /// Returns an inode of the type and number, followed by the fields of
+    /// the type
+    fn inode(kind: u16, number: u32, fields: &[u8]) -> Vec<u8> {
+        let mut inode = vec![0; 16];
+        inode[0..2].copy_from_slice(&kind.to_le_bytes());
+        inode[12..16].copy_from_slice(&number.to_le_bytes());
+        inode.extend_from_slice(fields);
+        inode
+    }
+
+    /// Concatenates little endian u32 fields
+    fn fields(values: &[u32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn read_inode_of_every_type() {
+        let mut basic_dir = fields(&[7, 3, 0, 0]);
+        basic_dir[8..10].copy_from_slice(&40_u16.to_le_bytes());
+        basic_dir[10..12].copy_from_slice(&12_u16.to_le_bytes());
+        let mut extended_dir = fields(&[4, 300, 9, 0, 0, 5]);
+        extended_dir[18..20].copy_from_slice(&20_u16.to_le_bytes());
+        let mut extended_file = vec![0; 40];
+        extended_file[24..28].copy_from_slice(&2_u32.to_le_bytes());
+        extended_file[36..40].copy_from_slice(&6_u32.to_le_bytes());
+        let mut extended_symlink = fields(&[2, 6]);
+        extended_symlink.extend_from_slice(b"target");
+        extended_symlink.extend_from_slice(&7_u32.to_le_bytes());
+
+        // type, fields, link count, xattr index, listing
+        let cases = [
+            (1, basic_dir, 3, None, Some((7, 12, 40))),
+            (2, Vec::new(), 1, None, None),
+            (3, fields(&[2]), 2, None, None),
+            (4, fields(&[3]), 3, None, None),
+            (5, fields(&[4]), 4, None, None),
+            (6, fields(&[5]), 5, None, None),
+            (7, fields(&[6]), 6, None, None),
+            (8, extended_dir, 4, Some(5), Some((9, 20, 300))),
+            (9, extended_file, 2, Some(6), None),
+            (10, extended_symlink, 2, Some(7), None),
+            (11, fields(&[3, 0x0801, 8]), 3, Some(8), None),
+            (12, fields(&[4, 0x0105, NO_XATTRS]), 4, None, None),
+            (13, fields(&[5, 9]), 5, Some(9), None),
+            (14, fields(&[6, 10]), 6, Some(10), None),
+        ];
+        for (number, (kind, fields, nlink, xattr_index, listing)) in cases.into_iter().enumerate() {
+            let mut reader = Cursor::new(block(&inode(kind, number as u32, &fields)));
+            let mut metadata = MetadataReader::new(&mut reader, 0, Compressor::None);
+            let inode = read_inode(&mut metadata).unwrap();
+            assert_eq!(inode.number, number as u32, "type {}", kind);
+            assert_eq!(inode.nlink, nlink, "type {}", kind);
+            assert_eq!(inode.xattr_index, xattr_index, "type {}", kind);
+            assert_eq!(inode.listing, listing, "type {}", kind);
+        }
+
+        let mut reader = Cursor::new(block(&inode(15, 0, &[])));
+        let mut metadata = MetadataReader::new(&mut reader, 0, Compressor::None);
+        assert!(read_inode(&mut metadata).is_err());
+    }
+
+    /// Builds the tables of a root directory listing the names, each
+    /// referring to the inode at the offset in the inode block. The root is
+    /// at offset 0, followed by a basic file at 32.
+    fn walk(names: &[(&[u8], u16)]) -> Result<Vec<(PathBuf, Inode)>> {
+        let mut listing = fields(&[names.len() as u32 - 1, 0, 0]);
+        for (name, offset) in names {
+            listing.extend_from_slice(&offset.to_le_bytes());
+            listing.extend_from_slice(&[0; 4]);
+            listing.extend_from_slice(&(name.len() as u16 - 1).to_le_bytes());
+            listing.extend_from_slice(name);
+        }
+
+        let mut root = fields(&[0, 2, 0, 0]);
+        root[8..10].copy_from_slice(&(listing.len() as u16 + 3).to_le_bytes());
+        let mut inodes = inode(1, 1, &root);
+        inodes.extend_from_slice(&inode(2, 2, &[]));
+
+        let mut image = block(&inodes);
+        let dir_table = image.len() as u64;
+        image.extend_from_slice(&block(&listing));
+        let superblock = Superblock {
+            compressor: Compressor::None,
+            root_inode: 0,
+            xattr_table: NOT_SET,
+            inode_table: 0,
+            dir_table,
+        };
+        let mut reader = Cursor::new(image);
+        walk_inodes(
+            &mut MetadataReader::new(&mut reader, 0, Compressor::None),
+            &superblock,
+        )
+    }
+
+    #[test]
+    fn walk_inodes_reads_the_tree() {
+        let inodes = walk(&[(b"file", 32)]).unwrap();
+        let numbers: Vec<(&Path, u32)> = inodes
+            .iter()
+            .map(|(path, inode)| (path.as_path(), inode.number))
+            .collect();
+        assert_eq!(numbers, [(Path::new("/"), 1), (Path::new("/file"), 2)]);
+    }
+
+    #[test]
+    fn walk_inodes_refuses_directory_loops() {
+        assert!(matches!(
+            walk(&[(b"file", 32), (b"loop", 0)]),
+            Err(SquishyError::InvalidSquashFS(message)) if message == "directory loop at /loop"
+        ));
+    }
+
+    #[test]
+    fn references_past_the_end_of_positions_fail() {
+        let mut reader = Cursor::new(block(&[0; 8]));
+        let mut metadata = MetadataReader::new(&mut reader, u64::MAX, Compressor::None);
+        assert!(metadata.read_u16().is_err());
+
+        let mut metadata = MetadataReader::new(&mut reader, 0, Compressor::None);
+        assert!(metadata.seek_ref(u64::MAX, 1 << 16).is_err());
+    }
+}
@@ -0,0 +1,296 @@
+use std::{io::Read, path::Path};
+
+use crate::{error::SquishyError, EntryKind, Result, SquashFS, SquashFSEntry};
+
+/// Bytes read from the start of files to sniff their type
+pub const SNIFF_SIZE: u64 = 1024;
+
+/// Files that are neither text nor of a known format
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Magic bytes and the offset they are found at, by MIME type. Checked in
+/// order, so longer magics go before their prefixes.
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (0, b"II*\0", "image/tiff"),
+    (0, b"MM\0*", "image/tiff"),
+    (0, b"\0\0\x01\0", "image/vnd.microsoft.icon"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"\xfd7zXZ\0", "application/x-xz"),
+    (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (257, b"ustar", "application/x-tar"),
+    (0, b"hsqs", "application/vnd.squashfs"),
+    (0, b"\0asm", "application/wasm"),
+    (0, b"SQLite format 3\0", "application/vnd.sqlite3"),
+    (0, b"\xca\xfe\xba\xbe", "application/java-vm"),
+    (0, b"\xde\x12\x04\x95", "application/x-gettext-translation"),
+    (0, b"\x95\x04\x12\xde", "application/x-gettext-translation"),
+    (0, b"OTTO", "font/otf"),
+    (0, b"\0\x01\0\0", "font/ttf"),
+    (0, b"wOFF", "font/woff"),
+    (0, b"wOF2", "font/woff2"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"ID3", "audio/mpeg"),
+];
+
+/// RIFF containers, by the form type at offset 8
+const RIFF: &[(&[u8], &str)] = &[
+    (b"WEBP", "image/webp"),
+    (b"WAVE", "audio/wav"),
+    (b"AVI ", "video/x-msvideo"),
+];
+
+/// Text formats, by how the text starts after leading whitespace
+const TEXT: &[(&[u8], &str)] = &[
+    (b"#!", "text/x-script"),
+    (b"[Desktop Entry]", "application/x-desktop"),
+    (b"<svg", "image/svg+xml"),
+    (b"<!DOCTYPE html", "text/html"),
+    (b"<!doctype html", "text/html"),
+    (b"<html", "text/html"),
+    (b"<?xml", "application/xml"),
+];
+
+/// Sniffs the MIME type of file contents from their first bytes, e.g. the
+/// first `SNIFF_SIZE` bytes of a file.
+///
+/// # Arguments
+/// * `data` - The start of the contents.
+///
+/// # Returns
+/// The MIME type, `text/plain` for other text, or `OCTET_STREAM` if the format
+/// isn't known.
+pub fn sniff(data: &[u8]) -> &'static str {
+    if data.starts_with(b"\x7fELF") {
+        return sniff_elf(data);
+    }
+    if let Some(form) = data.strip_prefix(b"RIFF").and_then(|data| data.get(4..)) {
+        if let Some((_, mime)) = RIFF.iter().find(|(magic, _)| form.starts_with(magic)) {
+            return mime;
+        }
+    }
+    if let Some((_, _, mime)) = MAGIC.iter().find(|(offset, magic, _)| {
+        data.get(*offset..)
+            .is_some_and(|data| data.starts_with(magic))
+    }) {
+        return mime;
+    }
+    if !is_text(data) {
+        return OCTET_STREAM;
+    }
+
+    let text = data.trim_ascii_start();
+    if let Some((_, mime)) = TEXT.iter().find(|(start, _)| text.starts_with(start)) {
+        // XML documents declare themselves before the root element
+        if *mime == "application/xml" && contains(text, b"<svg") {
+            return "image/svg+xml";
+        }
+        return mime;
+    }
+    "text/plain"
+}
+
+/// Tells ELF executables, shared libraries, objects and core dumps apart by
+/// the type in the header. Position independent executables are shared
+/// objects by their header.
+fn sniff_elf(data: &[u8]) -> &'static str {
+    let kind = match (data.get(5), data.get(16..18)) {
+        (Some(1), Some(kind)) => u16::from_le_bytes([kind[0], kind[1]]),
+        (Some(2), Some(kind)) => u16::from_be_bytes([kind[0], kind[1]]),
+        _ => 0,
+    };
+    match kind {
+        1 => "application/x-object",
+        2 => "application/x-executable",
+        3 => "application/x-sharedlib",
+        4 => "application/x-coredump",
+        _ => "application/x-elf",
+    }
+}
+
+/// Checks whether the bytes look like UTF-8 text, allowing a character cut
+/// off at the end
+fn is_text(data: &[u8]) -> bool {
+    if data.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(data) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+fn contains(data: &[u8], needle: &[u8]) -> bool {
+    data.windows(needle.len()).any(|window| window == needle)
+}
+
+impl SquashFS<'_> {
+    /// Sniffs the MIME type of an entry from the magic bytes at the start of
+    /// files, regardless of their extension. Directories are
+    /// `inode/directory`, symlinks `inode/symlink` and empty files
+    /// `inode/x-empty`, like `file --mime-type` reports them.
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to sniff.
+    ///
+    /// # Returns
+    /// The MIME type, None for devices, fifos and sockets, or an error if the
+    /// file cannot be read.
+    pub fn mime(&self, entry: &SquashFSEntry) -> Result<Option<&'static str>> {
+        match entry.kind {
+            EntryKind::File(_) if entry.size == 0 => Ok(Some("inode/x-empty")),
+            EntryKind::File(_) => {
+                let mut data = Vec::new();
+                self.open_entry(entry)?
+                    .take(SNIFF_SIZE)
+                    .read_to_end(&mut data)?;
+                Ok(Some(sniff(&data)))
+            }
+            EntryKind::Directory => Ok(Some("inode/directory")),
+            EntryKind::Symlink(_) => Ok(Some("inode/symlink")),
+            EntryKind::Unknown => Ok(None),
+        }
+    }
+
+    /// Sniffs the MIME type of the entry at the path. See `SquashFS::mime`.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the entry within the SquashFS filesystem.
+    ///
+    /// # Returns
+    /// The MIME type, None for devices, fifos and sockets, or an error if
+    /// there is no entry at the path or it cannot be read.
+    pub fn entry_mime<P: AsRef<Path>>(&self, path: P) -> Result<Option<&'static str>> {
+        let entry = self
+            .find(&path)
+            .ok_or_else(|| SquishyError::FileNotFound(path.as_ref().to_path_buf()))?;
+        self.mime(&entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the magic at its offset, followed by zeros
+    fn with_magic(offset: usize, magic: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; offset];
+        data.extend_from_slice(magic);
+        data.resize(SNIFF_SIZE as usize, 0);
+        data
+    }
+
+    fn elf(class: u8, data: u8, kind: u16) -> Vec<u8> {
+        let mut header = vec![0; 64];
+        header[..6].copy_from_slice(&[0x7f, b'E', b'L', b'F', class, data]);
+        let kind = match data {
+            1 => kind.to_le_bytes(),
+            _ => kind.to_be_bytes(),
+        };
+        header[16..18].copy_from_slice(&kind);
+        header
+    }
+
+    #[test]
+    fn sniffs_every_magic() {
+        for (offset, magic, mime) in MAGIC {
+            assert_eq!(sniff(&with_magic(*offset, magic)), *mime, "{:?}", magic);
+            // only the magic itself
+            assert_eq!(
+                sniff(&with_magic(*offset, magic)[..offset + magic.len()]),
+                *mime
+            );
+        }
+        assert_eq!(sniff(&with_magic(256, b"ustar")), OCTET_STREAM);
+    }
+
+    #[test]
+    fn sniffs_riff_forms() {
+        for (form, mime) in RIFF {
+            let mut data = b"RIFF\x24\0\0\0".to_vec();
+            data.extend_from_slice(form);
+            assert_eq!(sniff(&data), *mime);
+        }
+        // other forms aren't known
+        assert_eq!(sniff(b"RIFF\x24\0\0\0RMID"), OCTET_STREAM);
+        assert_eq!(sniff(b"RIFF"), "text/plain");
+    }
+
+    #[test]
+    fn sniffs_elf_types_of_either_endianness() {
+        for data in [1, 2] {
+            assert_eq!(sniff(&elf(2, data, 1)), "application/x-object");
+            assert_eq!(sniff(&elf(2, data, 2)), "application/x-executable");
+            assert_eq!(sniff(&elf(1, data, 3)), "application/x-sharedlib");
+            assert_eq!(sniff(&elf(2, data, 4)), "application/x-coredump");
+            assert_eq!(sniff(&elf(2, data, 0xfe00)), "application/x-elf");
+        }
+        assert_eq!(sniff(&elf(2, 3, 2)), "application/x-elf");
+        assert_eq!(sniff(b"\x7fELF\x02\x01"), "application/x-elf");
+    }
+
+    #[test]
+    fn sniffs_text_formats() {
+        for (start, mime) in TEXT {
+            let mut data = b" \n\t".to_vec();
+            data.extend_from_slice(start);
+            data.extend_from_slice(b" rest");
+            assert_eq!(sniff(&data), *mime);
+        }
+        assert_eq!(
+            sniff(b"<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>"),
+            "image/svg+xml"
+        );
+        assert_eq!(
+            sniff(b"<?xml version=\"1.0\"?>\n<component type=\"desktop-application\"/>"),
+            "application/xml"
+        );
+        assert_eq!(sniff("plain text, caf\u{e9}".as_bytes()), "text/plain");
+        assert_eq!(sniff(b""), "text/plain");
+    }
+
+    #[test]
+    fn tells_text_from_binary() {
+        // a character cut off by the end of the sniffed bytes
+        let cut = "caf\u{e9}".as_bytes();
+        assert_eq!(sniff(&cut[..cut.len() - 1]), "text/plain");
+        assert_eq!(sniff(b"caf\xe9 latin-1"), OCTET_STREAM);
+        assert_eq!(sniff(b"text\0with a nul"), OCTET_STREAM);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn sniffs_entries_by_kind() {
+        use crate::testing::{image, Node};
+
+        let data = image(&[
+            Node::File("icon.png", b"\x89PNG\r\n\x1a\n"),
+            Node::File("app.desktop.bak", b"[Desktop Entry]\nName=App\n"),
+            Node::File("empty", b""),
+            Node::Symlink("link", "icon.png"),
+            Node::Dir("dir"),
+        ]);
+        let squashfs = SquashFS::from_bytes(&data, None).unwrap();
+        for (path, mime) in [
+            ("/icon.png", "image/png"),
+            ("/app.desktop.bak", "application/x-desktop"),
+            ("/empty", "inode/x-empty"),
+            ("/link", "inode/symlink"),
+            ("/dir", "inode/directory"),
+        ] {
+            assert_eq!(squashfs.entry_mime(path).unwrap(), Some(mime));
+        }
+        assert!(matches!(
+            squashfs.entry_mime("/missing"),
+            Err(SquishyError::FileNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+//! Async wrappers for use from a tokio runtime. The SquashFS reader is
+//! blocking, so the work runs on tokio's blocking thread pool and the
+//! `SquashFS` is shared through an `Arc`.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Write},
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::mpsc,
+    task,
+};
+
+#[cfg(feature = "fs")]
+use crate::unpack::{UnpackOptions, UnpackReport};
+use crate::{
+    error::{IoContext, SquishyError},
+    Result, SquashFS,
+};
+
+/// Number of chunks a `FileStream` buffers ahead of the reader
+const STREAM_CHUNKS: usize = 4;
+/// Size of the chunks sent by a `FileStream`
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Runs the closure on the blocking thread pool
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    task::spawn_blocking(f)
+        .await
+        .map_err(|e| SquishyError::Io(io::Error::other(e)))?
+}
+
+impl SquashFS<'static> {
+    /// Opens a SquashFS file without blocking the runtime.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the SquashFS file.
+    /// * `offset` - Offset of the SquashFS data, found automatically if `None`
+    ///
+    /// # Returns
+    /// A shared SquashFS instance, or an error if no valid SquashFS data is found.
+    pub async fn open_async<P: AsRef<Path>>(path: P, offset: Option<u64>) -> Result<Arc<Self>> {
+        let path = path.as_ref().to_path_buf();
+        blocking(move || {
+            let file = File::open(&path).context("open", &path)?;
+            SquashFS::new(BufReader::new(file), offset).map(Arc::new)
+        })
+        .await
+    }
+
+    /// Reads the contents of a file without blocking the runtime, see `read_file`.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the file within the SquashFS filesystem.
+    ///
+    /// # Returns
+    /// The contents of the file, or an error if the file is not found.
+    pub async fn read_file_async<P: AsRef<Path>>(self: &Arc<Self>, path: P) -> Result<Vec<u8>> {
+        let squashfs = Arc::clone(self);
+        let path = path.as_ref().to_path_buf();
+        blocking(move || squashfs.read_file(path)).await
+    }
+
+    /// Extracts entries without blocking the runtime, see `unpack_to`.
+    ///
+    /// # Arguments
+    /// * `dest` - The directory to extract into
+    /// * `options` - Selects and maps the extracted entries
+    ///
+    /// # Returns
+    /// The report of what was done with every entry, or an error if an entry
+    /// can't be read or written and `on_error` aborts
+    #[cfg(feature = "fs")]
+    pub async fn unpack_to_async<P: AsRef<Path>>(
+        self: &Arc<Self>,
+        dest: P,
+        options: UnpackOptions,
+    ) -> Result<UnpackReport> {
+        let squashfs = Arc::clone(self);
+        let dest = dest.as_ref().to_path_buf();
+        blocking(move || squashfs.unpack_to(dest, &options)).await
+    }
+
+    /// Streams the contents of a file, decompressing it on the blocking
+    /// thread pool while the stream is read. Must be called from within a
+    /// tokio runtime.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the file within the SquashFS filesystem.
+    ///
+    /// # Returns
+    /// A reader of the file contents. A missing file is reported by the first read.
+    pub fn file_stream<P: AsRef<Path>>(self: &Arc<Self>, path: P) -> FileStream {
+        let squashfs = Arc::clone(self);
+        let path = path.as_ref().to_path_buf();
+        let (sender, receiver) = mpsc::channel(STREAM_CHUNKS);
+        task::spawn_blocking(move || {
+            let mut writer = ChunkWriter {
+                sender: sender.clone(),
+                chunk: Vec::with_capacity(STREAM_CHUNK_SIZE),
+            };
+            let result = match squashfs.find(&path) {
+                Some(entry) => squashfs
+                    .copy_entry_to(&entry, &mut writer)
+                    .and_then(|_| Ok(writer.flush()?)),
+                None => Err(SquishyError::FileNotFound(path)),
+            };
+            if let Err(e) = result {
+                let e = match e {
+                    SquishyError::Io(e) => e,
+                    e @ SquishyError::FileNotFound(_) => io::Error::new(io::ErrorKind::NotFound, e),
+                    e => io::Error::other(e),
+                };
+                let _ = sender.blocking_send(Err(e));
+            }
+        });
+        FileStream {
+            receiver,
+            chunk: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+/// Sends what is written to a `FileStream` in chunks
+struct ChunkWriter {
+    sender: mpsc::Sender<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(STREAM_CHUNK_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..len]);
+        if self.chunk.len() == STREAM_CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(STREAM_CHUNK_SIZE));
+        // the stream was dropped, stop decompressing
+        self.sender
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+/// The FileStream struct reads the contents of a file inside a SquashFS
+/// asynchronously, as returned by `SquashFS::file_stream`.
+pub struct FileStream {
+    receiver: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl AsyncRead for FileStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.position == self.chunk.len() {
+            match self.receiver.poll_recv(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                // all chunks were sent
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let len = buf.remaining().min(self.chunk.len() - self.position);
+        let start = self.position;
+        buf.put_slice(&self.chunk[start..start + len]);
+        self.position += len;
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,223 @@
+//! Opening any image squishy reads with a single function
+
+use std::{fs::File, io::BufReader, path::Path};
+
+#[cfg(all(feature = "appimage", feature = "erofs"))]
+use crate::appimage::ErofsAppImage;
+#[cfg(feature = "erofs")]
+use crate::erofs::Erofs;
+#[cfg(feature = "fs")]
+use crate::unpack::{UnpackOptions, UnpackReport};
+#[cfg(feature = "appimage")]
+use crate::{appimage::AppImage, filter::PathFilter};
+use crate::{
+    detect::{detect_filesystem_type, FilesystemType},
+    error::{IoContext, SquishyError},
+    EntryStats, Result, SquashFS,
+};
+
+/// Magic bytes at the start of ELF files
+#[cfg(feature = "appimage")]
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+
+/// The Image enum is an image of any filesystem squishy reads, as opened by
+/// `squishy::open`. The methods work the same for every kind of image.
+#[non_exhaustive]
+pub enum Image<'a> {
+    /// An AppImage carrying SquashFS
+    #[cfg(feature = "appimage")]
+    AppImage(AppImage<'a>),
+    /// An AppImage carrying EROFS
+    #[cfg(all(feature = "appimage", feature = "erofs"))]
+    ErofsAppImage(ErofsAppImage<'a>),
+    SquashFS(SquashFS<'a>),
+    #[cfg(feature = "erofs")]
+    Erofs(Erofs<'a>),
+}
+
+/// Opens the image, detecting which filesystem it holds and where, see
+/// `detect::detect_filesystem_type`.
+///
+/// # Arguments
+/// * `path` - The path to the image or AppImage
+///
+/// # Returns
+/// The image, or an error if no filesystem squishy reads is found in it.
+pub fn open<P: AsRef<Path>>(path: &P) -> Result<Image<'_>> {
+    let mut reader = BufReader::new(File::open(path).context("open", path)?);
+    let (kind, offset) = detect_filesystem_type(&mut reader)?;
+
+    match kind {
+        FilesystemType::SquashFS => {
+            #[cfg(feature = "appimage")]
+            if is_elf(&mut reader) {
+                let appimage = AppImage::new(PathFilter::default(), path, Some(offset))?;
+                return Ok(Image::AppImage(appimage));
+            }
+            Ok(Image::SquashFS(SquashFS::from_path_with_offset(
+                path, offset,
+            )?))
+        }
+        #[cfg(feature = "erofs")]
+        FilesystemType::Erofs => {
+            #[cfg(feature = "appimage")]
+            if is_elf(&mut reader) {
+                let appimage = ErofsAppImage::new(PathFilter::default(), path, Some(offset))?;
+                return Ok(Image::ErofsAppImage(appimage));
+            }
+            Ok(Image::Erofs(Erofs::from_path_with_offset(path, offset)?))
+        }
+        kind => Err(SquishyError::Unsupported(format!("{} images", kind))),
+    }
+}
+
+/// Checks whether the file starts with the ELF magic bytes
+#[cfg(feature = "appimage")]
+fn is_elf<R: std::io::Read>(reader: &mut R) -> bool {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic).is_ok() && magic == ELF_MAGIC
+}
+
+impl<'a> Image<'a> {
+    /// Returns the filesystem of the image
+    pub fn kind(&self) -> FilesystemType {
+        match self {
+            #[cfg(feature = "appimage")]
+            Image::AppImage(_) => FilesystemType::SquashFS,
+            #[cfg(all(feature = "appimage", feature = "erofs"))]
+            Image::ErofsAppImage(_) => FilesystemType::Erofs,
+            Image::SquashFS(_) => FilesystemType::SquashFS,
+            #[cfg(feature = "erofs")]
+            Image::Erofs(_) => FilesystemType::Erofs,
+        }
+    }
+
+    /// Returns the SquashFS of AppImages and SquashFS images, for everything
+    /// not available on all images
+    pub fn squashfs(&self) -> Option<&SquashFS<'a>> {
+        match self {
+            #[cfg(feature = "appimage")]
+            Image::AppImage(appimage) => Some(&appimage.squashfs),
+            #[cfg(all(feature = "appimage", feature = "erofs"))]
+            Image::ErofsAppImage(_) => None,
+            Image::SquashFS(squashfs) => Some(squashfs),
+            #[cfg(feature = "erofs")]
+            Image::Erofs(_) => None,
+        }
+    }
+
+    /// Returns the offset of the filesystem within the file.
+    pub fn offset(&self) -> u64 {
+        match self {
+            #[cfg(feature = "appimage")]
+            Image::AppImage(appimage) => appimage.squashfs.offset(),
+            #[cfg(all(feature = "appimage", feature = "erofs"))]
+            Image::ErofsAppImage(appimage) => appimage.offset(),
+            Image::SquashFS(squashfs) => squashfs.offset(),
+            #[cfg(feature = "erofs")]
+            Image::Erofs(erofs) => erofs.offset(),
+        }
+    }
+
+    /// Counts the entries of the image by kind, see `SquashFS::stats`.
+    pub fn stats(&self) -> EntryStats {
+        match self {
+            #[cfg(feature = "appimage")]
+            Image::AppImage(appimage) => appimage.squashfs.stats(),
+            #[cfg(all(feature = "appimage", feature = "erofs"))]
+            Image::ErofsAppImage(appimage) => appimage.erofs.stats(),
+            Image::SquashFS(squashfs) => squashfs.stats(),
+            #[cfg(feature = "erofs")]
+            Image::Erofs(erofs) => erofs.stats(),
+        }
+    }
+
+    /// Checks whether an entry exists at the path. The leading `/` is optional.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the entry within the image.
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        match self {
+            #[cfg(feature = "appimage")]
+            Image::AppImage(appimage) => appimage.squashfs.exists(path),
+            #[cfg(all(feature = "appimage", feature = "erofs"))]
+            Image::ErofsAppImage(appimage) => appimage.exists(path),
+            Image::SquashFS(squashfs) => squashfs.exists(path),
+            #[cfg(feature = "erofs")]
+            Image::Erofs(erofs) => erofs.exists(path),
+        }
+    }
+
+    /// Reads the contents of the file at the path.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file within the image.
+    ///
+    /// # Returns
+    /// The contents of the file, or an error if it is missing or can't be read.
+    pub fn read_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "appimage")]
+            Image::AppImage(appimage) => appimage.squashfs.read_file(path),
+            #[cfg(all(feature = "appimage", feature = "erofs"))]
+            Image::ErofsAppImage(appimage) => appimage.erofs.read_file(path),
+            Image::SquashFS(squashfs) => squashfs.read_file(path),
+            #[cfg(feature = "erofs")]
+            Image::Erofs(erofs) => erofs.read_file(path),
+        }
+    }
+
+    /// Extracts the entries selected by the options into the destination
+    /// directory, see `SquashFS::unpack_to`.
+    ///
+    /// # Arguments
+    /// * `dest` - The directory to extract into, created if missing
+    /// * `options` - Selects and maps the extracted entries
+    ///
+    /// # Returns
+    /// The report of what was done with every entry, or an error if an entry
+    /// can't be read or written and `on_error` aborts
+    #[cfg(feature = "fs")]
+    pub fn unpack_to<P: AsRef<Path>>(
+        &self,
+        dest: P,
+        options: &UnpackOptions,
+    ) -> Result<UnpackReport> {
+        match self {
+            #[cfg(feature = "appimage")]
+            Image::AppImage(appimage) => appimage.squashfs.unpack_to(dest, options),
+            #[cfg(all(feature = "appimage", feature = "erofs"))]
+            Image::ErofsAppImage(appimage) => appimage.unpack_to(dest, options),
+            Image::SquashFS(squashfs) => squashfs.unpack_to(dest, options),
+            #[cfg(feature = "erofs")]
+            Image::Erofs(erofs) => erofs.unpack_to(dest, options),
+        }
+    }
+
+    /// Extracts the entries selected by the options into the directory
+    /// capability, see `SquashFS::unpack_to_dir`.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory to extract into
+    /// * `options` - Selects and maps the extracted entries
+    ///
+    /// # Returns
+    /// The report of what was done with every entry, or an error if an entry
+    /// can't be read or written and `on_error` aborts
+    #[cfg(feature = "cap-std")]
+    pub fn unpack_to_dir(
+        &self,
+        dir: &cap_std::fs::Dir,
+        options: &UnpackOptions,
+    ) -> Result<UnpackReport> {
+        match self {
+            #[cfg(feature = "appimage")]
+            Image::AppImage(appimage) => appimage.squashfs.unpack_to_dir(dir, options),
+            #[cfg(all(feature = "appimage", feature = "erofs"))]
+            Image::ErofsAppImage(appimage) => appimage.erofs.unpack_to_dir(dir, options),
+            Image::SquashFS(squashfs) => squashfs.unpack_to_dir(dir, options),
+            #[cfg(feature = "erofs")]
+            Image::Erofs(erofs) => erofs.unpack_to_dir(dir, options),
+        }
+    }
+}
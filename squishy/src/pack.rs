@@ -0,0 +1,345 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, Write},
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+
+use backhand::{
+    compression::{CompressionOptions, Compressor, Gzip, Zstd},
+    CompressionExtra, ExtraXz, FilesystemCompressor, FilesystemWriter, InnerNode, NodeHeader,
+    DEFAULT_BLOCK_SIZE, MAX_BLOCK_SIZE, MIN_BLOCK_SIZE,
+};
+
+use crate::{
+    error::{IoContext, SquishyError},
+    Result, SquashFS,
+};
+
+/// The PackOptions struct controls how `pack_dir` builds a SquashFS image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackOptions {
+    /// Compression of data and metadata blocks
+    pub compressor: Compressor,
+    /// Size of a data block, a power of two from 4 KiB to 1 MiB
+    pub block_size: u32,
+    /// Modification time of the image, as seconds since the Unix epoch
+    pub mtime: u32,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        Self {
+            compressor: Compressor::Zstd,
+            block_size: DEFAULT_BLOCK_SIZE,
+            mtime: 0,
+        }
+    }
+}
+
+/// The RecompressOptions struct controls how `SquashFS::recompress` rewrites an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecompressOptions {
+    /// Compression of data and metadata blocks of the new image
+    pub compressor: Compressor,
+    /// Compression level: 1 to 9 for gzip, 0 to 9 for xz and 1 to 22 for
+    /// zstd. The default of the compressor if None.
+    pub level: Option<u32>,
+    /// Size of a data block, that of the source image if None
+    pub block_size: Option<u32>,
+}
+
+impl Default for RecompressOptions {
+    fn default() -> Self {
+        Self {
+            compressor: Compressor::Zstd,
+            level: None,
+            block_size: None,
+        }
+    }
+}
+
+/// The PackStats struct summarizes the image written by `pack_dir`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PackStats {
+    pub files: u64,
+    pub directories: u64,
+    pub symlinks: u64,
+    pub other: u64,
+    /// Size of the image, without the bytes before the offset
+    pub image_size: u64,
+}
+
+/// Opens the file only when the writer first reads it, so packing a large tree
+/// doesn't keep a descriptor open for every file. Errors name the path, as they
+/// reach the caller through the writer.
+struct LazyFile {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl LazyFile {
+    fn error(&self, e: io::Error) -> io::Error {
+        io::Error::new(e.kind(), format!("{}: {}", self.path.display(), e))
+    }
+}
+
+impl Read for LazyFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self
+                .file
+                .insert(File::open(&self.path).map_err(|e| self.error(e))?),
+        };
+        file.read(buf).map_err(|e| self.error(e))
+    }
+}
+
+fn header(metadata: &fs::Metadata) -> NodeHeader {
+    NodeHeader::new(
+        (metadata.mode() & 0o7777) as u16,
+        metadata.uid(),
+        metadata.gid(),
+        metadata.mtime().clamp(0, i64::from(u32::MAX)) as u32,
+    )
+}
+
+/// Adds the contents of the directory to the image, depth first in name order
+fn push_dir_contents(
+    writer: &mut FilesystemWriter,
+    root: &Path,
+    dir: &Path,
+    stats: &mut PackStats,
+) -> Result<()> {
+    let mut paths = fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()
+        })
+        .context("read directory", dir)?;
+    paths.sort();
+
+    for path in paths {
+        let image_path = Path::new("/").join(path.strip_prefix(root).unwrap_or(&path));
+        let metadata = fs::symlink_metadata(&path).context("read metadata of", &path)?;
+        let file_type = metadata.file_type();
+        let header = header(&metadata);
+
+        let pushed = if file_type.is_dir() {
+            stats.directories += 1;
+            writer.push_dir(&image_path, header)
+        } else if file_type.is_file() {
+            stats.files += 1;
+            let reader = LazyFile {
+                path: path.clone(),
+                file: None,
+            };
+            writer.push_file(reader, &image_path, header)
+        } else if file_type.is_symlink() {
+            stats.symlinks += 1;
+            writer.push_symlink(
+                fs::read_link(&path).context("read link", &path)?,
+                &image_path,
+                header,
+            )
+        } else {
+            stats.other += 1;
+            let device = metadata.rdev() as u32;
+            if file_type.is_char_device() {
+                writer.push_char_device(device, &image_path, header)
+            } else if file_type.is_block_device() {
+                writer.push_block_device(device, &image_path, header)
+            } else if file_type.is_fifo() {
+                writer.push_fifo(&image_path, header)
+            } else {
+                writer.push_socket(&image_path, header)
+            }
+        };
+        pushed.map_err(|e| SquishyError::Pack(format!("{}: {}", path.display(), e)))?;
+
+        if file_type.is_dir() {
+            push_dir_contents(writer, root, &path, stats)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the block size is a power of two that SquashFS supports
+fn check_block_size(block_size: u32) -> Result<()> {
+    if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) || !block_size.is_power_of_two() {
+        return Err(SquishyError::Pack(format!(
+            "block size must be a power of two from {} to {}, got {}",
+            MIN_BLOCK_SIZE, MAX_BLOCK_SIZE, block_size
+        )));
+    }
+    Ok(())
+}
+
+/// Creates the compressor of a new image, at the level if there is one
+fn compressor(compressor: Compressor, level: Option<u32>) -> Result<FilesystemCompressor> {
+    let invalid = |e: backhand::BackhandError| SquishyError::Pack(e.to_string());
+    let Some(level) = level else {
+        return FilesystemCompressor::new(compressor, None).map_err(invalid);
+    };
+
+    let (options, range) = match compressor {
+        Compressor::Gzip => (
+            Some(CompressionOptions::Gzip(Gzip {
+                compression_level: level,
+                window_size: 15,
+                strategies: 0,
+            })),
+            1..=9,
+        ),
+        Compressor::Xz => (None, 0..=9),
+        Compressor::Zstd => (
+            Some(CompressionOptions::Zstd(Zstd {
+                compression_level: level,
+            })),
+            1..=22,
+        ),
+        _ => {
+            return Err(SquishyError::Pack(format!(
+                "compression levels of {:?} are not supported",
+                compressor
+            )))
+        }
+    };
+    if !range.contains(&level) {
+        return Err(SquishyError::Pack(format!(
+            "compression level of {:?} must be from {} to {}, got {}",
+            compressor,
+            range.start(),
+            range.end(),
+            level
+        )));
+    }
+
+    let mut fs_compressor = FilesystemCompressor::new(compressor, options).map_err(invalid)?;
+    // xz keeps its preset out of the image
+    if compressor == Compressor::Xz {
+        let mut xz = ExtraXz::default();
+        xz.level(level).map_err(invalid)?;
+        fs_compressor
+            .extra(CompressionExtra::Xz(xz))
+            .map_err(invalid)?;
+    }
+    Ok(fs_compressor)
+}
+
+/// Builds a SquashFS image from the contents of a directory. Modes, owners and
+/// modification times are taken from the source, and the source directory
+/// becomes the root of the image.
+///
+/// # Arguments
+/// * `source` - The directory to pack
+/// * `writer` - Where the image is written
+/// * `offset` - Position in the writer where the image starts, e.g. the size of
+///   an AppImage runtime written before it. Offsets stored in the image are
+///   relative to this position.
+/// * `options` - Compression and block size of the image
+///
+/// # Returns
+/// Counts of the packed entries and the image size, or an error if the source
+/// can't be read or the options are invalid
+pub fn pack_dir<P, W>(source: P, writer: W, offset: u64, options: &PackOptions) -> Result<PackStats>
+where
+    P: AsRef<Path>,
+    W: Write + Seek,
+{
+    let source = source.as_ref();
+    check_block_size(options.block_size)?;
+    let compressor = compressor(options.compressor, None)?;
+
+    let metadata = fs::metadata(source).context("read metadata of", source)?;
+    if !metadata.is_dir() {
+        return Err(SquishyError::Pack(format!(
+            "{} is not a directory",
+            source.display()
+        )));
+    }
+
+    let mut fs_writer = FilesystemWriter::default();
+    fs_writer.set_compressor(compressor);
+    fs_writer.set_block_size(options.block_size);
+    fs_writer.set_time(options.mtime);
+    fs_writer.set_root_mode((metadata.mode() & 0o7777) as u16);
+    fs_writer.set_root_uid(metadata.uid());
+    fs_writer.set_root_gid(metadata.gid());
+
+    let mut stats = PackStats::default();
+    push_dir_contents(&mut fs_writer, source, source, &mut stats)?;
+
+    let (_, image_size) = fs_writer
+        .write_with_offset(writer, offset)
+        .map_err(|e| SquishyError::Pack(e.to_string()))?;
+    stats.image_size = image_size;
+    Ok(stats)
+}
+
+impl SquashFS<'_> {
+    /// Writes the image again with another compressor, level or block size,
+    /// without extracting it. Every file is decompressed and compressed
+    /// again, and all entries keep their metadata, as does the image its
+    /// modification time.
+    ///
+    /// # Arguments
+    /// * `writer` - Where the new image is written
+    /// * `offset` - Position in the writer where the image starts, e.g. the
+    ///   size of the AppImage runtime copied before it
+    /// * `options` - Compression, level and block size of the new image
+    ///
+    /// # Returns
+    /// The size of the new image, or an error if the options are invalid or
+    /// the image can't be read or written
+    pub fn recompress<W: Write + Seek>(
+        &self,
+        writer: W,
+        offset: u64,
+        options: &RecompressOptions,
+    ) -> Result<u64> {
+        let block_size = options.block_size.unwrap_or(self.reader.block_size);
+        check_block_size(block_size)?;
+
+        let mut fs_writer = FilesystemWriter::default();
+        fs_writer.set_compressor(compressor(options.compressor, options.level)?);
+        fs_writer.set_block_size(block_size);
+        fs_writer.set_time(self.reader.mod_time);
+
+        // nodes are sorted by path, so directories come before their contents
+        for node in &self.reader.root.nodes {
+            let path = &node.fullpath;
+            let header = node.header;
+            let pushed = match &node.inner {
+                InnerNode::Dir(_) if path == Path::new("/") => {
+                    fs_writer.set_root_mode(header.permissions);
+                    fs_writer.set_root_uid(header.uid);
+                    fs_writer.set_root_gid(header.gid);
+                    Ok(())
+                }
+                InnerNode::Dir(_) => fs_writer.push_dir(path, header),
+                InnerNode::File(file) => {
+                    fs_writer.push_file(self.reader.file(&file.basic).reader(), path, header)
+                }
+                InnerNode::Symlink(symlink) => fs_writer.push_symlink(&symlink.link, path, header),
+                InnerNode::CharacterDevice(device) => {
+                    fs_writer.push_char_device(device.device_number, path, header)
+                }
+                InnerNode::BlockDevice(device) => {
+                    fs_writer.push_block_device(device.device_number, path, header)
+                }
+                InnerNode::NamedPipe => fs_writer.push_fifo(path, header),
+                InnerNode::Socket => fs_writer.push_socket(path, header),
+            };
+            pushed.map_err(|e| SquishyError::Pack(format!("{}: {}", path.display(), e)))?;
+        }
+
+        let (_, image_size) = fs_writer
+            .write_with_offset(writer, offset)
+            .map_err(|e| SquishyError::Pack(e.to_string()))?;
+        Ok(image_size)
+    }
+}
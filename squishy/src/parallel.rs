@@ -0,0 +1,59 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+};
+
+use backhand::BasicFile;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{blocks::corrupt, SquashFS};
+
+/// Files with fewer blocks are decompressed on the calling thread
+pub(crate) const PARALLEL_MIN_BLOCKS: usize = 8;
+
+/// Blocks decompressed at once per thread of the pool. The batch is written
+/// in order before the next one starts, which bounds the memory used.
+const BLOCKS_PER_THREAD: usize = 4;
+
+impl SquashFS<'_> {
+    /// Copies the contents of the file to the writer like `copy_blocks`, but
+    /// decompresses batches of blocks on the rayon pool. The blocks are read
+    /// from `image` at their position, so the threads don't share a cursor.
+    pub(crate) fn copy_blocks_parallel<W: Write + ?Sized>(
+        &self,
+        image: &File,
+        file: &BasicFile,
+        writer: &mut W,
+    ) -> io::Result<u64> {
+        let file_size = u64::from(file.file_size);
+        let blocks = self.blocks(file);
+
+        let batch = rayon::current_num_threads() * BLOCKS_PER_THREAD;
+        let mut copied = 0;
+        for blocks in blocks.chunks(batch) {
+            let decompressed: Vec<Vec<u8>> = blocks
+                .par_iter()
+                .map(|block| self.read_block(image, block.start, block.size, block.len))
+                .collect::<io::Result<_>>()?;
+            for (data, block) in decompressed.iter().zip(blocks) {
+                if data.len() != block.len {
+                    return Err(corrupt("data block of the wrong size"));
+                }
+                writer.write_all(data)?;
+                copied += data.len() as u64;
+            }
+        }
+
+        // the tail shorter than a block is in a fragment shared with other files
+        if copied < file_size {
+            let block = self.read_fragment(image, file)?;
+            let start = file.block_offset as usize;
+            let tail = block
+                .get(start..start + (file_size - copied) as usize)
+                .ok_or_else(|| corrupt("fragment shorter than the file"))?;
+            writer.write_all(tail)?;
+            copied += tail.len() as u64;
+        }
+        Ok(copied)
+    }
+}
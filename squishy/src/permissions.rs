@@ -0,0 +1,219 @@
+use std::{fmt, path::PathBuf};
+
+use crate::{error::SquishyError, metadata::le32, Result, SquashFS};
+
+/// Name of the xattr holding the capabilities of a file
+pub const CAPABILITY_XATTR: &str = "security.capability";
+
+/// Names of the capabilities by number, as printed by getcap
+const CAPABILITY_NAMES: [&str; 41] = [
+    "cap_chown",
+    "cap_dac_override",
+    "cap_dac_read_search",
+    "cap_fowner",
+    "cap_fsetid",
+    "cap_kill",
+    "cap_setgid",
+    "cap_setuid",
+    "cap_setpcap",
+    "cap_linux_immutable",
+    "cap_net_bind_service",
+    "cap_net_broadcast",
+    "cap_net_admin",
+    "cap_net_raw",
+    "cap_ipc_lock",
+    "cap_ipc_owner",
+    "cap_sys_module",
+    "cap_sys_rawio",
+    "cap_sys_chroot",
+    "cap_sys_ptrace",
+    "cap_sys_pacct",
+    "cap_sys_admin",
+    "cap_sys_boot",
+    "cap_sys_nice",
+    "cap_sys_resource",
+    "cap_sys_time",
+    "cap_sys_tty_config",
+    "cap_mknod",
+    "cap_lease",
+    "cap_audit_write",
+    "cap_audit_control",
+    "cap_setfcap",
+    "cap_mac_override",
+    "cap_mac_admin",
+    "cap_syslog",
+    "cap_wake_alarm",
+    "cap_block_suspend",
+    "cap_audit_read",
+    "cap_perfmon",
+    "cap_bpf",
+    "cap_checkpoint_restore",
+];
+
+/// Revisions of the `vfs_cap_data` layout, in the upper byte of its first word
+const VFS_CAP_REVISION_MASK: u32 = 0xff00_0000;
+const VFS_CAP_REVISION_1: u32 = 0x0100_0000;
+const VFS_CAP_REVISION_2: u32 = 0x0200_0000;
+const VFS_CAP_REVISION_3: u32 = 0x0300_0000;
+const VFS_CAP_FLAGS_EFFECTIVE: u32 = 0x1;
+
+/// The FileCapabilities struct holds the capabilities granted to a file by its
+/// `security.capability` xattr, as bit sets indexed by capability number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FileCapabilities {
+    pub permitted: u64,
+    pub inheritable: u64,
+    /// Whether the capabilities are effective right after exec
+    pub effective: bool,
+    /// Root user id of the user namespace the capabilities apply in
+    pub root_uid: Option<u32>,
+}
+
+impl FileCapabilities {
+    /// Decodes the value of a `security.capability` xattr, in any revision
+    /// of the kernel's `vfs_cap_data` layout.
+    ///
+    /// # Arguments
+    /// * `value` - The xattr value.
+    ///
+    /// # Returns
+    /// The capabilities, or None if the value is malformed.
+    pub fn parse(value: &[u8]) -> Option<Self> {
+        if value.len() < 4 {
+            return None;
+        }
+        let magic = le32(value, 0);
+        let effective = magic & VFS_CAP_FLAGS_EFFECTIVE != 0;
+        match magic & VFS_CAP_REVISION_MASK {
+            VFS_CAP_REVISION_1 if value.len() >= 12 => Some(Self {
+                permitted: u64::from(le32(value, 4)),
+                inheritable: u64::from(le32(value, 8)),
+                effective,
+                root_uid: None,
+            }),
+            revision @ (VFS_CAP_REVISION_2 | VFS_CAP_REVISION_3) if value.len() >= 20 => {
+                let root_uid = match revision {
+                    VFS_CAP_REVISION_3 => Some(*value.get(20..24)?.first_chunk()?),
+                    _ => None,
+                };
+                Some(Self {
+                    permitted: u64::from(le32(value, 4)) | u64::from(le32(value, 12)) << 32,
+                    inheritable: u64::from(le32(value, 8)) | u64::from(le32(value, 16)) << 32,
+                    effective,
+                    root_uid: root_uid.map(u32::from_le_bytes),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the names of the capabilities in a bit set, e.g. `permitted`.
+    pub fn names(set: u64) -> Vec<String> {
+        (0..64)
+            .filter(|bit| set & 1 << bit != 0)
+            .map(|bit| match CAPABILITY_NAMES.get(bit) {
+                Some(name) => name.to_string(),
+                None => format!("cap_{}", bit),
+            })
+            .collect()
+    }
+}
+
+/// Formats the capabilities like getcap, e.g. `cap_net_admin,cap_net_raw=ep`
+impl fmt::Display for FileCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // capabilities with the same flags are listed together
+        let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+        for bit in 0..64 {
+            let flags: String = [
+                (self.effective, 'e'),
+                (self.inheritable & 1 << bit != 0, 'i'),
+                (self.permitted & 1 << bit != 0, 'p'),
+            ]
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, flag)| *flag)
+            .collect();
+            if !flags.contains(['i', 'p']) {
+                continue;
+            }
+            let name = Self::names(1 << bit).remove(0);
+            match groups.iter_mut().find(|(group, _)| *group == flags) {
+                Some((_, names)) => names.push(name),
+                None => groups.push((flags, vec![name])),
+            }
+        }
+
+        if groups.is_empty() {
+            write!(f, "=")?;
+        }
+        for (i, (flags, names)) in groups.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}={}", names.join(","), flags)?;
+        }
+        if let Some(root_uid) = self.root_uid {
+            write!(f, " [rootid={}]", root_uid)?;
+        }
+        Ok(())
+    }
+}
+
+/// The SpecialPermissions struct describes an entry whose permissions matter
+/// when the image is extracted or run: setuid, setgid and sticky bits, and
+/// file capabilities.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SpecialPermissions {
+    pub path: PathBuf,
+    /// Permission bits, including the special ones
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub setuid: bool,
+    pub setgid: bool,
+    pub sticky: bool,
+    pub capabilities: Option<FileCapabilities>,
+}
+
+impl SquashFS<'_> {
+    /// Lists the entries with setuid, setgid or sticky bits, or file
+    /// capabilities. These are worth reviewing before extracting an image
+    /// from an untrusted source with its permissions.
+    ///
+    /// # Returns
+    /// The entries, in the order of `entries`, or an error if the
+    /// capabilities of an entry are malformed.
+    pub fn special_permission_entries(&self) -> Result<Vec<SpecialPermissions>> {
+        let mut found = Vec::new();
+        for entry in self.entries() {
+            let mode = entry.mode();
+            let capabilities = match self.xattr(&entry, CAPABILITY_XATTR) {
+                Some(value) => Some(FileCapabilities::parse(value).ok_or_else(|| {
+                    SquishyError::InvalidSquashFS(format!(
+                        "invalid capabilities of {}",
+                        entry.path.display()
+                    ))
+                })?),
+                None => None,
+            };
+            if mode & 0o7000 == 0 && capabilities.is_none() {
+                continue;
+            }
+
+            found.push(SpecialPermissions {
+                mode,
+                uid: entry.uid(),
+                gid: entry.gid(),
+                setuid: mode & 0o4000 != 0,
+                setgid: mode & 0o2000 != 0,
+                sticky: mode & 0o1000 != 0,
+                capabilities,
+                path: entry.path,
+            });
+        }
+        Ok(found)
+    }
+}
@@ -0,0 +1,76 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use backhand::{FilesystemReaderFile, SquashfsReadFile};
+
+/// Readers that can also seek, for returning them as trait objects
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// The EntryReader struct reads a file inside the SquashFS filesystem,
+/// decompressing it as it is read. Seeking forward skips data, seeking
+/// backward starts decompressing from the beginning again.
+pub struct EntryReader<'a, 'b> {
+    file: FilesystemReaderFile<'a, 'b>,
+    reader: SquashfsReadFile<'a, 'b>,
+    size: u64,
+    /// Bytes consumed from `reader`
+    consumed: u64,
+    /// Position reported to the caller, past `consumed` only after seeking beyond the end
+    position: u64,
+}
+
+impl<'a, 'b> EntryReader<'a, 'b> {
+    pub(crate) fn new(file: FilesystemReaderFile<'a, 'b>, size: u64) -> Self {
+        Self {
+            reader: file.reader(),
+            file,
+            size,
+            consumed: 0,
+            position: 0,
+        }
+    }
+
+    /// Returns the size of the file
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Read for EntryReader<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position > self.consumed {
+            return Ok(0);
+        }
+        let read = self.reader.read(buf)?;
+        self.consumed += read as u64;
+        self.position = self.consumed;
+        Ok(read)
+    }
+}
+
+impl Seek for EntryReader<'_, '_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        let skip_to = target.min(self.size);
+        if skip_to < self.consumed {
+            self.reader = self.file.reader();
+            self.consumed = 0;
+        }
+        let mut skipped = (&mut self.reader).take(skip_to - self.consumed);
+        self.consumed += io::copy(&mut skipped, &mut io::sink())?;
+        self.position = target;
+        Ok(target)
+    }
+}
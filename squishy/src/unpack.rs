@@ -0,0 +1,2009 @@
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    fmt,
+    fs::{self, File, Permissions},
+    io::{self, Seek, SeekFrom, Write},
+    os::{
+        fd::{AsFd, BorrowedFd, OwnedFd},
+        unix::{self, ffi::OsStrExt, fs::PermissionsExt},
+    },
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "cap-std")]
+use cap_std::fs::Dir;
+use rustix::fs::{self as rfs, AtFlags, FileType, Mode};
+
+#[cfg(feature = "digest")]
+use crate::digest::file_sha256;
+#[cfg(feature = "erofs")]
+use crate::erofs::{Erofs, ErofsEntry, ErofsEntryKind};
+use crate::{
+    dirtree::{remove_dir_all_at, split, DirTree},
+    error::{IoContext, Quota, SquishyError},
+    filter::PathFilter,
+    verify::has_parent_dir,
+    EntryKind, Result, SquashFS, SquashFSEntry,
+};
+
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+/// The OverwritePolicy enum decides what happens when an extracted path already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Keep the existing path
+    #[default]
+    Skip,
+    /// Replace the existing path
+    Overwrite,
+    /// Replace the existing path unless it was modified after the entry
+    KeepNewer,
+}
+
+/// The UnpackAction enum describes what extraction does with a single entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum UnpackAction {
+    /// The destination doesn't exist and is created
+    Create,
+    /// The destination exists and is replaced (or merged into, for directories)
+    Overwrite,
+    /// The destination exists and is kept
+    Skip,
+    /// The destination was verified as extracted by an earlier extraction and
+    /// is kept, see `SquashFS::unpack_resume`
+    Keep,
+}
+
+impl fmt::Display for UnpackAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnpackAction::Create => write!(f, "create"),
+            UnpackAction::Overwrite => write!(f, "overwrite"),
+            UnpackAction::Skip => write!(f, "skip"),
+            UnpackAction::Keep => write!(f, "keep"),
+        }
+    }
+}
+
+impl OverwritePolicy {
+    /// Decides what to do with the destination of the entry, without touching it.
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to be extracted
+    /// * `dest` - The path the entry is extracted to
+    pub fn action(&self, entry: &SquashFSEntry, dest: &Path) -> UnpackAction {
+        self.action_for(u64::from(entry.mtime()), dest)
+    }
+
+    /// Decides what to do with the destination of an entry modified at `mtime`
+    fn action_for(&self, mtime: u64, dest: &Path) -> UnpackAction {
+        match dest.symlink_metadata() {
+            Ok(metadata) => self.action_on(mtime, metadata.modified().ok()),
+            Err(_) => UnpackAction::Create,
+        }
+    }
+
+    /// Decides what to do with an existing destination, last modified at
+    /// `modified` if known, of an entry modified at `mtime`
+    fn action_on(&self, mtime: u64, modified: Option<SystemTime>) -> UnpackAction {
+        let replace = match self {
+            OverwritePolicy::Skip => false,
+            OverwritePolicy::Overwrite => true,
+            OverwritePolicy::KeepNewer => modified
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .is_none_or(|modified| modified.as_secs() < mtime),
+        };
+        if replace {
+            UnpackAction::Overwrite
+        } else {
+            UnpackAction::Skip
+        }
+    }
+
+    /// Prepares the destination of the entry according to the policy, removing
+    /// the existing path if it is replaced. Existing directories are merged
+    /// into rather than removed, unless the entry is not a directory.
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to be extracted
+    /// * `dest` - The path the entry is extracted to
+    ///
+    /// # Returns
+    /// The action taken, or an error if the existing path can't be removed
+    pub fn prepare(&self, entry: &SquashFSEntry, dest: &Path) -> Result<UnpackAction> {
+        let directory = matches!(entry.kind, EntryKind::Directory);
+        self.prepare_for(u64::from(entry.mtime()), directory, dest)
+    }
+
+    /// Prepares the destination named `name` in the open directory `dir`, like
+    /// `prepare`. The destination is looked at and removed relative to `dir`,
+    /// so a parent swapped for a symlink isn't followed. `dest` is only used in
+    /// errors.
+    fn prepare_at(
+        &self,
+        dir: BorrowedFd<'_>,
+        name: &OsStr,
+        mtime: u64,
+        directory: bool,
+        dest: &Path,
+    ) -> Result<UnpackAction> {
+        let Ok(stat) = rfs::statat(dir, name, AtFlags::SYMLINK_NOFOLLOW) else {
+            return Ok(UnpackAction::Create);
+        };
+        let modified = u64::try_from(stat.st_mtime)
+            .ok()
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+        let action = self.action_on(mtime, modified);
+        if action == UnpackAction::Overwrite {
+            if FileType::from_raw_mode(stat.st_mode) != FileType::Directory {
+                rfs::unlinkat(dir, name, AtFlags::empty())
+                    .map_err(io::Error::from)
+                    .context("remove", dest)?;
+            } else if !directory {
+                remove_dir_all_at(dir, name).context("remove", dest)?;
+            }
+        }
+        Ok(action)
+    }
+
+    /// Prepares the destination of an entry modified at `mtime`
+    fn prepare_for(&self, mtime: u64, directory: bool, dest: &Path) -> Result<UnpackAction> {
+        let action = self.action_for(mtime, dest);
+        if action == UnpackAction::Overwrite {
+            if !dest.is_dir() || dest.is_symlink() {
+                fs::remove_file(dest).context("remove", dest)?;
+            } else if !directory {
+                fs::remove_dir_all(dest).context("remove", dest)?;
+            }
+        }
+        Ok(action)
+    }
+}
+
+/// The SymlinkPolicy enum decides how symlink targets are written on extraction.
+/// Targets are interpreted within the image, e.g. `/usr/lib/libfoo.so.1` means
+/// the file of the image, not of the host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Write targets as they are in the image, absolute targets point into the host
+    Keep,
+    /// Rewrite absolute targets relative to the symlink, so `/usr/lib/libfoo.so`
+    /// pointing to `/usr/lib/libfoo.so.1` becomes `libfoo.so.1`
+    #[default]
+    Relative,
+    /// Keep relative targets, and skip symlinks with absolute targets or targets
+    /// leaving the image root, e.g. `../../../etc/passwd` from `/usr/lib`
+    SkipExternal,
+}
+
+impl SymlinkPolicy {
+    /// Returns the target to write for the symlink
+    ///
+    /// # Arguments
+    /// * `link` - Path of the symlink within the SquashFS filesystem
+    /// * `target` - Target of the symlink, as in `EntryKind::Symlink`
+    ///
+    /// # Returns
+    /// The target, or None if the symlink is skipped
+    pub fn target(&self, link: &Path, target: &Path) -> Option<PathBuf> {
+        // entries prepend a `/` to the target stored in the image
+        let target = target.as_os_str().as_bytes();
+        let target = Path::new(OsStr::from_bytes(
+            target.strip_prefix(b"/").unwrap_or(target),
+        ));
+        let link_dir = normalize(link.parent().unwrap_or(Path::new("")));
+        match self {
+            SymlinkPolicy::Keep => Some(target.to_path_buf()),
+            SymlinkPolicy::Relative if target.is_absolute() => {
+                let target = resolve(target);
+                let common = link_dir
+                    .components()
+                    .zip(target.components())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                let relative: PathBuf = link_dir
+                    .components()
+                    .skip(common)
+                    .map(|_| Component::ParentDir)
+                    .chain(target.components().skip(common))
+                    .collect();
+                if relative.as_os_str().is_empty() {
+                    Some(PathBuf::from("."))
+                } else {
+                    Some(relative)
+                }
+            }
+            SymlinkPolicy::Relative => Some(target.to_path_buf()),
+            SymlinkPolicy::SkipExternal => {
+                if target.is_absolute() {
+                    return None;
+                }
+                let mut depth = link_dir.components().count();
+                for component in target.components() {
+                    match component {
+                        Component::ParentDir => depth = depth.checked_sub(1)?,
+                        Component::Normal(_) => depth += 1,
+                        _ => {}
+                    }
+                }
+                Some(target.to_path_buf())
+            }
+        }
+    }
+}
+
+/// The PermissionOptions struct normalizes the modes and ownership of extracted paths.
+/// The default keeps the modes from the image and the owner of the running process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PermissionOptions {
+    /// Don't apply the modes from the image, leaving files as created under the umask
+    pub ignore_mode: bool,
+    /// Bits cleared from every mode from the image, like a umask
+    pub mask: u32,
+    /// User and group ids every extracted path is changed to
+    pub owner: Option<(u32, u32)>,
+}
+
+impl PermissionOptions {
+    /// Returns the mode the entry is extracted with, or `None` if the mode is left as created.
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to be extracted
+    pub fn mode(&self, entry: &SquashFSEntry) -> Option<u32> {
+        self.mode_for(entry.mode())
+    }
+
+    /// Returns the mode a path with the mode from the image is extracted with
+    fn mode_for(&self, mode: u32) -> Option<u32> {
+        (!self.ignore_mode).then_some(mode & !self.mask)
+    }
+
+    /// Applies the owner and mode to the extracted path. Symlinks only get
+    /// their owner changed.
+    ///
+    /// # Arguments
+    /// * `entry` - The extracted entry
+    /// * `path` - The path the entry was extracted to
+    ///
+    /// # Returns
+    /// An empty result, or an error if the owner or mode can't be changed
+    pub fn apply(&self, entry: &SquashFSEntry, path: &Path) -> Result<()> {
+        let mode = match entry.kind {
+            EntryKind::Symlink(_) => None,
+            _ => self.mode(entry),
+        };
+        self.apply_mode(path, mode)
+    }
+
+    /// Changes the owner of the opened file or directory, then its mode if
+    /// given, so a path swapped after it was created isn't changed instead
+    fn apply_fd(&self, fd: BorrowedFd<'_>, path: &Path, mode: Option<u32>) -> Result<()> {
+        if let Some((uid, gid)) = self.owner {
+            unix::fs::fchown(fd, Some(uid), Some(gid)).context("change owner of", path)?;
+        }
+        if let Some(mode) = mode {
+            rustix::fs::fchmod(fd, Mode::from_raw_mode(mode))
+                .map_err(io::Error::from)
+                .context("set permissions of", path)?;
+        }
+        Ok(())
+    }
+
+    /// Changes the owner of the path, then its mode if given
+    fn apply_mode(&self, path: &Path, mode: Option<u32>) -> Result<()> {
+        // ownership goes first, as chown clears the setuid and setgid bits
+        if let Some((uid, gid)) = self.owner {
+            unix::fs::lchown(path, Some(uid), Some(gid)).context("change owner of", path)?;
+        }
+        if let Some(mode) = mode {
+            fs::set_permissions(path, Permissions::from_mode(mode))
+                .context("set permissions of", path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Smallest run of zeros left as a hole, the block size of most filesystems
+const MIN_HOLE: usize = 4096;
+
+/// The SparseWriter struct writes to a file, seeking over blocks of zeros
+/// instead of writing them, so they end up as holes.
+pub(crate) struct SparseWriter<'a> {
+    file: &'a File,
+}
+
+impl<'a> SparseWriter<'a> {
+    pub(crate) fn new(file: &'a File) -> Self {
+        Self { file }
+    }
+
+    /// Sets the length of the file, which a trailing hole would leave short
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        let len = self.file.stream_position()?;
+        self.file.set_len(len)
+    }
+}
+
+impl Write for SparseWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() >= MIN_HOLE && buf.iter().all(|&byte| byte == 0) {
+            self.file.seek(SeekFrom::Current(buf.len() as i64))?;
+            return Ok(buf.len());
+        }
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// The UnpackOptions struct controls which entries `unpack_to` extracts and where they go.
+#[derive(Debug, Clone, Default)]
+pub struct UnpackOptions {
+    /// Only entries matching the filter are extracted
+    pub filter: PathFilter,
+    /// Number of leading path components to remove, like `tar --strip-components`.
+    /// Entries with no components left are skipped.
+    pub strip_components: usize,
+    /// Leading path to remove, e.g. `/usr` to extract `/usr/bin` as `bin`.
+    /// Entries outside of it are skipped. Applied before `strip_components`.
+    pub strip_prefix: Option<PathBuf>,
+    /// Directories extracted somewhere else, as (image path, destination)
+    /// pairs like `/usr/lib` and `lib`. The longest matching image path wins,
+    /// and mapped entries are not stripped.
+    pub map: Vec<(PathBuf, PathBuf)>,
+    /// What to do with paths that already exist
+    pub overwrite: OverwritePolicy,
+    /// Modes and ownership of the extracted paths
+    pub permissions: PermissionOptions,
+    /// How symlink targets are written
+    pub symlinks: SymlinkPolicy,
+    /// Only report what would be extracted, without touching the disk
+    pub dry_run: bool,
+    /// Write blocks of zeros in files as data, instead of leaving holes
+    pub dense: bool,
+    /// Most bytes of files to extract, e.g. against decompression bombs.
+    /// Extraction stops with `SquishyError::QuotaExceeded` before the file
+    /// that would exceed it.
+    pub max_total_bytes: Option<u64>,
+    /// Most entries to extract, stopping like `max_total_bytes`
+    pub max_entries: Option<u64>,
+    /// Skip the checks keeping extracted paths inside the destination, see
+    /// `check_path`, following directories that are symlinks. Entries with
+    /// `..` components are then extracted with those components dropped.
+    pub insecure_paths: bool,
+    /// What to do when an entry can't be extracted
+    pub on_error: ErrorPolicy,
+}
+
+/// The ErrorPolicy enum decides what `unpack_to` does when an entry can't be
+/// extracted, e.g. because its destination can't be written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop at the first error and return it
+    #[default]
+    Abort,
+    /// Keep extracting, with the errors in `UnpackReport::errors`
+    Collect,
+    /// Keep extracting, with the failed entries in `UnpackReport::skipped`
+    Skip,
+}
+
+/// An entry that failed to extract, at its index in `UnpackReport::entries`
+type Failure = (usize, PathBuf, SquishyError);
+
+impl ErrorPolicy {
+    /// Returns the value, or records the error of the entry in the report and
+    /// returns None, unless the policy aborts
+    fn attempt<T>(
+        self,
+        report: &mut UnpackReport,
+        source: &Path,
+        result: Result<T>,
+    ) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(error) => self
+                .record(report, source.to_path_buf(), error)
+                .map(|_| None),
+        }
+    }
+
+    /// Records the error of the entry in the report, or returns it with `Abort`
+    fn record(self, report: &mut UnpackReport, source: PathBuf, error: SquishyError) -> Result<()> {
+        match self {
+            ErrorPolicy::Abort => return Err(error),
+            ErrorPolicy::Collect => report.errors.push(EntryError { source, error }),
+            ErrorPolicy::Skip => report.skip(source, SkipReason::Failed),
+        }
+        Ok(())
+    }
+
+    /// Keeps the error of the entry at `index` to be recorded once the
+    /// entries written in parallel are done, or returns it with `Abort`
+    fn defer(self, index: usize, source: &Path, result: Result<()>) -> Result<Option<Failure>> {
+        match result {
+            Ok(()) => Ok(None),
+            Err(error) if self == ErrorPolicy::Abort => Err(error),
+            Err(error) => Ok(Some((index, source.to_path_buf(), error))),
+        }
+    }
+}
+
+/// Checks that writing the entry to `output_path` can't escape `dest`: the
+/// entry path must not contain `..` components, and none of the directories
+/// between `dest` and `output_path` may be a symlink, which an image could
+/// otherwise plant to redirect later entries, e.g. `lib -> /etc` followed by
+/// `lib/passwd`.
+///
+/// # Arguments
+/// * `dest` - The directory extracted into
+/// * `entry_path` - Path of the entry within the SquashFS filesystem
+/// * `output_path` - The path the entry is written to, inside `dest`
+///
+/// # Returns
+/// An empty result, or `SquishyError::UnsafePath` if the path is unsafe
+pub fn check_path(dest: &Path, entry_path: &Path, output_path: &Path) -> Result<()> {
+    let unsafe_path = |reason| SquishyError::UnsafePath {
+        path: entry_path.to_path_buf(),
+        reason,
+    };
+    if has_parent_dir(entry_path) {
+        return Err(unsafe_path("the path contains `..`"));
+    }
+
+    let relative = output_path
+        .parent()
+        .and_then(|parent| parent.strip_prefix(dest).ok())
+        .ok_or_else(|| unsafe_path("the path is outside of the destination"))?;
+    let mut path = dest.to_path_buf();
+    for component in relative.components() {
+        path.push(component);
+        match path.symlink_metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(unsafe_path("a parent directory is a symlink"));
+            }
+            Ok(_) => {}
+            // created by the extraction as a directory
+            Err(_) => break,
+        }
+    }
+    Ok(())
+}
+
+/// The Target enum is where the entries are extracted: a path, or with the
+/// `cap-std` feature a directory capability no entry can escape.
+#[derive(Clone, Copy)]
+enum Target<'a> {
+    Path(&'a Path),
+    #[cfg(feature = "cap-std")]
+    Dir(&'a Dir),
+}
+
+impl Target<'_> {
+    /// Returns the path reported for the entry at the relative path, which
+    /// stays relative within a capability
+    fn output_path(&self, relative: &Path) -> PathBuf {
+        match self {
+            Target::Path(dest) => dest.join(relative),
+            #[cfg(feature = "cap-std")]
+            Target::Dir(_) => relative.to_path_buf(),
+        }
+    }
+
+    /// Opens the target for writing, creating a missing destination path.
+    /// Directories that are symlinks are never followed within a capability.
+    fn tree(&self, options: &UnpackOptions) -> Result<DirTree> {
+        match self {
+            Target::Path(dest) => DirTree::create(dest, options.insecure_paths),
+            #[cfg(feature = "cap-std")]
+            Target::Dir(dir) => {
+                let root = dir.as_fd().try_clone_to_owned().context("open", ".")?;
+                Ok(DirTree::new(root, Path::new(""), false))
+            }
+        }
+    }
+
+    /// Checks the entry like `check_path`. While extracting, symlinked parent
+    /// directories are refused as the tree opens them, so only the entry path
+    /// is checked here; a dry run to a path looks at the disk instead.
+    fn check(&self, entry_path: &Path, output_path: &Path, extracting: bool) -> Result<()> {
+        match self {
+            Target::Path(dest) if !extracting => check_path(dest, entry_path, output_path),
+            _ if has_parent_dir(entry_path) => Err(SquishyError::UnsafePath {
+                path: entry_path.to_path_buf(),
+                reason: "the path contains `..`",
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Decides what to do with the destination, see `OverwritePolicy::action`
+    fn action(&self, policy: OverwritePolicy, mtime: u64, output_path: &Path) -> UnpackAction {
+        match self {
+            Target::Path(_) => policy.action_for(mtime, output_path),
+            #[cfg(feature = "cap-std")]
+            Target::Dir(dir) => match dir.symlink_metadata(output_path) {
+                Ok(metadata) => {
+                    let modified = metadata.modified().ok().map(|time| time.into_std());
+                    policy.action_on(mtime, modified)
+                }
+                Err(_) => UnpackAction::Create,
+            },
+        }
+    }
+
+    /// Changes the owner of the extracted symlink. Symlinks within a
+    /// capability keep the owner of the process.
+    fn apply_symlink(&self, permissions: &PermissionOptions, output_path: &Path) -> Result<()> {
+        match self {
+            Target::Path(_) => permissions.apply_mode(output_path, None),
+            #[cfg(feature = "cap-std")]
+            Target::Dir(_) => Ok(()),
+        }
+    }
+}
+
+/// Returns the normal components of the path, without the root
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect()
+}
+
+/// Returns the normal components of the path, without the root, with `..`
+/// resolved lexically and clamped at the root like the kernel does
+fn resolve(path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => resolved.push(name),
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            _ => {}
+        }
+    }
+    resolved
+}
+
+impl UnpackOptions {
+    /// Returns the quota exceeded by extracting `entries` entries with
+    /// `bytes` bytes of files in total, if any
+    fn exceeded_quota(&self, entries: u64, bytes: u64) -> Option<Quota> {
+        if let Some(limit) = self.max_entries.filter(|limit| entries > *limit) {
+            return Some(Quota::Entries(limit));
+        }
+        self.max_total_bytes
+            .filter(|limit| bytes > *limit)
+            .map(Quota::TotalBytes)
+    }
+
+    /// Maps the entry path to its destination relative to the output directory
+    ///
+    /// # Arguments
+    /// * `path` - Path of the entry within the SquashFS filesystem
+    ///
+    /// # Returns
+    /// The relative destination, or None if the entry is skipped by the
+    /// prefix or has no components left
+    pub fn destination(&self, path: &Path) -> Option<PathBuf> {
+        let path = normalize(path);
+
+        let mapped = self
+            .map
+            .iter()
+            .filter_map(|(source, dest)| {
+                let source = normalize(source);
+                let rest = path.strip_prefix(&source).ok()?;
+                Some((source.components().count(), normalize(dest).join(rest)))
+            })
+            .max_by_key(|(depth, _)| *depth);
+
+        let relative = match mapped {
+            Some((_, mapped)) => mapped,
+            None => {
+                let path = match &self.strip_prefix {
+                    Some(prefix) => path.strip_prefix(normalize(prefix)).ok()?,
+                    None => &path,
+                };
+                path.components().skip(self.strip_components).collect()
+            }
+        };
+        (!relative.as_os_str().is_empty()).then_some(relative)
+    }
+}
+
+/// The QuotaCount struct counts the entries `unpack_to` extracts against the
+/// limits of the options.
+#[derive(Default)]
+struct QuotaCount {
+    entries: u64,
+    bytes: u64,
+    /// The quota the extraction stopped at
+    exceeded: Option<Quota>,
+}
+
+impl QuotaCount {
+    /// Counts an entry with `size` bytes of contents, unless that exceeds a
+    /// quota, which is then recorded
+    ///
+    /// # Returns
+    /// Whether the entry is within the quotas
+    fn add(&mut self, options: &UnpackOptions, size: u64) -> bool {
+        let entries = self.entries + 1;
+        let bytes = self.bytes.saturating_add(size);
+        self.exceeded = options.exceeded_quota(entries, bytes);
+        if self.exceeded.is_none() {
+            self.entries = entries;
+            self.bytes = bytes;
+        }
+        self.exceeded.is_none()
+    }
+
+    /// Returns the report, or `SquishyError::QuotaExceeded` with the entries
+    /// written before the extraction stopped
+    fn check(self, report: UnpackReport) -> Result<UnpackReport> {
+        match self.exceeded {
+            Some(quota) => Err(SquishyError::QuotaExceeded {
+                quota,
+                written: report
+                    .entries
+                    .into_iter()
+                    .filter(|entry| {
+                        matches!(entry.action, UnpackAction::Create | UnpackAction::Overwrite)
+                    })
+                    .map(|entry| entry.destination)
+                    .collect(),
+            }),
+            None => Ok(report),
+        }
+    }
+}
+
+/// The UnpackedKind enum is what an entry is extracted as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum UnpackedKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+impl UnpackedKind {
+    /// Returns what the entry is extracted as, or None for devices, fifos
+    /// and sockets, which aren't extracted
+    ///
+    /// # Arguments
+    /// * `kind` - The kind of the entry
+    pub fn of(kind: &EntryKind) -> Option<Self> {
+        match kind {
+            EntryKind::File(_) => Some(UnpackedKind::File),
+            EntryKind::Directory => Some(UnpackedKind::Directory),
+            EntryKind::Symlink(_) => Some(UnpackedKind::Symlink),
+            EntryKind::Unknown => None,
+        }
+    }
+}
+
+/// The UnpackedEntry struct describes an entry written by `unpack_to`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UnpackedEntry {
+    /// Path of the entry within the SquashFS filesystem
+    pub source: PathBuf,
+    /// Path the entry was written to
+    pub destination: PathBuf,
+    /// What the entry is written as
+    pub kind: UnpackedKind,
+    /// What was done with the destination
+    pub action: UnpackAction,
+}
+
+/// The ExtractedEntry struct is a file written by an earlier extraction,
+/// as recorded in its report or a manifest, for `SquashFS::unpack_resume`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ExtractedEntry {
+    /// Path of the entry within the image
+    pub source: PathBuf,
+    /// Path the entry was written to
+    pub destination: PathBuf,
+    /// Hex SHA-256 digest of the written file, if recorded
+    pub sha256: Option<String>,
+}
+
+impl From<&UnpackedEntry> for ExtractedEntry {
+    fn from(entry: &UnpackedEntry) -> Self {
+        Self {
+            source: entry.source.clone(),
+            destination: entry.destination.clone(),
+            sha256: None,
+        }
+    }
+}
+
+/// The SkipReason enum tells why `unpack_to` left an entry out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SkipReason {
+    /// Not matched by the filter
+    Filtered,
+    /// Outside of `strip_prefix`, or without components left after
+    /// `strip_components`
+    Stripped,
+    /// A device, fifo or socket
+    Unsupported,
+    /// A symlink left out by the symlink policy
+    Symlink,
+    /// The destination exists and is kept by the overwrite policy
+    Exists,
+    /// The entry failed with `ErrorPolicy::Skip`
+    Failed,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Filtered => write!(f, "not matched by the filter"),
+            SkipReason::Stripped => write!(f, "stripped"),
+            SkipReason::Unsupported => write!(f, "unsupported entry type"),
+            SkipReason::Symlink => write!(f, "symlink left out by the policy"),
+            SkipReason::Exists => write!(f, "destination exists"),
+            SkipReason::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// The SkippedEntry struct is an entry `unpack_to` left out.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SkippedEntry {
+    /// Path of the entry within the image
+    pub source: PathBuf,
+    pub reason: SkipReason,
+}
+
+/// The EntryError struct is the error of an entry `unpack_to` failed to
+/// extract with `ErrorPolicy::Collect`.
+#[derive(Debug)]
+pub struct EntryError {
+    /// Path of the entry within the image
+    pub source: PathBuf,
+    pub error: SquishyError,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for EntryError {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut error = serializer.serialize_struct("EntryError", 2)?;
+        error.serialize_field("source", &self.source)?;
+        error.serialize_field("error", &self.error.to_string())?;
+        error.end()
+    }
+}
+
+/// The UnpackReport struct details what `unpack_to` did with every entry of
+/// the image.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UnpackReport {
+    /// The selected entries with the action taken for each, in image order.
+    /// Entries that failed are left out.
+    pub entries: Vec<UnpackedEntry>,
+    /// Entries left out, with the reason
+    pub skipped: Vec<SkippedEntry>,
+    /// Errors of the entries that failed, with `ErrorPolicy::Collect`
+    pub errors: Vec<EntryError>,
+}
+
+impl UnpackReport {
+    /// Records the entry as left out
+    fn skip(&mut self, source: PathBuf, reason: SkipReason) {
+        self.skipped.push(SkippedEntry { source, reason });
+    }
+
+    /// Drops the entries at the indices, which failed after being listed
+    fn drop_failed(&mut self, mut failed: Vec<usize>) {
+        failed.sort_unstable();
+        let mut index = 0;
+        self.entries.retain(|_| {
+            index += 1;
+            failed.binary_search(&(index - 1)).is_err()
+        });
+    }
+}
+
+impl SquashFS<'_> {
+    /// Extracts the entries selected by the options into the destination directory.
+    /// Files, directories and symlinks are created with the permissions from the
+    /// image, normalized by the permission options. Paths that already exist are handled by the overwrite policy.
+    /// With `dry_run`, nothing is written and the planned actions are returned.
+    /// Files are written after all directories, in parallel with the `rayon` feature,
+    /// and symlinks last. Blocks of zeros in files are left as holes unless `dense` is set.
+    /// Paths are created relative to their open parent directory rather than
+    /// resolved from `dest` for every entry.
+    /// Unless `insecure_paths` is set, entries that could end up outside of the
+    /// destination fail the extraction, see `check_path`; directories are
+    /// opened without following symlinks, so one swapped in during the
+    /// extraction is refused too. Once `max_entries` or
+    /// `max_total_bytes` would be exceeded, the entries selected so far are
+    /// written and `SquishyError::QuotaExceeded` is returned. Entries that
+    /// fail are handled by `on_error`, see `ErrorPolicy`.
+    ///
+    /// # Arguments
+    /// * `dest` - The directory to extract into, created if missing
+    /// * `options` - Selects and maps the extracted entries
+    ///
+    /// # Returns
+    /// The report of what was done with every entry, or an error if an entry
+    /// can't be read or written and `on_error` aborts
+    pub fn unpack_to<P: AsRef<Path>>(
+        &self,
+        dest: P,
+        options: &UnpackOptions,
+    ) -> Result<UnpackReport> {
+        self.unpack_into(Target::Path(dest.as_ref()), options, &HashSet::new())
+    }
+
+    /// Resumes an extraction into the destination that was interrupted or
+    /// failed part of the way, given the files an earlier extraction wrote.
+    /// Files still at the destination the options map them to, with the size
+    /// of their entry and, with the `digest` feature, the SHA-256 recorded if
+    /// any, are kept as they are with `UnpackAction::Keep`. Everything else is
+    /// extracted like `unpack_to`, replacing existing paths whatever the
+    /// overwrite policy, so files left half-written are written again. Files
+    /// are verified in parallel with the `rayon` feature.
+    ///
+    /// # Arguments
+    /// * `dest` - The directory extracted into, created if missing
+    /// * `previous` - The files written by the earlier extraction
+    /// * `options` - Selects and maps the extracted entries, as for the
+    ///   earlier extraction
+    ///
+    /// # Returns
+    /// The report of what was done with every entry, or an error if an entry
+    /// can't be read or written and `on_error` aborts
+    pub fn unpack_resume<P: AsRef<Path>>(
+        &self,
+        dest: P,
+        previous: &[ExtractedEntry],
+        options: &UnpackOptions,
+    ) -> Result<UnpackReport> {
+        let dest = dest.as_ref();
+        let verify = |extracted: &ExtractedEntry| self.verify_extracted(dest, extracted, options);
+        #[cfg(feature = "rayon")]
+        let kept: HashSet<PathBuf> = previous.par_iter().filter_map(verify).collect();
+        #[cfg(not(feature = "rayon"))]
+        let kept: HashSet<PathBuf> = previous.iter().filter_map(verify).collect();
+
+        let options = UnpackOptions {
+            overwrite: OverwritePolicy::Overwrite,
+            ..options.clone()
+        };
+        self.unpack_into(Target::Path(dest), &options, &kept)
+    }
+
+    /// Checks that the file of an earlier extraction is complete
+    ///
+    /// # Returns
+    /// The path of the entry within the image if the file can be kept
+    fn verify_extracted(
+        &self,
+        dest: &Path,
+        extracted: &ExtractedEntry,
+        options: &UnpackOptions,
+    ) -> Option<PathBuf> {
+        let entry = self.find(&extracted.source)?;
+        let EntryKind::File(_) = entry.kind else {
+            return None;
+        };
+        let relative = options.destination(&entry.path)?;
+        is_complete(extracted, &dest.join(relative), u64::from(entry.size)).then_some(entry.path)
+    }
+
+    /// Extracts the entries selected by the options into the directory
+    /// capability, like `unpack_to`. Every path is resolved within the
+    /// directory, so no entry can be written outside of it, even through a
+    /// symlink planted by the image or swapped in during the extraction, and
+    /// whatever `insecure_paths` is set to. Extracted symlinks keep the owner
+    /// of the process. The destinations of the returned entries are relative
+    /// to the directory.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory to extract into
+    /// * `options` - Selects and maps the extracted entries
+    ///
+    /// # Returns
+    /// The report of what was done with every entry, or an error if an entry
+    /// can't be read or written and `on_error` aborts
+    #[cfg(feature = "cap-std")]
+    pub fn unpack_to_dir(&self, dir: &Dir, options: &UnpackOptions) -> Result<UnpackReport> {
+        self.unpack_into(Target::Dir(dir), options, &HashSet::new())
+    }
+
+    /// Extracts the entries into the target, see `unpack_to`. Files at the
+    /// paths in `kept` are left as they are, see `unpack_resume`.
+    fn unpack_into(
+        &self,
+        target: Target<'_>,
+        options: &UnpackOptions,
+        kept: &HashSet<PathBuf>,
+    ) -> Result<UnpackReport> {
+        let entries = self
+            .entries()
+            .map(|entry| ImageEntry { image: self, entry });
+        unpack_entries(entries, target, options, kept)
+    }
+}
+
+/// Checks that the file of an earlier extraction is still at `destination`,
+/// where the entry is now extracted to, with the `size` of the entry and the
+/// recorded SHA-256 if any
+fn is_complete(extracted: &ExtractedEntry, destination: &Path, size: u64) -> bool {
+    if extracted.destination != destination {
+        return false;
+    }
+    let Ok(metadata) = destination.symlink_metadata() else {
+        return false;
+    };
+    if !metadata.is_file() || metadata.len() != size {
+        return false;
+    }
+    #[cfg(feature = "digest")]
+    if let Some(sha256) = &extracted.sha256 {
+        return file_sha256(destination).is_ok_and(|digest| digest.eq_ignore_ascii_case(sha256));
+    }
+    true
+}
+
+/// The UnpackEntry trait is what extracting needs of an entry, so images of
+/// every format are extracted the same way.
+trait UnpackEntry: Send {
+    /// Path of the entry within the image
+    fn path(&self) -> &Path;
+
+    /// What the entry is extracted as, or None if it isn't extracted
+    fn kind(&self) -> Option<UnpackedKind>;
+
+    /// Target of the symlink, with a `/` prepended as in `EntryKind::Symlink`
+    fn symlink_target(&self) -> Option<&Path>;
+
+    /// Permission bits of the entry
+    fn mode(&self) -> u32;
+
+    /// Modification time of the entry, in seconds since the epoch
+    fn mtime(&self) -> u64;
+
+    /// Size of the contents of files, 0 for other entries
+    fn size(&self) -> u64;
+
+    /// Writes the contents of the file to the opened output, leaving blocks
+    /// of zeros as holes unless `dense` is set. `dest` is only used in errors.
+    fn write_to(&self, output: &File, dest: &Path, dense: bool) -> Result<()>;
+}
+
+/// An entry along with the image it is read from
+struct ImageEntry<'s, I, E> {
+    image: &'s I,
+    entry: E,
+}
+
+impl UnpackEntry for ImageEntry<'_, SquashFS<'_>, SquashFSEntry<'_>> {
+    fn path(&self) -> &Path {
+        &self.entry.path
+    }
+
+    fn kind(&self) -> Option<UnpackedKind> {
+        UnpackedKind::of(&self.entry.kind)
+    }
+
+    fn symlink_target(&self) -> Option<&Path> {
+        match &self.entry.kind {
+            EntryKind::Symlink(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    fn mode(&self) -> u32 {
+        self.entry.mode()
+    }
+
+    fn mtime(&self) -> u64 {
+        u64::from(self.entry.mtime())
+    }
+
+    fn size(&self) -> u64 {
+        u64::from(self.entry.size)
+    }
+
+    fn write_to(&self, output: &File, dest: &Path, dense: bool) -> Result<()> {
+        match self.entry.kind {
+            EntryKind::File(file) => self.image.write_to(file, output, dest, dense),
+            _ => Err(SquishyError::FileNotFound(self.entry.path.clone())),
+        }
+    }
+}
+
+#[cfg(feature = "erofs")]
+impl UnpackEntry for ImageEntry<'_, Erofs<'_>, ErofsEntry> {
+    fn path(&self) -> &Path {
+        &self.entry.path
+    }
+
+    fn kind(&self) -> Option<UnpackedKind> {
+        match self.entry.kind {
+            ErofsEntryKind::File => Some(UnpackedKind::File),
+            ErofsEntryKind::Directory => Some(UnpackedKind::Directory),
+            ErofsEntryKind::Symlink(_) => Some(UnpackedKind::Symlink),
+            ErofsEntryKind::Unknown => None,
+        }
+    }
+
+    fn symlink_target(&self) -> Option<&Path> {
+        match &self.entry.kind {
+            ErofsEntryKind::Symlink(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    fn mode(&self) -> u32 {
+        self.entry.mode()
+    }
+
+    fn mtime(&self) -> u64 {
+        self.entry.mtime()
+    }
+
+    fn size(&self) -> u64 {
+        self.entry.size
+    }
+
+    fn write_to(&self, output: &File, dest: &Path, dense: bool) -> Result<()> {
+        if dense {
+            let mut writer = io::BufWriter::with_capacity(self.image.io_tuning().write_buf, output);
+            self.image.copy_entry_to(&self.entry, &mut writer)?;
+            writer.flush().context("write", dest)
+        } else {
+            let mut writer = SparseWriter::new(output);
+            self.image.copy_entry_to(&self.entry, &mut writer)?;
+            writer.finish().context("write", dest)
+        }
+    }
+}
+
+/// Extracts the entries into the target, see `SquashFS::unpack_to`. Files at
+/// the paths in `kept` are left as they are, see `SquashFS::unpack_resume`.
+fn unpack_entries<E: UnpackEntry>(
+    entries: impl Iterator<Item = E>,
+    target: Target<'_>,
+    options: &UnpackOptions,
+    kept: &HashSet<PathBuf>,
+) -> Result<UnpackReport> {
+    let mut tree = (!options.dry_run)
+        .then(|| target.tree(options))
+        .transpose()?;
+
+    let mut report = UnpackReport::default();
+    let mut failed = Vec::new();
+    let mut directories = Vec::new();
+    let mut files = Vec::new();
+    let mut symlinks = Vec::new();
+    let mut quota = QuotaCount::default();
+    for entry in entries {
+        let source = entry.path().to_path_buf();
+        if !options.filter.matches(&source) {
+            report.skip(source, SkipReason::Filtered);
+            continue;
+        }
+        let Some(relative) = options.destination(&source) else {
+            report.skip(source, SkipReason::Stripped);
+            continue;
+        };
+        let Some(kind) = entry.kind() else {
+            report.skip(source, SkipReason::Unsupported);
+            continue;
+        };
+
+        let output_path = target.output_path(&relative);
+        let checked = if options.insecure_paths {
+            Ok(())
+        } else {
+            target.check(&source, &output_path, tree.is_some())
+        };
+        if options
+            .on_error
+            .attempt(&mut report, &source, checked)?
+            .is_none()
+        {
+            continue;
+        }
+        let symlink_target = match entry.symlink_target() {
+            Some(target) => match options.symlinks.target(&source, target) {
+                Some(target) => Some(target),
+                None => {
+                    report.skip(source.clone(), SkipReason::Symlink);
+                    report.entries.push(UnpackedEntry {
+                        source,
+                        destination: output_path,
+                        kind,
+                        action: UnpackAction::Skip,
+                    });
+                    continue;
+                }
+            },
+            None => None,
+        };
+        if kind == UnpackedKind::File && kept.contains(&source) {
+            report.entries.push(UnpackedEntry {
+                source,
+                destination: output_path,
+                kind,
+                action: UnpackAction::Keep,
+            });
+            continue;
+        }
+
+        let mtime = entry.mtime();
+        let directory = kind == UnpackedKind::Directory;
+        let planned = target.action(options.overwrite, mtime, &output_path);
+        if planned != UnpackAction::Skip && !quota.add(options, entry.size()) {
+            break;
+        }
+        let prepared = match tree.as_mut() {
+            Some(tree) => {
+                let (parent, name) = split(&relative);
+                tree.dir(parent, &source).and_then(|dir| {
+                    options
+                        .overwrite
+                        .prepare_at(dir, name, mtime, directory, &output_path)
+                })
+            }
+            None => Ok(planned),
+        };
+        let Some(action) = options.on_error.attempt(&mut report, &source, prepared)? else {
+            continue;
+        };
+        if action == UnpackAction::Skip {
+            report.skip(source.clone(), SkipReason::Exists);
+        }
+
+        let index = report.entries.len();
+        let unpacked_entry = UnpackedEntry {
+            source: source.clone(),
+            destination: output_path.clone(),
+            kind,
+            action,
+        };
+        match tree.as_mut() {
+            Some(tree) if action != UnpackAction::Skip => match kind {
+                // written once every directory exists, in parallel with rayon
+                UnpackedKind::File => files.push((index, entry, relative, output_path)),
+                UnpackedKind::Directory => {
+                    let created = tree.dir(&relative, &source).map(|_| ());
+                    if options
+                        .on_error
+                        .attempt(&mut report, &source, created)?
+                        .is_none()
+                    {
+                        continue;
+                    }
+                    let mode = options.permissions.mode_for(entry.mode());
+                    directories.push((index, source, relative, output_path, mode));
+                }
+                // created last, so no entry is written through a symlink from the image
+                UnpackedKind::Symlink => {
+                    if let Some(target) = symlink_target {
+                        symlinks.push((index, source, relative, output_path, target));
+                    }
+                }
+            },
+            _ => {}
+        }
+        report.entries.push(unpacked_entry);
+    }
+
+    let Some(mut tree) = tree else {
+        return quota.check(report);
+    };
+    // every file reuses the directory of the previous one on its thread
+    let write = |last: &mut Option<(PathBuf, OwnedFd)>,
+                 (index, entry, relative, output_path): (usize, E, PathBuf, PathBuf)|
+     -> Result<Option<Failure>> {
+        let written = tree
+            .create_file(last, &relative, entry.path())
+            .and_then(|file| {
+                entry.write_to(&file, &output_path, options.dense)?;
+                let mode = options.permissions.mode_for(entry.mode());
+                options
+                    .permissions
+                    .apply_fd(file.as_fd(), &output_path, mode)
+            });
+        options.on_error.defer(index, entry.path(), written)
+    };
+    #[cfg(feature = "rayon")]
+    let failures: Vec<Failure> = files
+        .into_par_iter()
+        .map_init(|| None, write)
+        .filter_map(Result::transpose)
+        .collect::<Result<_>>()?;
+    #[cfg(not(feature = "rayon"))]
+    let failures: Vec<Failure> = {
+        let mut last = None;
+        files
+            .into_iter()
+            .map(|file| write(&mut last, file))
+            .filter_map(Result::transpose)
+            .collect::<Result<_>>()?
+    };
+    for (index, source, error) in failures {
+        failed.push(index);
+        options.on_error.record(&mut report, source, error)?;
+    }
+
+    for (index, source, relative, output_path, symlink) in symlinks {
+        let created = tree
+            .symlink(&relative, &symlink, &source)
+            .and_then(|_| target.apply_symlink(&options.permissions, &output_path));
+        if options
+            .on_error
+            .attempt(&mut report, &source, created)?
+            .is_none()
+        {
+            failed.push(index);
+        }
+    }
+
+    // directory permissions are applied last, so read-only directories can still be filled
+    for (index, source, relative, output_path, mode) in directories.iter().rev() {
+        let applied = tree
+            .dir(relative, source)
+            .and_then(|dir| options.permissions.apply_fd(dir, output_path, *mode));
+        if options
+            .on_error
+            .attempt(&mut report, source, applied)?
+            .is_none()
+        {
+            failed.push(*index);
+        }
+    }
+
+    report.drop_failed(failed);
+    quota.check(report)
+}
+
+#[cfg(feature = "erofs")]
+impl Erofs<'_> {
+    /// Extracts the entries selected by the options into the destination
+    /// directory, like `SquashFS::unpack_to`.
+    ///
+    /// # Arguments
+    /// * `dest` - The directory to extract into, created if missing
+    /// * `options` - Selects and maps the extracted entries
+    ///
+    /// # Returns
+    /// The report of what was done with every entry, or an error if an entry
+    /// can't be read or written and `on_error` aborts
+    pub fn unpack_to<P: AsRef<Path>>(
+        &self,
+        dest: P,
+        options: &UnpackOptions,
+    ) -> Result<UnpackReport> {
+        self.unpack_into(Target::Path(dest.as_ref()), options)
+    }
+
+    /// Extracts the entries selected by the options into the directory
+    /// capability, like `SquashFS::unpack_to_dir`.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory to extract into
+    /// * `options` - Selects and maps the extracted entries
+    ///
+    /// # Returns
+    /// The report of what was done with every entry, or an error if an entry
+    /// can't be read or written and `on_error` aborts
+    #[cfg(feature = "cap-std")]
+    pub fn unpack_to_dir(&self, dir: &Dir, options: &UnpackOptions) -> Result<UnpackReport> {
+        self.unpack_into(Target::Dir(dir), options)
+    }
+
+    /// Extracts the entries into the target, see `unpack_to`
+    fn unpack_into(&self, target: Target<'_>, options: &UnpackOptions) -> Result<UnpackReport> {
+        let entries = self
+            .entries()
+            .map(|entry| ImageEntry { image: self, entry });
+        unpack_entries(entries, target, options, &HashSet::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, os::unix::fs::MetadataExt};
+
+    use super::*;
+
+    /// What a test entry is, with the contents of files
+    enum TestKind {
+        File(Vec<u8>),
+        /// A file that fails to be written
+        Broken,
+        Directory,
+        Symlink(PathBuf),
+        Device,
+    }
+
+    struct TestEntry {
+        path: PathBuf,
+        kind: TestKind,
+    }
+
+    impl UnpackEntry for TestEntry {
+        fn path(&self) -> &Path {
+            &self.path
+        }
+
+        fn kind(&self) -> Option<UnpackedKind> {
+            match self.kind {
+                TestKind::File(_) | TestKind::Broken => Some(UnpackedKind::File),
+                TestKind::Directory => Some(UnpackedKind::Directory),
+                TestKind::Symlink(_) => Some(UnpackedKind::Symlink),
+                TestKind::Device => None,
+            }
+        }
+
+        fn symlink_target(&self) -> Option<&Path> {
+            match &self.kind {
+                TestKind::Symlink(target) => Some(target),
+                _ => None,
+            }
+        }
+
+        fn mode(&self) -> u32 {
+            match self.kind {
+                TestKind::Directory => 0o755,
+                _ => 0o644,
+            }
+        }
+
+        fn mtime(&self) -> u64 {
+            0
+        }
+
+        fn size(&self) -> u64 {
+            match &self.kind {
+                TestKind::File(contents) => contents.len() as u64,
+                _ => 0,
+            }
+        }
+
+        fn write_to(&self, output: &File, dest: &Path, dense: bool) -> Result<()> {
+            let contents = match &self.kind {
+                TestKind::File(contents) => contents,
+                TestKind::Broken => return Err(io::Error::other("broken")).context("write", dest),
+                _ => return Err(SquishyError::FileNotFound(self.path.clone())),
+            };
+            if dense {
+                (&*output).write_all(contents).context("write", dest)
+            } else {
+                let mut writer = SparseWriter::new(output);
+                writer.write_all(contents).context("write", dest)?;
+                writer.finish().context("write", dest)
+            }
+        }
+    }
+
+    fn entry(path: &str, kind: TestKind) -> TestEntry {
+        TestEntry {
+            path: PathBuf::from(path),
+            kind,
+        }
+    }
+
+    fn file(path: &str, contents: &[u8]) -> TestEntry {
+        entry(path, TestKind::File(contents.to_vec()))
+    }
+
+    fn unpack(
+        entries: Vec<TestEntry>,
+        dest: &Path,
+        options: &UnpackOptions,
+    ) -> Result<UnpackReport> {
+        unpack_entries(
+            entries.into_iter(),
+            Target::Path(dest),
+            options,
+            &HashSet::new(),
+        )
+    }
+
+    #[test]
+    fn check_path_refuses_parent_dir() {
+        let dest = Path::new("/nonexistent/dest");
+        let result = check_path(dest, Path::new("/a/../b"), &dest.join("b"));
+        assert!(matches!(result, Err(SquishyError::UnsafePath { .. })));
+        assert!(check_path(dest, Path::new("/a/b"), &dest.join("a/b")).is_ok());
+    }
+
+    #[test]
+    fn check_path_refuses_symlinked_parent() {
+        let dest = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        unix::fs::symlink(outside.path(), dest.path().join("lib")).unwrap();
+        fs::create_dir(dest.path().join("usr")).unwrap();
+
+        let output_path = dest.path().join("lib/passwd");
+        let result = check_path(dest.path(), Path::new("/lib/passwd"), &output_path);
+        assert!(matches!(result, Err(SquishyError::UnsafePath { .. })));
+        let output_path = dest.path().join("usr/bin/sh");
+        assert!(check_path(dest.path(), Path::new("/usr/bin/sh"), &output_path).is_ok());
+    }
+
+    #[test]
+    fn check_path_refuses_outside_of_dest() {
+        let dest = Path::new("/nonexistent/dest");
+        let result = check_path(dest, Path::new("/passwd"), Path::new("/etc/passwd"));
+        assert!(matches!(result, Err(SquishyError::UnsafePath { .. })));
+    }
+
+    #[test]
+    fn unpack_refuses_parent_dir_entries() {
+        let dest = tempfile::tempdir().unwrap();
+        let entries = vec![file("/a/../../escaped", b"data")];
+        let result = unpack(entries, dest.path(), &UnpackOptions::default());
+        assert!(matches!(result, Err(SquishyError::UnsafePath { .. })));
+        assert!(!dest.path().parent().unwrap().join("escaped").exists());
+    }
+
+    #[test]
+    fn unpack_drops_parent_dir_with_insecure_paths() {
+        let dest = tempfile::tempdir().unwrap();
+        let options = UnpackOptions {
+            insecure_paths: true,
+            ..UnpackOptions::default()
+        };
+        let entries = vec![file("/a/../b", b"data")];
+        unpack(entries, dest.path(), &options).unwrap();
+        assert_eq!(fs::read(dest.path().join("a/b")).unwrap(), b"data");
+    }
+
+    #[test]
+    fn unpack_refuses_symlinked_parent() {
+        let dest = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        unix::fs::symlink(outside.path(), dest.path().join("lib")).unwrap();
+
+        let entries = vec![file("/lib/passwd", b"root")];
+        let result = unpack(entries, dest.path(), &UnpackOptions::default());
+        assert!(matches!(result, Err(SquishyError::UnsafePath { .. })));
+        assert!(!outside.path().join("passwd").exists());
+    }
+
+    #[test]
+    fn unpack_creates_symlinks_last() {
+        // a symlink from the image can't redirect the entries after it
+        let dest = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        // entries prepend a `/` to the target
+        let target = Path::new("/").join(outside.path());
+        let entries = vec![
+            entry("/lib", TestKind::Symlink(target)),
+            file("/lib/passwd", b"root"),
+        ];
+        let options = UnpackOptions {
+            symlinks: SymlinkPolicy::Keep,
+            on_error: ErrorPolicy::Collect,
+            ..UnpackOptions::default()
+        };
+        let report = unpack(entries, dest.path(), &options).unwrap();
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].source, Path::new("/lib"));
+        assert_eq!(fs::read(dest.path().join("lib/passwd")).unwrap(), b"root");
+        assert!(!outside.path().join("passwd").exists());
+    }
+
+    /// Returns the target written for a symlink at `link` to `stored`, the
+    /// target as stored in the image
+    fn symlink_target(policy: SymlinkPolicy, link: &str, stored: &str) -> Option<PathBuf> {
+        policy.target(Path::new(link), Path::new(&format!("/{stored}")))
+    }
+
+    #[test]
+    fn symlink_target_keep() {
+        let keep = |link, stored| symlink_target(SymlinkPolicy::Keep, link, stored);
+        assert_eq!(
+            keep("/usr/lib/libfoo.so", "/usr/lib/libfoo.so.1"),
+            Some(PathBuf::from("/usr/lib/libfoo.so.1"))
+        );
+        assert_eq!(
+            keep("/usr/lib/libfoo.so", "../../etc/passwd"),
+            Some(PathBuf::from("../../etc/passwd"))
+        );
+    }
+
+    #[test]
+    fn symlink_target_relative() {
+        let relative = |link, stored| symlink_target(SymlinkPolicy::Relative, link, stored);
+        assert_eq!(
+            relative("/usr/lib/libfoo.so", "/usr/lib/libfoo.so.1"),
+            Some(PathBuf::from("libfoo.so.1"))
+        );
+        assert_eq!(
+            relative("/usr/bin/sh", "/bin/bash"),
+            Some(PathBuf::from("../../bin/bash"))
+        );
+        assert_eq!(
+            relative("/usr/lib/current", "/usr/lib"),
+            Some(PathBuf::from("."))
+        );
+        assert_eq!(relative("/sh", "bash"), Some(PathBuf::from("bash")));
+    }
+
+    #[test]
+    fn symlink_target_relative_resolves_parent_dirs() {
+        let relative = |link, stored| symlink_target(SymlinkPolicy::Relative, link, stored);
+        assert_eq!(
+            relative("/usr/bin/x", "/usr/../lib/x"),
+            Some(PathBuf::from("../../lib/x"))
+        );
+        assert_eq!(
+            relative("/usr/lib/libfoo.so", "/usr/lib/../lib/./libfoo.so.1"),
+            Some(PathBuf::from("libfoo.so.1"))
+        );
+        // `..` at the root stays at the root
+        assert_eq!(
+            relative("/etc/link", "/../../etc/passwd"),
+            Some(PathBuf::from("passwd"))
+        );
+    }
+
+    #[test]
+    fn symlink_target_skip_external() {
+        let skip = |link, stored| symlink_target(SymlinkPolicy::SkipExternal, link, stored);
+        assert_eq!(skip("/usr/lib/libfoo.so", "/usr/lib/libfoo.so.1"), None);
+        assert_eq!(skip("/usr/lib/passwd", "../../../etc/passwd"), None);
+        assert_eq!(
+            skip("/usr/lib/passwd", "../../etc/passwd"),
+            Some(PathBuf::from("../../etc/passwd"))
+        );
+        assert_eq!(
+            skip("/usr/lib/libfoo.so", "libfoo.so.1"),
+            Some(PathBuf::from("libfoo.so.1"))
+        );
+    }
+
+    #[test]
+    fn unpack_skips_external_symlinks() {
+        let dest = tempfile::tempdir().unwrap();
+        let entries = vec![
+            entry("/etc", TestKind::Symlink(PathBuf::from("//etc"))),
+            entry("/lib", TestKind::Symlink(PathBuf::from("/usr/lib"))),
+        ];
+        let options = UnpackOptions {
+            symlinks: SymlinkPolicy::SkipExternal,
+            ..UnpackOptions::default()
+        };
+        let report = unpack(entries, dest.path(), &options).unwrap();
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].source, Path::new("/etc"));
+        assert_eq!(report.skipped[0].reason, SkipReason::Symlink);
+        assert_eq!(report.entries[0].action, UnpackAction::Skip);
+        assert!(dest.path().join("etc").symlink_metadata().is_err());
+        assert_eq!(
+            fs::read_link(dest.path().join("lib")).unwrap(),
+            Path::new("usr/lib")
+        );
+    }
+
+    #[test]
+    fn sparse_writer_leaves_holes() {
+        let output = tempfile::tempfile().unwrap();
+        let mut writer = SparseWriter::new(&output);
+        writer.write_all(b"start").unwrap();
+        writer.write_all(&vec![0; 1 << 20]).unwrap();
+        writer.write_all(b"end").unwrap();
+        writer.finish().unwrap();
+
+        let mut contents = Vec::new();
+        (&output).rewind().unwrap();
+        (&output).read_to_end(&mut contents).unwrap();
+        assert_eq!(contents.len(), 5 + (1 << 20) + 3);
+        assert_eq!(&contents[..5], b"start");
+        assert!(contents[5..5 + (1 << 20)].iter().all(|&byte| byte == 0));
+        assert_eq!(&contents[5 + (1 << 20)..], b"end");
+        // the zeros take no space on disk
+        assert!(output.metadata().unwrap().blocks() * 512 < 1 << 20);
+    }
+
+    #[test]
+    fn sparse_writer_sets_the_length_after_a_trailing_hole() {
+        let output = tempfile::tempfile().unwrap();
+        let mut writer = SparseWriter::new(&output);
+        writer.write_all(b"data").unwrap();
+        writer.write_all(&[0; MIN_HOLE * 2]).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(output.metadata().unwrap().len(), 4 + MIN_HOLE as u64 * 2);
+    }
+
+    #[test]
+    fn sparse_writer_writes_short_runs_of_zeros() {
+        let output = tempfile::tempfile().unwrap();
+        let mut writer = SparseWriter::new(&output);
+        writer.write_all(&[0; MIN_HOLE - 1]).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(output.metadata().unwrap().len(), MIN_HOLE as u64 - 1);
+        assert!(output.metadata().unwrap().blocks() > 0);
+    }
+
+    #[test]
+    fn unpack_writes_dense_files_with_dense() {
+        let dest = tempfile::tempdir().unwrap();
+        let options = UnpackOptions {
+            dense: true,
+            ..UnpackOptions::default()
+        };
+        let entries = vec![file("/zeros", &vec![0; 1 << 20])];
+        unpack(entries, dest.path(), &options).unwrap();
+        let metadata = dest.path().join("zeros").metadata().unwrap();
+        assert_eq!(metadata.len(), 1 << 20);
+        assert!(metadata.blocks() * 512 >= 1 << 20);
+    }
+
+    /// Returns the quota and the written paths of a `QuotaExceeded` error
+    fn exceeded(result: Result<UnpackReport>) -> (Quota, Vec<PathBuf>) {
+        match result {
+            Err(SquishyError::QuotaExceeded { quota, written }) => (quota, written),
+            other => panic!("expected QuotaExceeded, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn unpack_stops_at_max_entries() {
+        let dest = tempfile::tempdir().unwrap();
+        let options = UnpackOptions {
+            max_entries: Some(2),
+            ..UnpackOptions::default()
+        };
+        let entries = vec![file("/a", b"a"), file("/b", b"b"), file("/c", b"c")];
+        let (quota, written) = exceeded(unpack(entries, dest.path(), &options));
+        assert_eq!(quota, Quota::Entries(2));
+        assert_eq!(written, [dest.path().join("a"), dest.path().join("b")]);
+        assert!(dest.path().join("b").exists());
+        assert!(!dest.path().join("c").exists());
+    }
+
+    #[test]
+    fn unpack_stops_before_max_total_bytes() {
+        let dest = tempfile::tempdir().unwrap();
+        let options = UnpackOptions {
+            max_total_bytes: Some(10),
+            ..UnpackOptions::default()
+        };
+        let entries = vec![file("/a", b"1234"), file("/b", b"123456"), file("/c", b"1")];
+        let (quota, written) = exceeded(unpack(entries, dest.path(), &options));
+        assert_eq!(quota, Quota::TotalBytes(10));
+        assert_eq!(written, [dest.path().join("a"), dest.path().join("b")]);
+        assert!(!dest.path().join("c").exists());
+    }
+
+    #[test]
+    fn unpack_quota_leaves_out_kept_paths() {
+        let dest = tempfile::tempdir().unwrap();
+        fs::write(dest.path().join("a"), b"existing").unwrap();
+        let options = UnpackOptions {
+            max_entries: Some(1),
+            ..UnpackOptions::default()
+        };
+        // the existing file isn't counted nor reported as written
+        let entries = vec![file("/a", b"a"), file("/b", b"b"), file("/c", b"c")];
+        let (quota, written) = exceeded(unpack(entries, dest.path(), &options));
+        assert_eq!(quota, Quota::Entries(1));
+        assert_eq!(written, [dest.path().join("b")]);
+        assert_eq!(fs::read(dest.path().join("a")).unwrap(), b"existing");
+    }
+
+    #[test]
+    fn unpack_within_quota() {
+        let dest = tempfile::tempdir().unwrap();
+        let options = UnpackOptions {
+            max_entries: Some(2),
+            max_total_bytes: Some(2),
+            ..UnpackOptions::default()
+        };
+        let entries = vec![file("/a", b"a"), file("/b", b"b")];
+        let report = unpack(entries, dest.path(), &options).unwrap();
+        assert_eq!(report.entries.len(), 2);
+    }
+
+    #[cfg(feature = "cap-std")]
+    fn unpack_dir(
+        entries: Vec<TestEntry>,
+        dest: &Path,
+        options: &UnpackOptions,
+    ) -> Result<UnpackReport> {
+        let dir = Dir::open_ambient_dir(dest, cap_std::ambient_authority()).unwrap();
+        unpack_entries(
+            entries.into_iter(),
+            Target::Dir(&dir),
+            options,
+            &HashSet::new(),
+        )
+    }
+
+    #[cfg(feature = "cap-std")]
+    #[test]
+    fn unpack_to_dir_reports_relative_paths() {
+        let dest = tempfile::tempdir().unwrap();
+        let entries = vec![file("/usr/bin/sh", b"sh")];
+        let report = unpack_dir(entries, dest.path(), &UnpackOptions::default()).unwrap();
+        assert_eq!(report.entries[0].destination, Path::new("usr/bin/sh"));
+        assert_eq!(fs::read(dest.path().join("usr/bin/sh")).unwrap(), b"sh");
+    }
+
+    #[cfg(feature = "cap-std")]
+    #[test]
+    fn unpack_to_dir_never_follows_symlinks() {
+        let dest = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        unix::fs::symlink(outside.path(), dest.path().join("lib")).unwrap();
+        let options = UnpackOptions {
+            insecure_paths: true,
+            ..UnpackOptions::default()
+        };
+        let entries = vec![file("/lib/passwd", b"root")];
+        let result = unpack_dir(entries, dest.path(), &options);
+        assert!(matches!(result, Err(SquishyError::UnsafePath { .. })));
+        assert!(!outside.path().join("passwd").exists());
+    }
+
+    #[cfg(feature = "cap-std")]
+    #[test]
+    fn unpack_to_dir_keeps_existing_files() {
+        let dest = tempfile::tempdir().unwrap();
+        fs::write(dest.path().join("a"), b"existing").unwrap();
+        let entries = vec![file("/a", b"a"), file("/b", b"b")];
+        let report = unpack_dir(entries, dest.path(), &UnpackOptions::default()).unwrap();
+        let actions: Vec<UnpackAction> = report.entries.iter().map(|entry| entry.action).collect();
+        assert_eq!(actions, [UnpackAction::Skip, UnpackAction::Create]);
+        assert_eq!(fs::read(dest.path().join("a")).unwrap(), b"existing");
+
+        let options = UnpackOptions {
+            overwrite: OverwritePolicy::Overwrite,
+            ..UnpackOptions::default()
+        };
+        let entries = vec![file("/a", b"a")];
+        let report = unpack_dir(entries, dest.path(), &options).unwrap();
+        assert_eq!(report.entries[0].action, UnpackAction::Overwrite);
+        assert_eq!(fs::read(dest.path().join("a")).unwrap(), b"a");
+    }
+
+    fn entries_with_a_broken_file() -> Vec<TestEntry> {
+        vec![
+            entry("/dir", TestKind::Directory),
+            entry("/dir/broken", TestKind::Broken),
+            file("/dir/file", b"data"),
+            entry("/dev", TestKind::Device),
+        ]
+    }
+
+    fn sources(report: &UnpackReport) -> Vec<&Path> {
+        report
+            .entries
+            .iter()
+            .map(|entry| entry.source.as_path())
+            .collect()
+    }
+
+    #[test]
+    fn drop_failed_keeps_the_other_entries() {
+        let mut report = UnpackReport::default();
+        for path in ["/a", "/b", "/c", "/d", "/e"] {
+            report.entries.push(UnpackedEntry {
+                source: PathBuf::from(path),
+                destination: PathBuf::from(path),
+                kind: UnpackedKind::File,
+                action: UnpackAction::Create,
+            });
+        }
+        report.drop_failed(vec![3, 1, 3]);
+        assert_eq!(sources(&report), ["/a", "/c", "/e"].map(Path::new));
+        report.drop_failed(Vec::new());
+        assert_eq!(report.entries.len(), 3);
+    }
+
+    #[test]
+    fn unpack_aborts_on_error() {
+        let dest = tempfile::tempdir().unwrap();
+        let result = unpack(
+            entries_with_a_broken_file(),
+            dest.path(),
+            &UnpackOptions::default(),
+        );
+        assert!(matches!(
+            result,
+            Err(SquishyError::PathIo { op: "write", .. })
+        ));
+    }
+
+    #[test]
+    fn unpack_collects_errors() {
+        let dest = tempfile::tempdir().unwrap();
+        let options = UnpackOptions {
+            on_error: ErrorPolicy::Collect,
+            ..UnpackOptions::default()
+        };
+        let report = unpack(entries_with_a_broken_file(), dest.path(), &options).unwrap();
+        assert_eq!(sources(&report), ["/dir", "/dir/file"].map(Path::new));
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].source, Path::new("/dir/broken"));
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].source, Path::new("/dev"));
+        assert_eq!(report.skipped[0].reason, SkipReason::Unsupported);
+        assert_eq!(fs::read(dest.path().join("dir/file")).unwrap(), b"data");
+    }
+
+    #[test]
+    fn unpack_skips_failed_entries() {
+        let dest = tempfile::tempdir().unwrap();
+        let options = UnpackOptions {
+            on_error: ErrorPolicy::Skip,
+            ..UnpackOptions::default()
+        };
+        let report = unpack(entries_with_a_broken_file(), dest.path(), &options).unwrap();
+        assert_eq!(sources(&report), ["/dir", "/dir/file"].map(Path::new));
+        assert!(report.errors.is_empty());
+        let skipped: Vec<(&Path, SkipReason)> = report
+            .skipped
+            .iter()
+            .map(|entry| (entry.source.as_path(), entry.reason))
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                (Path::new("/dev"), SkipReason::Unsupported),
+                (Path::new("/dir/broken"), SkipReason::Failed),
+            ]
+        );
+    }
+
+    #[test]
+    fn unpack_applies_directory_modes_last() {
+        let dest = tempfile::tempdir().unwrap();
+        let entries = vec![
+            entry("/dir", TestKind::Directory),
+            file("/dir/file", b"data"),
+        ];
+        let options = UnpackOptions {
+            permissions: PermissionOptions {
+                mask: 0o222,
+                ..PermissionOptions::default()
+            },
+            ..UnpackOptions::default()
+        };
+        unpack(entries, dest.path(), &options).unwrap();
+        let mode = |path: &str| {
+            let metadata = dest.path().join(path).metadata().unwrap();
+            metadata.permissions().mode() & 0o7777
+        };
+        assert_eq!(mode("dir"), 0o555);
+        assert_eq!(mode("dir/file"), 0o444);
+        fs::set_permissions(dest.path().join("dir"), Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn unpack_overwrite_replaces_other_kinds() {
+        let dest = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("kept"), b"kept").unwrap();
+        fs::create_dir_all(dest.path().join("file/nested")).unwrap();
+        fs::write(dest.path().join("file/nested/a"), b"a").unwrap();
+        fs::write(dest.path().join("dir"), b"file").unwrap();
+        unix::fs::symlink(outside.path(), dest.path().join("link")).unwrap();
+
+        let options = UnpackOptions {
+            overwrite: OverwritePolicy::Overwrite,
+            ..UnpackOptions::default()
+        };
+        let entries = vec![
+            file("/file", b"data"),
+            entry("/dir", TestKind::Directory),
+            file("/link", b"link"),
+        ];
+        let report = unpack(entries, dest.path(), &options).unwrap();
+        assert!(report
+            .entries
+            .iter()
+            .all(|entry| entry.action == UnpackAction::Overwrite));
+        assert_eq!(fs::read(dest.path().join("file")).unwrap(), b"data");
+        assert!(dest.path().join("dir").is_dir());
+        // the symlink is replaced, not followed
+        assert_eq!(fs::read(dest.path().join("link")).unwrap(), b"link");
+        assert_eq!(fs::read(outside.path().join("kept")).unwrap(), b"kept");
+    }
+
+    fn extracted(destination: &Path, sha256: Option<&str>) -> ExtractedEntry {
+        ExtractedEntry {
+            source: PathBuf::from("/file"),
+            destination: destination.to_path_buf(),
+            sha256: sha256.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn is_complete_checks_the_file() {
+        let dest = tempfile::tempdir().unwrap();
+        let path = dest.path().join("file");
+        fs::write(&path, b"data").unwrap();
+
+        assert!(is_complete(&extracted(&path, None), &path, 4));
+        // truncated by an interrupted extraction
+        assert!(!is_complete(&extracted(&path, None), &path, 5));
+        // now extracted somewhere else
+        let other = dest.path().join("other");
+        assert!(!is_complete(&extracted(&path, None), &other, 4));
+        assert!(!is_complete(&extracted(&other, None), &other, 4));
+
+        let link = dest.path().join("link");
+        unix::fs::symlink(&path, &link).unwrap();
+        assert!(!is_complete(&extracted(&link, None), &link, 4));
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn is_complete_checks_the_digest() {
+        let dest = tempfile::tempdir().unwrap();
+        let path = dest.path().join("file");
+        fs::write(&path, b"data").unwrap();
+
+        let sha256 = "3A6EB0790F39AC87C94F3856B2DD2C5D110E6811602261A9A923D3BB23ADC8B7";
+        assert!(is_complete(&extracted(&path, Some(sha256)), &path, 4));
+        // same size, other contents
+        let other = "0000000000000000000000000000000000000000000000000000000000000000";
+        assert!(!is_complete(&extracted(&path, Some(other)), &path, 4));
+    }
+
+    #[test]
+    fn unpack_keeps_verified_files() {
+        let dest = tempfile::tempdir().unwrap();
+        fs::write(dest.path().join("a"), b"kept").unwrap();
+        fs::write(dest.path().join("b"), b"half").unwrap();
+        let options = UnpackOptions {
+            overwrite: OverwritePolicy::Overwrite,
+            ..UnpackOptions::default()
+        };
+        let kept = HashSet::from([PathBuf::from("/a")]);
+        let entries = vec![file("/a", b"a"), file("/b", b"b")];
+        let report = unpack_entries(
+            entries.into_iter(),
+            Target::Path(dest.path()),
+            &options,
+            &kept,
+        )
+        .unwrap();
+        let actions: Vec<UnpackAction> = report.entries.iter().map(|entry| entry.action).collect();
+        assert_eq!(actions, [UnpackAction::Keep, UnpackAction::Overwrite]);
+        assert_eq!(fs::read(dest.path().join("a")).unwrap(), b"kept");
+        assert_eq!(fs::read(dest.path().join("b")).unwrap(), b"b");
+    }
+}
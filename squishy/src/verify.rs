@@ -0,0 +1,173 @@
+use std::{
+    fmt, io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{EntryKind, SquashFS, SquashFSEntry};
+
+/// The CorruptedEntry struct describes an entry whose data couldn't be read back.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CorruptedEntry {
+    /// Path of the entry within the SquashFS filesystem
+    pub path: PathBuf,
+    /// Offset of the entry data within the input file
+    pub offset: u64,
+    /// Description of the failure
+    pub error: String,
+}
+
+/// The PathIssueKind enum describes why a path may break extraction on some
+/// filesystems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum PathIssueKind {
+    /// An earlier entry has the same path
+    Duplicate,
+    /// A component of the path isn't valid UTF-8
+    InvalidUtf8,
+    /// A component of the path is `..`
+    ParentDir,
+}
+
+impl fmt::Display for PathIssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathIssueKind::Duplicate => write!(f, "duplicate path"),
+            PathIssueKind::InvalidUtf8 => write!(f, "name is not valid UTF-8"),
+            PathIssueKind::ParentDir => write!(f, "path contains `..`"),
+        }
+    }
+}
+
+/// The PathIssue struct describes an entry whose path may break extraction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PathIssue {
+    /// Path of the entry within the SquashFS filesystem
+    pub path: PathBuf,
+    pub kind: PathIssueKind,
+}
+
+/// The VerifyReport struct holds the results of `SquashFS::verify`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct VerifyReport {
+    /// Number of entries checked
+    pub entries: u64,
+    /// Number of file bytes decompressed
+    pub bytes: u64,
+    /// Entries that failed to decompress or had an unexpected size
+    pub corrupted: Vec<CorruptedEntry>,
+    /// Entries with paths that may break extraction, see `SquashFS::path_issues`.
+    /// They don't make the image corrupted.
+    pub path_issues: Vec<PathIssue>,
+}
+
+impl VerifyReport {
+    /// Returns true if no corrupted entries were found.
+    pub fn is_ok(&self) -> bool {
+        self.corrupted.is_empty()
+    }
+}
+
+/// Checks whether a component of the path isn't valid UTF-8
+pub(crate) fn has_invalid_utf8(path: &Path) -> bool {
+    path.to_str().is_none()
+}
+
+/// Checks whether a component of the path is `..`
+pub(crate) fn has_parent_dir(path: &Path) -> bool {
+    path.components().any(|c| c == Component::ParentDir)
+}
+
+impl SquashFS<'_> {
+    /// Walks all entries, decompressing the data of every file and checking
+    /// that it has the size recorded in the inode. The superblock and the
+    /// directory tables are already validated when the SquashFS is opened.
+    ///
+    /// # Returns
+    /// The report listing corrupted entries with the offset of their data
+    pub fn verify(&self) -> VerifyReport {
+        let mut report = VerifyReport::default();
+        for entry in self.entries() {
+            report.entries += 1;
+            let EntryKind::File(basic_file) = entry.kind else {
+                continue;
+            };
+
+            let mut sink = io::sink();
+            let result = match self.copy_entry_to(&entry, &mut sink) {
+                Ok(size) if size == u64::from(basic_file.file_size) => Ok(size),
+                Ok(size) => Err(format!(
+                    "expected {} bytes, got {}",
+                    basic_file.file_size, size
+                )),
+                Err(e) => Err(e.to_string()),
+            };
+
+            match result {
+                Ok(size) => report.bytes += size,
+                Err(error) => report.corrupted.push(CorruptedEntry {
+                    offset: self.data_offset(&entry),
+                    path: entry.path,
+                    error,
+                }),
+            }
+        }
+        report.path_issues = self.path_issues();
+        report
+    }
+
+    /// Finds the entries with paths that may break extraction on some
+    /// filesystems: paths of more than one entry, names that aren't valid
+    /// UTF-8 and `..` components, which crafted images can contain.
+    ///
+    /// # Returns
+    /// The issues found, by path. Every entry after the first at a path is
+    /// reported as a duplicate.
+    pub fn path_issues(&self) -> Vec<PathIssue> {
+        let mut issues = Vec::new();
+        let mut previous: Option<PathBuf> = None;
+        // entries come sorted by path, so duplicates are next to each other
+        for entry in self.entries() {
+            let path = entry.path;
+            let kinds = [
+                (previous.as_ref() == Some(&path), PathIssueKind::Duplicate),
+                (has_invalid_utf8(&path), PathIssueKind::InvalidUtf8),
+                (has_parent_dir(&path), PathIssueKind::ParentDir),
+            ];
+            for (_, kind) in kinds.into_iter().filter(|(found, _)| *found) {
+                issues.push(PathIssue {
+                    path: path.clone(),
+                    kind,
+                });
+            }
+            previous = Some(path);
+        }
+        issues
+    }
+
+    /// Returns the offset of the file data within the input file, pointing
+    /// at the fragment for files stored entirely in one
+    fn data_offset(&self, entry: &SquashFSEntry) -> u64 {
+        let EntryKind::File(basic_file) = entry.kind else {
+            return self.offset;
+        };
+
+        let start = if basic_file.block_sizes.is_empty() {
+            self.reader
+                .fragments
+                .as_ref()
+                .and_then(|fragments| fragments.get(basic_file.frag_index as usize))
+                .map_or(0, |fragment| fragment.start)
+        } else {
+            u64::from(basic_file.blocks_start)
+        };
+        self.offset + start
+    }
+}
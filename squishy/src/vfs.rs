@@ -0,0 +1,140 @@
+use std::{
+    fmt,
+    io::Cursor,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use ::vfs::{
+    error::VfsErrorKind, SeekAndRead, SeekAndWrite, VfsError, VfsFileType, VfsMetadata, VfsResult,
+};
+pub use ::vfs::{FileSystem, VfsPath};
+
+use crate::{
+    error::{ErrorKind, SquishyError},
+    EntryKind, SquashFS, SquashFSEntry,
+};
+
+/// The SquashVfs struct is a read-only `vfs::FileSystem` over a SquashFS
+/// image, for code written against the vfs crate. Files are decompressed into
+/// memory when opened, and symlinks are followed. Writing fails with
+/// `NotSupported`. vfs paths are strings, so listing a directory with names
+/// that aren't valid UTF-8 fails.
+pub struct SquashVfs {
+    squashfs: SquashFS<'static>,
+}
+
+impl SquashVfs {
+    /// Creates the filesystem
+    ///
+    /// # Arguments
+    /// * `squashfs` - The image to expose, owning its reader
+    pub fn new(squashfs: SquashFS<'static>) -> Self {
+        Self { squashfs }
+    }
+
+    /// Returns the image, consuming the filesystem
+    pub fn into_inner(self) -> SquashFS<'static> {
+        self.squashfs
+    }
+
+    /// Looks up the entry at the vfs path, following symlinks
+    fn lookup(&self, path: &str) -> VfsResult<SquashFSEntry<'_>> {
+        let entry = self
+            .squashfs
+            .find(path)
+            .ok_or_else(|| VfsError::from(VfsErrorKind::FileNotFound))?;
+        match entry.kind {
+            EntryKind::Symlink(_) => self
+                .squashfs
+                .resolve_symlink(&entry)
+                .map_err(vfs_error)?
+                .ok_or_else(|| VfsErrorKind::FileNotFound.into()),
+            _ => Ok(entry),
+        }
+    }
+}
+
+impl fmt::Debug for SquashVfs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SquashVfs")
+            .field("offset", &self.squashfs.offset())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Converts the error, keeping missing files recognizable
+fn vfs_error(e: SquishyError) -> VfsError {
+    match e {
+        SquishyError::Io(e) => VfsErrorKind::IoError(e).into(),
+        e if e.kind() == ErrorKind::NotFound => VfsErrorKind::FileNotFound.into(),
+        e => VfsErrorKind::Other(e.to_string()).into(),
+    }
+}
+
+impl FileSystem for SquashVfs {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let dir = self.lookup(path)?;
+        if !matches!(dir.kind, EntryKind::Directory) {
+            return Err(VfsErrorKind::Other(format!("{} is not a directory", path)).into());
+        }
+        let names = self
+            .squashfs
+            .children(&dir.path)
+            .into_iter()
+            .map(|entry| {
+                let name = entry.path.file_name().unwrap_or_default();
+                name.to_str().map(str::to_owned).ok_or_else(|| {
+                    VfsErrorKind::Other(format!("{} is not valid UTF-8", entry.path.display()))
+                        .into()
+                })
+            })
+            .collect::<VfsResult<Vec<String>>>()?;
+        Ok(Box::new(names.into_iter()))
+    }
+
+    fn create_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let entry = self.lookup(path)?;
+        let contents = self.squashfs.read_entry(&entry).map_err(vfs_error)?;
+        Ok(Box::new(Cursor::new(contents)))
+    }
+
+    fn create_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn append_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let entry = self.lookup(path)?;
+        let file_type = match entry.kind {
+            EntryKind::Directory => VfsFileType::Directory,
+            _ => VfsFileType::File,
+        };
+        Ok(VfsMetadata {
+            file_type,
+            len: u64::from(entry.size),
+            created: None,
+            modified: Some(UNIX_EPOCH + Duration::from_secs(u64::from(entry.mtime()))),
+            accessed: None,
+        })
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        Ok(self.squashfs.exists(Path::new(path)))
+    }
+
+    fn remove_file(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn remove_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+}
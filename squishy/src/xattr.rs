@@ -0,0 +1,274 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    io::{Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+use crate::{
+    error::SquishyError,
+    metadata::{le32, le64, Inode, MetadataReader, Superblock, NOT_SET},
+    Result, SquashFS, SquashFSEntry,
+};
+
+/// Size of the entries of the xattr id table
+const XATTR_ID_SIZE: u64 = 16;
+
+/// Largest xattr value Linux supports
+const XATTR_SIZE_MAX: u32 = 64 << 10;
+
+/// Marks xattr values stored once and referenced by the other entries
+const XATTR_VALUE_OOL: u16 = 0x100;
+
+/// The Xattr struct is an extended attribute of an entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Xattr {
+    /// Full name, with the `user.`, `trusted.` or `security.` prefix
+    pub name: String,
+    pub value: Vec<u8>,
+}
+
+/// Reads the xattrs of every entry having some, by path. backhand drops the
+/// xattr index of inodes, so they come from the inodes `metadata::read_inodes`
+/// walked again.
+pub(crate) fn read_xattrs<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+    inodes: &[(PathBuf, Inode)],
+) -> Result<HashMap<PathBuf, Vec<Xattr>>> {
+    let superblock = Superblock::read(reader, offset)?;
+    if superblock.xattr_table == NOT_SET {
+        return Ok(HashMap::new());
+    }
+
+    // the id table header, followed by the positions of its metadata blocks
+    let table = offset
+        .checked_add(superblock.xattr_table)
+        .ok_or_else(|| SquishyError::InvalidSquashFS("invalid xattr table".to_string()))?;
+    let mut header = [0; 16];
+    reader.seek(SeekFrom::Start(table))?;
+    reader.read_exact(&mut header)?;
+    let kv_table = le64(&header, 0);
+    let ids = u64::from(le32(&header, 8));
+
+    // the id count comes from the image, so check the positions are inside
+    // the file before allocating them
+    let blocks_size = (ids * XATTR_ID_SIZE).div_ceil(8192) * 8;
+    let file_size = reader.seek(SeekFrom::End(0))?;
+    if table.saturating_add(16).saturating_add(blocks_size) > file_size {
+        return Err(SquishyError::InvalidSquashFS(format!(
+            "xattr id table of {} ids past the end of the file",
+            ids
+        )));
+    }
+    let mut blocks = vec![0; blocks_size as usize];
+    reader.seek(SeekFrom::Start(table + 16))?;
+    reader.read_exact(&mut blocks)?;
+
+    let mut metadata = MetadataReader::new(reader, offset, superblock.compressor);
+    let indexes = inodes
+        .iter()
+        .filter_map(|(path, inode)| Some((path, inode.xattr_index?)));
+
+    let mut by_index: HashMap<u32, Vec<Xattr>> = HashMap::new();
+    let mut xattrs = HashMap::new();
+    for (path, index) in indexes {
+        if u64::from(index) >= ids {
+            return Err(SquishyError::InvalidSquashFS(format!(
+                "invalid xattr index {} of {}",
+                index,
+                path.display()
+            )));
+        }
+        if let Entry::Vacant(vacant) = by_index.entry(index) {
+            let pos = u64::from(index) * XATTR_ID_SIZE;
+            let block = le64(&blocks, (pos / 8192) as usize * 8);
+            metadata.seek(block, (pos % 8192) as usize);
+            let reference = metadata.read_u64()?;
+            let count = metadata.read_u32()?;
+            metadata.seek_ref(kv_table, reference)?;
+            vacant.insert(read_pairs(&mut metadata, kv_table, count)?);
+        }
+        xattrs.insert(path.clone(), by_index[&index].clone());
+    }
+    Ok(xattrs)
+}
+
+/// Reads the key/value pairs the reader points to
+fn read_pairs<R: Read + Seek>(
+    metadata: &mut MetadataReader<'_, R>,
+    kv_table: u64,
+    count: u32,
+) -> Result<Vec<Xattr>> {
+    let mut xattrs = Vec::new();
+    for _ in 0..count {
+        let kind = metadata.read_u16()?;
+        let name_size = usize::from(metadata.read_u16()?);
+        let name = metadata.read_bytes(name_size)?;
+        let prefix = match kind & 0xff {
+            0 => "user.",
+            1 => "trusted.",
+            2 => "security.",
+            prefix => {
+                return Err(SquishyError::InvalidSquashFS(format!(
+                    "unknown xattr prefix {}",
+                    prefix
+                )))
+            }
+        };
+
+        let size = metadata.read_u32()?;
+        let value = if kind & XATTR_VALUE_OOL != 0 {
+            // the value holds a reference to the value stored elsewhere
+            let reference = metadata.read_u64()?;
+            let (block, at) = metadata.position();
+            metadata.seek_ref(kv_table, reference)?;
+            let size = metadata.read_u32()?;
+            let value = read_value(metadata, size)?;
+            metadata.seek(block, at);
+            value
+        } else {
+            read_value(metadata, size)?
+        };
+        xattrs.push(Xattr {
+            name: format!("{}{}", prefix, String::from_utf8_lossy(&name)),
+            value,
+        });
+    }
+    Ok(xattrs)
+}
+
+fn read_value<R: Read + Seek>(metadata: &mut MetadataReader<'_, R>, size: u32) -> Result<Vec<u8>> {
+    if size > XATTR_SIZE_MAX {
+        return Err(SquishyError::InvalidSquashFS(format!(
+            "xattr value of {} bytes",
+            size
+        )));
+    }
+    metadata.read_bytes(size as usize)
+}
+
+impl SquashFS<'_> {
+    /// Returns the extended attributes of an entry, like file capabilities
+    /// or SELinux labels.
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to look up.
+    ///
+    /// # Returns
+    /// The xattrs of the entry, empty if it has none.
+    pub fn xattrs(&self, entry: &SquashFSEntry) -> &[Xattr] {
+        self.xattrs
+            .get(&entry.path)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of an extended attribute of an entry.
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to look up.
+    /// * `name` - Full name of the xattr, e.g. `security.capability`.
+    ///
+    /// # Returns
+    /// The value, or None if the entry doesn't have the xattr.
+    pub fn xattr(&self, entry: &SquashFSEntry, name: &str) -> Option<&[u8]> {
+        self.xattrs(entry)
+            .iter()
+            .find(|xattr| xattr.name == name)
+            .map(|xattr| xattr.value.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use backhand::compression::Compressor;
+
+    use super::*;
+    use crate::SQUASHFS_MAGIC;
+
+    /// Appends a key of the xattr table with the prefix kind and name
+    fn key(table: &mut Vec<u8>, kind: u16, name: &[u8]) {
+        table.extend_from_slice(&kind.to_le_bytes());
+        table.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        table.extend_from_slice(name);
+    }
+
+    /// Appends a value of the xattr table, preceded by its size
+    fn value(table: &mut Vec<u8>, value: &[u8]) {
+        table.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        table.extend_from_slice(value);
+    }
+
+    /// Reads `count` pairs from the start of the table, stored as a single
+    /// uncompressed metadata block
+    fn read(table: &[u8], count: u32) -> Result<Vec<Xattr>> {
+        let mut data = (0x8000 | table.len() as u16).to_le_bytes().to_vec();
+        data.extend_from_slice(table);
+        let mut reader = Cursor::new(data);
+        let mut metadata = MetadataReader::new(&mut reader, 0, Compressor::None);
+        read_pairs(&mut metadata, 0, count)
+    }
+
+    #[test]
+    fn read_pairs_inline_and_out_of_line() {
+        let mut table = Vec::new();
+        key(&mut table, 0, b"foo");
+        value(&mut table, b"bar");
+        key(&mut table, 2 | XATTR_VALUE_OOL, b"selinux");
+        // the reference to the value stored after the pairs
+        value(&mut table, &60_u64.to_le_bytes());
+        key(&mut table, 1, b"overlay.opaque");
+        value(&mut table, b"y");
+        assert_eq!(table.len(), 60);
+        value(&mut table, b"system_u:object_r:bin_t:s0");
+
+        let xattrs = read(&table, 3).unwrap();
+        let pairs: Vec<(&str, &[u8])> = xattrs
+            .iter()
+            .map(|xattr| (xattr.name.as_str(), xattr.value.as_slice()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("user.foo", &b"bar"[..]),
+                ("security.selinux", b"system_u:object_r:bin_t:s0"),
+                ("trusted.overlay.opaque", b"y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_pairs_refuses_unknown_prefixes_and_huge_values() {
+        let mut table = Vec::new();
+        key(&mut table, 3, b"foo");
+        value(&mut table, b"bar");
+        assert!(matches!(
+            read(&table, 1),
+            Err(SquishyError::InvalidSquashFS(_))
+        ));
+
+        let mut table = Vec::new();
+        key(&mut table, 0, b"foo");
+        table.extend_from_slice(&(XATTR_SIZE_MAX + 1).to_le_bytes());
+        assert!(matches!(
+            read(&table, 1),
+            Err(SquishyError::InvalidSquashFS(_))
+        ));
+    }
+
+    #[test]
+    fn read_xattrs_bounds_the_id_table_by_the_file() {
+        let mut image = vec![0; 96];
+        image[..4].copy_from_slice(&SQUASHFS_MAGIC);
+        image[56..64].copy_from_slice(&96_u64.to_le_bytes());
+        // the id table header claims 2^32 - 1 ids
+        image.extend_from_slice(&0_u64.to_le_bytes());
+        image.extend_from_slice(&u32::MAX.to_le_bytes());
+        image.extend_from_slice(&[0; 4]);
+        let err = read_xattrs(&mut Cursor::new(image), 0, &[]).unwrap_err();
+        assert!(matches!(err, SquishyError::InvalidSquashFS(_)));
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    io::{self, Seek, Write},
+    path::Path,
+};
+
+use backhand::InnerNode;
+use zip::{write::SimpleFileOptions, CompressionMethod, DateTime, ZipWriter};
+
+#[cfg(feature = "erofs")]
+use crate::erofs::{Erofs, ErofsEntryKind};
+use crate::{filter::PathFilter, Result, SquashFS};
+
+/// Converts seconds since the Unix epoch to a zip (MS-DOS) timestamp, which
+/// can't go before 1980 or past 2107
+fn zip_time(mtime: u64) -> DateTime {
+    let days = (mtime / 86400) as i64;
+    let seconds = mtime % 86400;
+
+    // civil date from days since epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    DateTime::from_date_and_time(
+        year.clamp(1980, 2107) as u16,
+        month as u8,
+        day as u8,
+        (seconds / 3600) as u8,
+        (seconds % 3600 / 60) as u8,
+        (seconds % 60) as u8,
+    )
+    .unwrap_or_default()
+}
+
+/// Options of an entry with the mode and modification time
+fn options(mode: u32, mtime: u64, size: u64) -> SimpleFileOptions {
+    SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .unix_permissions(mode)
+        .last_modified_time(zip_time(mtime))
+        .large_file(size >= u64::from(u32::MAX))
+}
+
+/// Returns the name of the entry in the archive, relative to the root of
+/// the image, or None for the root itself
+fn zip_name(path: &Path) -> Option<String> {
+    let path = path.strip_prefix("/").unwrap_or(path);
+    (!path.as_os_str().is_empty()).then(|| path.to_string_lossy().into_owned())
+}
+
+impl SquashFS<'_> {
+    /// Writes the entries matching the filter as a zip archive to the writer,
+    /// with files deflated. Modes and modification times are kept, symlinks
+    /// are stored as links, other entries are left out. An empty filter
+    /// selects every entry.
+    ///
+    /// # Arguments
+    /// * `writer` - The writer to write the archive to.
+    /// * `filter` - The filter selecting the entries to write.
+    ///
+    /// # Returns
+    /// The writer, or an error if an entry cannot be read or written.
+    pub fn write_zip<W: Write + Seek>(&self, writer: W, filter: &PathFilter) -> Result<W> {
+        let mut zip = ZipWriter::new(writer);
+        for node in self.reader.files() {
+            let Some(name) = zip_name(&node.fullpath) else {
+                continue;
+            };
+            if !filter.matches(&node.fullpath) {
+                continue;
+            }
+
+            let mode = u32::from(node.header.permissions);
+            let mtime = u64::from(node.header.mtime);
+            match &node.inner {
+                InnerNode::File(file) => {
+                    let size = u64::from(file.basic.file_size);
+                    zip.start_file(name, options(mode, mtime, size))
+                        .map_err(io::Error::from)?;
+                    self.copy_blocks(&file.basic, &mut zip)?;
+                }
+                InnerNode::Dir(_) => zip
+                    .add_directory(name, options(mode, mtime, 0))
+                    .map_err(io::Error::from)?,
+                InnerNode::Symlink(symlink) => zip
+                    .add_symlink(
+                        name,
+                        symlink.link.to_string_lossy(),
+                        options(mode, mtime, 0),
+                    )
+                    .map_err(io::Error::from)?,
+                _ => {}
+            }
+        }
+        Ok(zip.finish().map_err(io::Error::from)?)
+    }
+}
+
+#[cfg(feature = "erofs")]
+impl Erofs<'_> {
+    /// Writes the entries matching the filter as a zip archive to the writer,
+    /// like `SquashFS::write_zip`.
+    ///
+    /// # Arguments
+    /// * `writer` - The writer to write the archive to.
+    /// * `filter` - The filter selecting the entries to write.
+    ///
+    /// # Returns
+    /// The writer, or an error if an entry cannot be read or written.
+    pub fn write_zip<W: Write + Seek>(&self, writer: W, filter: &PathFilter) -> Result<W> {
+        let mut zip = ZipWriter::new(writer);
+        for entry in self.entries() {
+            let Some(name) = zip_name(&entry.path) else {
+                continue;
+            };
+            if !filter.matches(&entry.path) {
+                continue;
+            }
+
+            let options = options(entry.mode(), entry.mtime(), entry.size);
+            match &entry.kind {
+                ErofsEntryKind::File => {
+                    zip.start_file(name, options).map_err(io::Error::from)?;
+                    self.copy_entry_to(&entry, &mut zip)?;
+                }
+                ErofsEntryKind::Directory => {
+                    zip.add_directory(name, options).map_err(io::Error::from)?
+                }
+                ErofsEntryKind::Symlink(target) => {
+                    // without the `/` prepended to the stored target
+                    let target = target.to_string_lossy();
+                    let target = target.strip_prefix('/').unwrap_or(&target);
+                    zip.add_symlink(name, target, options)
+                        .map_err(io::Error::from)?
+                }
+                ErofsEntryKind::Unknown => {}
+            }
+        }
+        Ok(zip.finish().map_err(io::Error::from)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "fs", feature = "erofs"))]
+    use std::io::{Cursor, Read};
+
+    #[cfg(any(feature = "fs", feature = "erofs"))]
+    use zip::ZipArchive;
+
+    use super::*;
+
+    /// An entry read back from an archive: its name, permission bits, whether
+    /// it's a directory or a symlink, and its data or link target
+    #[cfg(any(feature = "fs", feature = "erofs"))]
+    type Entry = (String, u32, bool, bool, Vec<u8>);
+
+    #[cfg(any(feature = "fs", feature = "erofs"))]
+    fn read_zip(archive: Vec<u8>) -> Vec<Entry> {
+        let mut archive = ZipArchive::new(Cursor::new(archive)).unwrap();
+        (0..archive.len())
+            .map(|index| {
+                let mut file = archive.by_index(index).unwrap();
+                let mut data = Vec::new();
+                file.read_to_end(&mut data).unwrap();
+                (
+                    file.name().unwrap().into_owned(),
+                    file.unix_mode().unwrap() & 0o7777,
+                    file.is_dir(),
+                    file.is_symlink(),
+                    data,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn zip_times_are_clamped_to_the_dos_range() {
+        let time = zip_time(1_700_000_000);
+        assert_eq!((time.year(), time.month(), time.day()), (2023, 11, 14));
+        assert_eq!((time.hour(), time.minute(), time.second()), (22, 13, 20));
+        assert_eq!(zip_time(0).year(), 1980);
+        assert_eq!(zip_time(u64::from(u32::MAX) * 2).year(), 2107);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn squashfs_selected_entries_are_written() {
+        use crate::testing::{image, Node};
+
+        let data = image(&[
+            Node::File("usr/bin/tool", b"tool"),
+            Node::Symlink("usr/bin/alias", "tool"),
+            Node::File("usr/share/doc/README", b"readme"),
+        ]);
+        let squashfs = SquashFS::from_bytes(&data, None).unwrap();
+        let filter = PathFilter::new(["*/bin/*"], Vec::<&str>::new()).unwrap();
+        let archive = squashfs
+            .write_zip(Cursor::new(Vec::new()), &filter)
+            .unwrap()
+            .into_inner();
+
+        let mut entries = read_zip(archive);
+        entries.sort();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.0.as_str()).collect();
+        assert_eq!(names, ["usr/bin/alias", "usr/bin/tool"]);
+        assert!(entries[0].3);
+        assert_eq!(entries[0].4, b"tool");
+        assert_eq!(entries[1].4, b"tool");
+    }
+
+    #[cfg(feature = "erofs")]
+    #[test]
+    fn erofs_selected_entries_are_written() {
+        let data = crate::erofs::tests::image();
+        let erofs = Erofs::from_bytes(&data, None).unwrap();
+
+        let archive = erofs
+            .write_zip(Cursor::new(Vec::new()), &PathFilter::default())
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            read_zip(archive),
+            [
+                ("bin/".to_string(), 0o700, true, false, Vec::new()),
+                ("hello".to_string(), 0o644, false, false, b"hello".to_vec()),
+                ("link".to_string(), 0o777, false, true, b"hello".to_vec()),
+            ]
+        );
+
+        let filter = PathFilter::new(Vec::<&str>::new(), ["bin", "link"]).unwrap();
+        let archive = erofs
+            .write_zip(Cursor::new(Vec::new()), &filter)
+            .unwrap()
+            .into_inner();
+        let names: Vec<String> = read_zip(archive).into_iter().map(|entry| entry.0).collect();
+        assert_eq!(names, ["hello"]);
+    }
+}